@@ -1,16 +1,18 @@
 use eyre::{OptionExt, Result};
-use itertools::{Either, Itertools};
 use lux_lib::{
     config::Config,
+    git::shorthand::GitUrlShorthand,
+    package::{PackageReq, PackageReqOrGitShorthand},
     progress::MultiProgress,
     project::Project,
     remote_package_db::RemotePackageDB,
     rockspec::lua_dependency::{self},
 };
+use url::Url;
 
 use crate::utils::project::{
     sync_build_dependencies_if_locked, sync_dependencies_if_locked,
-    sync_test_dependencies_if_locked, PackageReqOrGitShorthand,
+    sync_test_dependencies_if_locked,
 };
 
 #[derive(clap::Args)]
@@ -23,7 +25,11 @@ pub struct Add {
     /// Example: "github:owner/repo" {n}
     /// Supported git host prefixes are: "github:", "gitlab:", "sourcehut:" and "codeberg:". {n}
     /// Lux will automatically fetch the latest SemVer tag or commit SHA if no SemVer tag is found. {n}
-    /// Note that projects with git dependencies cannot be published to luarocks.org.
+    /// Note that projects with git dependencies cannot be published to luarocks.org. {n}
+    /// {n}
+    /// You can also add a package from the URL of its rockspec. {n}
+    /// Example: "https://luarocks.org/busted-2.2.0-1.rockspec" {n}
+    /// The package name and version are read from the rockspec.
     package_req: Vec<PackageReqOrGitShorthand>,
 
     /// Reinstall without prompt if a package is already installed.
@@ -49,11 +55,8 @@ pub async fn add(data: Add, config: Config) -> Result<()> {
 
     let progress = MultiProgress::new_arc(&config);
 
-    let (dependencies, git_dependencies): (Vec<_>, Vec<_>) =
-        data.package_req.iter().partition_map(|req| match req {
-            PackageReqOrGitShorthand::PackageReq(req) => Either::Left(req.clone()),
-            PackageReqOrGitShorthand::GitShorthand(url) => Either::Right(url.clone()),
-        });
+    let (dependencies, git_dependencies, rockspec_urls) =
+        split_package_reqs(data.package_req.iter());
 
     if !data.package_req.is_empty() {
         project
@@ -62,44 +65,75 @@ pub async fn add(data: Add, config: Config) -> Result<()> {
         project
             .add_git(lua_dependency::LuaDependencyType::Regular(git_dependencies))
             .await?;
+        project
+            .add_rockspecs(
+                lua_dependency::LuaDependencyType::Regular(rockspec_urls),
+                &config,
+                &bar,
+            )
+            .await?;
         sync_dependencies_if_locked(&project, progress.clone(), &config).await?;
     }
 
     let build_packages = data.build.unwrap_or_default();
     if !build_packages.is_empty() {
-        let (dependencies, git_dependencies): (Vec<_>, Vec<_>) =
-            build_packages.iter().partition_map(|req| match req {
-                PackageReqOrGitShorthand::PackageReq(req) => Either::Left(req.clone()),
-                PackageReqOrGitShorthand::GitShorthand(url) => Either::Right(url.clone()),
-            });
+        let (dependencies, git_dependencies, rockspec_urls) =
+            split_package_reqs(build_packages.iter());
         project
             .add(lua_dependency::DependencyType::Build(dependencies), &db)
             .await?;
         project
             .add_git(lua_dependency::LuaDependencyType::Build(git_dependencies))
             .await?;
+        project
+            .add_rockspecs(
+                lua_dependency::LuaDependencyType::Build(rockspec_urls),
+                &config,
+                &bar,
+            )
+            .await?;
         sync_build_dependencies_if_locked(&project, progress.clone(), &config).await?;
     }
 
     let test_packages = data.test.unwrap_or_default();
     if !test_packages.is_empty() {
-        let (dependencies, git_dependencies): (Vec<_>, Vec<_>) =
-            test_packages.iter().partition_map(|req| match req {
-                PackageReqOrGitShorthand::PackageReq(req) => Either::Left(req.clone()),
-                PackageReqOrGitShorthand::GitShorthand(url) => Either::Right(url.clone()),
-            });
+        let (dependencies, git_dependencies, rockspec_urls) =
+            split_package_reqs(test_packages.iter());
         project
             .add(lua_dependency::DependencyType::Test(dependencies), &db)
             .await?;
         project
             .add_git(lua_dependency::LuaDependencyType::Test(git_dependencies))
             .await?;
+        project
+            .add_rockspecs(
+                lua_dependency::LuaDependencyType::Test(rockspec_urls),
+                &config,
+                &bar,
+            )
+            .await?;
         sync_test_dependencies_if_locked(&project, progress.clone(), &config).await?;
     }
 
     Ok(())
 }
 
+fn split_package_reqs<'a>(
+    package_reqs: impl Iterator<Item = &'a PackageReqOrGitShorthand>,
+) -> (Vec<PackageReq>, Vec<GitUrlShorthand>, Vec<Url>) {
+    let mut dependencies = Vec::new();
+    let mut git_dependencies = Vec::new();
+    let mut rockspec_urls = Vec::new();
+    for package_req in package_reqs {
+        match package_req {
+            PackageReqOrGitShorthand::PackageReq(req) => dependencies.push(req.clone()),
+            PackageReqOrGitShorthand::GitShorthand(url) => git_dependencies.push(url.clone()),
+            PackageReqOrGitShorthand::RockspecUrl(url) => rockspec_urls.push(url.clone()),
+        }
+    }
+    (dependencies, git_dependencies, rockspec_urls)
+}
+
 #[cfg(test)]
 mod test {
     use assert_fs::{prelude::PathCopy, TempDir};
@@ -18,22 +18,40 @@ use lux_lib::{
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+
     let cli = Cli::parse();
 
-    let mut config_builder = ConfigBuilder::new()
-        .unwrap()
+    let config_builder = match &cli.config {
+        Some(config_file) => ConfigBuilder::default().apply_config_file(config_file)?,
+        None => ConfigBuilder::new().unwrap(),
+    };
+    let mut config_builder = config_builder
         .dev(Some(cli.dev))
         .extra_servers(cli.extra_servers)
         .generate_luarc(Some(!cli.no_luarc))
         .lua_dir(cli.lua_dir)
+        .system_lua(cli.system_lua.then_some(true))
+        .strict_binary_arch(cli.strict_binary_arch.then_some(true))
+        .deny_binary_collisions(cli.deny_binary_collisions.then_some(true))
+        .keep_build_dir_on_failure(cli.keep_build_dir.then_some(true))
         .lua_version(cli.lua_version)
         .namespace(cli.namespace)
         .only_sources(cli.only_sources)
         .server(cli.server)
-        .timeout(
+        .request_timeout(
             cli.timeout
                 .map(|duration| Duration::from_secs(duration as u64)),
         )
+        .download_timeout(
+            cli.download_timeout
+                .map(|duration| Duration::from_secs(duration as u64)),
+        )
         .max_jobs(cli.max_jobs)
         .user_tree(cli.tree)
         .variables(
@@ -65,9 +83,7 @@ async fn main() -> Result<()> {
             Debug::Project(debug_project) => project::debug_project(debug_project)?,
         },
         Commands::New(project_data) => project::write_project_rockspec(project_data).await?,
-        Commands::Build(build_data) => {
-            build::build(build_data, config).await?;
-        }
+        Commands::Build(build_data) => build::build_command(build_data, config).await?,
         Commands::List(list_data) => list::list_installed(list_data, config)?,
         Commands::Lua(run_lua) => run_lua::run_lua(run_lua, config).await?,
         Commands::Install(install_data) => install::install(install_data, config).await?,
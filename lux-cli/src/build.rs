@@ -16,6 +16,40 @@ pub struct Build {
     /// Build only the dependencies
     #[arg(long)]
     only_deps: bool,
+
+    /// Install the project in editable mode.{n}
+    /// Symlinks the project's Lua sources into the tree instead of copying them,{n}
+    /// so that changes are picked up without reinstalling.
+    #[arg(long)]
+    editable: bool,
+}
+
+#[derive(Args)]
+pub struct BuildCommand {
+    #[clap(flatten)]
+    build: Build,
+
+    /// Check that the project builds, without installing it.{n}
+    /// Compiles the project into a temporary staging directory,{n}
+    /// leaving the project tree and lockfile untouched.{n}
+    /// Dependencies are not installed.
+    #[arg(long, conflicts_with_all = ["no_lock", "only_deps", "editable"])]
+    check: bool,
+}
+
+pub async fn build_command(data: BuildCommand, config: Config) -> Result<()> {
+    if data.check {
+        let project = Project::current_or_err()?;
+        operations::BuildProject::new(&project, &config)
+            .no_lock(false)
+            .only_deps(false)
+            .no_install(true)
+            .build()
+            .await?;
+    } else {
+        build(data.build, config).await?;
+    }
+    Ok(())
 }
 
 /// Returns `Some` if the `only_deps` arg is set to `false`.
@@ -24,6 +58,7 @@ pub async fn build(data: Build, config: Config) -> Result<Option<LocalPackage>>
     let result = operations::BuildProject::new(&project, &config)
         .no_lock(data.no_lock)
         .only_deps(data.only_deps)
+        .editable(data.editable)
         .build()
         .await?;
     Ok(result)
@@ -50,6 +50,10 @@ pub async fn check(args: Check, config: Config) -> Result<()> {
     sync_dependencies_if_locked(&project, progress.clone(), &config).await?;
     sync_test_dependencies_if_locked(&project, progress, &config).await?;
 
+    for issue in project.tree(&config)?.verify()? {
+        eprintln!("⚠️ WARNING: {issue}");
+    }
+
     let project_root = project.root();
     let workspace = vec![
         project_root.join("src"),
@@ -3,7 +3,7 @@ use eyre::{eyre, Result};
 use inquire::{Confirm, Select};
 use itertools::Itertools;
 use lux_lib::{
-    config::{Config, LuaVersion},
+    config::Config,
     lockfile::LocalPackage,
     lua_rockspec::RemoteLuaRockspec,
     package::PackageReq,
@@ -23,7 +23,7 @@ pub struct Doc {
 }
 
 pub async fn doc(args: Doc, config: Config) -> Result<()> {
-    let tree = config.user_tree(LuaVersion::from(&config)?.clone())?;
+    let tree = config.user_tree(config.resolve_lua_version(None)?)?;
     let package_id = match tree.match_rocks(&args.package)? {
         RockMatches::NotFound(package_req) => {
             Err(eyre!("No package matching {} found.", package_req))
@@ -3,7 +3,7 @@ use std::env;
 use clap::Args;
 use eyre::Result;
 use lux_lib::{
-    config::{Config, LuaVersion},
+    config::Config,
     operations::{self, install_command},
     path::Paths,
     project::Project,
@@ -33,7 +33,7 @@ pub async fn exec(run: Exec, config: Config) -> Result<()> {
     let tree = match &project {
         Some(project) => project.tree(&config)?,
         None => {
-            let lua_version = LuaVersion::from(&config)?.clone();
+            let lua_version = config.resolve_lua_version(None)?;
             config.user_tree(lua_version)?
         }
     };
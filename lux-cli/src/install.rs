@@ -1,8 +1,9 @@
 use eyre::Result;
+use indicatif::HumanBytes;
 use lux_lib::{
-    config::{Config, LuaVersion},
+    config::Config,
     lockfile::PinnedState,
-    operations,
+    operations::{self, install::InstallPlan},
     package::PackageReq,
     progress::MultiProgress,
 };
@@ -21,24 +22,57 @@ pub struct Install {
     /// Reinstall without prompt if a package is already installed.
     #[arg(long)]
     force: bool,
+
+    /// Rebuild the packages if they are already installed,
+    /// without rebuilding their dependencies.
+    #[arg(long)]
+    reinstall: bool,
+
+    /// Print the packages that would be installed, without installing them.
+    #[arg(long)]
+    dry_run: bool,
 }
 
 /// Install a rock into the user tree.
 pub async fn install(data: Install, config: Config) -> Result<()> {
     let pin = PinnedState::from(data.pin);
 
-    let lua_version = LuaVersion::from(&config)?.clone();
+    let lua_version = config.resolve_lua_version(None)?;
     let tree = config.user_tree(lua_version)?;
 
-    let packages = apply_build_behaviour(data.package_req, pin, data.force, &tree)?;
+    let packages = apply_build_behaviour(data.package_req, pin, data.force, data.reinstall, &tree)?;
 
     // TODO(vhyrro): If the tree doesn't exist then error out.
-    operations::Install::new(&config)
+    let install = operations::Install::new(&config)
         .packages(packages)
         .tree(tree)
-        .progress(MultiProgress::new_arc(&config))
-        .install()
-        .await?;
+        .progress(MultiProgress::new_arc(&config));
+    if data.dry_run {
+        print_install_plan(&install.plan().await?);
+    } else {
+        install.install().await?;
+    }
 
     Ok(())
 }
+
+fn print_install_plan(plan: &InstallPlan) {
+    if plan.is_empty() {
+        println!("Nothing to install.");
+        return;
+    }
+    println!("Packages to install:");
+    for package in plan.packages() {
+        println!("  {package}");
+    }
+    if !plan.build_dependencies().is_empty() {
+        println!("Build dependencies to install:");
+        for package in plan.build_dependencies() {
+            println!("  {package}");
+        }
+    }
+    println!(
+        "Packed rocks to download: {}",
+        HumanBytes(plan.download_size())
+    );
+}
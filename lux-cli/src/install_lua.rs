@@ -1,12 +1,12 @@
 use eyre::Result;
 use lux_lib::{
-    config::{Config, LuaVersion},
+    config::Config,
     lua_installation::LuaInstallation,
     progress::{MultiProgress, ProgressBar},
 };
 
 pub async fn install_lua(config: Config) -> Result<()> {
-    let version_stringified = &LuaVersion::from(&config)?;
+    let version_stringified = &config.resolve_lua_version(None)?;
 
     let progress = MultiProgress::new(&config);
 
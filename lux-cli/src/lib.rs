@@ -3,7 +3,7 @@ use std::error::Error;
 use std::path::PathBuf;
 
 use add::Add;
-use build::Build;
+use build::BuildCommand;
 use check::Check;
 use clap::{Parser, Subcommand};
 use config::ConfigCmd;
@@ -101,6 +101,29 @@ pub struct Cli {
     #[arg(long, value_name = "prefix")]
     pub lua_dir: Option<PathBuf>,
 
+    /// Never build Lua from source.{n}
+    /// Use this if Lua is provided by the system, to fail if it can't be found{n}
+    /// instead of building it.
+    #[arg(long)]
+    pub system_lua: bool,
+
+    /// Only install binary rocks for the 'all' architecture{n}
+    /// if their rockspec does not declare a native build.{n}
+    /// Otherwise, fall back to building from source.
+    #[arg(long)]
+    pub strict_binary_arch: bool,
+
+    /// Fail to install a rock if one of its binaries{n}
+    /// is already provided by a different rock.{n}
+    /// By default, lux warns and overwrites the binary.
+    #[arg(long)]
+    pub deny_binary_collisions: bool,
+
+    /// Keep the build directory of a failed build for debugging.{n}
+    /// Can also be enabled by setting LUX_KEEP_BUILD_DIR=1.
+    #[arg(long)]
+    pub keep_build_dir: bool,
+
     /// Which Lua installation to use.{n}
     /// Valid versions are: '5.1', '5.2', '5.3', '5.4', 'jit' and 'jit52'.
     #[arg(long, value_name = "ver")]
@@ -110,6 +133,11 @@ pub struct Cli {
     #[arg(long, value_name = "tree")]
     pub tree: Option<PathBuf>,
 
+    /// Load the configuration from this file instead of the default config file.{n}
+    /// Command line flags take precedence over the settings in the file.
+    #[arg(long, value_name = "path")]
+    pub config: Option<PathBuf>,
+
     /// Specifies the cache directory for e.g. luarocks manifests.
     #[arg(long, value_name = "path")]
     pub cache_path: Option<PathBuf>,
@@ -136,6 +164,11 @@ pub struct Cli {
     #[arg(long, value_name = "seconds")]
     pub timeout: Option<usize>,
 
+    /// Timeout on large downloads, such as packed rocks and source archives, in seconds.{n}
+    /// 0 means no timeout (wait forever). Default is 300.
+    #[arg(long, value_name = "seconds")]
+    pub download_timeout: Option<usize>,
+
     /// Maximum buffer size for parallel jobs, such as downloading rockspecs and installing rocks.
     /// 0 means no limit. Default is 0.
     #[arg(long, visible_short_alias = 'j')]
@@ -155,7 +188,7 @@ pub enum Commands {
     /// Add a dependency to the current project.
     Add(Add),
     /// Build/compile a project.
-    Build(Build),
+    Build(BuildCommand),
     /// [EXPERIMENTAL]{n}
     /// Type check the current project based on EmmyLua/LuaCATS annotations.{n}
     /// Respects `.emmyrc.json` and `.luarc.json` files in the project directory.
@@ -312,7 +345,7 @@ pub enum Commands {
 }
 
 /// Parse a key=value pair.
-fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
+pub(crate) fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
 where
     T: std::str::FromStr,
     T::Err: Error + Send + Sync + 'static,
@@ -1,21 +1,28 @@
 use clap::Args;
 use eyre::Result;
 use itertools::Itertools as _;
-use lux_lib::{
-    config::{Config, LuaVersion},
-    lockfile::PinnedState,
-};
+use lux_lib::{config::Config, lockfile::PinnedState};
 use text_trees::{FormatCharacters, StringTreeNode, TreeFormatting};
 
 #[derive(Args)]
 pub struct ListCmd {
     #[arg(long)]
     porcelain: bool,
+
+    /// Output a stable JSON array of the installed rocks,
+    /// with their name, version, pinned state, entrypoint flag and source kind.
+    #[arg(long, conflicts_with = "porcelain")]
+    json: bool,
 }
 
 /// List rocks that are installed in the user tree
 pub fn list_installed(list_data: ListCmd, config: Config) -> Result<()> {
-    let tree = config.user_tree(LuaVersion::from(&config)?.clone())?;
+    let tree = config.user_tree(config.resolve_lua_version(None)?)?;
+    if list_data.json {
+        println!("{}", serde_json::to_string(&tree.listing()?)?);
+        return Ok(());
+    }
+
     let available_rocks = tree.list()?;
 
     if list_data.porcelain {
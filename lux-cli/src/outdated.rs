@@ -4,10 +4,7 @@ use clap::Args;
 use eyre::Result;
 use itertools::Itertools;
 use lux_lib::{
-    config::{Config, LuaVersion},
-    progress::MultiProgress,
-    project::Project,
-    remote_package_db::RemotePackageDB,
+    config::Config, progress::MultiProgress, project::Project, remote_package_db::RemotePackageDB,
 };
 use text_trees::{FormatCharacters, StringTreeNode, TreeFormatting};
 
@@ -32,7 +29,7 @@ pub async fn outdated(outdated_data: Outdated, config: Config) -> Result<()> {
             project.tree(&config)?
         }
         None => {
-            let lua_version = LuaVersion::from(&config)?.clone();
+            let lua_version = config.resolve_lua_version(None)?;
             config.user_tree(lua_version)?
         }
     };
@@ -5,7 +5,7 @@ use clap::Args;
 use eyre::{eyre, Result};
 use lux_lib::{
     build::{Build, BuildBehaviour},
-    config::{Config, LuaVersion},
+    config::Config,
     lua_installation::LuaInstallation,
     lua_rockspec::RemoteLuaRockspec,
     operations::{self, Install, PackageInstallSpec},
@@ -64,7 +64,7 @@ pub struct Pack {
 }
 
 pub async fn pack(args: Pack, config: Config) -> Result<()> {
-    let lua_version = LuaVersion::from(&config)?.clone();
+    let lua_version = config.resolve_lua_version(None)?;
     let dest_dir = std::env::current_dir()?;
     let progress = MultiProgress::new_arc(&config);
     let result: Result<PathBuf> = match args.package_or_rockspec {
@@ -3,7 +3,7 @@ use eyre::eyre;
 use eyre::Context;
 use eyre::Result;
 use itertools::Itertools;
-use lux_lib::config::{Config, LuaVersion};
+use lux_lib::config::Config;
 use lux_lib::lockfile::PinnedState;
 use lux_lib::operations;
 use lux_lib::package::PackageName;
@@ -82,7 +82,7 @@ pub async fn set_pinned_state(data: ChangePin, config: Config, pin: PinnedState)
             }
         }
         None => {
-            let tree = config.user_tree(LuaVersion::from(&config)?.clone())?;
+            let tree = config.user_tree(config.resolve_lua_version(None)?)?;
 
             for package in &data.package {
                 match tree.match_rocks_and(package, |package| pin != package.pinned())? {
@@ -1,13 +1,13 @@
 use eyre::Result;
 use inquire::Confirm;
 use lux_lib::{
-    config::{Config, LuaVersion},
+    config::Config,
     progress::{MultiProgress, ProgressBar},
 };
 
 /// Purge the user tree
 pub async fn purge(config: Config) -> Result<()> {
-    let tree = config.user_tree(LuaVersion::from(&config)?.clone())?;
+    let tree = config.user_tree(config.resolve_lua_version(None)?)?;
 
     let len = tree.list()?.len();
 
@@ -5,13 +5,7 @@ use tokio::process::Command;
 use clap::Args;
 use eyre::{eyre, Result};
 use itertools::Itertools;
-use lux_lib::{
-    config::{Config, LuaVersion},
-    lua_installation::LuaBinary,
-    operations,
-    project::Project,
-    rockspec::LuaVersionCompatibility,
-};
+use lux_lib::{config::Config, lua_installation::LuaBinary, operations, project::Project};
 
 use crate::build::{self, Build};
 
@@ -52,7 +46,7 @@ pub async fn run_lua(run_lua: RunLua, config: Config) -> Result<()> {
     let project = Project::current()?;
     let (lua_version, root, tree, mut welcome_message) = match &project {
         Some(project) => (
-            project.toml().lua_version_matches(&config)?,
+            config.resolve_lua_version(Some(project))?,
             project.root().to_path_buf(),
             project.tree(&config)?,
             format!(
@@ -61,7 +55,7 @@ pub async fn run_lua(run_lua: RunLua, config: Config) -> Result<()> {
             ),
         ),
         None => {
-            let version = LuaVersion::from(&config)?.clone();
+            let version = config.resolve_lua_version(None)?;
             (
                 version.clone(),
                 std::env::current_dir()?,
@@ -6,6 +6,8 @@ use lux_lib::{
     project::Project,
 };
 
+use crate::parse_key_val;
+
 #[derive(Args)]
 pub struct Test {
     /// Extra arguments to pass to the test runner or test script.
@@ -18,6 +20,11 @@ pub struct Test {
     /// Ignore the project's lockfile and don't create one.
     #[arg(long)]
     no_lock: bool,
+
+    /// Set an environment variable for the test runner.{n}
+    /// Example: `lx test --env "LUA=nlua"`
+    #[arg(long = "env", value_name = "variable", value_parser = parse_key_val::<String, String>)]
+    env_vars: Vec<(String, String)>,
 }
 
 pub async fn test(test: Test, config: Config) -> Result<()> {
@@ -32,6 +39,7 @@ pub async fn test(test: Test, config: Config) -> Result<()> {
     operations::Test::new(project, &config)
         .args(test_args)
         .env(test_env)
+        .env_vars(test.env_vars)
         .no_lock(test.no_lock)
         .run()
         .await?;
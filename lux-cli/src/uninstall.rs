@@ -4,7 +4,7 @@ use inquire::Confirm;
 use itertools::Itertools;
 use lux_lib::{
     build::BuildBehaviour,
-    config::{Config, LuaVersion},
+    config::Config,
     lockfile::LocalPackageId,
     operations::{self, PackageInstallSpec},
     package::PackageReq,
@@ -20,7 +20,7 @@ pub struct Uninstall {
 
 /// Uninstall one or multiple rocks from the user tree
 pub async fn uninstall(uninstall_args: Uninstall, config: Config) -> Result<()> {
-    let tree = config.user_tree(LuaVersion::from(&config)?.clone())?;
+    let tree = config.user_tree(config.resolve_lua_version(None)?)?;
 
     let package_matches = uninstall_args
         .packages
@@ -163,7 +163,7 @@ Reinstall?
 
     let mut has_dangling_rocks = true;
     while has_dangling_rocks {
-        let tree = config.user_tree(LuaVersion::from(&config)?.clone())?;
+        let tree = config.user_tree(config.resolve_lua_version(None)?)?;
         let lockfile = tree.lockfile()?;
         let dangling_rocks = lockfile
             .rocks()
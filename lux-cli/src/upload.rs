@@ -1,9 +1,6 @@
 use clap::Args;
 use eyre::Result;
-use lux_lib::{
-    config::Config, progress::MultiProgress, project::Project, remote_package_db::RemotePackageDB,
-    upload::ProjectUpload,
-};
+use lux_lib::{config::Config, progress::MultiProgress, project::Project, upload::ProjectUpload};
 
 #[cfg(feature = "gpgme")]
 use lux_lib::upload::SignatureProtocol;
@@ -22,15 +19,14 @@ pub async fn upload(data: Upload, config: Config) -> Result<()> {
 
     let progress = MultiProgress::new(&config);
     let bar = progress.map(MultiProgress::new_bar);
-    let package_db = RemotePackageDB::from_config(&config, &bar).await?;
-    ProjectUpload::new()
+    let uploaded = ProjectUpload::new()
         .project(project)
         .config(&config)
         .sign_protocol(data.sign_protocol)
         .progress(&bar)
-        .package_db(&package_db)
         .upload_to_luarocks()
         .await?;
+    println!("Uploaded {} as {}", uploaded.package(), uploaded.version());
 
     Ok(())
 }
@@ -40,15 +36,14 @@ pub async fn upload(_data: Upload, config: Config) -> Result<()> {
     let project = Project::current()?.unwrap();
     let progress = MultiProgress::new(&config);
     let bar = progress.map(MultiProgress::new_bar);
-    let package_db = RemotePackageDB::from_config(&config, &bar).await?;
 
-    ProjectUpload::new()
+    let uploaded = ProjectUpload::new()
         .project(project)
         .config(&config)
         .progress(&bar)
-        .package_db(&package_db)
         .upload_to_luarocks()
         .await?;
+    println!("Uploaded {} as {}", uploaded.package(), uploaded.version());
 
     Ok(())
 }
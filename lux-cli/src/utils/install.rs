@@ -14,6 +14,7 @@ pub fn apply_build_behaviour(
     package_reqs: Vec<PackageReq>,
     pin: PinnedState,
     force: bool,
+    reinstall: bool,
     tree: &Tree,
 ) -> Result<Vec<PackageInstallSpec>> {
     let lockfile = tree.lockfile()?;
@@ -34,20 +35,22 @@ pub fn apply_build_behaviour(
                 || existing_packages
                     .iter()
                     .all(|pkg_id| !lockfile.is_entrypoint(pkg_id));
-            let build_behaviour: Option<BuildBehaviour> = if force || existing_packages.is_empty() {
-                Some(BuildBehaviour::from(force))
-            } else if Confirm::new(&format!("Package {req} already exists. Overwrite?"))
-                .with_default(false)
-                .prompt()
-                .expect("Error prompting for reinstall")
-            {
-                Some(BuildBehaviour::Force)
-            } else {
-                None
-            };
+            let build_behaviour: Option<BuildBehaviour> =
+                if force || reinstall || existing_packages.is_empty() {
+                    Some(BuildBehaviour::from(force))
+                } else if Confirm::new(&format!("Package {req} already exists. Overwrite?"))
+                    .with_default(false)
+                    .prompt()
+                    .expect("Error prompting for reinstall")
+                {
+                    Some(BuildBehaviour::Force)
+                } else {
+                    None
+                };
             build_behaviour.map(|build_behaviour| {
                 PackageInstallSpec::new(req, tree::EntryType::Entrypoint)
                     .build_behaviour(build_behaviour)
+                    .reinstall(reinstall)
                     .pin(pin)
                     .opt(OptState::Required)
                     .build()
@@ -1,36 +1,14 @@
-use std::{str::FromStr, sync::Arc};
+use std::sync::Arc;
 
 use eyre::{Context, Result};
 use lux_lib::{
-    config::{Config, LuaVersion},
-    git::shorthand::GitUrlShorthand,
+    config::Config,
     operations::Sync,
-    package::PackageReq,
     progress::{MultiProgress, Progress},
     project::Project,
     tree::Tree,
 };
 
-/// Used for parsing alternatives between a git URL shorthand and a package requirement.
-// The `FromStr` instance tries to parse a git URL shorthand first (expecting a git host prefix),
-// and then a package requirement.
-#[derive(Debug, Clone)]
-pub enum PackageReqOrGitShorthand {
-    PackageReq(PackageReq),
-    GitShorthand(GitUrlShorthand),
-}
-
-impl FromStr for PackageReqOrGitShorthand {
-    type Err = eyre::Error;
-
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match GitUrlShorthand::parse_with_prefix(s) {
-            Ok(shorthand) => Ok(Self::GitShorthand(shorthand)),
-            Err(_) => Ok(Self::PackageReq(PackageReq::parse(s)?)),
-        }
-    }
-}
-
 /// Get the current project's tree, or fall back to
 /// the user tree if not in a project
 pub fn current_project_or_user_tree(config: &Config) -> Result<Tree> {
@@ -38,7 +16,7 @@ pub fn current_project_or_user_tree(config: &Config) -> Result<Tree> {
     Ok(match &project {
         Some(project) => project.tree(config)?,
         None => {
-            let lua_version = LuaVersion::from(config)?.clone();
+            let lua_version = config.resolve_lua_version(None)?;
             config.user_tree(lua_version)?
         }
     })
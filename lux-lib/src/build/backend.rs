@@ -11,13 +11,15 @@ use crate::{
     config::Config,
     lua_installation::LuaInstallation,
     lua_rockspec::DeploySpec,
+    package::PackageName,
     progress::{Progress, ProgressBar},
     tree::{RockLayout, Tree},
 };
 
-#[derive(Builder)]
+#[derive(Builder, Clone)]
 #[builder(start_fn(name = "new"))]
 pub(crate) struct RunBuildArgs<'a> {
+    pub(crate) package: &'a PackageName,
     pub(crate) output_paths: &'a RockLayout,
     pub(crate) no_install: bool,
     pub(crate) lua: &'a LuaInstallation,
@@ -11,7 +11,7 @@ use walkdir::{DirEntry, WalkDir};
 use crate::{
     build::{
         backend::{BuildBackend, BuildInfo, RunBuildArgs},
-        utils,
+        check_binary_collisions, utils, BinaryCollisionError,
     },
     lua_rockspec::{BuiltinBuildSpec, LuaModule, ModuleSpec},
     tree::TreeError,
@@ -31,6 +31,8 @@ pub enum BuiltinBuildError {
     Io(#[from] io::Error),
     #[error(transparent)]
     Tree(#[from] TreeError),
+    #[error(transparent)]
+    BinaryCollision(#[from] BinaryCollisionError),
 }
 
 impl BuildBackend for BuiltinBuildSpec {
@@ -72,6 +74,7 @@ impl BuildBackend for BuiltinBuildSpec {
                             lua,
                             external_dependencies,
                             config,
+                            progress,
                         )
                         .await?
                     } else {
@@ -101,6 +104,7 @@ impl BuildBackend for BuiltinBuildSpec {
                         lua,
                         external_dependencies,
                         config,
+                        progress,
                     )
                     .await?
                 }
@@ -114,6 +118,7 @@ impl BuildBackend for BuiltinBuildSpec {
                         lua,
                         external_dependencies,
                         config,
+                        progress,
                     )
                     .await?
                 }
@@ -124,6 +129,13 @@ impl BuildBackend for BuiltinBuildSpec {
         for bin_script in autodetect_bin_scripts(build_dir) {
             if let Some(target) = bin_script.file_name() {
                 let file_name = target.to_string_lossy().to_string();
+                check_binary_collisions(
+                    args.package,
+                    [file_name.as_str()],
+                    tree,
+                    progress,
+                    config,
+                )?;
                 let installed_bin_script =
                     utils::install_binary(&bin_script, &file_name, tree, lua, args.deploy, config)
                         .await
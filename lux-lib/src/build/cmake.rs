@@ -1,15 +1,12 @@
 use itertools::Itertools;
-use std::{
-    env, io,
-    process::{ExitStatus, Stdio},
-};
+use std::{env, io, process::Stdio};
 use thiserror::Error;
 use tokio::process::Command;
 
 use crate::{
     build::{
         backend::{BuildBackend, BuildInfo, RunBuildArgs},
-        utils,
+        utils::{self, CommandFailure},
     },
     config::Config,
     lua_rockspec::CMakeBuildSpec,
@@ -26,13 +23,8 @@ pub enum CMakeError {
     Tree(#[from] TreeError),
     #[error(transparent)]
     Paths(#[from] PathsError),
-    #[error("{name} step failed.\n\n{status}\n\nstdout:\n{stdout}\n\nstderr:\n{stderr}")]
-    CommandFailure {
-        name: String,
-        status: ExitStatus,
-        stdout: String,
-        stderr: String,
-    },
+    #[error(transparent)]
+    CommandFailure(#[from] CommandFailure),
     #[error("failed to run `cmake` step: {0}")]
     Io(io::Error),
     #[error("failed to write CMakeLists.txt: {0}")]
@@ -153,14 +145,7 @@ async fn spawn_cmake_cmd(cmd: &mut Command, config: &Config) -> Result<(), CMake
     match cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
         Ok(child) => match child.wait_with_output().await {
             Ok(output) if output.status.success() => utils::log_command_output(&output, config),
-            Ok(output) => {
-                return Err(CMakeError::CommandFailure {
-                    name: config.cmake_cmd().clone(),
-                    status: output.status,
-                    stdout: String::from_utf8_lossy(&output.stdout).into(),
-                    stderr: String::from_utf8_lossy(&output.stderr).into(),
-                });
-            }
+            Ok(output) => return Err(CommandFailure::new(cmd, &output).into()),
             Err(err) => return Err(CMakeError::Io(err)),
         },
         Err(_) => return Err(CMakeError::CommandNotFound(config.cmake_cmd().clone())),
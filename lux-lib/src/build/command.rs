@@ -1,9 +1,4 @@
-use std::{
-    collections::HashMap,
-    io,
-    path::Path,
-    process::{ExitStatus, Stdio},
-};
+use std::{collections::HashMap, io, path::Path, process::Stdio};
 use thiserror::Error;
 use tokio::process::Command;
 use which::which;
@@ -19,7 +14,7 @@ use crate::{
 };
 
 use super::external_dependency::ExternalDependencyInfo;
-use super::utils;
+use super::utils::{self, CommandFailure};
 
 #[derive(Error, Debug)]
 pub enum CommandError {
@@ -27,7 +22,7 @@ pub enum CommandError {
     Tree(#[from] TreeError),
     #[error(transparent)]
     Paths(#[from] PathsError),
-    #[error("'build_command' and 'install_command' cannot be empty.")]
+    #[error("'build_command', 'install_command', 'pre_build' and 'post_build' cannot be empty.")]
     EmptyCommand,
     #[error("error parsing command:\n{command}\n\nerror: {err}")]
     ParseError {
@@ -38,13 +33,8 @@ pub enum CommandError {
     ShellNotFoundError(#[from] which::Error),
     #[error("error executing command:\n{command}\n\nerror: {err}")]
     Io { err: io::Error, command: String },
-    #[error("failed to execute command:\n{command}\n\nstatus: {status}\nstdout: {stdout}\nstderr: {stderr}")]
-    CommandFailure {
-        command: String,
-        status: ExitStatus,
-        stdout: String,
-        stderr: String,
-    },
+    #[error(transparent)]
+    CommandFailure(#[from] CommandFailure),
     #[error(transparent)]
     VariableSubstitutionError(#[from] VariableSubstitutionError),
 }
@@ -97,6 +87,22 @@ impl BuildBackend for CommandBuildSpec {
     }
 }
 
+/// Run a `pre_build` or `post_build` hook in the build directory.
+pub(crate) async fn run_hook(command: &str, args: &RunBuildArgs<'_>) -> Result<(), CommandError> {
+    let build_tree = args.tree.build_tree(args.config)?;
+    let build_paths = Paths::new(&build_tree)?;
+    run_command(
+        command,
+        args.output_paths,
+        args.lua,
+        args.external_dependencies,
+        args.config,
+        args.build_dir,
+        &build_paths,
+    )
+    .await
+}
+
 async fn run_command(
     command: &str,
     output_paths: &RockLayout,
@@ -121,17 +127,16 @@ async fn run_command(
     #[cfg(not(target_env = "msvc"))]
     let (shell, shell_arg) = (which("sh")?, "-c");
 
-    match Command::new(shell)
-        .arg(shell_arg)
+    let mut cmd = Command::new(shell);
+    cmd.arg(shell_arg)
         .arg(&substituted_cmd)
         .current_dir(build_dir)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .env("PATH", &bin_path)
         .env("LUA_PATH", &lua_path)
-        .env("LUA_CPATH", &lua_cpath)
-        .spawn()
-    {
+        .env("LUA_CPATH", &lua_cpath);
+    match cmd.spawn() {
         Err(err) => {
             return Err(CommandError::Io {
                 err,
@@ -140,14 +145,7 @@ async fn run_command(
         }
         Ok(child) => match child.wait_with_output().await {
             Ok(output) if output.status.success() => utils::log_command_output(&output, config),
-            Ok(output) => {
-                return Err(CommandError::CommandFailure {
-                    command: substituted_cmd,
-                    status: output.status,
-                    stdout: String::from_utf8_lossy(&output.stdout).into(),
-                    stderr: String::from_utf8_lossy(&output.stderr).into(),
-                });
-            }
+            Ok(output) => return Err(CommandFailure::new(&cmd, &output).into()),
             Err(err) => {
                 return Err(CommandError::Io {
                     err,
@@ -1,17 +1,13 @@
 use itertools::Itertools;
 use path_slash::PathBufExt;
-use std::{
-    io,
-    path::PathBuf,
-    process::{ExitStatus, Stdio},
-};
+use std::{io, path::PathBuf, process::Stdio};
 use thiserror::Error;
 use tokio::process::Command;
 
 use crate::{
     build::{
         backend::{BuildBackend, BuildInfo, RunBuildArgs},
-        utils,
+        utils::{self, CommandFailure},
     },
     lua_rockspec::MakeBuildSpec,
     path::{Paths, PathsError},
@@ -25,13 +21,8 @@ pub enum MakeError {
     Tree(#[from] TreeError),
     #[error(transparent)]
     Paths(#[from] PathsError),
-    #[error("{name} step failed.\n\n{status}\n\nstdout:\n{stdout}\n\nstderr:\n{stderr}")]
-    CommandFailure {
-        name: String,
-        status: ExitStatus,
-        stdout: String,
-        stderr: String,
-    },
+    #[error(transparent)]
+    CommandFailure(#[from] CommandFailure),
     #[error("failed to run `make` step: {0}")]
     Io(io::Error),
     #[error("failed to run `make` step: `{0}` command not found!")]
@@ -97,18 +88,7 @@ impl BuildBackend for MakeBuildSpec {
                         utils::log_command_output(&output, config)
                     }
                     Ok(output) => {
-                        return Err(MakeError::CommandFailure {
-                            name: match self.build_target {
-                                Some(build_target) => {
-                                    format!("{} {}", config.make_cmd(), build_target)
-                                }
-                                None => config.make_cmd(),
-                            },
-
-                            status: output.status,
-                            stdout: String::from_utf8_lossy(&output.stdout).into(),
-                            stderr: String::from_utf8_lossy(&output.stderr).into(),
-                        });
+                        return Err(CommandFailure::new(&cmd, &output).into());
                     }
                     Err(err) => return Err(MakeError::Io(err)),
                 },
@@ -134,26 +114,17 @@ impl BuildBackend for MakeBuildSpec {
                     Ok(format!("{key}={substituted_value}").trim().to_string())
                 })
                 .try_collect::<_, Vec<_>, Self::Err>()?;
-            match Command::new(config.make_cmd())
-                .current_dir(build_dir)
+            let mut cmd = Command::new(config.make_cmd());
+            cmd.current_dir(build_dir)
                 .arg(&self.install_target)
                 .args(["-f", &self.makefile.to_slash_lossy()])
                 .args(install_args)
                 .env("PATH", &bin_path)
                 .env("LUA_PATH", &lua_path)
-                .env("LUA_CPATH", &lua_cpath)
-                .output()
-                .await
-            {
+                .env("LUA_CPATH", &lua_cpath);
+            match cmd.output().await {
                 Ok(output) if output.status.success() => utils::log_command_output(&output, config),
-                Ok(output) => {
-                    return Err(MakeError::CommandFailure {
-                        name: format!("{} {}", config.make_cmd(), self.install_target),
-                        status: output.status,
-                        stdout: String::from_utf8_lossy(&output.stdout).into(),
-                        stderr: String::from_utf8_lossy(&output.stderr).into(),
-                    })
-                }
+                Ok(output) => return Err(CommandFailure::new(&cmd, &output).into()),
                 Err(err) => return Err(MakeError::Io(err)),
             }
         };
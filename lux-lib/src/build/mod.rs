@@ -8,7 +8,11 @@ use crate::tree::{self, EntryType, TreeError};
 use bytes::Bytes;
 use std::collections::HashMap;
 use std::io::Cursor;
-use std::{io, path::Path};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
 
 use crate::{
     config::Config,
@@ -17,7 +21,7 @@ use crate::{
     lua_installation::LuaInstallation,
     lua_rockspec::BuildBackendSpec,
     operations::{self, FetchSrcError},
-    package::PackageSpec,
+    package::{PackageName, PackageSpec, PackageVersion},
     progress::{Progress, ProgressBar},
     remote_package_source::RemotePackageSource,
     tree::{RockLayout, Tree},
@@ -39,7 +43,10 @@ use source::SourceBuildError;
 use ssri::Integrity;
 use thiserror::Error;
 use treesitter_parser::TreesitterBuildError;
-use utils::{recursive_copy_dir, CompileCFilesError, InstallBinaryError};
+use utils::{
+    has_files, recursive_copy_dir, CommandFailure, CompileCFilesError, CompileCModulesError,
+    InstallBinaryError,
+};
 
 mod builtin;
 mod cmake;
@@ -76,6 +83,24 @@ pub struct Build<'a, R: Rockspec + HasIntegrity> {
     constraint: LockConstraint,
     #[builder(default)]
     behaviour: BuildBehaviour,
+    /// Compile the package without installing it.
+    /// This skips the build backend's install pass, the rockspec's `build.install` entries,
+    /// `copy_directories` and docs. Used to check that a package builds,
+    /// e.g. by building into a temporary staging tree.
+    #[builder(default)]
+    no_install: bool,
+
+    /// Install the package under a different name than the one in the rockspec.
+    /// Must be combined with `override_version`.
+    ///
+    /// The overrides determine the installed package's `RockLayout` and lockfile entry,
+    /// so a package built with overrides will not be updated from a remote source.
+    /// The `pin` state applies to the overridden package, and pinning it is recommended
+    /// to make this explicit.
+    override_name: Option<PackageName>,
+    /// Install the package under a different version than the one in the rockspec.
+    /// Must be combined with `override_name`.
+    override_version: Option<PackageVersion>,
 
     #[builder(setters(vis = "pub(crate)"))]
     source_spec: Option<RemotePackageSourceSpec>,
@@ -108,15 +133,19 @@ where
 #[derive(Error, Debug)]
 pub enum BuildError {
     #[error("builtin build failed: {0}")]
-    Builtin(#[from] BuiltinBuildError),
+    Builtin(BuiltinBuildError),
     #[error("cmake build failed: {0}")]
-    CMake(#[from] CMakeError),
+    CMake(CMakeError),
     #[error("make build failed: {0}")]
-    Make(#[from] MakeError),
+    Make(MakeError),
     #[error("command build failed: {0}")]
-    Command(#[from] CommandError),
+    Command(CommandError),
+    #[error("pre_build hook failed: {0}")]
+    PreBuildHook(CommandError),
+    #[error("post_build hook failed: {0}")]
+    PostBuildHook(CommandError),
     #[error("rust-mlua build failed: {0}")]
-    Rust(#[from] RustError),
+    Rust(RustError),
     #[error("treesitter-parser build failed: {0}")]
     TreesitterBuild(#[from] TreesitterBuildError),
     #[error("luarocks build failed: {0}")]
@@ -136,7 +165,7 @@ pub enum BuildError {
     #[error(transparent)]
     PatchError(#[from] PatchError),
     #[error(transparent)]
-    CompileCFiles(#[from] CompileCFilesError),
+    CompileCFiles(CompileCFilesError),
     #[error(transparent)]
     LuaVersion(#[from] LuaVersionError),
     #[error("source integrity mismatch.\nExpected: {expected},\nbut got: {actual}")]
@@ -152,6 +181,99 @@ pub enum BuildError {
     InstallBinary(String, InstallBinaryError),
     #[error(transparent)]
     LuaInstallation(#[from] LuaInstallationError),
+    #[error(
+        "cannot override only the {0} of {1}: both the package name and version must be overridden"
+    )]
+    PartialOverride(&'static str, PackageSpec),
+    #[error("cannot install {0} in editable mode: expected a `src` or `lua` directory")]
+    EditableSourceNotFound(PathBuf),
+    #[error(
+        "build command failed: `{}`\n\n{status}\n\nstdout:\n{stdout}\n\nstderr:\n{stderr}",
+        utils::command_line(program, args)
+    )]
+    CommandFailed {
+        program: String,
+        args: Vec<String>,
+        status: ExitStatus,
+        stdout: String,
+        stderr: String,
+    },
+    #[error(transparent)]
+    BinaryCollision(#[from] BinaryCollisionError),
+    #[error("{source}\nthe build directory was kept at {}", build_dir.display())]
+    BuildDirKept {
+        build_dir: PathBuf,
+        source: Box<BuildError>,
+    },
+}
+
+impl From<CommandFailure> for BuildError {
+    fn from(failure: CommandFailure) -> Self {
+        Self::CommandFailed {
+            program: failure.program,
+            args: failure.args,
+            status: failure.status,
+            stdout: failure.stdout,
+            stderr: failure.stderr,
+        }
+    }
+}
+
+impl From<BuiltinBuildError> for BuildError {
+    fn from(err: BuiltinBuildError) -> Self {
+        match err {
+            BuiltinBuildError::CompileCFiles(CompileCFilesError::CommandFailure(failure))
+            | BuiltinBuildError::CompileCModules(CompileCModulesError::CommandFailure(failure)) => {
+                failure.into()
+            }
+            err => Self::Builtin(err),
+        }
+    }
+}
+
+impl From<CMakeError> for BuildError {
+    fn from(err: CMakeError) -> Self {
+        match err {
+            CMakeError::CommandFailure(failure) => failure.into(),
+            err => Self::CMake(err),
+        }
+    }
+}
+
+impl From<MakeError> for BuildError {
+    fn from(err: MakeError) -> Self {
+        match err {
+            MakeError::CommandFailure(failure) => failure.into(),
+            err => Self::Make(err),
+        }
+    }
+}
+
+impl From<CommandError> for BuildError {
+    fn from(err: CommandError) -> Self {
+        match err {
+            CommandError::CommandFailure(failure) => failure.into(),
+            err => Self::Command(err),
+        }
+    }
+}
+
+impl From<RustError> for BuildError {
+    fn from(err: RustError) -> Self {
+        match err {
+            RustError::CargoBuild(failure) => failure.into(),
+            err => Self::Rust(err),
+        }
+    }
+}
+
+impl From<CompileCFilesError> for BuildError {
+    fn from(err: CompileCFilesError) -> Self {
+        match err {
+            CompileCFilesError::CommandFailure(failure) => failure.into(),
+            err => Self::CompileCFiles(err),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -189,28 +311,89 @@ async fn run_build<R: Rockspec + HasIntegrity>(
     args: RunBuildArgs<'_>,
 ) -> Result<BuildInfo, BuildError> {
     let progress = args.progress;
+    let build_spec = rockspec.build().current_platform();
+
+    if let Some(pre_build) = &build_spec.pre_build {
+        progress.map(|p| p.set_message(format!("🪝 Running pre_build hook: {pre_build}")));
+        command::run_hook(pre_build, &args)
+            .await
+            .map_err(BuildError::PreBuildHook)?;
+    }
+
     progress.map(|p| p.set_message("🛠️ Building..."));
 
-    Ok(
-        match rockspec.build().current_platform().build_backend.to_owned() {
-            Some(BuildBackendSpec::Builtin(build_spec)) => build_spec.run(args).await?,
-            Some(BuildBackendSpec::Make(make_spec)) => make_spec.run(args).await?,
-            Some(BuildBackendSpec::CMake(cmake_spec)) => cmake_spec.run(args).await?,
-            Some(BuildBackendSpec::Command(command_spec)) => command_spec.run(args).await?,
-            Some(BuildBackendSpec::RustMlua(rust_mlua_spec)) => rust_mlua_spec.run(args).await?,
-            Some(BuildBackendSpec::TreesitterParser(treesitter_parser_spec)) => {
-                treesitter_parser_spec.run(args).await?
+    let hook_args = args.clone();
+    let build_info = match build_spec.build_backend.to_owned() {
+        Some(BuildBackendSpec::Builtin(build_spec)) => build_spec.run(args).await?,
+        Some(BuildBackendSpec::Make(make_spec)) => make_spec.run(args).await?,
+        Some(BuildBackendSpec::CMake(cmake_spec)) => cmake_spec.run(args).await?,
+        Some(BuildBackendSpec::Command(command_spec)) => command_spec.run(args).await?,
+        Some(BuildBackendSpec::RustMlua(rust_mlua_spec)) => rust_mlua_spec.run(args).await?,
+        Some(BuildBackendSpec::TreesitterParser(treesitter_parser_spec)) => {
+            treesitter_parser_spec.run(args).await?
+        }
+        Some(BuildBackendSpec::LuaRock(_)) => luarocks::build(rockspec, args).await?,
+        Some(BuildBackendSpec::Source) => source::build(args).await?,
+        None => BuildInfo::default(),
+    };
+
+    if let Some(post_build) = &build_spec.post_build {
+        progress.map(|p| p.set_message(format!("🪝 Running post_build hook: {post_build}")));
+        command::run_hook(post_build, &hook_args)
+            .await
+            .map_err(BuildError::PostBuildHook)?;
+    }
+
+    Ok(build_info)
+}
+
+#[derive(Error, Debug)]
+pub enum BinaryCollisionError {
+    #[error(transparent)]
+    Tree(#[from] TreeError),
+    #[error("binary `{binary}` of {package} is already provided by {provider}")]
+    Collision {
+        binary: String,
+        package: PackageName,
+        provider: Box<PackageSpec>,
+    },
+}
+
+/// Warn about, or with [`Config::deny_binary_collisions`] fail on,
+/// binaries that a different rock has already installed into the tree.
+pub(crate) fn check_binary_collisions<'b>(
+    package: &PackageName,
+    binaries: impl IntoIterator<Item = &'b str>,
+    tree: &Tree,
+    progress: &Progress<ProgressBar>,
+    config: &Config,
+) -> Result<(), BinaryCollisionError> {
+    let lockfile = tree.lockfile()?;
+    let bin_scripts = tree.bin_scripts()?;
+    for binary in binaries {
+        let provider = bin_scripts
+            .get(binary)
+            .and_then(|id| lockfile.get(id))
+            .filter(|provider| provider.name() != package);
+        if let Some(provider) = provider {
+            let err = BinaryCollisionError::Collision {
+                binary: binary.to_string(),
+                package: package.clone(),
+                provider: Box::new(provider.to_package()),
+            };
+            if config.deny_binary_collisions() {
+                return Err(err);
             }
-            Some(BuildBackendSpec::LuaRock(_)) => luarocks::build(rockspec, args).await?,
-            Some(BuildBackendSpec::Source) => source::build(args).await?,
-            None => BuildInfo::default(),
-        },
-    )
+            progress.map(|p| p.println(format!("⚠️ {err}")));
+        }
+    }
+    Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn install<R: Rockspec + HasIntegrity>(
     rockspec: &R,
+    package_spec: &PackageSpec,
     tree: &Tree,
     output_paths: &RockLayout,
     lua: &LuaInstallation,
@@ -222,8 +405,8 @@ async fn install<R: Rockspec + HasIntegrity>(
     progress.map(|p| {
         p.set_message(format!(
             "💻 Installing {} {}",
-            rockspec.package(),
-            rockspec.version()
+            package_spec.name(),
+            package_spec.version()
         ))
     });
 
@@ -232,7 +415,8 @@ async fn install<R: Rockspec + HasIntegrity>(
     let lib_len = install_spec.lib.len();
     let bin_len = install_spec.bin.len();
     let conf_len = install_spec.conf.len();
-    let total_len = lua_len + lib_len + bin_len + conf_len;
+    let doc_len = install_spec.doc.len();
+    let total_len = lua_len + lib_len + bin_len + conf_len + doc_len;
     progress.map(|p| p.set_position(total_len as u64));
 
     if lua_len > 0 {
@@ -255,6 +439,13 @@ async fn install<R: Rockspec + HasIntegrity>(
     if entry_type.is_entrypoint() {
         if bin_len > 0 {
             progress.map(|p| p.set_message("💻 Installing binaries..."));
+            check_binary_collisions(
+                package_spec.name(),
+                install_spec.bin.keys().map(String::as_str),
+                tree,
+                progress,
+                config,
+            )?;
         }
         let deploy_spec = rockspec.deploy().current_platform();
         for (target, source) in &install_spec.bin {
@@ -283,10 +474,30 @@ async fn install<R: Rockspec + HasIntegrity>(
             progress.map(|p| p.set_position(p.position() + 1));
         }
     }
+    if doc_len > 0 {
+        progress.map(|p| p.set_message("📋 Copying documentation files..."));
+        for (target, source) in &install_spec.doc {
+            let absolute_source = build_dir.join(source);
+            let target = output_paths.doc.join(target);
+            if let Some(parent_dir) = target.parent() {
+                tokio::fs::create_dir_all(parent_dir).await?;
+            }
+            tokio::fs::copy(absolute_source, target).await?;
+            progress.map(|p| p.set_position(p.position() + 1));
+        }
+    }
     Ok(())
 }
 
-async fn do_build<R>(build: Build<'_, R>) -> Result<LocalPackage, BuildError>
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "build",
+        skip_all,
+        fields(package = %build.rockspec.package(), version = %build.rockspec.version())
+    )
+)]
+async fn do_build<R>(mut build: Build<'_, R>) -> Result<LocalPackage, BuildError>
 where
     R: Rockspec + HasIntegrity,
 {
@@ -300,31 +511,96 @@ where
         ))
     });
 
-    let lua = build.lua;
-
-    rockspec.validate_lua_version(&lua.version)?;
+    rockspec.validate_lua_version(&build.lua.version)?;
 
-    let tree = build.tree;
+    // Overriding only the name or only the version would produce a `LocalPackageId`
+    // that can be confused with the upstream rock's.
+    let package_spec = match (build.override_name.take(), build.override_version.take()) {
+        (Some(name), Some(version)) => PackageSpec::new(name, version),
+        (None, None) => PackageSpec::new(rockspec.package().clone(), rockspec.version().clone()),
+        (Some(_), None) => {
+            return Err(BuildError::PartialOverride(
+                "name",
+                PackageSpec::new(rockspec.package().clone(), rockspec.version().clone()),
+            ))
+        }
+        (None, Some(_)) => {
+            return Err(BuildError::PartialOverride(
+                "version",
+                PackageSpec::new(rockspec.package().clone(), rockspec.version().clone()),
+            ))
+        }
+    };
 
     let temp_dir = tempdir::TempDir::new(&rockspec.package().to_string())?;
+    let config = build.config;
+
+    match build_in_dir(build, package_spec.clone(), temp_dir.path()).await {
+        Err(err) if config.keep_build_dir_on_failure() => {
+            let build_dir = keep_build_dir(
+                temp_dir,
+                config
+                    .cache_dir()
+                    .join("failed-builds")
+                    .join(package_spec.to_string()),
+            );
+            Err(BuildError::BuildDirKept {
+                build_dir,
+                source: Box::new(err),
+            })
+        }
+        result => result,
+    }
+}
+
+/// Move the directory of a failed build to `dest`, so that it can be inspected.
+/// If it can't be moved (e.g. because `dest` is on a different file system),
+/// the temporary directory is kept in place instead.
+fn keep_build_dir(temp_dir: tempdir::TempDir, dest: PathBuf) -> PathBuf {
+    let temp_path = temp_dir.into_path();
+    if dest.exists() {
+        let _ = std::fs::remove_dir_all(&dest);
+    }
+    let moved = dest
+        .parent()
+        .is_some_and(|parent| std::fs::create_dir_all(parent).is_ok())
+        && std::fs::rename(&temp_path, &dest).is_ok();
+    if moved {
+        dest
+    } else {
+        temp_path
+    }
+}
+
+async fn build_in_dir<R>(
+    build: Build<'_, R>,
+    package_spec: PackageSpec,
+    temp_dir: &Path,
+) -> Result<LocalPackage, BuildError>
+where
+    R: Rockspec + HasIntegrity,
+{
+    let rockspec = build.rockspec;
+    let lua = build.lua;
+    let tree = build.tree;
 
     let source_metadata = match build.source_spec {
         Some(RemotePackageSourceSpec::SrcRock(SrcRockSource { bytes, source_url })) => {
             let hash = bytes.hash()?;
             let cursor = Cursor::new(&bytes);
-            operations::unpack_src_rock(cursor, temp_dir.path().to_path_buf(), build.progress)
+            operations::unpack_src_rock(cursor, temp_dir.to_path_buf(), build.progress)
                 .await
                 .map_err(BuildError::UnpackSrcRock)?;
             RemotePackageSourceMetadata { hash, source_url }
         }
         Some(RemotePackageSourceSpec::RockSpec(source_url)) => {
-            operations::FetchSrc::new(temp_dir.path(), rockspec, build.config, build.progress)
+            operations::FetchSrc::new(temp_dir, rockspec, build.config, build.progress)
                 .maybe_source_url(source_url)
                 .fetch_internal()
                 .await?
         }
         None => {
-            operations::FetchSrc::new(temp_dir.path(), rockspec, build.config, build.progress)
+            operations::FetchSrc::new(temp_dir, rockspec, build.config, build.progress)
                 .fetch_internal()
                 .await?
         }
@@ -336,7 +612,7 @@ where
     };
 
     let mut package = LocalPackage::from(
-        &PackageSpec::new(rockspec.package().clone(), rockspec.version().clone()),
+        &package_spec,
         build.constraint,
         rockspec.binaries(),
         build
@@ -355,21 +631,33 @@ where
     package.spec.opt = build.opt;
 
     match tree.lockfile()?.get(&package.id()) {
-        Some(package) if build.behaviour == BuildBehaviour::NoForce => Ok(package.clone()),
+        Some(package)
+            if build.behaviour == BuildBehaviour::NoForce
+                && !package.requires_rebuild(tree.version()) =>
+        {
+            Ok(package.clone())
+        }
         _ => {
             let output_paths = match build.entry_type {
                 tree::EntryType::Entrypoint => tree.entrypoint(&package)?,
                 tree::EntryType::DependencyOnly => tree.dependency(&package)?,
             };
 
+            // A previous editable install leaves a symlink to the working copy,
+            // which we must not install into.
+            if output_paths.src.is_symlink() {
+                remove_symlink_dir(&output_paths.src)?;
+                std::fs::create_dir_all(&output_paths.src)?;
+            }
+
             let rock_source = rockspec.source().current_platform();
             let build_dir = match &rock_source.unpack_dir {
-                Some(unpack_dir) => temp_dir.path().join(unpack_dir),
+                Some(unpack_dir) => temp_dir.join(unpack_dir),
                 None => {
                     // Some older/off-spec rockspecs don't specify a source.dir.
                     // If there exists a single directory with the archive name
                     // after unpacking an archive, we assume it's the source directory.
-                    let dir_entries = std::fs::read_dir(temp_dir.path())?
+                    let dir_entries = std::fs::read_dir(temp_dir)?
                         .filter_map(Result::ok)
                         .filter(|f| f.path().is_dir())
                         .collect_vec();
@@ -389,9 +677,9 @@ where
                             )
                         })
                     {
-                        temp_dir.path().join(dir_entries.first().unwrap().path())
+                        temp_dir.join(dir_entries.first().unwrap().path())
                     } else {
-                        temp_dir.path().into()
+                        temp_dir.into()
                     }
                 }
             };
@@ -416,8 +704,9 @@ where
             let output = run_build(
                 rockspec,
                 RunBuildArgs::new()
+                    .package(package_spec.name())
                     .output_paths(&output_paths)
-                    .no_install(false)
+                    .no_install(build.no_install)
                     .lua(lua)
                     .external_dependencies(&external_dependencies)
                     .deploy(rockspec.deploy().current_platform())
@@ -431,8 +720,13 @@ where
 
             package.spec.binaries.extend(output.binaries);
 
+            if build.no_install {
+                return Ok(package);
+            }
+
             install(
                 rockspec,
+                &package_spec,
                 tree,
                 &output_paths,
                 lua,
@@ -462,6 +756,15 @@ where
 
             recursive_copy_doc_dir(&output_paths, &build_dir).await?;
 
+            // Native libraries are only compatible with the Lua ABI they were built against.
+            if has_files(&output_paths.lib) {
+                package.lua_version = Some(lua.version.clone());
+            }
+
+            if let RemotePackageSource::LocalPath(source_dir) = &package.source {
+                link_editable_src(source_dir, &output_paths.src)?;
+            }
+
             if let Ok(rockspec_str) = rockspec.to_lua_remote_rockspec_string() {
                 std::fs::write(output_paths.rockspec_path(), rockspec_str)?;
             }
@@ -483,6 +786,33 @@ async fn recursive_copy_doc_dir(
     Ok(())
 }
 
+/// Replace the installed Lua sources of an editable package with a symlink
+/// to the `src` or `lua` directory of its working copy.
+fn link_editable_src(source_dir: &Path, src: &Path) -> Result<(), BuildError> {
+    let lua_dir = ["src", "lua"]
+        .into_iter()
+        .map(|dir| source_dir.join(dir))
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| BuildError::EditableSourceNotFound(source_dir.to_path_buf()))?;
+    if src.is_symlink() {
+        remove_symlink_dir(src)?;
+    } else if src.exists() {
+        std::fs::remove_dir_all(src)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(lua_dir, src)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_dir(lua_dir, src)?;
+    Ok(())
+}
+
+fn remove_symlink_dir(path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    return std::fs::remove_dir(path);
+    #[cfg(not(windows))]
+    std::fs::remove_file(path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -491,11 +821,12 @@ mod tests {
 
     use assert_fs::{
         assert::PathAssert,
-        prelude::{PathChild, PathCopy},
+        prelude::{FileTouch, FileWriteStr, PathChild, PathCopy},
     };
 
     use crate::{
         config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackage,
         lua_installation::{detect_installed_lua_version, LuaInstallation},
         progress::MultiProgress,
         project::Project,
@@ -541,6 +872,7 @@ mod tests {
         run_build(
             &rockspec,
             RunBuildArgs::new()
+                .package(rockspec.package())
                 .output_paths(&rock_layout)
                 .no_install(false)
                 .lua(&lua)
@@ -575,4 +907,187 @@ mod tests {
         bin_file.assert(predicate::str::contains("#!/usr/bin/env bash"));
         bin_file.assert(predicate::str::contains("echo \"Hello\""));
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_failure_surfaces_command_line() {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg("echo oops >&2; exit 3");
+        let output = cmd.output().await.unwrap();
+        let err: BuildError = MakeError::CommandFailure(CommandFailure::new(&cmd, &output)).into();
+        match &err {
+            BuildError::CommandFailed {
+                program,
+                args,
+                status,
+                stderr,
+                ..
+            } => {
+                assert_eq!(program, "sh");
+                assert_eq!(args, &["-c", "echo oops >&2; exit 3"]);
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(stderr.trim(), "oops");
+            }
+            err => panic!("expected CommandFailed, got: {err}"),
+        }
+        assert!(err.to_string().contains("`sh -c 'echo oops >&2; exit 3'`"));
+    }
+
+    #[test]
+    fn test_keep_build_dir() {
+        let temp_dir = tempdir::TempDir::new("foo").unwrap();
+        std::fs::write(temp_dir.path().join("foo.o"), "").unwrap();
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let dest = cache_dir.join("failed-builds").join("foo@1.0.0-1");
+        std::fs::create_dir_all(&dest).unwrap();
+        std::fs::write(dest.join("stale.o"), "").unwrap();
+
+        let build_dir = keep_build_dir(temp_dir, dest.clone());
+        assert_eq!(build_dir, dest);
+        assert!(dest.join("foo.o").is_file());
+        assert!(!dest.join("stale.o").exists());
+    }
+
+    #[test]
+    fn test_link_editable_src() {
+        let working_copy = assert_fs::TempDir::new().unwrap();
+        working_copy.child("lua/foo/init.lua").touch().unwrap();
+        let rock_dir = assert_fs::TempDir::new().unwrap();
+        let src = rock_dir.child("src");
+        src.child("foo/init.lua").touch().unwrap();
+
+        link_editable_src(working_copy.path(), src.path()).unwrap();
+        assert!(src.path().is_symlink());
+        src.child("foo/init.lua").assert(predicate::path::is_file());
+
+        // Re-linking must not touch the working copy
+        link_editable_src(working_copy.path(), src.path()).unwrap();
+        working_copy
+            .child("lua/foo/init.lua")
+            .assert(predicate::path::is_file());
+
+        let empty = assert_fs::TempDir::new().unwrap();
+        assert!(matches!(
+            link_editable_src(empty.path(), src.path()),
+            Err(BuildError::EditableSourceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn binary_collisions() {
+        let tree_dir = assert_fs::TempDir::new().unwrap();
+        let mk_config = |deny_binary_collisions| {
+            ConfigBuilder::new()
+                .unwrap()
+                .user_tree(Some(tree_dir.to_path_buf()))
+                .deny_binary_collisions(Some(deny_binary_collisions))
+                .build()
+                .unwrap()
+        };
+        let config = mk_config(true);
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let mut provider = LocalPackage::mock("busted", "2.2.0-1");
+        provider.spec.binaries.push("bin/busted".into());
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&provider);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+
+        let my_busted: PackageName = "my-busted".into();
+        let busted: PackageName = "busted".into();
+        let result = check_binary_collisions(&my_busted, ["busted"], &tree, &bar, &config);
+        assert!(matches!(
+            result,
+            Err(BinaryCollisionError::Collision { binary, provider, .. })
+                if binary == "busted" && provider.name().to_string() == "busted"
+        ));
+        check_binary_collisions(&my_busted, ["busted"], &tree, &bar, &mk_config(false)).unwrap();
+        check_binary_collisions(&busted, ["busted"], &tree, &bar, &config).unwrap();
+    }
+
+    #[tokio::test]
+    async fn binary_collisions_with_overrides() {
+        let sample_project = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/sample-projects/no-source/");
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        project_root
+            .child("bin/foo-cli")
+            .write_str("print('foo')")
+            .unwrap();
+        let lux_toml = project_root.child("lux.toml");
+        let content = std::fs::read_to_string(&lux_toml).unwrap();
+        lux_toml
+            .write_str(&format!(
+                "{content}bin = {{ \"foo-cli\" = \"bin/foo-cli\" }}\n"
+            ))
+            .unwrap();
+        let project = Project::from(&project_root).unwrap().unwrap();
+        let project_toml = project.toml().into_local().unwrap();
+
+        let tree_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_dir.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .deny_binary_collisions(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let lua = LuaInstallation::without_headers(LuaVersion::Lua51);
+
+        let mut provider = LocalPackage::mock("vendored-fork", "1.0.0-1");
+        provider.spec.binaries.push("bin/foo-cli".into());
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&provider);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+
+        // The binary is provided by a package with the override name
+        let package = Build::new()
+            .rockspec(&project_toml)
+            .lua(&lua)
+            .tree(&tree)
+            .entry_type(tree::EntryType::Entrypoint)
+            .config(&config)
+            .progress(&bar)
+            .behaviour(BuildBehaviour::Force)
+            .override_name("vendored-fork".into())
+            .override_version("1.0.0-1".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(package.name().to_string(), "vendored-fork");
+        assert!(tree.bin().join("foo-cli").is_file());
+
+        let result = Build::new()
+            .rockspec(&project_toml)
+            .lua(&lua)
+            .tree(&tree)
+            .entry_type(tree::EntryType::Entrypoint)
+            .config(&config)
+            .progress(&bar)
+            .behaviour(BuildBehaviour::Force)
+            .build()
+            .await;
+        assert!(matches!(
+            result,
+            Err(BuildError::Builtin(BuiltinBuildError::BinaryCollision(
+                BinaryCollisionError::Collision { package, .. }
+            ))) if package.to_string() == "lux"
+        ));
+    }
 }
@@ -1,24 +1,19 @@
-use super::utils::c_dylib_extension;
+use super::utils::{c_dylib_extension, CommandFailure};
 use crate::build::backend::{BuildBackend, BuildInfo, RunBuildArgs};
-use crate::config::LuaVersionUnset;
+use crate::lua_rockspec::LuaVersionError;
 use crate::progress::{Progress, ProgressBar};
 use crate::{config::LuaVersion, lua_rockspec::RustMluaBuildSpec, tree::RockLayout};
 use itertools::Itertools;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::process::ExitStatus;
 use std::{fs, io};
 use thiserror::Error;
 use tokio::process::Command;
 
 #[derive(Error, Debug)]
 pub enum RustError {
-    #[error("`cargo build` failed.\nstatus: {status}\nstdout: {stdout}\nstderr: {stderr}")]
-    CargoBuild {
-        status: ExitStatus,
-        stdout: String,
-        stderr: String,
-    },
+    #[error(transparent)]
+    CargoBuild(#[from] CommandFailure),
     #[error("failed to run `cargo build`: {0}")]
     RustBuild(io::Error),
     #[error("unable to create directory {0}:\n{1}")]
@@ -28,7 +23,7 @@ pub enum RustError {
     #[error(transparent)]
     InstallLuaLib(#[from] InstallLuaLibError),
     #[error(transparent)]
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
 }
 
 #[derive(Error, Debug)]
@@ -53,7 +48,7 @@ impl BuildBackend for RustMluaBuildSpec {
         let config = args.config;
         let build_dir = args.build_dir;
         let progress = args.progress;
-        let lua_version = LuaVersion::from(config)?;
+        let lua_version = config.resolve_lua_version(None)?;
         let lua_feature = match lua_version {
             LuaVersion::Lua51 => "lua51",
             LuaVersion::Lua52 => "lua52",
@@ -76,20 +71,11 @@ impl BuildBackend for RustMluaBuildSpec {
         build_args.push(&features);
         build_args.extend(self.cargo_extra_args.iter().map(|arg| arg.as_str()));
         progress.map(|bar| bar.set_message("🦀 Building (rust-mlua)..."));
-        match Command::new("cargo")
-            .current_dir(build_dir)
-            .args(build_args)
-            .output()
-            .await
-        {
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(build_dir).args(build_args);
+        match cmd.output().await {
             Ok(output) if output.status.success() => {}
-            Ok(output) => {
-                return Err(RustError::CargoBuild {
-                    status: output.status,
-                    stdout: String::from_utf8_lossy(&output.stdout).into(),
-                    stderr: String::from_utf8_lossy(&output.stderr).into(),
-                });
-            }
+            Ok(output) => return Err(CommandFailure::new(&cmd, &output).into()),
             Err(err) => return Err(RustError::RustBuild(err)),
         }
         fs::create_dir_all(&output_paths.lib).map_err(|err| {
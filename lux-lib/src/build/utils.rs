@@ -3,6 +3,7 @@ use crate::{
     lua_installation::LuaInstallation,
     lua_rockspec::{DeploySpec, LuaModule, ModulePaths},
     path::{Paths, PathsError},
+    progress::{Progress, ProgressBar},
     tree::{RockLayout, Tree},
     variables::{self, Environment, VariableSubstitutionError},
 };
@@ -55,6 +56,14 @@ pub(crate) fn project_files(src: &PathBuf) -> Vec<PathBuf> {
         .collect_vec()
 }
 
+/// Whether a directory contains any files, including in its subdirectories.
+pub(crate) fn has_files(dir: &Path) -> bool {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_type().is_file())
+}
+
 /// Recursively copy a directory.
 /// This respects ignore files and excludes hidden files and directories.
 pub(crate) async fn recursive_copy_dir(src: &PathBuf, dest: &Path) -> Result<(), io::Error> {
@@ -72,27 +81,73 @@ pub(crate) async fn recursive_copy_dir(src: &PathBuf, dest: &Path) -> Result<(),
     Ok(())
 }
 
+/// An external process that exited unsuccessfully during a build step.
 #[derive(Error, Debug)]
-pub enum OutputValidationError {
-    #[error("compilation failed.\nstatus: {status}\nstdout: {stdout}\nstderr: {stderr}")]
-    CommandFailure {
-        status: ExitStatus,
-        stdout: String,
-        stderr: String,
-    },
+#[error("`{}` failed.\n\n{status}\n\nstdout:\n{stdout}\n\nstderr:\n{stderr}", self.command_line())]
+pub struct CommandFailure {
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+    pub(crate) status: ExitStatus,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
 }
 
-fn validate_output(output: &Output) -> Result<(), OutputValidationError> {
-    if !output.status.success() {
-        return Err(OutputValidationError::CommandFailure {
+impl CommandFailure {
+    pub(crate) fn new(cmd: &Command, output: &Output) -> Self {
+        let cmd = cmd.as_std();
+        Self {
+            program: cmd.get_program().to_string_lossy().to_string(),
+            args: cmd
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect(),
             status: output.status,
             stdout: String::from_utf8_lossy(&output.stdout).into(),
             stderr: String::from_utf8_lossy(&output.stderr).into(),
-        });
+        }
+    }
+
+    /// The invoked command line, quoted so that it can be copied into a shell.
+    pub fn command_line(&self) -> String {
+        command_line(&self.program, &self.args)
+    }
+}
+
+pub(crate) fn command_line(program: &str, args: &[String]) -> String {
+    shell_words::join(std::iter::once(program).chain(args.iter().map(String::as_str)))
+}
+
+fn validate_output(cmd: &Command, output: &Output) -> Result<(), CommandFailure> {
+    if !output.status.success() {
+        return Err(CommandFailure::new(cmd, output));
     }
     Ok(())
 }
 
+/// Re-runs the compiler on each source file of a failed `cc` build,
+/// so that we can report the failing command along with its output,
+/// which `cc` doesn't capture.
+async fn find_compile_failure(build: &cc::Build) -> Option<CommandFailure> {
+    let compiler = build.try_get_compiler().ok()?;
+    let out_dir = tempdir::TempDir::new("lux-compile").ok()?;
+    for (i, file) in build.get_files().enumerate() {
+        let object = out_dir.path().join(format!("{i}.o"));
+        let mut cmd: Command = compiler.to_command().into();
+        if compiler.is_like_msvc() {
+            cmd.arg("/c")
+                .arg(file)
+                .arg(format!("/Fo{}", object.display()));
+        } else {
+            cmd.arg("-c").arg(file).arg("-o").arg(&object);
+        }
+        let output = cmd.output().await.ok()?;
+        if let Err(failure) = validate_output(&cmd, &output) {
+            return Some(failure);
+        }
+    }
+    None
+}
+
 #[derive(Error, Debug)]
 pub enum CompileCFilesError {
     #[error("IO operation while compiling C files: {0}")]
@@ -101,8 +156,8 @@ pub enum CompileCFilesError {
     CompileIntermediates(cc::Error),
     #[error("error compiling C files (compilation failed): {0}")]
     Compilation(#[from] cc::Error),
-    #[error("error compiling C files (output validation failed): {0}")]
-    OutputValidation(#[from] OutputValidationError),
+    #[error("error compiling C files: {0}")]
+    CommandFailure(#[from] CommandFailure),
     #[error("compiling C files succeeded, but the expected library {0} was not created")]
     LibOutputNotCreated(String),
 }
@@ -117,6 +172,7 @@ pub(crate) async fn compile_c_files(
     lua: &LuaInstallation,
     external_dependencies: &HashMap<String, ExternalDependencyInfo>,
     config: &Config,
+    progress: &Progress<ProgressBar>,
 ) -> Result<(), CompileCFilesError> {
     let target = target_dir.join(target_module.to_lib_path());
 
@@ -159,17 +215,23 @@ pub(crate) async fn compile_c_files(
     } else {
         build.flag("-w");
     }
-    for arg in lua.define_flags() {
+    for arg in lua.compile_args(config) {
         build.flag(&arg);
     }
 
-    let objects = build
-        .try_compile_intermediates()
-        .map_err(CompileCFilesError::CompileIntermediates)?;
+    let objects = match build.try_compile_intermediates() {
+        Ok(objects) => objects,
+        Err(err) => {
+            return Err(match find_compile_failure(build).await {
+                Some(failure) => failure.into(),
+                None => CompileCFilesError::CompileIntermediates(err),
+            })
+        }
+    };
 
     let output_path = parent.join(&file);
 
-    let output = if compiler.is_like_msvc() {
+    let mut cmd = if compiler.is_like_msvc() {
         let def_temp_dir = tempdir::TempDir::new("msvc-def")?.into_path().to_path_buf();
         let def_file = mk_def_file(def_temp_dir, &file, target_module)?;
         let cmd = compiler.to_command();
@@ -180,28 +242,27 @@ pub(crate) async fn compile_c_files(
             .arg("/link")
             .arg(format!("/DEF:{}", def_file.display()))
             .arg(format!("/OUT:{}", output_path.display()))
-            .args(lua.lib_link_args(&compiler))
+            .args(lua.link_args(&compiler, config, progress))
             .args(
                 external_dependencies
                     .iter()
                     .flat_map(|(_, dep)| dep.lib_link_args(&compiler)),
-            )
-            .output()
-            .await?
+            );
+        cmd
     } else {
         let cmd = build.shared_flag(true).try_get_compiler()?.to_command();
         let mut cmd: tokio::process::Command = cmd.into();
         cmd.args(vec!["-o".into(), output_path.to_string_lossy().to_string()])
-            .args(lua.lib_link_args(&compiler))
+            .args(lua.link_args(&compiler, config, progress))
             .args(
                 external_dependencies
                     .iter()
                     .flat_map(|(_, dep)| dep.lib_link_args(&compiler)),
             )
-            .args(&objects)
-            .output()
-            .await?
+            .args(&objects);
+        cmd
     };
+    let output = cmd.output().await?;
 
     if config.verbose() {
         if !&output.stdout.is_empty() {
@@ -212,7 +273,7 @@ pub(crate) async fn compile_c_files(
         }
     }
 
-    validate_output(&output)?;
+    validate_output(&cmd, &output)?;
     log_command_output(&output, config);
 
     if output_path.exists() {
@@ -301,8 +362,8 @@ pub enum CompileCModulesError {
     CompileIntermediates(cc::Error),
     #[error("error compiling C modules (compilation failed): {0}")]
     Compilation(#[from] cc::Error),
-    #[error("error compiling C modules (output validation failed): {0}")]
-    OutputValidation(#[from] OutputValidationError),
+    #[error("error compiling C modules: {0}")]
+    CommandFailure(#[from] CommandFailure),
     #[error("compiling C modules succeeded, but the expected library {0} was not created")]
     LibOutputNotCreated(String),
 }
@@ -310,6 +371,7 @@ pub enum CompileCModulesError {
 /// Compiles a set of C files (with extra metadata) to a given destination.
 /// # Panics
 /// Panics if no filename for the target path can be determined.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn compile_c_modules(
     data: &ModulePaths,
     source_dir: &Path,
@@ -318,6 +380,7 @@ pub(crate) async fn compile_c_modules(
     lua: &LuaInstallation,
     external_dependencies: &HashMap<String, ExternalDependencyInfo>,
     config: &Config,
+    progress: &Progress<ProgressBar>,
 ) -> Result<(), CompileCModulesError> {
     let target = target_dir.join(target_module.to_lib_path());
 
@@ -370,7 +433,7 @@ pub(crate) async fn compile_c_modules(
     } else {
         build.flag("-w");
     }
-    for arg in lua.define_flags() {
+    for arg in lua.compile_args(config) {
         build.flag(&arg);
     }
 
@@ -386,9 +449,15 @@ pub(crate) async fn compile_c_modules(
         .to_string_lossy()
         .to_string();
     // See https://github.com/rust-lang/cc-rs/issues/594#issuecomment-2110551057
-    let objects = build
-        .try_compile_intermediates()
-        .map_err(CompileCModulesError::CompileIntermediates)?;
+    let objects = match build.try_compile_intermediates() {
+        Ok(objects) => objects,
+        Err(err) => {
+            return Err(match find_compile_failure(build).await {
+                Some(failure) => failure.into(),
+                None => CompileCModulesError::CompileIntermediates(err),
+            })
+        }
+    };
 
     let libdir_args = data.libdirs.iter().map(|libdir| {
         if is_msvc {
@@ -407,7 +476,7 @@ pub(crate) async fn compile_c_modules(
     });
 
     let output_path = parent.join(&file);
-    let output = if is_msvc {
+    let mut cmd = if is_msvc {
         let def_temp_dir = tempdir::TempDir::new("msvc-def")?.into_path().to_path_buf();
         let def_file = mk_def_file(def_temp_dir, &file, target_module)?;
         let cmd = build.try_get_compiler()?.to_command();
@@ -418,21 +487,20 @@ pub(crate) async fn compile_c_modules(
             .arg("/link")
             .arg(format!("/DEF:{}", def_file.display()))
             .arg(format!("/OUT:{}", output_path.display()))
-            .args(lua.lib_link_args(&build.try_get_compiler()?))
+            .args(lua.link_args(&build.try_get_compiler()?, config, progress))
             .args(
                 external_dependencies
                     .iter()
                     .flat_map(|(_, dep)| dep.lib_link_args(&compiler)),
             )
             .args(libdir_args)
-            .args(library_args)
-            .output()
-            .await?
+            .args(library_args);
+        cmd
     } else {
         let cmd = build.shared_flag(true).try_get_compiler()?.to_command();
         let mut cmd: tokio::process::Command = cmd.into();
         cmd.args(vec!["-o".into(), output_path.to_string_lossy().to_string()])
-            .args(lua.lib_link_args(&build.try_get_compiler()?))
+            .args(lua.link_args(&build.try_get_compiler()?, config, progress))
             .args(
                 external_dependencies
                     .iter()
@@ -440,10 +508,10 @@ pub(crate) async fn compile_c_modules(
             )
             .args(&objects)
             .args(libdir_args)
-            .args(library_args)
-            .output()
-            .await?
+            .args(library_args);
+        cmd
     };
+    let output = cmd.output().await?;
 
     if config.verbose() {
         if !&output.stdout.is_empty() {
@@ -454,7 +522,7 @@ pub(crate) async fn compile_c_modules(
         }
     }
 
-    validate_output(&output)?;
+    validate_output(&cmd, &output)?;
     log_command_output(&output, config);
 
     if output_path.exists() {
@@ -749,4 +817,150 @@ mod tests {
             .await
             .is_ok_and(|status| status.success()));
     }
+
+    #[tokio::test]
+    async fn test_compile_c_modules_with_defines_and_libraries() {
+        let source_dir = assert_fs::TempDir::new().unwrap();
+        std::fs::write(
+            source_dir.join("foo.c"),
+            r#"
+#include <math.h>
+#ifndef USE_FOO
+#error "USE_FOO is not defined"
+#endif
+#if FOO_VALUE != 42
+#error "FOO_VALUE is not 42"
+#endif
+int luaopen_foo(void *L) { return (int)floor(FOO_VALUE); }
+"#,
+        )
+        .unwrap();
+        let config = ConfigBuilder::new().unwrap().build().unwrap();
+        let lua = LuaInstallation::without_headers(LuaVersion::Lua51);
+        let module: LuaModule = "foo".parse().unwrap();
+        let mut data = ModulePaths {
+            sources: vec!["foo.c".into()],
+            // MSVC links the C runtime's math functions by default
+            libraries: if cfg!(windows) {
+                Vec::new()
+            } else {
+                vec!["m".into()]
+            },
+            defines: vec![
+                ("USE_FOO".into(), None),
+                ("FOO_VALUE".into(), Some("42".into())),
+            ],
+            incdirs: Vec::new(),
+            libdirs: Vec::new(),
+        };
+        let target_dir = assert_fs::TempDir::new().unwrap();
+        compile_c_modules(
+            &data,
+            &source_dir,
+            &module,
+            &target_dir,
+            &lua,
+            &HashMap::default(),
+            &config,
+            &Progress::no_progress(),
+        )
+        .await
+        .unwrap();
+        assert!(target_dir.join(module.to_lib_path()).is_file());
+
+        data.defines.clear();
+        let target_dir = assert_fs::TempDir::new().unwrap();
+        let err = compile_c_modules(
+            &data,
+            &source_dir,
+            &module,
+            &target_dir,
+            &lua,
+            &HashMap::default(),
+            &config,
+            &Progress::no_progress(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&err, CompileCModulesError::CommandFailure(failure)
+                if failure.stderr.contains("USE_FOO is not defined")),
+            "{err}"
+        );
+
+        let target_dir = assert_fs::TempDir::new().unwrap();
+        let err = compile_c_files(
+            &vec![source_dir.join("foo.c")],
+            &module,
+            &target_dir,
+            &lua,
+            &HashMap::default(),
+            &config,
+            &Progress::no_progress(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&err, CompileCFilesError::CommandFailure(failure)
+                if failure.stderr.contains("USE_FOO is not defined")),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compile_c_module_including_lua_h() {
+        let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_version(lua_version)
+            .user_tree(Some(temp.to_path_buf()))
+            .build()
+            .unwrap();
+        let lua_version = config.lua_version().unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let lua = LuaInstallation::new(lua_version, &config, &bar)
+            .await
+            .unwrap();
+        let source_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/c-module-lua-h");
+        let module: LuaModule = "hello".parse().unwrap();
+
+        let target_dir = assert_fs::TempDir::new().unwrap();
+        compile_c_files(
+            &vec![source_dir.join("hello.c")],
+            &module,
+            &target_dir,
+            &lua,
+            &HashMap::default(),
+            &config,
+            &bar,
+        )
+        .await
+        .unwrap();
+        assert!(target_dir.join(module.to_lib_path()).is_file());
+
+        let target_dir = assert_fs::TempDir::new().unwrap();
+        let data = ModulePaths {
+            sources: vec!["hello.c".into()],
+            libraries: Vec::new(),
+            defines: Vec::new(),
+            incdirs: Vec::new(),
+            libdirs: Vec::new(),
+        };
+        compile_c_modules(
+            &data,
+            &source_dir,
+            &module,
+            &target_dir,
+            &lua,
+            &HashMap::default(),
+            &config,
+            &bar,
+        )
+        .await
+        .unwrap();
+        assert!(target_dir.join(module.to_lib_path()).is_file());
+    }
 }
@@ -47,6 +47,58 @@ impl Default for ExternalDependencySearchConfig {
     }
 }
 
+impl ExternalDependencySearchConfig {
+    /// Fill in the settings that are unchanged from their defaults with the ones from `fallback`.
+    /// Known prefixes are merged, with the ones in `self` taking precedence.
+    pub(crate) fn or(self, fallback: Self) -> Self {
+        let default = Self::default();
+        let mut prefixes = fallback.prefixes;
+        prefixes.extend(self.prefixes);
+        Self {
+            bin_patterns: non_default_or(
+                self.bin_patterns,
+                default.bin_patterns,
+                fallback.bin_patterns,
+            ),
+            include_patterns: non_default_or(
+                self.include_patterns,
+                default.include_patterns,
+                fallback.include_patterns,
+            ),
+            lib_patterns: non_default_or(
+                self.lib_patterns,
+                default.lib_patterns,
+                fallback.lib_patterns,
+            ),
+            bin_subdir: non_default_or(self.bin_subdir, default.bin_subdir, fallback.bin_subdir),
+            include_subdir: non_default_or(
+                self.include_subdir,
+                default.include_subdir,
+                fallback.include_subdir,
+            ),
+            lib_subdirs: non_default_or(
+                self.lib_subdirs,
+                default.lib_subdirs,
+                fallback.lib_subdirs,
+            ),
+            search_prefixes: non_default_or(
+                self.search_prefixes,
+                default.search_prefixes,
+                fallback.search_prefixes,
+            ),
+            prefixes,
+        }
+    }
+}
+
+fn non_default_or<T: PartialEq>(value: T, default: T, fallback: T) -> T {
+    if value == default {
+        fallback
+    } else {
+        value
+    }
+}
+
 fn default_bin_patterns() -> Vec<String> {
     vec!["?".into()]
 }
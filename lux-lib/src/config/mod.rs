@@ -2,21 +2,28 @@ use directories::ProjectDirs;
 use external_deps::ExternalDependencySearchConfig;
 use itertools::Itertools;
 use mlua::{ExternalError, ExternalResult, FromLua, IntoLua, UserData};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
 use serde::{Deserialize, Serialize, Serializer};
 use std::env::current_exe;
 use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
 use std::{
     collections::HashMap, env, fmt::Display, io, path::PathBuf, str::FromStr, time::Duration,
 };
+use target_lexicon::Triple;
 use thiserror::Error;
 use tree::RockLayoutConfig;
 use url::Url;
+use walkdir::WalkDir;
 
 use crate::tree::{Tree, TreeError};
 use crate::variables::GetVariableError;
 use crate::{
     build::utils,
     package::{PackageVersion, PackageVersionReq},
+    project::Project,
+    rockspec::LuaVersionCompatibility,
     variables::HasVariables,
 };
 
@@ -25,7 +32,15 @@ pub mod tree;
 
 const DEV_PATH: &str = "dev/";
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+const DEFAULT_MAX_RESOLUTION_DEPTH: usize = 100;
+
+/// How long to wait for a server to respond when selecting a server.
+const SERVER_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The maximum number of redirects to follow, which matches reqwest's default policy.
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub enum LuaVersion {
     #[serde(rename = "5.1")]
     Lua51,
@@ -73,6 +88,15 @@ impl LuaVersion {
             LuaVersion::LuaJIT52 => "5.2.0".parse().unwrap(),
         }
     }
+    /// The Lua version that this version is compatible with,
+    /// as used in manifest file names (e.g. `manifest-5.1`) and tree directories.
+    ///
+    /// ```
+    /// use lux_lib::config::LuaVersion;
+    ///
+    /// assert_eq!(LuaVersion::LuaJIT.version_compatibility_str(), "5.1");
+    /// assert_eq!(LuaVersion::Lua54.version_compatibility_str(), "5.4");
+    /// ```
     pub fn version_compatibility_str(&self) -> String {
         match self {
             LuaVersion::Lua51 | LuaVersion::LuaJIT => "5.1".into(),
@@ -106,10 +130,37 @@ impl LuaVersion {
         }
     }
 
-    pub(crate) fn is_luajit(&self) -> bool {
+    /// Whether this is a LuaJIT version.
+    ///
+    /// ```
+    /// use lux_lib::config::LuaVersion;
+    ///
+    /// assert!(LuaVersion::LuaJIT52.is_luajit());
+    /// assert!(!LuaVersion::Lua51.is_luajit());
+    /// ```
+    pub fn is_luajit(&self) -> bool {
         matches!(self, Self::LuaJIT | Self::LuaJIT52)
     }
 
+    /// The version of the Lua (or LuaJIT) sources that lux builds
+    /// when installing this Lua version.
+    ///
+    /// ```
+    /// use lux_lib::config::LuaVersion;
+    ///
+    /// assert_eq!(LuaVersion::Lua54.lua_src_version(), "5.4.8");
+    /// assert_eq!(LuaVersion::LuaJIT.lua_src_version(), "2.1");
+    /// ```
+    pub fn lua_src_version(&self) -> &'static str {
+        match self {
+            LuaVersion::Lua51 => "5.1.5",
+            LuaVersion::Lua52 => "5.2.4",
+            LuaVersion::Lua53 => "5.3.6",
+            LuaVersion::Lua54 => "5.4.8",
+            LuaVersion::LuaJIT | LuaVersion::LuaJIT52 => "2.1",
+        }
+    }
+
     /// Searches for the path to the lux-lua library for this version
     pub fn lux_lib_dir(&self) -> Option<PathBuf> {
         option_env!("LUX_LIB_DIR")
@@ -179,25 +230,43 @@ impl LuaVersion {
     }
 }
 
+#[derive(Error, Debug)]
+#[error("unrecognized Lua version: '{0}'. Allowed versions: '5.1', '5.2', '5.3', '5.4', 'jit', 'jit52'.")]
+pub struct ParseLuaVersionError(String);
+
 impl FromStr for LuaVersion {
-    type Err = String;
+    type Err = ParseLuaVersionError;
 
+    /// Parses the spellings commonly used for Lua versions,
+    /// e.g. `5.1`, `51`, `lua5.1`, `jit`, `luajit` or `LuaJIT`.
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s {
+        let normalized = s.trim().to_lowercase();
+        let version = normalized
+            .strip_prefix("lua")
+            .map(|version| version.trim_start_matches(['-', ' ']))
+            .unwrap_or(&normalized);
+        match version {
             "5.1" | "51" => Ok(LuaVersion::Lua51),
             "5.2" | "52" => Ok(LuaVersion::Lua52),
             "5.3" | "53" => Ok(LuaVersion::Lua53),
             "5.4" | "54" => Ok(LuaVersion::Lua54),
-            "jit" | "luajit" => Ok(LuaVersion::LuaJIT),
-            "jit52" | "luajit52" => Ok(LuaVersion::LuaJIT52),
-            _ => Err(
-                "unrecognized Lua version. Allowed versions: '5.1', '5.2', '5.3', '5.4', 'jit', 'jit52'."
-                    .into(),
-            ),
+            "jit" => Ok(LuaVersion::LuaJIT),
+            "jit52" | "jit5.2" | "jit-5.2" => Ok(LuaVersion::LuaJIT52),
+            _ => Err(ParseLuaVersionError(s.to_string())),
         }
     }
 }
 
+impl<'de> Deserialize<'de> for LuaVersion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let version_str = String::deserialize(deserializer)?;
+        LuaVersion::from_str(&version_str).map_err(serde::de::Error::custom)
+    }
+}
+
 impl Display for LuaVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -219,6 +288,10 @@ pub struct NoValidHomeDirectory;
 pub struct Config {
     enable_development_packages: bool,
     server: Url,
+    /// Servers to fall back to, in order, if the `server` is unreachable.
+    fallback_servers: Vec<Url>,
+    /// The server chosen by [`Config::select_server`].
+    selected_server: Arc<OnceLock<Url>>,
     extra_servers: Vec<Url>,
     only_sources: Option<String>,
     namespace: Option<String>,
@@ -228,10 +301,45 @@ pub struct Config {
     verbose: bool,
     /// Don't display progress bars
     no_progress: bool,
-    timeout: Duration,
+    /// Timeout for network requests. Zero means no timeout.
+    request_timeout: Duration,
+    /// Timeout for large downloads, such as packed rocks and source archives.
+    /// Zero means no timeout.
+    download_timeout: Duration,
     max_jobs: usize,
+    /// The maximum length of a chain of transitive dependencies during resolution.
+    /// Guards against pathological dependency graphs.
+    max_resolution_depth: usize,
     variables: HashMap<String, String>,
+    /// Environment variables to set for the test runner of `lx test`,
+    /// e.g. to point busted to a custom interpreter.
+    test_env: HashMap<String, String>,
     external_deps: ExternalDependencySearchConfig,
+    /// Extra flags to pass to the C compiler when building C modules,
+    /// e.g. `-I` flags for libraries that can't be found via pkg-config.
+    extra_cflags: Vec<String>,
+    /// Extra flags to pass to the linker when building C modules,
+    /// e.g. `-L` and `-l` flags for libraries that can't be found via pkg-config.
+    extra_ldflags: Vec<String>,
+    /// Link C modules against a static Lua library (e.g. `liblua.a`) if one is available.
+    prefer_static_lua: bool,
+    /// Lua is provided by the system, so never build it from source.
+    /// See [`LuaInstallation::new`](crate::lua_installation::LuaInstallation::new).
+    system_lua: bool,
+    /// Only install binary rocks built for the `all` architecture
+    /// if they are genuinely architecture-independent, i.e. their rockspec
+    /// does not declare a native build. Otherwise, fall back to the source rock.
+    strict_binary_arch: bool,
+    /// Fail to install a rock if one of its binaries is already provided
+    /// by a different rock, instead of warning and overwriting it.
+    deny_binary_collisions: bool,
+    /// A directory of `<name>-<version>.rockspec` files that take precedence
+    /// over the rockspecs of the same packages on the remote servers.
+    rockspec_overlay_dir: Option<PathBuf>,
+    /// Keep the build directory of a failed build in the cache directory,
+    /// so that the build commands and intermediate files can be inspected.
+    /// Can also be enabled by setting `LUX_KEEP_BUILD_DIR=1`.
+    keep_build_dir_on_failure: bool,
     /// The rock layout for entrypoints of new install trees.
     /// Does not affect existing install trees or dependency rock layouts.
     entrypoint_layout: RockLayoutConfig,
@@ -239,6 +347,32 @@ pub struct Config {
     cache_dir: PathBuf,
     data_dir: PathBuf,
     generate_luarc: bool,
+
+    /// Additional CA certificates (PEM) to trust, e.g. for private registries.
+    extra_ca_certs: Vec<PathBuf>,
+    /// Accept invalid TLS certificates and allow plain HTTP uploads.
+    allow_insecure: bool,
+    /// Hosts (optionally with a port) whose TLS certificates are not verified,
+    /// e.g. an internal mirror with a self-signed certificate.
+    /// Unlike `allow_insecure`, TLS is still verified for all other hosts.
+    insecure_hosts: Vec<String>,
+    /// The platform to resolve platform-specific dependencies for.
+    /// Defaults to the host platform.
+    target_platform: Option<Triple>,
+    /// The proxy to send all requests through.
+    /// If not set, the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are respected.
+    proxy: Option<Url>,
+    /// Hosts that should bypass the `proxy`, in the same format as the `NO_PROXY` environment variable.
+    no_proxy: Vec<String>,
+    /// `Authorization` headers to send with requests, keyed by host (optionally with a port),
+    /// e.g. for private registries that require authentication for downloads.
+    /// The header values are marked as sensitive, so that they are never logged.
+    auth_tokens: HashMap<String, HeaderValue>,
+    /// The HTTP client shared by all requests.
+    http_client: reqwest::Client,
+    /// The HTTP client for requests to the `insecure_hosts`,
+    /// which does not verify TLS certificates.
+    insecure_http_client: Option<reqwest::Client>,
 }
 
 impl Config {
@@ -270,10 +404,49 @@ impl Config {
         }
     }
 
+    /// The server to use for uploads and checks.
+    /// This is the server chosen by [`Config::select_server`],
+    /// if it has been called, or the primary server otherwise.
     pub fn server(&self) -> &Url {
+        self.selected_server.get().unwrap_or(&self.server)
+    }
+
+    pub fn fallback_servers(&self) -> &Vec<Url> {
+        self.fallback_servers.as_ref()
+    }
+
+    /// Select the first reachable server out of the primary server and the fallback servers.
+    /// The choice is cached, so that subsequent calls and [`Config::server`] return it.
+    /// If no fallback servers are configured, this returns the primary server without checking it.
+    /// If no server is reachable, this returns the primary server without caching it.
+    pub async fn select_server(&self) -> &Url {
+        if self.fallback_servers.is_empty() {
+            return &self.server;
+        }
+        if let Some(server) = self.selected_server.get() {
+            return server;
+        }
+        for server in std::iter::once(&self.server).chain(&self.fallback_servers) {
+            if self.is_server_reachable(server).await {
+                return self.selected_server.get_or_init(|| server.clone());
+            }
+        }
         &self.server
     }
 
+    async fn is_server_reachable(&self, server: &Url) -> bool {
+        let url = match server.join("api/tool_version") {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+        self.http_client_for(&url)
+            .head(url)
+            .timeout(SERVER_HEALTH_CHECK_TIMEOUT)
+            .send()
+            .await
+            .is_ok_and(|response| !response.status().is_server_error())
+    }
+
     pub fn extra_servers(&self) -> &Vec<Url> {
         self.extra_servers.as_ref()
     }
@@ -306,12 +479,46 @@ impl Config {
         self.lua_version.as_ref()
     }
 
+    /// Resolve the Lua version to use, in order of precedence:
+    ///
+    /// 1. The `.lua-version` file at the root of the `project`, if there is one.
+    /// 2. The configured Lua version, e.g. set with `--lua-version` or in the config file.
+    /// 3. The version of the Lua installation detected on the system when building the config.
+    ///
+    /// In a project, the resolved version must satisfy the `lua` constraint of the `lux.toml`.
+    /// If the configured or detected version doesn't, or if neither is set,
+    /// the project's Lua version is inferred from a `lua` constraint
+    /// that only allows a single version, e.g. `lua = "==5.1"`.
+    /// There is no hardcoded default: if no version can be determined,
+    /// this fails with [`LuaVersionUnset`].
+    pub fn resolve_lua_version(
+        &self,
+        project: Option<&Project>,
+    ) -> Result<LuaVersion, crate::lua_rockspec::LuaVersionError> {
+        match project {
+            Some(project) => match project.lua_version_file()? {
+                Some(lua_version) => {
+                    project.toml().validate_lua_version(&lua_version)?;
+                    Ok(lua_version)
+                }
+                None => project.toml().lua_version_matches(self),
+            },
+            None => Ok(LuaVersion::from(self)?.clone()),
+        }
+    }
+
     /// The tree in which to install rocks.
     /// If installing packges for a project, use `Project::tree` instead.
     pub fn user_tree(&self, version: LuaVersion) -> Result<Tree, TreeError> {
         Tree::new(self.user_tree.clone(), version, self)
     }
 
+    /// An install tree rooted at an explicit directory, which does not require a project.
+    /// The tree's lockfile is located at `<root>/<lua-version>/lux.lock`.
+    pub fn tree(&self, root: PathBuf, version: LuaVersion) -> Result<Tree, TreeError> {
+        Tree::new(root, version, self)
+    }
+
     pub fn verbose(&self) -> bool {
         self.verbose
     }
@@ -320,13 +527,27 @@ impl Config {
         self.no_progress
     }
 
+    #[deprecated(note = "use `request_timeout` instead")]
     pub fn timeout(&self) -> &Duration {
-        &self.timeout
+        &self.request_timeout
+    }
+
+    pub fn request_timeout(&self) -> &Duration {
+        &self.request_timeout
+    }
+
+    pub fn download_timeout(&self) -> &Duration {
+        &self.download_timeout
     }
 
     pub fn max_jobs(&self) -> usize {
         self.max_jobs
     }
+
+    pub fn max_resolution_depth(&self) -> usize {
+        self.max_resolution_depth
+    }
+
     pub fn make_cmd(&self) -> String {
         match self.variables.get("MAKE") {
             Some(make) => make.clone(),
@@ -345,10 +566,46 @@ impl Config {
         &self.variables
     }
 
+    pub fn test_env(&self) -> &HashMap<String, String> {
+        &self.test_env
+    }
+
     pub fn external_deps(&self) -> &ExternalDependencySearchConfig {
         &self.external_deps
     }
 
+    pub fn extra_cflags(&self) -> &Vec<String> {
+        &self.extra_cflags
+    }
+
+    pub fn extra_ldflags(&self) -> &Vec<String> {
+        &self.extra_ldflags
+    }
+
+    pub fn prefer_static_lua(&self) -> bool {
+        self.prefer_static_lua
+    }
+
+    pub fn system_lua(&self) -> bool {
+        self.system_lua
+    }
+
+    pub fn strict_binary_arch(&self) -> bool {
+        self.strict_binary_arch
+    }
+
+    pub fn deny_binary_collisions(&self) -> bool {
+        self.deny_binary_collisions
+    }
+
+    pub fn rockspec_overlay_dir(&self) -> Option<&PathBuf> {
+        self.rockspec_overlay_dir.as_ref()
+    }
+
+    pub fn keep_build_dir_on_failure(&self) -> bool {
+        self.keep_build_dir_on_failure
+    }
+
     pub fn entrypoint_layout(&self) -> &RockLayoutConfig {
         &self.entrypoint_layout
     }
@@ -357,6 +614,47 @@ impl Config {
         &self.cache_dir
     }
 
+    /// Delete entries in the `cache_dir` that haven't been used within `max_age`,
+    /// returning the number of bytes freed.
+    pub fn prune_cache(&self, max_age: Duration) -> io::Result<u64> {
+        self.prune_cache_with_size_limit(max_age, None)
+    }
+
+    /// Like [`Config::prune_cache`], but if `max_size` is set, also evicts the least recently
+    /// used entries until the cache takes up no more than `max_size` bytes.
+    pub fn prune_cache_with_size_limit(
+        &self,
+        max_age: Duration,
+        max_size: Option<u64>,
+    ) -> io::Result<u64> {
+        if !self.cache_dir.is_dir() {
+            return Ok(0);
+        }
+        let now = SystemTime::now();
+        let mut entries = std::fs::read_dir(&self.cache_dir)?
+            .map(|entry| CacheEntry::new(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // most recently used first, so that we evict from the back
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_used));
+        let mut kept_size: u64 = 0;
+        let mut over_size_limit = false;
+        let mut freed: u64 = 0;
+        for entry in entries {
+            let expired = now
+                .duration_since(entry.last_used)
+                .is_ok_and(|age| age > max_age);
+            over_size_limit = over_size_limit
+                || max_size.is_some_and(|max_size| kept_size + entry.size > max_size);
+            if expired || over_size_limit {
+                entry.remove()?;
+                freed += entry.size;
+            } else {
+                kept_size += entry.size;
+            }
+        }
+        Ok(freed)
+    }
+
     pub fn data_dir(&self) -> &PathBuf {
         &self.data_dir
     }
@@ -364,6 +662,90 @@ impl Config {
     pub fn generate_luarc(&self) -> bool {
         self.generate_luarc
     }
+
+    pub fn extra_ca_certs(&self) -> &Vec<PathBuf> {
+        &self.extra_ca_certs
+    }
+
+    pub fn allow_insecure(&self) -> bool {
+        self.allow_insecure
+    }
+
+    pub fn insecure_hosts(&self) -> &Vec<String> {
+        &self.insecure_hosts
+    }
+
+    pub fn proxy(&self) -> Option<&Url> {
+        self.proxy.as_ref()
+    }
+
+    /// The platform to resolve platform-specific dependencies for, if not the host platform.
+    /// We don't cross-compile, so this does not affect how packages are built.
+    pub fn target_platform(&self) -> Option<&Triple> {
+        self.target_platform.as_ref()
+    }
+
+    pub fn no_proxy(&self) -> &Vec<String> {
+        &self.no_proxy
+    }
+
+    /// The HTTP client to use for requests,
+    /// which trusts the `extra_ca_certs` in addition to the system's root certificates
+    /// and sends requests through the `proxy`, if configured.
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http_client
+    }
+
+    /// Create a new HTTP client builder, configured with the `extra_ca_certs`,
+    /// `allow_insecure` and proxy settings.
+    pub(crate) fn http_client_builder(&self) -> Result<reqwest::ClientBuilder, ConfigError> {
+        mk_http_client_builder(
+            &self.extra_ca_certs,
+            self.allow_insecure,
+            &self.request_timeout,
+            self.proxy.as_ref(),
+            &self.no_proxy,
+        )
+    }
+
+    /// The HTTP client to send requests to the URL with.
+    /// This skips TLS verification if the URL's host is one of the `insecure_hosts`.
+    fn http_client_for(&self, url: &Url) -> &reqwest::Client {
+        match &self.insecure_http_client {
+            Some(insecure_http_client) if url_host_matches(url, &self.insecure_hosts) => {
+                insecure_http_client
+            }
+            _ => &self.http_client,
+        }
+    }
+
+    /// Start a request with the `http_client`, which sends the `Authorization` header
+    /// if an auth token is configured for the URL's host.
+    pub(crate) fn request(&self, method: reqwest::Method, url: Url) -> reqwest::RequestBuilder {
+        let auth_token = url.host_str().and_then(|host| {
+            url.port()
+                .and_then(|port| self.auth_tokens.get(&format!("{host}:{port}")))
+                .or_else(|| self.auth_tokens.get(host))
+                .cloned()
+        });
+        let request = self.http_client_for(&url).request(method, url);
+        match auth_token {
+            Some(auth_token) => request.header(AUTHORIZATION, auth_token),
+            None => request,
+        }
+    }
+
+    /// Start a GET request for a large download, which uses the `download_timeout`
+    /// instead of the `request_timeout`.
+    pub(crate) fn download(&self, url: Url) -> reqwest::RequestBuilder {
+        let timeout = if self.download_timeout.is_zero() {
+            // A per-request timeout overrides the client's, so we can't unset it.
+            Duration::MAX
+        } else {
+            self.download_timeout
+        };
+        self.request(reqwest::Method::GET, url).timeout(timeout)
+    }
 }
 
 impl HasVariables for Config {
@@ -380,10 +762,28 @@ pub enum ConfigError {
     NoValidHomeDirectory(#[from] NoValidHomeDirectory),
     #[error("error deserializing lux config: {0}")]
     Deserialize(#[from] toml::de::Error),
+    #[error("error reading lux config file {0}: {1}")]
+    ReadConfigFile(PathBuf, io::Error),
+    #[error("error deserializing lux config file {0}: {1}")]
+    DeserializeConfigFile(PathBuf, toml::de::Error),
     #[error("error parsing URL: {0}")]
     UrlParseError(#[from] url::ParseError),
     #[error("error initializing compiler toolchain: {0}")]
     CompilerToolchain(#[from] cc::Error),
+    #[error("error reading CA certificate {0}: {1}")]
+    ReadCaCert(PathBuf, io::Error),
+    #[error("invalid CA certificate {0}: {1}")]
+    InvalidCaCert(PathBuf, reqwest::Error),
+    #[error("error initialising HTTP client: {0}")]
+    HttpClient(reqwest::Error),
+    #[error("invalid target platform {0}: {1}")]
+    InvalidTargetPlatform(String, target_lexicon::ParseError),
+    #[error("invalid proxy URL {0}: expected an http:// or https:// URL with a host")]
+    UnsupportedProxy(Url),
+    #[error("invalid proxy URL {0}: {1}")]
+    InvalidProxy(Url, reqwest::Error),
+    #[error("invalid auth token for {0}: tokens may only contain visible ASCII characters")]
+    InvalidAuthToken(String),
 }
 
 #[derive(Clone, Default, Deserialize, Serialize)]
@@ -399,6 +799,12 @@ pub struct ConfigBuilder {
         deserialize_with = "deserialize_url_vec",
         serialize_with = "serialize_url_vec"
     )]
+    fallback_servers: Option<Vec<Url>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_url_vec",
+        serialize_with = "serialize_url_vec"
+    )]
     extra_servers: Option<Vec<Url>>,
     only_sources: Option<String>,
     namespace: Option<String>,
@@ -410,16 +816,42 @@ pub struct ConfigBuilder {
     enable_development_packages: Option<bool>,
     verbose: Option<bool>,
     no_progress: Option<bool>,
-    timeout: Option<Duration>,
+    #[serde(alias = "timeout")]
+    request_timeout: Option<Duration>,
+    download_timeout: Option<Duration>,
     max_jobs: Option<usize>,
+    max_resolution_depth: Option<usize>,
     variables: Option<HashMap<String, String>>,
+    test_env: Option<HashMap<String, String>>,
     #[serde(default)]
     external_deps: ExternalDependencySearchConfig,
+    extra_cflags: Option<Vec<String>>,
+    extra_ldflags: Option<Vec<String>>,
+    prefer_static_lua: Option<bool>,
+    system_lua: Option<bool>,
+    strict_binary_arch: Option<bool>,
+    deny_binary_collisions: Option<bool>,
+    rockspec_overlay_dir: Option<PathBuf>,
+    keep_build_dir_on_failure: Option<bool>,
     /// The rock layout for new install trees.
     /// Does not affect existing install trees.
     #[serde(default)]
     entrypoint_layout: RockLayoutConfig,
     generate_luarc: Option<bool>,
+    extra_ca_certs: Option<Vec<PathBuf>>,
+    allow_insecure: Option<bool>,
+    /// Hosts (optionally with a port) whose TLS certificates are not verified.
+    insecure_hosts: Option<Vec<String>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_url",
+        serialize_with = "serialize_url"
+    )]
+    proxy: Option<Url>,
+    no_proxy: Option<Vec<String>>,
+    /// Bearer tokens to authenticate with, keyed by host (optionally with a port).
+    auth_tokens: Option<HashMap<String, String>>,
+    target_platform: Option<String>,
 }
 
 /// A builder for the lux `Config`.
@@ -442,6 +874,67 @@ impl ConfigBuilder {
         Ok(project_dirs.config_dir().join("config.toml").to_path_buf())
     }
 
+    /// Apply the settings from a TOML config file.
+    /// Settings that have already been set on this builder (e.g. via CLI flags)
+    /// take precedence over the ones in the config file.
+    pub fn apply_config_file(self, path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::ReadConfigFile(path.to_path_buf(), err))?;
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|err| ConfigError::DeserializeConfigFile(path.to_path_buf(), err))?;
+        let has_entrypoint_layout = table.contains_key("entrypoint_layout");
+        let file: ConfigBuilder = table
+            .try_into()
+            .map_err(|err| ConfigError::DeserializeConfigFile(path.to_path_buf(), err))?;
+        Ok(Self {
+            server: self.server.or(file.server),
+            fallback_servers: self.fallback_servers.or(file.fallback_servers),
+            extra_servers: self.extra_servers.or(file.extra_servers),
+            only_sources: self.only_sources.or(file.only_sources),
+            namespace: self.namespace.or(file.namespace),
+            lua_version: self.lua_version.or(file.lua_version),
+            user_tree: self.user_tree.or(file.user_tree),
+            lua_dir: self.lua_dir.or(file.lua_dir),
+            cache_dir: self.cache_dir.or(file.cache_dir),
+            data_dir: self.data_dir.or(file.data_dir),
+            enable_development_packages: self
+                .enable_development_packages
+                .or(file.enable_development_packages),
+            verbose: self.verbose.or(file.verbose),
+            no_progress: self.no_progress.or(file.no_progress),
+            request_timeout: self.request_timeout.or(file.request_timeout),
+            download_timeout: self.download_timeout.or(file.download_timeout),
+            max_jobs: self.max_jobs.or(file.max_jobs),
+            max_resolution_depth: self.max_resolution_depth.or(file.max_resolution_depth),
+            variables: self.variables.or(file.variables),
+            test_env: self.test_env.or(file.test_env),
+            external_deps: self.external_deps.or(file.external_deps),
+            extra_cflags: self.extra_cflags.or(file.extra_cflags),
+            extra_ldflags: self.extra_ldflags.or(file.extra_ldflags),
+            prefer_static_lua: self.prefer_static_lua.or(file.prefer_static_lua),
+            system_lua: self.system_lua.or(file.system_lua),
+            strict_binary_arch: self.strict_binary_arch.or(file.strict_binary_arch),
+            deny_binary_collisions: self.deny_binary_collisions.or(file.deny_binary_collisions),
+            rockspec_overlay_dir: self.rockspec_overlay_dir.or(file.rockspec_overlay_dir),
+            keep_build_dir_on_failure: self
+                .keep_build_dir_on_failure
+                .or(file.keep_build_dir_on_failure),
+            entrypoint_layout: if has_entrypoint_layout && self.entrypoint_layout.is_default() {
+                file.entrypoint_layout
+            } else {
+                self.entrypoint_layout
+            },
+            generate_luarc: self.generate_luarc.or(file.generate_luarc),
+            extra_ca_certs: self.extra_ca_certs.or(file.extra_ca_certs),
+            allow_insecure: self.allow_insecure.or(file.allow_insecure),
+            insecure_hosts: self.insecure_hosts.or(file.insecure_hosts),
+            proxy: self.proxy.or(file.proxy),
+            no_proxy: self.no_proxy.or(file.no_proxy),
+            auth_tokens: self.auth_tokens.or(file.auth_tokens),
+            target_platform: self.target_platform.or(file.target_platform),
+        })
+    }
+
     pub fn dev(self, dev: Option<bool>) -> Self {
         Self {
             enable_development_packages: dev.or(self.enable_development_packages),
@@ -456,6 +949,13 @@ impl ConfigBuilder {
         }
     }
 
+    pub fn fallback_servers(self, fallback_servers: Option<Vec<Url>>) -> Self {
+        Self {
+            fallback_servers: fallback_servers.or(self.fallback_servers),
+            ..self
+        }
+    }
+
     pub fn extra_servers(self, extra_servers: Option<Vec<Url>>) -> Self {
         Self {
             extra_servers: extra_servers.or(self.extra_servers),
@@ -505,6 +1005,69 @@ impl ConfigBuilder {
         }
     }
 
+    pub fn test_env(self, test_env: Option<HashMap<String, String>>) -> Self {
+        Self {
+            test_env: test_env.or(self.test_env),
+            ..self
+        }
+    }
+
+    pub fn extra_cflags(self, extra_cflags: Option<Vec<String>>) -> Self {
+        Self {
+            extra_cflags: extra_cflags.or(self.extra_cflags),
+            ..self
+        }
+    }
+
+    pub fn extra_ldflags(self, extra_ldflags: Option<Vec<String>>) -> Self {
+        Self {
+            extra_ldflags: extra_ldflags.or(self.extra_ldflags),
+            ..self
+        }
+    }
+
+    pub fn prefer_static_lua(self, prefer_static_lua: Option<bool>) -> Self {
+        Self {
+            prefer_static_lua: prefer_static_lua.or(self.prefer_static_lua),
+            ..self
+        }
+    }
+
+    pub fn system_lua(self, system_lua: Option<bool>) -> Self {
+        Self {
+            system_lua: system_lua.or(self.system_lua),
+            ..self
+        }
+    }
+
+    pub fn strict_binary_arch(self, strict_binary_arch: Option<bool>) -> Self {
+        Self {
+            strict_binary_arch: strict_binary_arch.or(self.strict_binary_arch),
+            ..self
+        }
+    }
+
+    pub fn deny_binary_collisions(self, deny_binary_collisions: Option<bool>) -> Self {
+        Self {
+            deny_binary_collisions: deny_binary_collisions.or(self.deny_binary_collisions),
+            ..self
+        }
+    }
+
+    pub fn rockspec_overlay_dir(self, rockspec_overlay_dir: Option<PathBuf>) -> Self {
+        Self {
+            rockspec_overlay_dir: rockspec_overlay_dir.or(self.rockspec_overlay_dir),
+            ..self
+        }
+    }
+
+    pub fn keep_build_dir_on_failure(self, keep_build_dir_on_failure: Option<bool>) -> Self {
+        Self {
+            keep_build_dir_on_failure: keep_build_dir_on_failure.or(self.keep_build_dir_on_failure),
+            ..self
+        }
+    }
+
     pub fn verbose(self, verbose: Option<bool>) -> Self {
         Self {
             verbose: verbose.or(self.verbose),
@@ -519,9 +1082,21 @@ impl ConfigBuilder {
         }
     }
 
+    #[deprecated(note = "use `request_timeout` instead")]
     pub fn timeout(self, timeout: Option<Duration>) -> Self {
+        self.request_timeout(timeout)
+    }
+
+    pub fn request_timeout(self, request_timeout: Option<Duration>) -> Self {
+        Self {
+            request_timeout: request_timeout.or(self.request_timeout),
+            ..self
+        }
+    }
+
+    pub fn download_timeout(self, download_timeout: Option<Duration>) -> Self {
         Self {
-            timeout: timeout.or(self.timeout),
+            download_timeout: download_timeout.or(self.download_timeout),
             ..self
         }
     }
@@ -533,6 +1108,13 @@ impl ConfigBuilder {
         }
     }
 
+    pub fn max_resolution_depth(self, max_resolution_depth: Option<usize>) -> Self {
+        Self {
+            max_resolution_depth: max_resolution_depth.or(self.max_resolution_depth),
+            ..self
+        }
+    }
+
     pub fn cache_dir(self, cache_dir: Option<PathBuf>) -> Self {
         Self {
             cache_dir: cache_dir.or(self.cache_dir),
@@ -561,20 +1143,139 @@ impl ConfigBuilder {
         }
     }
 
+    pub fn extra_ca_certs(self, extra_ca_certs: Option<Vec<PathBuf>>) -> Self {
+        Self {
+            extra_ca_certs: extra_ca_certs.or(self.extra_ca_certs),
+            ..self
+        }
+    }
+
+    pub fn allow_insecure(self, allow_insecure: Option<bool>) -> Self {
+        Self {
+            allow_insecure: allow_insecure.or(self.allow_insecure),
+            ..self
+        }
+    }
+
+    pub fn insecure_hosts(self, insecure_hosts: Option<Vec<String>>) -> Self {
+        Self {
+            insecure_hosts: insecure_hosts.or(self.insecure_hosts),
+            ..self
+        }
+    }
+
+    pub fn proxy(self, proxy: Option<Url>) -> Self {
+        Self {
+            proxy: proxy.or(self.proxy),
+            ..self
+        }
+    }
+
+    pub fn no_proxy(self, no_proxy: Option<Vec<String>>) -> Self {
+        Self {
+            no_proxy: no_proxy.or(self.no_proxy),
+            ..self
+        }
+    }
+
+    pub fn auth_tokens(self, auth_tokens: Option<HashMap<String, String>>) -> Self {
+        Self {
+            auth_tokens: auth_tokens.or(self.auth_tokens),
+            ..self
+        }
+    }
+
+    pub fn target_platform(self, target_platform: Option<Triple>) -> Self {
+        Self {
+            target_platform: target_platform
+                .map(|triple| triple.to_string())
+                .or(self.target_platform),
+            ..self
+        }
+    }
+
     pub fn build(self) -> Result<Config, ConfigError> {
         let data_dir = self.data_dir.unwrap_or(Config::get_default_data_path()?);
         let cache_dir = self.cache_dir.unwrap_or(Config::get_default_cache_path()?);
         let user_tree = self.user_tree.unwrap_or(data_dir.join("tree"));
 
+        // See `Config::resolve_lua_version` for the full precedence
         let lua_version = self
             .lua_version
             .or(crate::lua_installation::detect_installed_lua_version());
 
+        let extra_ca_certs = self.extra_ca_certs.unwrap_or_default();
+        let allow_insecure = self.allow_insecure.unwrap_or(false);
+        let request_timeout = self
+            .request_timeout
+            .unwrap_or_else(|| Duration::from_secs(30));
+        let target_platform = self
+            .target_platform
+            .map(|triple| {
+                Triple::from_str(&triple)
+                    .map_err(|err| ConfigError::InvalidTargetPlatform(triple.clone(), err))
+            })
+            .transpose()?;
+        let no_proxy = self.no_proxy.unwrap_or_default();
+        let auth_tokens = self
+            .auth_tokens
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(host, token)| {
+                let mut header = HeaderValue::from_str(&format!("Bearer {token}"))
+                    .map_err(|_| ConfigError::InvalidAuthToken(host.clone()))?;
+                header.set_sensitive(true);
+                Ok((host, header))
+            })
+            .try_collect::<_, _, ConfigError>()?;
+        let http_client = mk_http_client_builder(
+            &extra_ca_certs,
+            allow_insecure,
+            &request_timeout,
+            self.proxy.as_ref(),
+            &no_proxy,
+        )?
+        .build()
+        .map_err(ConfigError::HttpClient)?;
+        let insecure_hosts = self.insecure_hosts.unwrap_or_default();
+        let insecure_http_client = if allow_insecure || insecure_hosts.is_empty() {
+            None
+        } else {
+            let redirect_hosts = insecure_hosts.clone();
+            Some(
+                mk_http_client_builder(
+                    &extra_ca_certs,
+                    true,
+                    &request_timeout,
+                    self.proxy.as_ref(),
+                    &no_proxy,
+                )?
+                // Don't skip TLS verification for hosts we are redirected to.
+                .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        attempt.error("too many redirects")
+                    } else if url_host_matches(attempt.url(), &redirect_hosts) {
+                        attempt.follow()
+                    } else {
+                        let err = format!(
+                            "refusing to follow redirect to {}: TLS verification is only disabled for the insecure_hosts",
+                            attempt.url()
+                        );
+                        attempt.error(err)
+                    }
+                }))
+                .build()
+                .map_err(ConfigError::HttpClient)?,
+            )
+        };
+
         Ok(Config {
             enable_development_packages: self.enable_development_packages.unwrap_or(false),
             server: self
                 .server
                 .unwrap_or_else(|| Url::parse("https://luarocks.org/").unwrap()),
+            fallback_servers: self.fallback_servers.unwrap_or_default(),
+            selected_server: Arc::default(),
             extra_servers: self.extra_servers.unwrap_or_default(),
             only_sources: self.only_sources,
             namespace: self.namespace,
@@ -583,19 +1284,45 @@ impl ConfigBuilder {
             user_tree,
             verbose: self.verbose.unwrap_or(false),
             no_progress: self.no_progress.unwrap_or(false),
-            timeout: self.timeout.unwrap_or_else(|| Duration::from_secs(30)),
+            request_timeout,
+            download_timeout: self
+                .download_timeout
+                .unwrap_or_else(|| Duration::from_secs(300)),
             max_jobs: match self.max_jobs.unwrap_or(usize::MAX) {
                 0 => usize::MAX,
                 max_jobs => max_jobs,
             },
+            max_resolution_depth: self
+                .max_resolution_depth
+                .unwrap_or(DEFAULT_MAX_RESOLUTION_DEPTH),
             variables: default_variables()
                 .chain(self.variables.unwrap_or_default())
                 .collect(),
+            test_env: self.test_env.unwrap_or_default(),
             external_deps: self.external_deps,
+            extra_cflags: self.extra_cflags.unwrap_or_default(),
+            extra_ldflags: self.extra_ldflags.unwrap_or_default(),
+            prefer_static_lua: self.prefer_static_lua.unwrap_or(false),
+            system_lua: self.system_lua.unwrap_or(false),
+            strict_binary_arch: self.strict_binary_arch.unwrap_or(false),
+            deny_binary_collisions: self.deny_binary_collisions.unwrap_or(false),
+            rockspec_overlay_dir: self.rockspec_overlay_dir,
+            keep_build_dir_on_failure: self
+                .keep_build_dir_on_failure
+                .unwrap_or_else(|| env::var("LUX_KEEP_BUILD_DIR").is_ok_and(|value| value == "1")),
             entrypoint_layout: self.entrypoint_layout,
             cache_dir,
             data_dir,
             generate_luarc: self.generate_luarc.unwrap_or(true),
+            extra_ca_certs,
+            allow_insecure,
+            insecure_hosts,
+            proxy: self.proxy,
+            no_proxy,
+            auth_tokens,
+            target_platform,
+            http_client,
+            insecure_http_client,
         })
     }
 }
@@ -606,6 +1333,7 @@ impl From<Config> for ConfigBuilder {
         ConfigBuilder {
             enable_development_packages: Some(value.enable_development_packages),
             server: Some(value.server),
+            fallback_servers: Some(value.fallback_servers),
             extra_servers: Some(value.extra_servers),
             only_sources: value.only_sources,
             namespace: value.namespace,
@@ -614,18 +1342,172 @@ impl From<Config> for ConfigBuilder {
             user_tree: Some(value.user_tree),
             verbose: Some(value.verbose),
             no_progress: Some(value.no_progress),
-            timeout: Some(value.timeout),
+            request_timeout: Some(value.request_timeout),
+            download_timeout: Some(value.download_timeout),
             max_jobs: Some(value.max_jobs),
+            max_resolution_depth: Some(value.max_resolution_depth),
             variables: Some(value.variables),
+            test_env: Some(value.test_env),
             cache_dir: Some(value.cache_dir),
             data_dir: Some(value.data_dir),
             external_deps: value.external_deps,
+            extra_cflags: Some(value.extra_cflags),
+            extra_ldflags: Some(value.extra_ldflags),
+            prefer_static_lua: Some(value.prefer_static_lua),
+            system_lua: Some(value.system_lua),
+            strict_binary_arch: Some(value.strict_binary_arch),
+            deny_binary_collisions: Some(value.deny_binary_collisions),
+            rockspec_overlay_dir: value.rockspec_overlay_dir,
+            keep_build_dir_on_failure: Some(value.keep_build_dir_on_failure),
             entrypoint_layout: value.entrypoint_layout,
             generate_luarc: Some(value.generate_luarc),
+            extra_ca_certs: Some(value.extra_ca_certs),
+            allow_insecure: Some(value.allow_insecure),
+            insecure_hosts: Some(value.insecure_hosts),
+            proxy: value.proxy,
+            no_proxy: Some(value.no_proxy),
+            auth_tokens: Some(
+                value
+                    .auth_tokens
+                    .into_iter()
+                    .filter_map(|(host, header)| {
+                        let token = header.to_str().ok()?.strip_prefix("Bearer ")?.to_string();
+                        Some((host, token))
+                    })
+                    .collect(),
+            ),
+            target_platform: value.target_platform.map(|triple| triple.to_string()),
+        }
+    }
+}
+
+/// A top-level entry in the cache directory, e.g. a cached manifest.
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl CacheEntry {
+    fn new(path: PathBuf) -> io::Result<Self> {
+        let mut size = 0;
+        let mut last_used = SystemTime::UNIX_EPOCH;
+        for entry in WalkDir::new(&path) {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+            let modified = metadata.modified()?;
+            let used = metadata
+                .accessed()
+                .map_or(modified, |accessed| accessed.max(modified));
+            last_used = last_used.max(used);
+        }
+        Ok(Self {
+            path,
+            size,
+            last_used,
+        })
+    }
+
+    fn remove(&self) -> io::Result<()> {
+        if self.path.symlink_metadata()?.is_dir() {
+            std::fs::remove_dir_all(&self.path)
+        } else {
+            std::fs::remove_file(&self.path)
         }
     }
 }
 
+/// Whether the URL's host, with or without its port, is one of the `hosts`.
+fn url_host_matches(url: &Url, hosts: &[String]) -> bool {
+    url.host_str().is_some_and(|host| {
+        let host_with_port = url.port().map(|port| format!("{host}:{port}"));
+        hosts
+            .iter()
+            .any(|entry| entry == host || Some(entry) == host_with_port.as_ref())
+    })
+}
+
+/// The proxy set by an environment variable, e.g. `HTTPS_PROXY` or `https_proxy`.
+/// Like reqwest, this defaults to the `http` scheme if the proxy has none.
+fn env_proxy_url(var: &str) -> Option<Url> {
+    let value = env::var(var.to_uppercase())
+        .or_else(|_| env::var(var))
+        .ok()?;
+    Url::parse(&value)
+        .ok()
+        .filter(Url::has_host)
+        .or_else(|| Url::parse(&format!("http://{value}")).ok())
+}
+
+/// Formats a request error so that users can tell a timeout from other failures.
+pub(crate) fn display_request_error(err: &reqwest::Error) -> String {
+    if err.is_timeout() {
+        format!("request timed out: {err}")
+    } else {
+        err.to_string()
+    }
+}
+
+fn mk_http_client_builder(
+    extra_ca_certs: &[PathBuf],
+    allow_insecure: bool,
+    request_timeout: &Duration,
+    proxy: Option<&Url>,
+    no_proxy: &[String],
+) -> Result<reqwest::ClientBuilder, ConfigError> {
+    let builder = reqwest::Client::builder().danger_accept_invalid_certs(allow_insecure);
+    let builder = if request_timeout.is_zero() {
+        builder
+    } else {
+        builder.timeout(*request_timeout)
+    };
+    // Without an explicit proxy, reqwest falls back to the proxy environment variables.
+    let builder = match proxy {
+        Some(url) if !matches!(url.scheme(), "http" | "https") || !url.has_host() => {
+            return Err(ConfigError::UnsupportedProxy(url.clone()))
+        }
+        Some(url) => builder.proxy(
+            reqwest::Proxy::all(url.clone())
+                .map_err(|err| ConfigError::InvalidProxy(url.clone(), err))?
+                .no_proxy(reqwest::NoProxy::from_string(&no_proxy.join(","))),
+        ),
+        None if no_proxy.is_empty() => builder,
+        // reqwest only applies `NO_PROXY` to the proxy environment variables,
+        // so we set up those proxies ourselves to also skip the `no_proxy` hosts.
+        None => {
+            let no_proxy = env::var("NO_PROXY")
+                .or_else(|_| env::var("no_proxy"))
+                .into_iter()
+                .chain(no_proxy.iter().cloned())
+                .join(",");
+            [
+                ("http_proxy", reqwest::Proxy::http::<Url> as fn(_) -> _),
+                ("https_proxy", reqwest::Proxy::https::<Url>),
+                ("all_proxy", reqwest::Proxy::all::<Url>),
+            ]
+            .into_iter()
+            .filter_map(|(var, mk_proxy)| Some((env_proxy_url(var)?, mk_proxy)))
+            .try_fold(builder, |builder, (url, mk_proxy)| {
+                let proxy = mk_proxy(url.clone())
+                    .map_err(|err| ConfigError::InvalidProxy(url, err))?
+                    .no_proxy(reqwest::NoProxy::from_string(&no_proxy));
+                Ok::<_, ConfigError>(builder.proxy(proxy))
+            })?
+        }
+    };
+    extra_ca_certs.iter().try_fold(builder, |builder, path| {
+        let pem =
+            std::fs::read(path).map_err(|err| ConfigError::ReadCaCert(path.to_path_buf(), err))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|err| ConfigError::InvalidCaCert(path.to_path_buf(), err))?;
+        Ok(certs
+            .into_iter()
+            .fold(builder, |builder, cert| builder.add_root_certificate(cert)))
+    })
+}
+
 fn default_variables() -> impl Iterator<Item = (String, String)> {
     let cflags = env::var("CFLAGS").unwrap_or(utils::default_cflags().into());
     let ldflags = env::var("LDFLAGS").unwrap_or("".into());
@@ -707,6 +1589,13 @@ impl UserData for Config {
         methods.add_function("builder", |_, ()| ConfigBuilder::new().into_lua_err());
 
         methods.add_method("server", |_, this, ()| Ok(this.server().to_string()));
+        methods.add_method("fallback_servers", |_, this, ()| {
+            Ok(this
+                .fallback_servers()
+                .iter()
+                .map(|url| url.to_string())
+                .collect_vec())
+        });
         methods.add_method("extra_servers", |_, this, ()| {
             Ok(this
                 .extra_servers()
@@ -722,15 +1611,66 @@ impl UserData for Config {
         methods.add_method("user_tree", |_, this, lua_version: LuaVersion| {
             this.user_tree(lua_version).into_lua_err()
         });
+        methods.add_method(
+            "tree",
+            |_, this, (root, lua_version): (PathBuf, LuaVersion)| {
+                this.tree(root, lua_version).into_lua_err()
+            },
+        );
         methods.add_method("verbose", |_, this, ()| Ok(this.verbose()));
         methods.add_method("no_progress", |_, this, ()| Ok(this.no_progress()));
-        methods.add_method("timeout", |_, this, ()| Ok(this.timeout().as_secs()));
+        methods.add_method("timeout", |_, this, ()| {
+            Ok(this.request_timeout().as_secs())
+        });
+        methods.add_method("request_timeout", |_, this, ()| {
+            Ok(this.request_timeout().as_secs())
+        });
+        methods.add_method("download_timeout", |_, this, ()| {
+            Ok(this.download_timeout().as_secs())
+        });
         methods.add_method("cache_dir", |_, this, ()| Ok(this.cache_dir().clone()));
         methods.add_method("data_dir", |_, this, ()| Ok(this.data_dir().clone()));
         methods.add_method("entrypoint_layout", |_, this, ()| {
             Ok(this.entrypoint_layout().clone())
         });
         methods.add_method("variables", |_, this, ()| Ok(this.variables().clone()));
+        methods.add_method("test_env", |_, this, ()| Ok(this.test_env().clone()));
+        methods.add_method("extra_cflags", |_, this, ()| {
+            Ok(this.extra_cflags().clone())
+        });
+        methods.add_method("extra_ldflags", |_, this, ()| {
+            Ok(this.extra_ldflags().clone())
+        });
+        methods.add_method("prefer_static_lua", |_, this, ()| {
+            Ok(this.prefer_static_lua())
+        });
+        methods.add_method("system_lua", |_, this, ()| Ok(this.system_lua()));
+        methods.add_method("strict_binary_arch", |_, this, ()| {
+            Ok(this.strict_binary_arch())
+        });
+        methods.add_method("deny_binary_collisions", |_, this, ()| {
+            Ok(this.deny_binary_collisions())
+        });
+        methods.add_method("rockspec_overlay_dir", |_, this, ()| {
+            Ok(this.rockspec_overlay_dir().cloned())
+        });
+        methods.add_method("keep_build_dir_on_failure", |_, this, ()| {
+            Ok(this.keep_build_dir_on_failure())
+        });
+        methods.add_method("extra_ca_certs", |_, this, ()| {
+            Ok(this.extra_ca_certs().clone())
+        });
+        methods.add_method("allow_insecure", |_, this, ()| Ok(this.allow_insecure()));
+        methods.add_method("insecure_hosts", |_, this, ()| {
+            Ok(this.insecure_hosts().clone())
+        });
+        methods.add_method("proxy", |_, this, ()| {
+            Ok(this.proxy().map(|url| url.to_string()))
+        });
+        methods.add_method("no_proxy", |_, this, ()| Ok(this.no_proxy().clone()));
+        methods.add_method("target_platform", |_, this, ()| {
+            Ok(this.target_platform().map(|triple| triple.to_string()))
+        });
         // FIXME: This is a temporary workaround to get the external_deps hooked up to Lua
         // methods.add_method("external_deps", |_, this, ()| {
         //     Ok(this.external_deps().clone())
@@ -756,6 +1696,14 @@ impl UserData for ConfigBuilder {
         methods.add_method("server", |_, this, server: Option<LuaUrl>| {
             Ok(this.clone().server(server.map(|url| url.0)))
         });
+        methods.add_method(
+            "fallback_servers",
+            |_, this, servers: Option<Vec<LuaUrl>>| {
+                Ok(this.clone().fallback_servers(
+                    servers.map(|urls| urls.into_iter().map(|url| url.0).collect()),
+                ))
+            },
+        );
         methods.add_method("extra_servers", |_, this, servers: Option<Vec<LuaUrl>>| {
             Ok(this
                 .clone()
@@ -783,7 +1731,19 @@ impl UserData for ConfigBuilder {
             Ok(this.clone().no_progress(no_progress))
         });
         methods.add_method("timeout", |_, this, timeout: Option<u64>| {
-            Ok(this.clone().timeout(timeout.map(Duration::from_secs)))
+            Ok(this
+                .clone()
+                .request_timeout(timeout.map(Duration::from_secs)))
+        });
+        methods.add_method("request_timeout", |_, this, timeout: Option<u64>| {
+            Ok(this
+                .clone()
+                .request_timeout(timeout.map(Duration::from_secs)))
+        });
+        methods.add_method("download_timeout", |_, this, timeout: Option<u64>| {
+            Ok(this
+                .clone()
+                .download_timeout(timeout.map(Duration::from_secs)))
         });
         methods.add_method("cache_dir", |_, this, cache_dir: Option<PathBuf>| {
             Ok(this.clone().cache_dir(cache_dir))
@@ -802,6 +1762,466 @@ impl UserData for ConfigBuilder {
         methods.add_method("generate_luarc", |_, this, generate: Option<bool>| {
             Ok(this.clone().generate_luarc(generate))
         });
+        methods.add_method(
+            "rockspec_overlay_dir",
+            |_, this, rockspec_overlay_dir: Option<PathBuf>| {
+                Ok(this.clone().rockspec_overlay_dir(rockspec_overlay_dir))
+            },
+        );
+        methods.add_method(
+            "extra_ca_certs",
+            |_, this, extra_ca_certs: Option<Vec<PathBuf>>| {
+                Ok(this.clone().extra_ca_certs(extra_ca_certs))
+            },
+        );
+        methods.add_method("allow_insecure", |_, this, allow_insecure: Option<bool>| {
+            Ok(this.clone().allow_insecure(allow_insecure))
+        });
+        methods.add_method(
+            "insecure_hosts",
+            |_, this, insecure_hosts: Option<Vec<String>>| {
+                Ok(this.clone().insecure_hosts(insecure_hosts))
+            },
+        );
+        methods.add_method("proxy", |_, this, proxy: Option<LuaUrl>| {
+            Ok(this.clone().proxy(proxy.map(|url| url.0)))
+        });
+        methods.add_method("no_proxy", |_, this, no_proxy: Option<Vec<String>>| {
+            Ok(this.clone().no_proxy(no_proxy))
+        });
+        methods.add_method("target_platform", |_, this, target: Option<String>| {
+            let target = target
+                .map(|target| {
+                    Triple::from_str(&target).map_err(|err| {
+                        mlua::Error::RuntimeError(format!(
+                            "invalid target platform {target}: {err}"
+                        ))
+                    })
+                })
+                .transpose()?;
+            Ok(this.clone().target_platform(target))
+        });
+        methods.add_method("extra_cflags", |_, this, flags: Option<Vec<String>>| {
+            Ok(this.clone().extra_cflags(flags))
+        });
+        methods.add_method("extra_ldflags", |_, this, flags: Option<Vec<String>>| {
+            Ok(this.clone().extra_ldflags(flags))
+        });
+        methods.add_method("prefer_static_lua", |_, this, prefer: Option<bool>| {
+            Ok(this.clone().prefer_static_lua(prefer))
+        });
         methods.add_method("build", |_, this, ()| this.clone().build().into_lua_err());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    fn prune_cache() {
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::default()
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .build()
+            .unwrap();
+        let now = SystemTime::now();
+        let write_entry = |name: &str, size: usize, age: Duration| {
+            let path = cache_dir.join(name);
+            std::fs::write(&path, vec![0u8; size]).unwrap();
+            let time = now - age;
+            let times = std::fs::FileTimes::new()
+                .set_accessed(time)
+                .set_modified(time);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_times(times)
+                .unwrap();
+            path
+        };
+        let day = Duration::from_secs(60 * 60 * 24);
+        let stale = write_entry("stale", 10, day * 30);
+        let old = write_entry("old", 20, day * 2);
+        let recent = write_entry("recent", 30, day);
+        let fresh = write_entry("fresh", 40, Duration::ZERO);
+
+        assert_eq!(config.prune_cache(day * 7).unwrap(), 10);
+        assert!(!stale.exists());
+        assert!(old.exists());
+
+        assert_eq!(
+            config
+                .prune_cache_with_size_limit(day * 7, Some(75))
+                .unwrap(),
+            20
+        );
+        assert!(!old.exists());
+        assert!(recent.exists());
+        assert!(fresh.exists());
+        assert_eq!(config.prune_cache(day * 7).unwrap(), 0);
+    }
+
+    #[test]
+    fn unreadable_ca_cert() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let cert = temp.join("missing.pem");
+        let result = ConfigBuilder::default()
+            .extra_ca_certs(Some(vec![cert.clone()]))
+            .build();
+        assert!(matches!(result, Err(ConfigError::ReadCaCert(path, _)) if path == cert));
+    }
+
+    #[test]
+    fn apply_config_file() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config_file = temp.join("config.toml");
+        std::fs::write(
+            &config_file,
+            r#"
+server = "https://example.com/"
+namespace = "from-file"
+lua_version = "5.2"
+generate_luarc = false
+"#,
+        )
+        .unwrap();
+        let config = ConfigBuilder::default()
+            .namespace(Some("from-cli".into()))
+            .apply_config_file(&config_file)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(config.server().as_str(), "https://example.com/");
+        assert_eq!(config.namespace(), Some(&"from-cli".to_string()));
+        assert_eq!(config.lua_version(), Some(&LuaVersion::Lua52));
+        assert!(!config.generate_luarc());
+
+        std::fs::write(
+            &config_file,
+            r#"
+[external_deps]
+lib_subdirs = ["lib64"]
+
+[external_deps.prefixes]
+FOO = "/from/file"
+BAR = "/from/file"
+"#,
+        )
+        .unwrap();
+        let mut external_deps = ExternalDependencySearchConfig::default();
+        external_deps
+            .prefixes
+            .insert("FOO".into(), "/from/cli".into());
+        let config = ConfigBuilder {
+            external_deps,
+            ..ConfigBuilder::default()
+        }
+        .apply_config_file(&config_file)
+        .unwrap()
+        .build()
+        .unwrap();
+        let external_deps = config.external_deps();
+        assert_eq!(external_deps.lib_subdirs, vec![PathBuf::from("lib64")]);
+        assert_eq!(external_deps.prefixes["FOO"], PathBuf::from("/from/cli"));
+        assert_eq!(external_deps.prefixes["BAR"], PathBuf::from("/from/file"));
+
+        let result = ConfigBuilder::default().apply_config_file(&temp.join("missing.toml"));
+        assert!(matches!(result, Err(ConfigError::ReadConfigFile(..))));
+    }
+
+    #[tokio::test]
+    async fn proxy() {
+        use httptest::{
+            matchers::{all_of, contains, request},
+            responders::status_code,
+            Expectation, Server,
+        };
+
+        let proxy = Server::run();
+        proxy.expect(
+            Expectation::matching(all_of![
+                request::path("/manifest"),
+                request::headers(contains(("host", "lux.invalid"))),
+            ])
+            .respond_with(status_code(200)),
+        );
+        let config = ConfigBuilder::default()
+            .proxy(Some(proxy.url_str("/").parse().unwrap()))
+            .build()
+            .unwrap();
+        assert_eq!(config.proxy().map(Url::to_string), Some(proxy.url_str("/")));
+        config
+            .http_client()
+            .get("http://lux.invalid/manifest")
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::path("/manifest")).respond_with(status_code(200)),
+        );
+        let config = ConfigBuilder::default()
+            .proxy(Some("http://127.0.0.1:1".parse().unwrap()))
+            .no_proxy(Some(vec![server.addr().ip().to_string()]))
+            .build()
+            .unwrap();
+        config
+            .http_client()
+            .get(server.url_str("/manifest"))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn no_proxy_applies_to_env_proxy() {
+        use httptest::{matchers::request, responders::status_code, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::path("/manifest"))
+                .times(1)
+                .respond_with(status_code(200)),
+        );
+        std::env::set_var("HTTP_PROXY", "http://127.0.0.1:1");
+        let proxied_config = ConfigBuilder::default().build().unwrap();
+        let config = ConfigBuilder::default()
+            .no_proxy(Some(vec![server.addr().ip().to_string()]))
+            .build()
+            .unwrap();
+        std::env::remove_var("HTTP_PROXY");
+
+        assert!(proxied_config
+            .http_client()
+            .get(server.url_str("/manifest"))
+            .send()
+            .await
+            .is_err());
+        config
+            .http_client()
+            .get(server.url_str("/manifest"))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn insecure_hosts() {
+        use httptest::{matchers::request, responders::status_code, Expectation, Server};
+
+        let mirror = Server::run();
+        let other = Server::run();
+        mirror.expect(
+            Expectation::matching(request::path("/manifest")).respond_with(status_code(200)),
+        );
+        mirror.expect(
+            Expectation::matching(request::path("/redirect")).respond_with(
+                status_code(302).insert_header("Location", other.url_str("/manifest").as_str()),
+            ),
+        );
+        let config = ConfigBuilder::default()
+            .insecure_hosts(Some(vec![mirror.addr().to_string()]))
+            .build()
+            .unwrap();
+        let mirror_url: Url = mirror.url_str("/manifest").parse().unwrap();
+        let other_url: Url = other.url_str("/manifest").parse().unwrap();
+        assert!(std::ptr::eq(
+            config.http_client_for(&mirror_url),
+            config.insecure_http_client.as_ref().unwrap()
+        ));
+        assert!(std::ptr::eq(
+            config.http_client_for(&other_url),
+            config.http_client()
+        ));
+        config
+            .request(reqwest::Method::GET, mirror_url)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+        // Redirects to other hosts must not skip TLS verification
+        assert!(config
+            .request(
+                reqwest::Method::GET,
+                mirror.url_str("/redirect").parse().unwrap()
+            )
+            .send()
+            .await
+            .is_err());
+
+        let config = ConfigBuilder::default()
+            .insecure_hosts(Some(vec![mirror.addr().ip().to_string()]))
+            .allow_insecure(Some(true))
+            .build()
+            .unwrap();
+        assert!(config.insecure_http_client.is_none());
+    }
+
+    #[tokio::test]
+    async fn auth_tokens() {
+        use httptest::{
+            matchers::{all_of, contains, key, not, request},
+            responders::status_code,
+            Expectation, Server,
+        };
+
+        let registry = Server::run();
+        registry.expect(
+            Expectation::matching(all_of![
+                request::path("/foo-1.0.0-1.src.rock"),
+                request::headers(contains(("authorization", "Bearer s3cr3t"))),
+            ])
+            .respond_with(status_code(200)),
+        );
+        let other = Server::run();
+        other.expect(
+            Expectation::matching(all_of![
+                request::path("/foo-1.0.0-1.src.rock"),
+                request::headers(not(contains(key("authorization")))),
+            ])
+            .respond_with(status_code(200)),
+        );
+        let config = ConfigBuilder::default()
+            .auth_tokens(Some(HashMap::from([(
+                registry.addr().to_string(),
+                "s3cr3t".into(),
+            )])))
+            .build()
+            .unwrap();
+        assert!(!format!("{config:?}").contains("s3cr3t"));
+        for server in [&registry, &other] {
+            config
+                .download(server.url_str("/foo-1.0.0-1.src.rock").parse().unwrap())
+                .send()
+                .await
+                .unwrap()
+                .error_for_status()
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn select_server_falls_back_if_primary_is_down() {
+        use httptest::{
+            matchers::{all_of, request},
+            responders::status_code,
+            Expectation, Server,
+        };
+
+        let primary: Url = "http://127.0.0.1:1/".parse().unwrap();
+        let secondary = Server::run();
+        secondary.expect(
+            Expectation::matching(all_of![
+                request::method("HEAD"),
+                request::path("/api/tool_version"),
+            ])
+            .times(1)
+            .respond_with(status_code(200)),
+        );
+        let secondary_url: Url = secondary.url_str("/").parse().unwrap();
+        let config = ConfigBuilder::default()
+            .server(Some(primary.clone()))
+            .fallback_servers(Some(vec![secondary_url.clone()]))
+            .build()
+            .unwrap();
+        assert_eq!(config.server(), &primary);
+        assert_eq!(config.select_server().await, &secondary_url);
+        assert_eq!(config.server(), &secondary_url);
+        // The choice is cached, including for clones of the config
+        assert_eq!(config.clone().select_server().await, &secondary_url);
+    }
+
+    #[tokio::test]
+    async fn select_server_without_fallback_servers() {
+        let primary: Url = "http://127.0.0.1:1/".parse().unwrap();
+        let config = ConfigBuilder::default()
+            .server(Some(primary.clone()))
+            .build()
+            .unwrap();
+        assert_eq!(config.select_server().await, &primary);
+    }
+
+    #[test]
+    fn invalid_proxy() {
+        let proxy: Url = "file:///tmp/proxy".parse().unwrap();
+        let result = ConfigBuilder::default().proxy(Some(proxy.clone())).build();
+        assert!(matches!(result, Err(ConfigError::UnsupportedProxy(url)) if url == proxy));
+    }
+
+    #[test]
+    fn extra_build_flags() {
+        let config: ConfigBuilder = toml::from_str(
+            r#"
+extra_cflags = ["-I/opt/foo/include"]
+extra_ldflags = ["-L/opt/foo/lib", "-lfoo"]
+"#,
+        )
+        .unwrap();
+        let config = config.build().unwrap();
+        assert_eq!(
+            config.extra_cflags(),
+            &vec!["-I/opt/foo/include".to_string()]
+        );
+        assert_eq!(
+            config.extra_ldflags(),
+            &vec!["-L/opt/foo/lib".to_string(), "-lfoo".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_lua_version() {
+        for (input, expected) in [
+            ("5.1", LuaVersion::Lua51),
+            ("51", LuaVersion::Lua51),
+            ("lua5.1", LuaVersion::Lua51),
+            ("lua51", LuaVersion::Lua51),
+            ("Lua 5.1", LuaVersion::Lua51),
+            ("5.2", LuaVersion::Lua52),
+            ("lua5.2", LuaVersion::Lua52),
+            ("5.3", LuaVersion::Lua53),
+            ("lua-5.3", LuaVersion::Lua53),
+            ("5.4", LuaVersion::Lua54),
+            ("54", LuaVersion::Lua54),
+            ("lua5.4", LuaVersion::Lua54),
+            ("jit", LuaVersion::LuaJIT),
+            ("luajit", LuaVersion::LuaJIT),
+            ("LuaJIT", LuaVersion::LuaJIT),
+            ("jit52", LuaVersion::LuaJIT52),
+            ("jit5.2", LuaVersion::LuaJIT52),
+            ("luajit52", LuaVersion::LuaJIT52),
+        ] {
+            assert_eq!(input.parse::<LuaVersion>().unwrap(), expected, "{input}");
+        }
+        for version in [
+            LuaVersion::Lua51,
+            LuaVersion::Lua52,
+            LuaVersion::Lua53,
+            LuaVersion::Lua54,
+            LuaVersion::LuaJIT,
+            LuaVersion::LuaJIT52,
+        ] {
+            assert_eq!(version.to_string().parse::<LuaVersion>().unwrap(), version);
+        }
+        let err = "5.5".parse::<LuaVersion>().unwrap_err();
+        assert!(err.to_string().contains("'5.5'"));
+        assert!("lua".parse::<LuaVersion>().is_err());
+
+        let version: LuaVersion = serde_json::from_str("\"jit5.2\"").unwrap();
+        assert_eq!(version, LuaVersion::LuaJIT52);
+        let version: LuaVersion = serde_json::from_str("\"lua5.4\"").unwrap();
+        assert_eq!(version, LuaVersion::Lua54);
+        assert!(serde_json::from_str::<LuaVersion>("\"5.0\"").is_err());
+    }
+}
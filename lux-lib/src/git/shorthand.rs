@@ -47,6 +47,15 @@ impl FromStr for GitUrlShorthand {
     }
 }
 
+/// Whether `s` has a git host prefix (e.g. "github:") or looks like a git URL,
+/// as opposed to a package requirement.
+pub(crate) fn has_git_host_prefix(s: &str) -> bool {
+    [GITHUB, GITLAB, SOURCEHUT, CODEBERG].iter().any(|host| {
+        s.strip_prefix(host)
+            .is_some_and(|rest| rest.starts_with(':'))
+    })
+}
+
 impl<'de> Deserialize<'de> for GitUrlShorthand {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
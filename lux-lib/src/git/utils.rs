@@ -71,7 +71,8 @@ fn latest_semver_tag(url: &GitUrl) -> Result<Option<String>, GitError> {
         .cloned())
 }
 
-fn latest_commit_sha(url: &GitUrl) -> Result<Option<String>, GitError> {
+/// Resolve the commit SHA that the remote's default branch points to.
+pub(crate) fn latest_commit_sha(url: &GitUrl) -> Result<Option<String>, GitError> {
     let temp_dir = TempDir::new("lux-git-meta").map_err(GitError::CreateTempDir)?;
     let url_str = url.to_string();
     let repo = Repository::init_bare(&temp_dir).map_err(GitError::BareRepoInit)?;
@@ -1,20 +1,27 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fmt::Display;
 use std::io::{self, Write};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::{collections::HashMap, fs::File, io::ErrorKind, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
+use git_url_parse::{GitUrl, GitUrlParseError};
 use itertools::Itertools;
 use mlua::{ExternalResult, FromLua, IntoLua, UserData};
-use serde::{de, Deserialize, Serialize, Serializer};
+use serde::{de, ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use sha2::{Digest, Sha256};
 use ssri::Integrity;
 use thiserror::Error;
 use url::Url;
 
 use crate::config::tree::RockLayoutConfig;
+use crate::config::LuaVersion;
 use crate::package::{
     PackageName, PackageReq, PackageSpec, PackageVersion, PackageVersionReq,
     PackageVersionReqError, RemotePackageTypeFilterSpec,
@@ -24,6 +31,7 @@ use crate::rockspec::lua_dependency::LuaDependencySpec;
 use crate::rockspec::RockBinaries;
 
 const LOCKFILE_VERSION_STR: &str = "1.0.0";
+const LOCKFILE_GENERATOR: &str = concat!("lux ", env!("CARGO_PKG_VERSION"));
 
 #[derive(Copy, Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
 pub enum PinnedState {
@@ -203,6 +211,46 @@ impl LocalPackageId {
         pinned: PinnedState,
         opt: OptState,
         constraint: LockConstraint,
+    ) -> Self {
+        Self::hash(
+            &name.normalized(),
+            version,
+            pinned,
+            opt,
+            match constraint {
+                LockConstraint::Unconstrained => String::default(),
+                LockConstraint::Constrained(version_req) => version_req.canonical().to_string(),
+            },
+        )
+    }
+
+    /// The id computed by older versions of lux,
+    /// which hashed names and constraints verbatim.
+    fn legacy(
+        name: &PackageName,
+        version: &PackageVersion,
+        pinned: PinnedState,
+        opt: OptState,
+        constraint: LockConstraint,
+    ) -> Self {
+        Self::hash(
+            &name.to_string(),
+            version,
+            pinned,
+            opt,
+            match constraint {
+                LockConstraint::Unconstrained => String::default(),
+                LockConstraint::Constrained(version_req) => version_req.to_string(),
+            },
+        )
+    }
+
+    fn hash(
+        name: &str,
+        version: &PackageVersion,
+        pinned: PinnedState,
+        opt: OptState,
+        constraint: String,
     ) -> Self {
         let mut hasher = Sha256::new();
 
@@ -212,10 +260,7 @@ impl LocalPackageId {
             version,
             pinned.as_bool(),
             opt.as_bool(),
-            match constraint {
-                LockConstraint::Unconstrained => String::default(),
-                LockConstraint::Constrained(version_req) => version_req.to_string(),
-            },
+            constraint,
         ));
 
         Self(hex::encode(hasher.finalize()))
@@ -233,6 +278,11 @@ impl LocalPackageId {
     }
 }
 
+/// The name of a rock's install directory in a tree.
+pub(crate) fn rock_dir_name(id: &LocalPackageId, package: &LocalPackage) -> String {
+    format!("{}-{}@{}", id, package.name(), package.version())
+}
+
 impl Display for LocalPackageId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.fmt(f)
@@ -282,6 +332,16 @@ impl LocalPackageSpec {
         )
     }
 
+    fn legacy_id(&self) -> LocalPackageId {
+        LocalPackageId::legacy(
+            self.name(),
+            self.version(),
+            self.pinned,
+            self.opt,
+            self.constraint(),
+        )
+    }
+
     pub fn constraint(&self) -> LockConstraint {
         // Safe to unwrap as the data can only end up in the struct as a valid constraint
         LockConstraint::try_from(&self.constraint).unwrap()
@@ -355,6 +415,112 @@ impl RemotePackageSourceUrl {
             }
         }
     }
+
+    /// If the source is the full URL of a packed rock, this returns that URL.
+    /// Older lockfiles may record the server URL instead.
+    pub(crate) fn packed_rock_url(&self) -> Option<&Url> {
+        match self {
+            RemotePackageSourceUrl::Url { url } if url.path().ends_with(".rock") => Some(url),
+            _ => None,
+        }
+    }
+
+    /// Check that the source can be fetched, without fetching it:
+    /// Git URLs must be parseable, URLs must be downloadable
+    /// and local source files must exist.
+    pub(crate) fn validate(&self) -> Result<(), SourceUrlError> {
+        match self {
+            RemotePackageSourceUrl::Git { url, .. } => {
+                url.parse::<GitUrl>()
+                    .map_err(|err| SourceUrlError::InvalidGitUrl {
+                        url: url.clone(),
+                        err,
+                    })?;
+            }
+            RemotePackageSourceUrl::Url { url } => {
+                if !matches!(url.scheme(), "http" | "https") {
+                    return Err(SourceUrlError::UnsupportedScheme(url.clone()));
+                }
+            }
+            RemotePackageSourceUrl::File { path } => {
+                if !path.exists() {
+                    return Err(SourceUrlError::FileNotFound(path.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SourceUrlError {
+    #[error("invalid git URL {url}: {err}")]
+    InvalidGitUrl { url: String, err: GitUrlParseError },
+    #[error("unsupported URL scheme: {0}")]
+    UnsupportedScheme(Url),
+    #[error("source file {0} does not exist")]
+    FileNotFound(PathBuf),
+}
+
+/// The invalid source URLs of the packages in a lockfile.
+#[derive(Debug)]
+pub struct InvalidSourceUrls(Vec<(PackageSpec, SourceUrlError)>);
+
+impl InvalidSourceUrls {
+    pub fn iter(&self) -> impl Iterator<Item = &(PackageSpec, SourceUrlError)> {
+        self.0.iter()
+    }
+}
+
+impl Display for InvalidSourceUrls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0
+            .iter()
+            .map(|(package, err)| format!("{package}: {err}"))
+            .join("\n")
+            .fmt(f)
+    }
+}
+
+fn validate_source_urls(
+    invalid_source_urls: impl Iterator<Item = (PackageSpec, SourceUrlError)>,
+) -> Result<(), LockfileError> {
+    let invalid_source_urls = invalid_source_urls.collect_vec();
+    if invalid_source_urls.is_empty() {
+        Ok(())
+    } else {
+        Err(LockfileError::InvalidSourceUrls(InvalidSourceUrls(
+            invalid_source_urls,
+        )))
+    }
+}
+
+/// The kind of source a [`LocalPackage`] was installed from.
+#[derive(Copy, Debug, PartialEq, Eq, Hash, Clone)]
+#[non_exhaustive]
+pub enum SourceKind {
+    /// A rockspec from a luarocks server.
+    Rockspec,
+    /// A source rock from a luarocks server.
+    SrcRock,
+    /// A packed binary rock from a luarocks server.
+    BinaryRock,
+    /// A rockspec that is not hosted on a luarocks server, e.g. a git dependency.
+    RockspecContent,
+    /// A local rock or project.
+    Local,
+}
+
+impl Display for SourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            SourceKind::Rockspec => "rockspec".fmt(f),
+            SourceKind::SrcRock => "src_rock".fmt(f),
+            SourceKind::BinaryRock => "binary_rock".fmt(f),
+            SourceKind::RockspecContent => "rockspec_content".fmt(f),
+            SourceKind::Local => "local".fmt(f),
+        }
+    }
 }
 
 // TODO(vhyrro): Move to `package/local.rs`
@@ -364,6 +530,9 @@ pub struct LocalPackage {
     pub(crate) source: RemotePackageSource,
     pub(crate) source_url: Option<RemotePackageSourceUrl>,
     hashes: LocalPackageHashes,
+    /// The Lua version this package's native libraries were built against.
+    /// `None` for pure Lua packages.
+    pub(crate) lua_version: Option<LuaVersion>,
 }
 
 impl UserData for LocalPackage {
@@ -379,6 +548,10 @@ impl UserData for LocalPackage {
             Ok(this.spec.constraint.clone())
         });
         methods.add_method("hashes", |_, this, _: ()| Ok(this.hashes.clone()));
+        methods.add_method("source_kind", |_, this, _: ()| {
+            Ok(this.source_kind().to_string())
+        });
+        methods.add_method("is_editable", |_, this, _: ()| Ok(this.is_editable()));
         methods.add_method("to_package", |_, this, _: ()| Ok(this.to_package()));
         methods.add_method("to_package_req", |_, this, _: ()| {
             Ok(this.clone().into_package_req())
@@ -396,6 +569,27 @@ impl LocalPackage {
     }
 }
 
+#[cfg(test)]
+impl LocalPackage {
+    /// An unconstrained package from luarocks.org with mock hashes, for use in tests.
+    pub(crate) fn mock(name: &str, version: &str) -> Self {
+        let mock_hash: Integrity = "sha256-uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+            .parse()
+            .unwrap();
+        Self::from(
+            &PackageSpec::parse(name.into(), version.into()).unwrap(),
+            LockConstraint::Unconstrained,
+            RockBinaries::default(),
+            RemotePackageSource::LuarocksRockspec("https://luarocks.org".parse().unwrap()),
+            None,
+            LocalPackageHashes {
+                rockspec: mock_hash.clone(),
+                source: mock_hash,
+            },
+        )
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct LocalPackageIntermediate {
     name: PackageName,
@@ -408,6 +602,8 @@ struct LocalPackageIntermediate {
     source: RemotePackageSource,
     source_url: Option<RemotePackageSourceUrl>,
     hashes: LocalPackageHashes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lua_version: Option<LuaVersion>,
 }
 
 impl TryFrom<LocalPackageIntermediate> for LocalPackage {
@@ -428,6 +624,7 @@ impl TryFrom<LocalPackageIntermediate> for LocalPackage {
             source: value.source,
             source_url: value.source_url,
             hashes: value.hashes,
+            lua_version: value.lua_version,
         })
     }
 }
@@ -439,12 +636,14 @@ impl From<&LocalPackage> for LocalPackageIntermediate {
             version: value.spec.version.clone(),
             pinned: value.spec.pinned,
             opt: value.spec.opt,
-            dependencies: value.spec.dependencies.clone(),
+            // Sorted, so that the output does not depend on the installation order.
+            dependencies: value.spec.dependencies.iter().sorted().cloned().collect(),
             constraint: value.spec.constraint.clone(),
             binaries: value.spec.binaries.clone(),
             source: value.source.clone(),
             source_url: value.source_url.clone(),
             hashes: value.hashes.clone(),
+            lua_version: value.lua_version.clone(),
         }
     }
 }
@@ -490,6 +689,7 @@ impl LocalPackage {
             source,
             source_url,
             hashes,
+            lua_version: None,
         }
     }
 
@@ -509,6 +709,24 @@ impl LocalPackage {
         self.spec.pinned()
     }
 
+    /// The full URL this package's source archive or packed rock was downloaded from.
+    /// `None` for git, local and file sources.
+    pub fn download_url(&self) -> Option<&Url> {
+        match &self.source_url {
+            Some(RemotePackageSourceUrl::Url { url }) => Some(url),
+            _ => None,
+        }
+    }
+
+    /// The local file this package was installed from, e.g. a `.rockspec` file.
+    /// `None` for remote sources.
+    pub fn source_path(&self) -> Option<&Path> {
+        match &self.source_url {
+            Some(RemotePackageSourceUrl::File { path }) => Some(path),
+            _ => None,
+        }
+    }
+
     pub fn opt(&self) -> OptState {
         self.spec.opt()
     }
@@ -517,6 +735,39 @@ impl LocalPackage {
         &self.source
     }
 
+    /// The Lua version this package's native libraries were built against.
+    /// `None` for pure Lua packages.
+    pub fn lua_version(&self) -> Option<&LuaVersion> {
+        self.lua_version.as_ref()
+    }
+
+    /// Whether this package contains native libraries that were built against
+    /// a different Lua ABI than `lua_version`, and must therefore be rebuilt.
+    pub fn requires_rebuild(&self, lua_version: &LuaVersion) -> bool {
+        self.lua_version
+            .as_ref()
+            .is_some_and(|built_for| built_for != lua_version)
+    }
+
+    /// Whether this package was installed in editable mode,
+    /// i.e. its Lua sources are symlinked to a local working copy.
+    pub fn is_editable(&self) -> bool {
+        matches!(self.source, RemotePackageSource::LocalPath(_))
+    }
+
+    /// The kind of source this package was installed from.
+    pub fn source_kind(&self) -> SourceKind {
+        match self.source {
+            RemotePackageSource::LuarocksRockspec(_) => SourceKind::Rockspec,
+            RemotePackageSource::LuarocksSrcRock(_) => SourceKind::SrcRock,
+            RemotePackageSource::LuarocksBinaryRock(_) => SourceKind::BinaryRock,
+            RemotePackageSource::RockspecContent(_) => SourceKind::RockspecContent,
+            RemotePackageSource::Local | RemotePackageSource::LocalPath(_) => SourceKind::Local,
+            #[cfg(test)]
+            RemotePackageSource::Test => SourceKind::Local,
+        }
+    }
+
     pub fn dependencies(&self) -> Vec<&LocalPackageId> {
         self.spec.dependencies()
     }
@@ -605,10 +856,18 @@ impl LockConstraint {
         }
     }
 
-    fn matches_version_req(&self, req: &PackageVersionReq) -> bool {
+    /// Whether this is the constraint a package with `version` was installed with for `req`.
+    fn matches_version_req(&self, req: &PackageVersionReq, version: &PackageVersion) -> bool {
         match self {
             LockConstraint::Unconstrained => req.is_any(),
-            LockConstraint::Constrained(package_version_req) => package_version_req == req,
+            LockConstraint::Constrained(package_version_req) if package_version_req == req => true,
+            // Exact constraints that pin a specrev used to be locked without the specrev.
+            LockConstraint::Constrained(package_version_req) => match req {
+                PackageVersionReq::Exact(req_version) => {
+                    req.matches(version) && &req_version.into_version_req() == package_version_req
+                }
+                _ => false,
+            },
         }
     }
 }
@@ -652,16 +911,26 @@ pub struct ReadWrite;
 impl LockfilePermissions for ReadOnly {}
 impl LockfilePermissions for ReadWrite {}
 
-#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Default)]
 pub(crate) struct LocalPackageLock {
     // NOTE: We cannot directly serialize to a `Sha256` object as they don't implement serde traits.
     // NOTE: We want to retain ordering of rocks and entrypoints when de/serializing.
     rocks: BTreeMap<LocalPackageId, LocalPackage>,
-
-    #[serde(serialize_with = "serialize_sorted_package_ids")]
     entrypoints: Vec<LocalPackageId>,
 }
 
+impl Serialize for LocalPackageLock {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut lock = serializer.serialize_struct("LocalPackageLock", 2)?;
+        lock.serialize_field("rocks", &self.rocks)?;
+        lock.serialize_field("entrypoints", &self.sorted_entrypoints())?;
+        lock.end()
+    }
+}
+
 impl LocalPackageLock {
     fn get(&self, id: &LocalPackageId) -> Option<&LocalPackage> {
         self.rocks.get(id)
@@ -679,6 +948,115 @@ impl LocalPackageLock {
         self.entrypoints.contains(package)
     }
 
+    /// Re-keys rocks whose ids were computed by an older version of lux,
+    /// e.g. before version constraints were canonicalized.
+    /// Returns the old ids of the migrated rocks.
+    fn migrate_ids(&mut self) -> Vec<(LocalPackageId, LocalPackageId)> {
+        let migrated: HashMap<LocalPackageId, LocalPackageId> = self
+            .rocks
+            .iter()
+            .filter(|(id, rock)| **id == rock.spec.legacy_id())
+            .map(|(id, rock)| (id.clone(), rock.id()))
+            .filter(|(id, new_id)| id != new_id)
+            .collect();
+        if migrated.is_empty() {
+            return Vec::new();
+        }
+        let migrate_id = |id: &mut LocalPackageId| {
+            if let Some(new_id) = migrated.get(id) {
+                *id = new_id.clone();
+            }
+        };
+        self.rocks = std::mem::take(&mut self.rocks)
+            .into_iter()
+            .map(|(id, mut rock)| {
+                rock.spec.dependencies.iter_mut().for_each(migrate_id);
+                (migrated.get(&id).cloned().unwrap_or(id), rock)
+            })
+            .collect();
+        self.entrypoints.iter_mut().for_each(migrate_id);
+        migrated.into_iter().collect_vec()
+    }
+
+    fn invalid_source_urls(&self) -> impl Iterator<Item = (PackageSpec, SourceUrlError)> + '_ {
+        self.rocks.values().filter_map(|package| {
+            let err = package.source_url.as_ref()?.validate().err()?;
+            Some((package.to_package(), err))
+        })
+    }
+
+    /// The entrypoints, sorted by name and version, so that the order
+    /// doesn't depend on the order in which they were installed.
+    fn sorted_entrypoints(&self) -> Vec<LocalPackageId> {
+        self.entrypoints
+            .iter()
+            .sorted_by_key(|id| {
+                (
+                    self.rocks
+                        .get(*id)
+                        .map(|rock| (rock.name(), rock.version())),
+                    *id,
+                )
+            })
+            .cloned()
+            .collect_vec()
+    }
+
+    fn reorder(&mut self) {
+        self.entrypoints = self.sorted_entrypoints();
+    }
+
+    fn entrypoints(&self) -> Vec<&LocalPackage> {
+        self.sorted_entrypoints()
+            .iter()
+            .filter_map(|id| self.get(id))
+            .collect_vec()
+    }
+
+    /// Compare the versions of each package in this lock with those in `other`.
+    /// If exactly one version of a package was replaced with another,
+    /// it is reported as changed. Otherwise, the versions are reported as added or removed.
+    fn diff(&self, other: &LocalPackageLock) -> LockfileDiff {
+        let versions_by_name = |lock: &LocalPackageLock| {
+            lock.rocks
+                .values()
+                .map(|rock| (rock.name().clone(), rock.version().clone()))
+                .into_group_map()
+        };
+        let old_versions = versions_by_name(self);
+        let new_versions = versions_by_name(other);
+        let mut diff = LockfileDiff::default();
+        for name in old_versions
+            .keys()
+            .chain(new_versions.keys())
+            .unique()
+            .sorted()
+        {
+            let old = old_versions.get(name).cloned().unwrap_or_default();
+            let new = new_versions.get(name).cloned().unwrap_or_default();
+            let removed = old
+                .iter()
+                .filter(|version| !new.contains(version))
+                .sorted()
+                .map(|version| PackageSpec::new(name.clone(), version.clone()))
+                .collect_vec();
+            let added = new
+                .iter()
+                .filter(|version| !old.contains(version))
+                .sorted()
+                .map(|version| PackageSpec::new(name.clone(), version.clone()))
+                .collect_vec();
+            match (removed.as_slice(), added.as_slice()) {
+                ([old], [new]) => diff.changed.push((old.clone(), new.clone())),
+                _ => {
+                    diff.removed.extend(removed);
+                    diff.added.extend(added);
+                }
+            }
+        }
+        diff
+    }
+
     fn is_dependency(&self, package: &LocalPackageId) -> bool {
         self.rocks
             .values()
@@ -686,6 +1064,25 @@ impl LocalPackageLock {
             .any(|dep_id| dep_id == package)
     }
 
+    /// Rocks that cannot be reached from any entrypoint via the dependency graph.
+    fn unreachable_rocks(&self) -> Vec<LocalPackageId> {
+        let mut reachable: HashSet<&LocalPackageId> = HashSet::new();
+        let mut queue: VecDeque<&LocalPackageId> = self.entrypoints.iter().collect();
+        while let Some(id) = queue.pop_front() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            if let Some(rock) = self.rocks.get(id) {
+                queue.extend(rock.dependencies());
+            }
+        }
+        self.rocks
+            .keys()
+            .filter(|id| !reachable.contains(id))
+            .cloned()
+            .collect()
+    }
+
     fn list(&self) -> HashMap<PackageName, Vec<LocalPackage>> {
         self.rocks()
             .values()
@@ -720,13 +1117,14 @@ impl LocalPackageLock {
                             RemotePackageSource::LuarocksBinaryRock(_) => filter_spec.binary,
                             RemotePackageSource::RockspecContent(_) => true,
                             RemotePackageSource::Local => true,
+                            RemotePackageSource::LocalPath(_) => true,
                             #[cfg(test)]
                             RemotePackageSource::Test => unimplemented!(),
                         },
                         None => true,
                     })
-                    .rev()
-                    .find(|package| req.version_req().matches(package.version()))
+                    .filter(|package| req.version_req().matches(package.version()))
+                    .max_by(|a, b| a.version().cmp(b.version()))
             })?
             .cloned()
     }
@@ -735,10 +1133,11 @@ impl LocalPackageLock {
         self.list()
             .get(req.name())
             .map(|packages| {
-                packages
-                    .iter()
-                    .rev()
-                    .find(|package| package.constraint().matches_version_req(req.version_req()))
+                packages.iter().rev().find(|package| {
+                    package
+                        .constraint()
+                        .matches_version_req(req.version_req(), package.version())
+                })
             })?
             .cloned()
     }
@@ -761,7 +1160,7 @@ impl LocalPackageLock {
                 packages.iter().any(|req| {
                     local_pkg
                         .constraint()
-                        .matches_version_req(req.version_req())
+                        .matches_version_req(req.version_req(), local_pkg.version())
                 })
             })
             .cloned()
@@ -804,6 +1203,18 @@ impl LocalPackageLock {
     }
 }
 
+impl FromIterator<LocalPackage> for LocalPackageLock {
+    fn from_iter<I: IntoIterator<Item = LocalPackage>>(iter: I) -> Self {
+        Self {
+            rocks: iter
+                .into_iter()
+                .map(|package| (package.id(), package))
+                .collect(),
+            entrypoints: Vec::new(),
+        }
+    }
+}
+
 /// A lockfile for an install tree
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Lockfile<P: LockfilePermissions> {
@@ -813,18 +1224,52 @@ pub struct Lockfile<P: LockfilePermissions> {
     _marker: PhantomData<P>,
     // TODO: Serialize this directly into a `Version`
     version: String,
+    /// The lux version that last wrote this lockfile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    generator: Option<String>,
     #[serde(flatten)]
     lock: LocalPackageLock,
     #[serde(default, skip_serializing_if = "RockLayoutConfig::is_default")]
     pub(crate) entrypoint_layout: RockLayoutConfig,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LocalPackageLockType {
     Regular,
     Test,
     Build,
 }
 
+/// The packages that were added, removed or changed to a different version
+/// between two locks of the same dependency kind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockfileDiff {
+    added: Vec<PackageSpec>,
+    removed: Vec<PackageSpec>,
+    changed: Vec<(PackageSpec, PackageSpec)>,
+}
+
+impl LockfileDiff {
+    /// Packages that are only in the new lock.
+    pub fn added(&self) -> &Vec<PackageSpec> {
+        &self.added
+    }
+
+    /// Packages that are only in the old lock.
+    pub fn removed(&self) -> &Vec<PackageSpec> {
+        &self.removed
+    }
+
+    /// Packages that changed from one version to another, as `(old, new)` pairs.
+    pub fn changed(&self) -> &Vec<(PackageSpec, PackageSpec)> {
+        &self.changed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
 /// A lockfile for a Lua project
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ProjectLockfile<P: LockfilePermissions> {
@@ -833,12 +1278,17 @@ pub struct ProjectLockfile<P: LockfilePermissions> {
     #[serde(skip)]
     _marker: PhantomData<P>,
     version: String,
+    /// The lux version that last wrote this lockfile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    generator: Option<String>,
     #[serde(default, skip_serializing_if = "LocalPackageLock::is_empty")]
     dependencies: LocalPackageLock,
     #[serde(default, skip_serializing_if = "LocalPackageLock::is_empty")]
     test_dependencies: LocalPackageLock,
     #[serde(default, skip_serializing_if = "LocalPackageLock::is_empty")]
     build_dependencies: LocalPackageLock,
+    #[serde(default, skip_serializing_if = "RockLayoutConfig::is_default")]
+    pub(crate) entrypoint_layout: RockLayoutConfig,
 }
 
 #[derive(Error, Debug)]
@@ -853,6 +1303,10 @@ pub enum LockfileError {
     WriteJson(serde_json::Error),
     #[error("attempt load to a lockfile that does not match the expected rock layout.")]
     MismatchedRockLayout,
+    #[error("lockfile contains invalid sources:\n{0}")]
+    InvalidSourceUrls(InvalidSourceUrls),
+    #[error("error migrating installed rock {0}: {1}")]
+    MigrateRockDir(String, io::Error),
 }
 
 #[derive(Error, Debug)]
@@ -882,6 +1336,7 @@ pub(crate) struct PackageSyncSpec {
 impl UserData for Lockfile<ReadOnly> {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
         methods.add_method("version", |_, this, _: ()| Ok(this.version().clone()));
+        methods.add_method("generator", |_, this, _: ()| Ok(this.generator().cloned()));
         methods.add_method("rocks", |_, this, _: ()| Ok(this.rocks().clone()));
         methods.add_method("get", |_, this, id: LocalPackageId| {
             Ok(this.get(&id).cloned())
@@ -891,7 +1346,39 @@ impl UserData for Lockfile<ReadOnly> {
             f.call::<()>(lockfile)?;
             Ok(())
         });
+        methods.add_method("dependency_tree", |lua, this, id: LocalPackageId| {
+            this.get(&id)
+                .map(|package| dependency_tree_table(lua, &this.lock, package, &mut HashSet::new()))
+                .transpose()
+        });
+    }
+}
+
+/// Build a nested `{ id, name, version, dependencies = {...} }` table for a package.
+/// Cyclic dependencies are not expanded a second time.
+fn dependency_tree_table(
+    lua: &mlua::Lua,
+    lock: &LocalPackageLock,
+    package: &LocalPackage,
+    visited: &mut HashSet<LocalPackageId>,
+) -> mlua::Result<mlua::Table> {
+    let table = lua.create_table()?;
+    table.set("id", package.id())?;
+    table.set("name", package.name().to_string())?;
+    table.set("version", package.version().to_string())?;
+    let dependencies = lua.create_table()?;
+    if visited.insert(package.id()) {
+        for dependency in package
+            .dependencies()
+            .into_iter()
+            .filter_map(|id| lock.get(id))
+        {
+            dependencies.push(dependency_tree_table(lua, lock, dependency, visited)?)?;
+        }
+        visited.remove(&package.id());
     }
+    table.set("dependencies", dependencies)?;
+    Ok(table)
 }
 
 impl<P: LockfilePermissions> Lockfile<P> {
@@ -899,6 +1386,11 @@ impl<P: LockfilePermissions> Lockfile<P> {
         &self.version
     }
 
+    /// The lux version that last wrote this lockfile, if known.
+    pub fn generator(&self) -> Option<&String> {
+        self.generator.as_ref()
+    }
+
     pub fn rocks(&self) -> &BTreeMap<LocalPackageId, LocalPackage> {
         self.lock.rocks()
     }
@@ -911,6 +1403,11 @@ impl<P: LockfilePermissions> Lockfile<P> {
         self.lock.is_entrypoint(package)
     }
 
+    /// Rocks that are neither entrypoints nor (transitive) dependencies of an entrypoint.
+    pub fn unreachable_rocks(&self) -> Vec<LocalPackageId> {
+        self.lock.unreachable_rocks()
+    }
+
     pub fn entry_type(&self, package: &LocalPackageId) -> bool {
         self.lock.is_entrypoint(package)
     }
@@ -923,6 +1420,21 @@ impl<P: LockfilePermissions> Lockfile<P> {
         self.lock.get(id)
     }
 
+    /// All (transitive) dependencies of a package, excluding the package itself.
+    pub fn transitive_dependencies(&self, id: &LocalPackageId) -> Vec<&LocalPackage> {
+        self.lock
+            .get_all_dependencies(id)
+            .into_iter()
+            .filter(|package| package.id() != *id)
+            .sorted()
+            .collect_vec()
+    }
+
+    /// The number of (transitive) dependencies of a package, excluding the package itself.
+    pub fn transitive_dependency_count(&self, id: &LocalPackageId) -> usize {
+        self.transitive_dependencies(id).len()
+    }
+
     /// Unsafe because this assumes a prior check if the package is present
     ///
     /// # Safety
@@ -948,11 +1460,20 @@ impl<P: LockfilePermissions> Lockfile<P> {
 
     /// Find all rocks that match the requirement
     pub(crate) fn find_rocks(&self, req: &PackageReq) -> Vec<LocalPackageId> {
+        self.find_rocks_where(req, |_| true)
+    }
+
+    /// Find all rocks that match the requirement and the `filter`,
+    /// e.g. to skip pinned rocks.
+    pub(crate) fn find_rocks_where<F>(&self, req: &PackageReq, filter: F) -> Vec<LocalPackageId>
+    where
+        F: Fn(&LocalPackage) -> bool,
+    {
         match self.list().get(req.name()) {
             Some(packages) => packages
                 .iter()
                 .rev()
-                .filter(|package| req.version_req().matches(package.version()))
+                .filter(|package| req.version_req().matches(package.version()) && filter(package))
                 .map(|package| package.id())
                 .collect_vec(),
             None => Vec::default(),
@@ -985,11 +1506,13 @@ impl<P: LockfilePermissions> Lockfile<P> {
                             got: package.hashes.rockspec.clone(),
                         });
                     }
-                    if package
-                        .hashes
-                        .source
-                        .matches(&expected_package.hashes.source)
-                        .is_none()
+                    // The sources of editable packages are expected to change.
+                    if !expected_package.is_editable()
+                        && package
+                            .hashes
+                            .source
+                            .matches(&expected_package.hashes.source)
+                            .is_none()
                     {
                         return Err(LockfileIntegrityError::SourceIntegrityMismatch {
                             expected: expected_package.hashes.source.clone(),
@@ -1003,7 +1526,7 @@ impl<P: LockfilePermissions> Lockfile<P> {
     }
 
     fn flush(&self) -> Result<(), FlushLockfileError> {
-        let content = serde_json::to_string_pretty(&self).map_err(|err| FlushLockfileError {
+        let content = to_canonical_json(&self).map_err(|err| FlushLockfileError {
             filepath: self.filepath.to_string_lossy().to_string(),
             cause: io::Error::other(err),
         })?;
@@ -1016,6 +1539,11 @@ impl<P: LockfilePermissions> Lockfile<P> {
 }
 
 impl<P: LockfilePermissions> ProjectLockfile<P> {
+    /// The lux version that last wrote this lockfile, if known.
+    pub fn generator(&self) -> Option<&String> {
+        self.generator.as_ref()
+    }
+
     pub(crate) fn rocks(
         &self,
         deps: &LocalPackageLockType,
@@ -1039,6 +1567,15 @@ impl<P: LockfilePermissions> ProjectLockfile<P> {
         }
     }
 
+    /// The entrypoints of the given dependency tree, sorted by name and version.
+    pub(crate) fn entrypoints(&self, deps: &LocalPackageLockType) -> Vec<&LocalPackage> {
+        match deps {
+            LocalPackageLockType::Regular => self.dependencies.entrypoints(),
+            LocalPackageLockType::Test => self.test_dependencies.entrypoints(),
+            LocalPackageLockType::Build => self.build_dependencies.entrypoints(),
+        }
+    }
+
     pub(crate) fn is_entrypoint(
         &self,
         package: &LocalPackageId,
@@ -1071,8 +1608,31 @@ impl<P: LockfilePermissions> ProjectLockfile<P> {
         }
     }
 
+    /// The changes from this lockfile to `other`, for each kind of dependency.
+    pub fn diff<Q: LockfilePermissions>(
+        &self,
+        other: &ProjectLockfile<Q>,
+    ) -> HashMap<LocalPackageLockType, LockfileDiff> {
+        [
+            LocalPackageLockType::Regular,
+            LocalPackageLockType::Test,
+            LocalPackageLockType::Build,
+        ]
+        .into_iter()
+        .map(|deps| {
+            let diff = self.local_pkg_lock(&deps).diff(other.local_pkg_lock(&deps));
+            (deps, diff)
+        })
+        .collect()
+    }
+
+    /// The rock layout of the project's dependency trees' entrypoints.
+    pub fn entrypoint_layout(&self) -> &RockLayoutConfig {
+        &self.entrypoint_layout
+    }
+
     fn flush(&self) -> io::Result<()> {
-        let content = serde_json::to_string_pretty(&self)?;
+        let content = to_canonical_json(&self)?;
 
         std::fs::write(&self.filepath, content)?;
 
@@ -1093,11 +1653,12 @@ impl Lockfile<ReadOnly> {
                     filepath: filepath.clone(),
                     _marker: PhantomData,
                     version: LOCKFILE_VERSION_STR.into(),
+                    generator: Some(LOCKFILE_GENERATOR.into()),
                     lock: LocalPackageLock::default(),
                     entrypoint_layout: rock_layout.clone(),
                 };
                 let json_str =
-                    serde_json::to_string(&empty_lockfile).map_err(LockfileError::WriteJson)?;
+                    to_canonical_json(&empty_lockfile).map_err(LockfileError::WriteJson)?;
                 write!(file, "{json_str}").map_err(LockfileError::Create)?;
             }
             Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
@@ -1122,6 +1683,40 @@ impl Lockfile<ReadOnly> {
                 return Err(LockfileError::MismatchedRockLayout);
             }
         }
+        lockfile.migrate_rock_dirs()?;
+        Ok(lockfile)
+    }
+
+    /// Migrates legacy rock ids, and renames the install directories
+    /// of migrated rocks that live next to the lockfile.
+    fn migrate_rock_dirs(&mut self) -> Result<(), LockfileError> {
+        let migrated = self.lock.migrate_ids();
+        let root = match self.filepath.parent() {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        for (old_id, new_id) in migrated {
+            let rock = &self.lock.rocks[&new_id];
+            let old_dir = root.join(rock_dir_name(&old_id, rock));
+            let new_dir = root.join(rock_dir_name(&new_id, rock));
+            if old_dir.is_dir() && !new_dir.exists() {
+                std::fs::rename(&old_dir, &new_dir).map_err(|err| {
+                    LockfileError::MigrateRockDir(rock.to_package().to_string(), err)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`Lockfile::load`], but also fails if any of the rocks' source URLs are invalid,
+    /// e.g. if a local source file no longer exists.
+    /// All invalid sources are reported at once.
+    pub fn load_strict(
+        filepath: PathBuf,
+        expected_rock_layout: Option<&RockLayoutConfig>,
+    ) -> Result<Lockfile<ReadOnly>, LockfileError> {
+        let lockfile = Self::load(filepath, expected_rock_layout)?;
+        validate_source_urls(lockfile.lock.invalid_source_urls())?;
         Ok(lockfile)
     }
 
@@ -1131,6 +1726,7 @@ impl Lockfile<ReadOnly> {
             _marker: PhantomData,
             filepath: self.filepath,
             version: self.version,
+            generator: Some(LOCKFILE_GENERATOR.into()),
             lock: self.lock,
             entrypoint_layout: self.entrypoint_layout,
         }
@@ -1184,37 +1780,90 @@ impl Lockfile<ReadOnly> {
 
 impl ProjectLockfile<ReadOnly> {
     /// Create a new `ProjectLockfile`, writing an empty file if none exists.
-    pub fn new(filepath: PathBuf) -> Result<ProjectLockfile<ReadOnly>, LockfileError> {
-        // Ensure that the lockfile exists
-        match File::options().create_new(true).write(true).open(&filepath) {
+    pub fn new(
+        filepath: PathBuf,
+        rock_layout: RockLayoutConfig,
+    ) -> Result<ProjectLockfile<ReadOnly>, LockfileError> {
+        Self::create_if_missing(&filepath, &rock_layout)?;
+        Self::load(filepath, Some(&rock_layout))
+    }
+
+    /// Like [`ProjectLockfile::new`], but doesn't validate the rock layout
+    /// of an existing lockfile.
+    pub(crate) fn new_unchecked(
+        filepath: PathBuf,
+    ) -> Result<ProjectLockfile<ReadOnly>, LockfileError> {
+        Self::create_if_missing(&filepath, &RockLayoutConfig::default())?;
+        Self::load(filepath, None)
+    }
+
+    /// Write an empty lockfile if none exists.
+    fn create_if_missing(
+        filepath: &Path,
+        rock_layout: &RockLayoutConfig,
+    ) -> Result<(), LockfileError> {
+        match File::options().create_new(true).write(true).open(filepath) {
             Ok(mut file) => {
                 let empty_lockfile: ProjectLockfile<ReadOnly> = ProjectLockfile {
-                    filepath: filepath.clone(),
+                    filepath: filepath.to_path_buf(),
                     _marker: PhantomData,
                     version: LOCKFILE_VERSION_STR.into(),
+                    generator: Some(LOCKFILE_GENERATOR.into()),
                     dependencies: LocalPackageLock::default(),
                     test_dependencies: LocalPackageLock::default(),
                     build_dependencies: LocalPackageLock::default(),
+                    entrypoint_layout: rock_layout.clone(),
                 };
                 let json_str =
-                    serde_json::to_string(&empty_lockfile).map_err(LockfileError::WriteJson)?;
+                    to_canonical_json(&empty_lockfile).map_err(LockfileError::WriteJson)?;
                 write!(file, "{json_str}").map_err(LockfileError::Create)?;
             }
             Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
             Err(err) => return Err(LockfileError::Create(err)),
         }
-
-        Self::load(filepath)
+        Ok(())
     }
 
     /// Load a `ProjectLockfile`, failing if none exists.
-    pub fn load(filepath: PathBuf) -> Result<ProjectLockfile<ReadOnly>, LockfileError> {
+    /// If `expected_rock_layout` is `Some`, this fails if the rock layouts don't match
+    pub fn load(
+        filepath: PathBuf,
+        expected_rock_layout: Option<&RockLayoutConfig>,
+    ) -> Result<ProjectLockfile<ReadOnly>, LockfileError> {
         let content = std::fs::read_to_string(&filepath).map_err(LockfileError::Load)?;
         let mut lockfile: ProjectLockfile<ReadOnly> =
             serde_json::from_str(&content).map_err(LockfileError::ParseJson)?;
 
         lockfile.filepath = filepath;
 
+        if let Some(expected_rock_layout) = expected_rock_layout {
+            if &lockfile.entrypoint_layout != expected_rock_layout {
+                return Err(LockfileError::MismatchedRockLayout);
+            }
+        }
+
+        lockfile.dependencies.migrate_ids();
+        lockfile.test_dependencies.migrate_ids();
+        lockfile.build_dependencies.migrate_ids();
+
+        Ok(lockfile)
+    }
+
+    /// Like [`ProjectLockfile::load`], but also fails if any of the rocks' source URLs
+    /// are invalid, e.g. if a local source file no longer exists.
+    /// All invalid sources are reported at once.
+    pub fn load_strict(
+        filepath: PathBuf,
+        expected_rock_layout: Option<&RockLayoutConfig>,
+    ) -> Result<ProjectLockfile<ReadOnly>, LockfileError> {
+        let lockfile = Self::load(filepath, expected_rock_layout)?;
+        validate_source_urls(
+            lockfile
+                .dependencies
+                .invalid_source_urls()
+                .chain(lockfile.test_dependencies.invalid_source_urls())
+                .chain(lockfile.build_dependencies.invalid_source_urls()),
+        )?;
         Ok(lockfile)
     }
 
@@ -1224,9 +1873,11 @@ impl ProjectLockfile<ReadOnly> {
             _marker: PhantomData,
             filepath: self.filepath,
             version: self.version,
+            generator: Some(LOCKFILE_GENERATOR.into()),
             dependencies: self.dependencies,
             test_dependencies: self.test_dependencies,
             build_dependencies: self.build_dependencies,
+            entrypoint_layout: self.entrypoint_layout,
         }
     }
 
@@ -1240,7 +1891,16 @@ impl ProjectLockfile<ReadOnly> {
 impl Lockfile<ReadWrite> {
     pub(crate) fn add_entrypoint(&mut self, rock: &LocalPackage) {
         self.add(rock);
-        self.lock.entrypoints.push(rock.id().clone())
+        if !self.lock.is_entrypoint(&rock.id()) {
+            self.lock.entrypoints.push(rock.id());
+        }
+        self.reorder();
+    }
+
+    /// Sort the entrypoints by name and version,
+    /// to keep the lockfile stable across regenerations.
+    pub(crate) fn reorder(&mut self) {
+        self.lock.reorder();
     }
 
     pub(crate) fn remove_entrypoint(&mut self, rock: &LocalPackage) {
@@ -1323,6 +1983,7 @@ pub struct ProjectLockfileGuard(ProjectLockfile<ReadWrite>);
 impl UserData for LockfileGuard {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
         methods.add_method("version", |_, this, _: ()| Ok(this.version().clone()));
+        methods.add_method("generator", |_, this, _: ()| Ok(this.generator().cloned()));
         methods.add_method("rocks", |_, this, _: ()| Ok(this.rocks().clone()));
         methods.add_method("get", |_, this, id: LocalPackageId| {
             Ok(this.get(&id).cloned())
@@ -1413,6 +2074,7 @@ impl Drop for ProjectLockfileGuard {
 impl UserData for Lockfile<ReadWrite> {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
         methods.add_method("version", |_, this, ()| Ok(this.version().to_owned()));
+        methods.add_method("generator", |_, this, ()| Ok(this.generator().cloned()));
         methods.add_method("rocks", |_, this, ()| {
             Ok(this
                 .rocks()
@@ -1428,20 +2090,6 @@ impl UserData for Lockfile<ReadWrite> {
     }
 }
 
-fn serialize_sorted_package_ids<S>(
-    package_ids: &[LocalPackageId],
-    serializer: S,
-) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    package_ids
-        .iter()
-        .sorted()
-        .collect_vec()
-        .serialize(serializer)
-}
-
 fn integrity_err_not_found(package: &LocalPackage) -> LockfileIntegrityError {
     LockfileIntegrityError::PackageNotFound(
         package.name().clone(),
@@ -1463,6 +2111,20 @@ where
     Url::parse(&s).map_err(serde::de::Error::custom)
 }
 
+/// Serialize a lockfile to pretty-printed JSON, indented with two spaces
+/// and terminated by a newline.
+/// Maps are serialized from `BTreeMap`s and struct fields in declaration order,
+/// so reserializing an unchanged lockfile yields byte-identical output.
+fn to_canonical_json<T: Serialize>(value: &T) -> serde_json::Result<String> {
+    let mut buf = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"  ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    value.serialize(&mut serializer)?;
+    buf.push(b'\n');
+    // serde_json only emits valid UTF-8
+    Ok(String::from_utf8(buf).expect("invalid UTF-8 in serialized lockfile"))
+}
+
 fn serialize_url<S>(url: &Url, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -1500,7 +2162,10 @@ mod tests {
         let tree = config.user_tree(Lua51).unwrap();
         let lockfile = tree.lockfile().unwrap();
 
-        assert_json_snapshot!(lockfile, { ".**" => sorted_redaction() });
+        assert_json_snapshot!(lockfile, {
+            ".**" => sorted_redaction(),
+            ".generator" => "[generator]",
+        });
     }
 
     #[test]
@@ -1553,7 +2218,10 @@ mod tests {
         test_local_dep_package.spec.pinned = PinnedState::Pinned;
         lockfile.add_dependency(&test_local_package, &test_local_dep_package);
 
-        assert_json_snapshot!(lockfile, { ".**" => sorted_redaction() });
+        assert_json_snapshot!(lockfile, {
+            ".**" => sorted_redaction(),
+            ".generator" => "[generator]",
+        });
     }
 
     #[test]
@@ -1576,12 +2244,317 @@ mod tests {
         let _ = tree.lockfile().unwrap().write_guard(); // Try to create the lockfile but don't actually do anything with it.
     }
 
+    #[test]
+    fn lockfile_generator() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let legacy_path = temp.join("legacy.lock");
+        std::fs::write(
+            &legacy_path,
+            r#"{"version":"1.0.0","rocks":{},"entrypoints":[]}"#,
+        )
+        .unwrap();
+        let legacy_lockfile = Lockfile::load(legacy_path.clone(), None).unwrap();
+        assert_eq!(legacy_lockfile.generator(), None);
+        drop(legacy_lockfile.write_guard());
+        let lockfile = Lockfile::load(legacy_path, None).unwrap();
+        assert_eq!(
+            lockfile.generator().map(String::as_str),
+            Some(LOCKFILE_GENERATOR)
+        );
+
+        let project_lockfile =
+            ProjectLockfile::new(temp.join("lux.lock"), RockLayoutConfig::default()).unwrap();
+        assert_eq!(
+            project_lockfile.generator().map(String::as_str),
+            Some(LOCKFILE_GENERATOR)
+        );
+    }
+
+    #[test]
+    fn project_lockfile_diff() {
+        let lock = |packages: &[(&str, &str)]| -> LocalPackageLock {
+            packages
+                .iter()
+                .map(|(name, version)| LocalPackage::mock(name, version))
+                .collect()
+        };
+        let project_lockfile = |dependencies, test_dependencies| ProjectLockfile::<ReadOnly> {
+            filepath: PathBuf::default(),
+            _marker: PhantomData,
+            version: LOCKFILE_VERSION_STR.into(),
+            generator: None,
+            dependencies,
+            test_dependencies,
+            build_dependencies: LocalPackageLock::default(),
+            entrypoint_layout: RockLayoutConfig::default(),
+        };
+        let old = project_lockfile(
+            lock(&[("foo", "1.0.0-1"), ("bar", "1.0.0-1"), ("baz", "1.0.0-1")]),
+            lock(&[("busted", "2.1.0-1")]),
+        );
+        let new = project_lockfile(
+            lock(&[("foo", "1.1.0-1"), ("baz", "1.0.0-1"), ("qux", "0.1.0-1")]),
+            lock(&[("busted", "2.1.0-1")]),
+        );
+        let spec = |name: &str, version: &str| {
+            PackageSpec::parse(name.to_string(), version.to_string()).unwrap()
+        };
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.len(), 3);
+        let regular = &diff[&LocalPackageLockType::Regular];
+        assert_eq!(regular.added(), &vec![spec("qux", "0.1.0-1")]);
+        assert_eq!(regular.removed(), &vec![spec("bar", "1.0.0-1")]);
+        assert_eq!(
+            regular.changed(),
+            &vec![(spec("foo", "1.0.0-1"), spec("foo", "1.1.0-1"))]
+        );
+        assert!(diff[&LocalPackageLockType::Test].is_empty());
+        assert!(diff[&LocalPackageLockType::Build].is_empty());
+
+        assert!(old
+            .diff(&old)
+            .values()
+            .all(|lockfile_diff| lockfile_diff.is_empty()));
+    }
+
+    #[test]
+    fn project_lockfile_rock_layout() {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let legacy_path = temp.join("legacy.lock");
+        std::fs::write(&legacy_path, r#"{"version":"1.0.0"}"#).unwrap();
+        let legacy_lockfile =
+            ProjectLockfile::load(legacy_path, Some(&RockLayoutConfig::default())).unwrap();
+        assert!(legacy_lockfile.entrypoint_layout().is_default());
+
+        let path = temp.join("lux.lock");
+        let nvim_layout = RockLayoutConfig::new_nvim_layout();
+        let lockfile = ProjectLockfile::new(path.clone(), nvim_layout.clone()).unwrap();
+        assert_eq!(lockfile.entrypoint_layout(), &nvim_layout);
+        drop(lockfile.write_guard());
+
+        assert!(ProjectLockfile::load(path.clone(), Some(&nvim_layout)).is_ok());
+        assert!(ProjectLockfile::load(path.clone(), None).is_ok());
+        assert!(matches!(
+            ProjectLockfile::load(path, Some(&RockLayoutConfig::default())),
+            Err(LockfileError::MismatchedRockLayout)
+        ));
+    }
+
+    #[test]
+    fn entrypoint_order_independent_of_install_order() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let packages = [
+            ("foo", "1.0.0"),
+            ("bar", "2.0.0"),
+            ("bar", "1.0.0"),
+            ("baz", "0.1.0"),
+        ]
+        .into_iter()
+        .map(|(name, version)| LocalPackage::mock(name, version))
+        .collect_vec();
+
+        let write_lockfile = |name: &str, packages: Vec<&LocalPackage>| {
+            let path = temp.join(name);
+            let mut lockfile = Lockfile::new(path.clone(), RockLayoutConfig::default())
+                .unwrap()
+                .write_guard();
+            for package in packages {
+                lockfile.add_entrypoint(package);
+            }
+            drop(lockfile);
+            std::fs::read_to_string(path).unwrap()
+        };
+        let content = write_lockfile("lux.lock", packages.iter().collect_vec());
+        let reversed_content = write_lockfile("reversed.lock", packages.iter().rev().collect_vec());
+        assert_eq!(content, reversed_content);
+
+        let lockfile: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let entrypoint_names = lockfile["entrypoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|id| {
+                let rock = &lockfile["rocks"][id.as_str().unwrap()];
+                format!(
+                    "{}@{}",
+                    rock["name"].as_str().unwrap(),
+                    rock["version"].as_str().unwrap()
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            entrypoint_names,
+            vec!["bar@1.0.0-1", "bar@2.0.0-1", "baz@0.1.0-1", "foo@1.0.0-1"]
+        );
+    }
+
+    #[test]
+    fn transitive_dependencies() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mk_package = |name: &str| LocalPackage::mock(name, "1.0.0");
+        let neorg = mk_package("neorg");
+        let nui = mk_package("nui");
+        let plenary = mk_package("plenary");
+        let mut lockfile = Lockfile::new(temp.join("lux.lock"), RockLayoutConfig::default())
+            .unwrap()
+            .write_guard();
+        lockfile.add_entrypoint(&neorg);
+        lockfile.add_dependency(&neorg, &plenary);
+        lockfile.add_dependency(&neorg, &nui);
+        lockfile.add_dependency(&nui, &plenary);
+
+        assert_eq!(lockfile.transitive_dependency_count(&neorg.id()), 2);
+        assert_eq!(
+            lockfile
+                .transitive_dependencies(&neorg.id())
+                .into_iter()
+                .map(|package| package.name().to_string())
+                .collect_vec(),
+            vec!["nui", "plenary"]
+        );
+        assert_eq!(lockfile.transitive_dependency_count(&nui.id()), 1);
+        assert_eq!(lockfile.transitive_dependency_count(&plenary.id()), 0);
+    }
+
     fn get_test_lockfile() -> Lockfile<ReadOnly> {
         let sample_tree = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
             .join("resources/test/sample-tree/5.1/lux.lock");
         Lockfile::new(sample_tree, RockLayoutConfig::default()).unwrap()
     }
 
+    #[test]
+    fn local_package_id_equivalent_constraints() {
+        let name = PackageName::new("foo".into());
+        let version = PackageVersion::parse("1.5.0-1").unwrap();
+        let id = |constraint: &str| {
+            LocalPackageId::new(
+                &name,
+                &version,
+                PinnedState::Unpinned,
+                OptState::Required,
+                LockConstraint::Constrained(constraint.parse().unwrap()),
+            )
+        };
+        for equivalent_constraints in [
+            vec![">=1.0.0, <2.0.0", "<2.0.0, >=1.0.0", ">= 1.0.0,<2.0.0"],
+            vec![">=1.0.0", ">=1.0.0, >=1.0.0"],
+            vec!["==1.5.0", "@1.5.0", "1.5.0", "= 1.5.0"],
+        ] {
+            let expected = id(equivalent_constraints[0]);
+            for constraint in equivalent_constraints {
+                assert_eq!(id(constraint), expected, "{constraint}");
+            }
+        }
+        assert_ne!(id(">=1.0.0, <2.0.0"), id(">=1.0.0"));
+    }
+
+    #[test]
+    fn local_package_id_single_constraint_stable() {
+        let name = PackageName::new("foo".into());
+        let version = PackageVersion::parse("1.5.0-1").unwrap();
+        let constraint: PackageVersionReq = ">=1.0.0".parse().unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{name}{version}falsefalse{constraint}"));
+        assert_eq!(
+            LocalPackageId::new(
+                &name,
+                &version,
+                PinnedState::Unpinned,
+                OptState::Required,
+                LockConstraint::Constrained(constraint),
+            )
+            .to_string(),
+            hex::encode(hasher.finalize())
+        );
+    }
+
+    #[test]
+    fn local_package_id_normalized_name() {
+        let version = PackageVersion::parse("0.1.4-1").unwrap();
+        let id = |name: &str| {
+            LocalPackageId::new(
+                &PackageName::new(name.into()),
+                &version,
+                PinnedState::Unpinned,
+                OptState::Required,
+                LockConstraint::Unconstrained,
+            )
+        };
+        assert_eq!(id("plenary.nvim"), id("plenary-nvim"));
+        assert_eq!(id("plenary.nvim"), id("Plenary.nvim"));
+        assert_ne!(id("plenary.nvim"), id("plenary"));
+    }
+
+    #[test]
+    fn load_migrates_legacy_ids() {
+        let mut foo = LocalPackage::mock("foo.nvim", "1.0.0");
+        foo.spec.constraint = Some(">=1.0.0, <2.0.0".into());
+        let bar = LocalPackage::mock("bar", "1.0.0");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let lockfile_path = temp.join("lux.lock");
+        Lockfile::new(lockfile_path.clone(), RockLayoutConfig::default())
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&foo);
+                lockfile.add_dependency(&bar, &foo);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+
+        // Simulate a lockfile and tree written before names and constraints were normalized
+        let legacy_id = foo.spec.legacy_id();
+        assert_ne!(legacy_id, foo.id());
+        let content = std::fs::read_to_string(&lockfile_path).unwrap();
+        std::fs::write(
+            &lockfile_path,
+            content.replace(&foo.id().to_string(), &legacy_id.to_string()),
+        )
+        .unwrap();
+        let legacy_dir = temp.join(rock_dir_name(&legacy_id, &foo));
+        std::fs::create_dir_all(&legacy_dir).unwrap();
+
+        let lockfile = Lockfile::load(lockfile_path, None).unwrap();
+        assert!(lockfile.get(&foo.id()).is_some());
+        assert!(lockfile.get(&legacy_id).is_none());
+        assert!(lockfile.is_entrypoint(&foo.id()));
+        assert_eq!(
+            lockfile.get(&bar.id()).unwrap().dependencies(),
+            vec![&foo.id()]
+        );
+        assert!(!legacy_dir.exists());
+        assert!(temp.join(rock_dir_name(&foo.id(), &foo)).is_dir());
+    }
+
+    #[test]
+    fn lua_dependency_tree() {
+        let lua = mlua::Lua::new();
+        lua.globals().set("lockfile", get_test_lockfile()).unwrap();
+        lua.load(
+            r#"
+            local tree = lockfile:dependency_tree("fdd927c4d96d1063ce66246cfd5ecb1939a8f8e8425f29428894b43586fb72cd")
+            assert(tree.name == "neorg", "expected neorg, got " .. tostring(tree.name))
+            assert(tree.version == "8.8.1-1", "unexpected version " .. tostring(tree.version))
+            assert(#tree.dependencies == 5, "expected 5 dependencies, got " .. #tree.dependencies)
+            local names = {}
+            for _, dependency in ipairs(tree.dependencies) do
+                assert(#dependency.dependencies == 0, dependency.name .. " should have no dependencies")
+                names[dependency.name] = true
+            end
+            for _, name in ipairs({ "nvim-nio", "lua-utils.nvim", "plenary.nvim", "nui.nvim", "pathlib.nvim" }) do
+                assert(names[name], "missing dependency " .. name)
+            end
+
+            local unknown = lockfile:dependency_tree("0000000000000000000000000000000000000000000000000000000000000000")
+            assert(unknown == nil, "unknown package ids should return nil")
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+
     #[test]
     fn test_sync_spec() {
         let lockfile = get_test_lockfile();
@@ -1702,4 +2675,251 @@ mod tests {
             .iter()
             .any(|pkg| pkg.name().to_string() == "nvim-nio"));
     }
+
+    #[test]
+    fn native_rocks_require_rebuild_on_lua_version_change() {
+        let mut native = LocalPackage::mock("lua-cjson", "2.1.0-1");
+        native.lua_version = Some(Lua51);
+        let pure_lua = LocalPackage::mock("neorg", "8.0.0-1");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let lockfile_path = temp.join("lux.lock");
+        Lockfile::new(lockfile_path.clone(), RockLayoutConfig::default())
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&native);
+                lockfile.add_entrypoint(&pure_lua);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+        let lockfile = Lockfile::load(lockfile_path, None).unwrap();
+
+        let native = lockfile.get(&native.id()).unwrap();
+        assert_eq!(native.lua_version(), Some(&Lua51));
+        assert!(!native.requires_rebuild(&Lua51));
+        assert!(native.requires_rebuild(&LuaVersion::Lua54));
+
+        let pure_lua = lockfile.get(&pure_lua.id()).unwrap();
+        assert_eq!(pure_lua.lua_version(), None);
+        assert!(!pure_lua.requires_rebuild(&Lua51));
+        assert!(!pure_lua.requires_rebuild(&LuaVersion::Lua54));
+    }
+
+    #[test]
+    fn local_package_source_kind() {
+        let url = Url::parse("https://luarocks.org").unwrap();
+        for (source, kind) in [
+            (
+                RemotePackageSource::LuarocksRockspec(url.clone()),
+                SourceKind::Rockspec,
+            ),
+            (
+                RemotePackageSource::LuarocksSrcRock(url.clone()),
+                SourceKind::SrcRock,
+            ),
+            (
+                RemotePackageSource::LuarocksBinaryRock(url),
+                SourceKind::BinaryRock,
+            ),
+            (
+                RemotePackageSource::RockspecContent("package = 'neorg'".into()),
+                SourceKind::RockspecContent,
+            ),
+            (RemotePackageSource::Local, SourceKind::Local),
+            (
+                RemotePackageSource::LocalPath("/path/to/neorg".into()),
+                SourceKind::Local,
+            ),
+        ] {
+            let editable = matches!(source, RemotePackageSource::LocalPath(_));
+            let mut local_package = LocalPackage::mock("neorg", "8.0.0-1");
+            local_package.source = source;
+            assert_eq!(local_package.source_kind(), kind);
+            assert_eq!(local_package.is_editable(), editable);
+        }
+    }
+
+    #[test]
+    fn find_unpinned_rocks() {
+        let mk_package = |version: &str, pinned: PinnedState| {
+            let mut package = LocalPackage::mock("neorg", version);
+            package.spec.pinned = pinned;
+            package
+        };
+        let unpinned = mk_package("8.0.0-1", PinnedState::Unpinned);
+        let pinned = mk_package("8.1.0-1", PinnedState::Pinned);
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let lockfile_path = temp.join("lux.lock");
+        Lockfile::new(lockfile_path.clone(), RockLayoutConfig::default())
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&unpinned);
+                lockfile.add_entrypoint(&pinned);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+        let lockfile = Lockfile::load(lockfile_path, None).unwrap();
+
+        let req: PackageReq = "neorg >= 8.0.0".parse().unwrap();
+        assert_eq!(lockfile.find_rocks(&req).len(), 2);
+        assert_eq!(
+            lockfile.find_rocks_where(&req, |package| package.pinned() == PinnedState::Unpinned),
+            vec![unpinned.id()]
+        );
+        let req: PackageReq = "neorg >= 8.1.0".parse().unwrap();
+        assert!(lockfile
+            .find_rocks_where(&req, |package| package.pinned() == PinnedState::Unpinned)
+            .is_empty());
+    }
+
+    #[test]
+    fn reserialize_lockfile_is_byte_identical() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        for (lockfile, is_project_lockfile) in [
+            ("resources/test/sample-tree/5.1/lux.lock", false),
+            (
+                "resources/test/sample-projects/busted-with-lockfile/lux.lock",
+                true,
+            ),
+        ] {
+            let lockfile_path = temp.join("lux.lock");
+            std::fs::copy(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(lockfile),
+                &lockfile_path,
+            )
+            .unwrap();
+            let reserialize = || {
+                if is_project_lockfile {
+                    let _ = ProjectLockfile::load(lockfile_path.clone(), None)
+                        .unwrap()
+                        .write_guard();
+                } else {
+                    let _ = Lockfile::load(lockfile_path.clone(), None)
+                        .unwrap()
+                        .write_guard();
+                }
+                std::fs::read_to_string(&lockfile_path).unwrap()
+            };
+            let canonical = reserialize();
+            assert!(canonical.ends_with("}\n"));
+            assert_eq!(reserialize(), canonical);
+        }
+    }
+
+    #[test]
+    fn lockfile_independent_of_dependency_order() {
+        let mk_package = |name: &str| LocalPackage::mock(name, "1.0.0-1");
+        let neorg = mk_package("neorg");
+        let nio = mk_package("nvim-nio");
+        let plenary = mk_package("plenary.nvim");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let write_lockfile = |file_name: &str, dependencies: [&LocalPackage; 2]| {
+            let lockfile_path = temp.join(file_name);
+            Lockfile::new(lockfile_path.clone(), RockLayoutConfig::default())
+                .unwrap()
+                .map_then_flush(|lockfile| {
+                    lockfile.add_entrypoint(&neorg);
+                    for dependency in dependencies {
+                        lockfile.add_dependency(&neorg, dependency);
+                    }
+                    Ok::<_, io::Error>(())
+                })
+                .unwrap();
+            std::fs::read_to_string(lockfile_path).unwrap()
+        };
+        assert_eq!(
+            write_lockfile("a.lock", [&nio, &plenary]),
+            write_lockfile("b.lock", [&plenary, &nio])
+        );
+    }
+
+    #[test]
+    fn load_strict_validates_source_urls() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mk_package = |name: &str, source_url: RemotePackageSourceUrl| {
+            let mut package = LocalPackage::mock(name, "1.0.0-1");
+            package.source_url = Some(source_url);
+            package
+        };
+        let valid = [
+            mk_package(
+                "git",
+                RemotePackageSourceUrl::Git {
+                    url: "https://github.com/nvim-neorocks/lux.git".into(),
+                    checkout_ref: "main".into(),
+                },
+            ),
+            mk_package(
+                "url",
+                RemotePackageSourceUrl::Url {
+                    url: Url::parse("https://example.com/foo.tar.gz").unwrap(),
+                },
+            ),
+            mk_package(
+                "file",
+                RemotePackageSourceUrl::File {
+                    path: temp.to_path_buf(),
+                },
+            ),
+        ];
+        let invalid = [
+            mk_package(
+                "bad-url",
+                RemotePackageSourceUrl::Url {
+                    url: Url::parse("ftp://example.com/foo.tar.gz").unwrap(),
+                },
+            ),
+            mk_package(
+                "missing-file",
+                RemotePackageSourceUrl::File {
+                    path: temp.join("missing.tar.gz"),
+                },
+            ),
+        ];
+        let lockfile_path = temp.join("lux.lock");
+        Lockfile::new(lockfile_path.clone(), RockLayoutConfig::default())
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                valid
+                    .iter()
+                    .for_each(|package| lockfile.add_entrypoint(package));
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+        assert!(Lockfile::load_strict(lockfile_path.clone(), None).is_ok());
+
+        Lockfile::load(lockfile_path.clone(), None)
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                invalid
+                    .iter()
+                    .for_each(|package| lockfile.add_entrypoint(package));
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+        assert!(Lockfile::load(lockfile_path.clone(), None).is_ok());
+        match Lockfile::load_strict(lockfile_path, None) {
+            Err(LockfileError::InvalidSourceUrls(invalid_source_urls)) => {
+                let invalid_packages = invalid_source_urls
+                    .iter()
+                    .map(|(package, _)| package.name().to_string())
+                    .sorted()
+                    .collect_vec();
+                assert_eq!(invalid_packages, vec!["bad-url", "missing-file"]);
+            }
+            Err(err) => panic!("expected invalid source URLs, but got {err}"),
+            Ok(_) => panic!("expected invalid source URLs"),
+        }
+
+        let git_url = RemotePackageSourceUrl::Git {
+            url: "https://github.com/nvim-neorocks/lux\0.git".into(),
+            checkout_ref: "main".into(),
+        };
+        assert!(matches!(
+            git_url.validate(),
+            Err(SourceUrlError::InvalidGitUrl { .. })
+        ));
+    }
 }
@@ -13,8 +13,8 @@ use crate::build::external_dependency::to_lib_name;
 use crate::build::external_dependency::ExternalDependencyInfo;
 use crate::build::utils::{c_lib_extension, format_path};
 use crate::config::external_deps::ExternalDependencySearchConfig;
-use crate::config::LuaVersionUnset;
 use crate::lua_rockspec::ExternalDependencySpec;
+use crate::lua_rockspec::LuaVersionError;
 use crate::operations;
 use crate::operations::BuildLuaError;
 use crate::progress::Progress;
@@ -47,7 +47,7 @@ pub enum LuaBinaryError {
     #[error("neither `lua` nor `luajit` found on the PATH")]
     LuaBinaryNotFound,
     #[error(transparent)]
-    DetectLuaVersion(#[from] DetectLuaVersionError),
+    DetectLuaVersion(#[from] GetLuaVersionError),
     #[error(
         "{} -v (= {}) does not match expected Lua version {}",
         lua_cmd,
@@ -64,33 +64,61 @@ pub enum LuaBinaryError {
 }
 
 #[derive(Error, Debug)]
-pub enum DetectLuaVersionError {
+pub enum GetLuaVersionError {
     #[error("failed to run {0}: {1}")]
     RunLuaCommand(String, io::Error),
     #[error("failed to parse Lua version from output: {0}")]
     ParseLuaVersion(String),
+    #[error("`{0} -v` failed or did not report a version. Is {0} a Lua interpreter?")]
+    NoVersionOutput(String),
     #[error(transparent)]
     PackageVersionParse(#[from] crate::package::PackageVersionParseError),
     #[error(transparent)]
     LuaVersion(#[from] crate::config::LuaVersionError),
 }
 
+#[deprecated(note = "use `GetLuaVersionError` instead")]
+pub type DetectLuaVersionError = GetLuaVersionError;
+
 #[derive(Error, Debug)]
 pub enum LuaInstallationError {
     #[error("could not find a Lua installation and failed to build Lua from source:\n{0}")]
     Build(#[from] BuildLuaError),
+    #[error("could not find a Lua {0} installation on the system, and `system_lua` is set, so Lua won't be built from source")]
+    SystemLuaNotFound(LuaVersion),
+    #[error("cannot install Lua {0} into {1}, as it is not writable: {2}\nPlease install Lua {0} there, or set a writable `lua_dir`.")]
+    LuaDirNotWritable(LuaVersion, PathBuf, io::Error),
     #[error(transparent)]
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
 }
 
 impl LuaInstallation {
+    /// Detect the `LuaVersion` of a Lua interpreter by running it.
+    /// For LuaJIT, which reports its own version (e.g. `2.1.x`),
+    /// this also probes whether it was built with Lua 5.2 compatibility.
+    pub fn detect_version(binary: &Path) -> Result<LuaVersion, GetLuaVersionError> {
+        let version = detect_installed_lua_version_from_path(binary)?;
+        match LuaVersion::from_version(version)? {
+            LuaVersion::LuaJIT if detect_luajit_lua52_compat(binary)? => Ok(LuaVersion::LuaJIT52),
+            lua_version => Ok(lua_version),
+        }
+    }
+
     pub async fn new_from_config(
         config: &Config,
         progress: &Progress<ProgressBar>,
     ) -> Result<Self, LuaInstallationError> {
-        Self::new(LuaVersion::from(config)?, config, progress).await
+        Self::new(&config.resolve_lua_version(None)?, config, progress).await
     }
 
+    /// Find a Lua installation for the given version, either on the system (via pkg-config)
+    /// or in the config's `lua_dir` (or the tree's `.lua` directory).
+    /// If none is found, Lua is built from source and installed there,
+    /// unless the config's `system_lua` is set, in which case this fails instead.
+    ///
+    /// NOTE: Lux never resolves `lua` as a rock dependency.
+    /// A rockspec's `lua` dependency only constrains the Lua version,
+    /// which is validated against the version of the Lua installation.
     pub async fn new(
         version: &LuaVersion,
         config: &Config,
@@ -123,6 +151,8 @@ impl LuaInstallation {
                 },
                 bin,
             })
+        } else if config.system_lua() {
+            Err(LuaInstallationError::SystemLuaNotFound(version.clone()))
         } else {
             Self::install(version, config, progress).await
         }
@@ -182,6 +212,11 @@ impl LuaInstallation {
         let _lock = INSTALL_MUTEX.lock().await;
 
         let target = Self::root_dir(version, config);
+        // Fail early instead of after building Lua, e.g. if the `lua_dir` is a shared,
+        // read-only location, where Lua has to be provisioned beforehand.
+        ensure_writable_dir(&target).map_err(|err| {
+            LuaInstallationError::LuaDirNotWritable(version.clone(), target.clone(), err)
+        })?;
 
         operations::BuildLua::new()
             .lua_version(version)
@@ -237,13 +272,69 @@ impl LuaInstallation {
         self.dependency_info.lib_name.clone()
     }
 
-    pub(crate) fn define_flags(&self) -> Vec<String> {
-        self.dependency_info.define_flags()
+    /// Flags to pass to the C compiler when building C modules.
+    /// The config's `extra_cflags` are appended after Lua's own flags.
+    pub(crate) fn compile_args(&self, config: &Config) -> Vec<String> {
+        self.dependency_info
+            .define_flags()
+            .into_iter()
+            .chain(config.extra_cflags().iter().cloned())
+            .collect_vec()
     }
 
-    /// NOTE: In luarocks, these are behind a link_lua_explicity config option
-    pub(crate) fn lib_link_args(&self, compiler: &cc::Tool) -> Vec<String> {
-        self.dependency_info.lib_link_args(compiler)
+    /// Flags to pass to the linker when building C modules.
+    /// The config's `extra_ldflags` are appended after Lua's own flags.
+    /// If `prefer_static_lua` is set and a static Lua library is available,
+    /// its path is passed directly instead of linking dynamically.
+    /// NOTE: In luarocks, the Lua link args are behind a link_lua_explicity config option
+    pub(crate) fn link_args(
+        &self,
+        compiler: &cc::Tool,
+        config: &Config,
+        progress: &Progress<ProgressBar>,
+    ) -> Vec<String> {
+        let lua_link_args = if config.prefer_static_lua() && !compiler.is_like_msvc() {
+            match self.static_lib() {
+                Some(static_lib) => vec![static_lib.to_slash_lossy().to_string()],
+                None => {
+                    progress.map(|p| {
+                        p.println(format!(
+                            "⚠️ WARNING: No static Lua {} library found. Falling back to dynamic linking.",
+                            self.version
+                        ))
+                    });
+                    self.dependency_info.lib_link_args(compiler)
+                }
+            }
+        } else {
+            self.dependency_info.lib_link_args(compiler)
+        };
+        lua_link_args
+            .into_iter()
+            .chain(config.extra_ldflags().iter().cloned())
+            .collect_vec()
+    }
+
+    /// The path to a static Lua library (e.g. `liblua.a`), if one exists in the library directories.
+    fn static_lib(&self) -> Option<PathBuf> {
+        let lib_info = self.dependency_info.lib_info.as_ref();
+        let lib_dirs = self
+            .dependency_info
+            .lib_dir
+            .iter()
+            .chain(lib_info.iter().flat_map(|info| info.link_paths.iter()));
+        let lib_names = self
+            .dependency_info
+            .lib_name
+            .iter()
+            .chain(lib_info.iter().flat_map(|info| info.libs.iter()))
+            .collect_vec();
+        lib_dirs
+            .cartesian_product(lib_names)
+            .map(|(lib_dir, lib_name)| {
+                lib_dir.join(format!("lib{}.{}", lib_name, c_lib_extension()))
+            })
+            .find(|static_lib| static_lib.is_file())
     }
 
     /// Get the Lua binary (if present), prioritising
@@ -372,11 +463,7 @@ pub fn detect_installed_lua_version() -> Option<LuaVersion> {
     which("lua")
         .ok()
         .or(which("luajit").ok())
-        .and_then(|lua_cmd| {
-            detect_installed_lua_version_from_path(&lua_cmd)
-                .ok()
-                .and_then(|version| LuaVersion::from_version(version).ok())
-        })
+        .and_then(|lua_cmd| LuaInstallation::detect_version(&lua_cmd).ok())
 }
 
 fn find_lua_executable(bin_path: &Path) -> Option<PathBuf> {
@@ -415,6 +502,12 @@ fn is_lua_lib_name(name: &str, lua_version: &LuaVersion) -> bool {
             && (name.contains(&version_str) || name.contains(&version_suffix))
 }
 
+/// Create the directory if it doesn't exist and check that we can write to it.
+fn ensure_writable_dir(dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    tempdir::TempDir::new_in(dir, "lux-write-check")?.close()
+}
+
 fn get_lua_lib_name(lib_dir: &Path, lua_version: &LuaVersion) -> Option<String> {
     std::fs::read_dir(lib_dir)
         .ok()
@@ -436,14 +529,22 @@ fn get_lua_lib_name(lib_dir: &Path, lua_version: &LuaVersion) -> Option<String>
 
 fn detect_installed_lua_version_from_path(
     lua_cmd: &Path,
-) -> Result<PackageVersion, DetectLuaVersionError> {
+) -> Result<PackageVersion, GetLuaVersionError> {
     let output = match std::process::Command::new(lua_cmd).arg("-v").output() {
         Ok(output) => Ok(output),
-        Err(err) => Err(DetectLuaVersionError::RunLuaCommand(
+        Err(err) => Err(GetLuaVersionError::RunLuaCommand(
             lua_cmd.to_string_lossy().to_string(),
             err,
         )),
     }?;
+    // Some wrapper interpreters (e.g. nlua) don't support `-v`
+    if !output.status.success()
+        || (output.stdout.trim_ascii().is_empty() && output.stderr.trim_ascii().is_empty())
+    {
+        return Err(GetLuaVersionError::NoVersionOutput(
+            lua_cmd.to_string_lossy().to_string(),
+        ));
+    }
     let output_vec = if output.stderr.is_empty() {
         output.stdout
     } else {
@@ -454,21 +555,47 @@ fn detect_installed_lua_version_from_path(
     parse_lua_version_from_output(&lua_output)
 }
 
-fn parse_lua_version_from_output(
-    lua_output: &str,
-) -> Result<PackageVersion, DetectLuaVersionError> {
+/// `table.pack` is only available if LuaJIT was built with `LUAJIT_ENABLE_LUA52COMPAT`.
+fn detect_luajit_lua52_compat(luajit_cmd: &Path) -> Result<bool, GetLuaVersionError> {
+    let output = std::process::Command::new(luajit_cmd)
+        .args(["-e", "io.write(table.pack and '5.2' or '5.1')"])
+        .output()
+        .map_err(|err| {
+            GetLuaVersionError::RunLuaCommand(luajit_cmd.to_string_lossy().to_string(), err)
+        })?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "5.2")
+}
+
+fn parse_lua_version_from_output(lua_output: &str) -> Result<PackageVersion, GetLuaVersionError> {
     let lua_version_str = lua_output
         .trim_start_matches("Lua")
         .trim_start_matches("JIT")
         .split_whitespace()
         .next()
         .map(|s| s.to_string())
-        .ok_or(DetectLuaVersionError::ParseLuaVersion(
-            lua_output.to_string(),
-        ))?;
+        .ok_or(GetLuaVersionError::ParseLuaVersion(lua_output.to_string()))?;
     Ok(PackageVersion::parse(&lua_version_str)?)
 }
 
+#[cfg(test)]
+impl LuaInstallation {
+    /// A Lua installation without any headers or libraries,
+    /// for compiling C code that doesn't use the Lua API, without having to install Lua.
+    pub(crate) fn without_headers(version: LuaVersion) -> Self {
+        Self {
+            version,
+            dependency_info: ExternalDependencyInfo {
+                include_dir: None,
+                lib_dir: None,
+                bin_dir: None,
+                lib_name: None,
+                lib_info: None,
+            },
+            bin: None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::{config::ConfigBuilder, progress::MultiProgress};
@@ -488,6 +615,152 @@ mod test {
         parse_lua_version_from_output(lua_output).unwrap();
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn detect_luajit_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mk_fake_luajit = |name: &str, compat: &str| {
+            let path = temp.join(name);
+            std::fs::write(
+                &path,
+                format!(
+                    r#"#!/bin/sh
+if [ "$1" = "-v" ]; then
+  echo "LuaJIT 2.1.1713773202 -- Copyright (C) 2005-2023 Mike Pall. https://luajit.org/"
+else
+  printf '{compat}'
+fi
+"#
+                ),
+            )
+            .unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+        assert_eq!(
+            LuaInstallation::detect_version(&mk_fake_luajit("luajit", "5.1")).unwrap(),
+            LuaVersion::LuaJIT
+        );
+        assert_eq!(
+            LuaInstallation::detect_version(&mk_fake_luajit("luajit52", "5.2")).unwrap(),
+            LuaVersion::LuaJIT52
+        );
+        assert!(matches!(
+            LuaInstallation::detect_version(&temp.join("missing")),
+            Err(GetLuaVersionError::RunLuaCommand(..))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detect_version_without_output() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mk_fake_lua = |name: &str, script: &str| {
+            let path = temp.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+        for (name, script) in [
+            ("silent", "exit 0"),
+            ("failing", "echo 'Lua 5.1.5' >&2; exit 1"),
+        ] {
+            let lua = mk_fake_lua(name, script);
+            match LuaInstallation::detect_version(&lua) {
+                Err(GetLuaVersionError::NoVersionOutput(cmd)) => {
+                    assert_eq!(cmd, lua.to_string_lossy())
+                }
+                result => panic!("expected NoVersionOutput, got {result:?}"),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn system_lua_not_found() {
+        let lua_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_dir(Some(lua_dir.to_path_buf()))
+            .system_lua(Some(true))
+            .build()
+            .unwrap();
+        if LuaInstallation::probe(&LuaVersion::Lua51, config.external_deps()).is_some() {
+            println!("Skipping system_lua_not_found test, as Lua 5.1 is installed on the system.");
+            return;
+        }
+        let result =
+            LuaInstallation::new(&LuaVersion::Lua51, &config, &Progress::no_progress()).await;
+        assert!(matches!(
+            result,
+            Err(LuaInstallationError::SystemLuaNotFound(LuaVersion::Lua51))
+        ));
+        assert!(!lua_dir.join("include").exists());
+    }
+
+    #[tokio::test]
+    async fn lua_dir_not_writable() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let file = temp.join("file");
+        std::fs::write(&file, "").unwrap();
+        let lua_dir = file.join("lua");
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_dir(Some(lua_dir.clone()))
+            .build()
+            .unwrap();
+        let result =
+            LuaInstallation::install(&LuaVersion::Lua51, &config, &Progress::no_progress()).await;
+        assert!(matches!(
+            result,
+            Err(LuaInstallationError::LuaDirNotWritable(LuaVersion::Lua51, dir, _)) if dir == lua_dir
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn link_args_prefer_static_lua() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let lib_dir = temp.join("lib");
+        std::fs::create_dir_all(&lib_dir).unwrap();
+        let lua = LuaInstallation {
+            version: LuaVersion::Lua51,
+            dependency_info: ExternalDependencyInfo {
+                include_dir: None,
+                lib_dir: Some(lib_dir.clone()),
+                bin_dir: None,
+                lib_name: Some("lua".into()),
+                lib_info: None,
+            },
+            bin: None,
+        };
+        let host = target_lexicon::Triple::host().to_string();
+        let compiler = cc::Build::new()
+            .host(&host)
+            .target(&host)
+            .opt_level(0)
+            .try_get_compiler()
+            .unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .prefer_static_lua(Some(true))
+            .build()
+            .unwrap();
+
+        // No static library: fall back to dynamic linking
+        let dynamic_args = lua.link_args(&compiler, &config, &Progress::no_progress());
+        assert!(dynamic_args.contains(&"-llua".to_string()));
+
+        let static_lib = lib_dir.join("liblua.a");
+        std::fs::write(&static_lib, "").unwrap();
+        let static_args = lua.link_args(&compiler, &config, &Progress::no_progress());
+        assert_eq!(static_args, vec![static_lib.to_slash_lossy().to_string()]);
+    }
+
     #[tokio::test]
     async fn lua_installation_bin() {
         if std::env::var("LUX_SKIP_IMPURE_TESTS").unwrap_or("0".into()) == "1" {
@@ -20,7 +20,11 @@ use itertools::Itertools;
 
 use mlua::{FromLua, IntoLua, Lua, LuaSerdeExt, UserData, Value};
 use std::{
-    collections::HashMap, env::consts::DLL_EXTENSION, fmt::Display, path::PathBuf, str::FromStr,
+    collections::HashMap,
+    env::consts::DLL_EXTENSION,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 use thiserror::Error;
 
@@ -54,6 +58,11 @@ pub struct BuildSpec {
     // NOTE: This cannot be a diffy::Patch<'a, str>
     // because Lua::from_value requires a DeserializeOwned
     pub patches: HashMap<PathBuf, String>,
+    /// A shell command to run in the build directory before the build backend.
+    pub pre_build: Option<String>,
+    /// A shell command to run in the build directory after the build backend
+    /// and before installing.
+    pub post_build: Option<String>,
 }
 
 impl Default for BuildSpec {
@@ -63,6 +72,8 @@ impl Default for BuildSpec {
             install: InstallSpec::default(),
             copy_directories: Vec::default(),
             patches: HashMap::default(),
+            pre_build: None,
+            post_build: None,
         }
     }
 }
@@ -77,6 +88,8 @@ impl UserData for BuildSpec {
             Ok(this.copy_directories.clone())
         });
         methods.add_method("patches", |_, this, _: ()| Ok(this.patches.clone()));
+        methods.add_method("pre_build", |_, this, _: ()| Ok(this.pre_build.clone()));
+        methods.add_method("post_build", |_, this, _: ()| Ok(this.post_build.clone()));
     }
 }
 
@@ -204,6 +217,8 @@ impl BuildSpec {
             install: internal.install.unwrap_or_default(),
             copy_directories: internal.copy_directories.unwrap_or_default(),
             patches: internal.patches.unwrap_or_default(),
+            pre_build: internal.pre_build,
+            post_build: internal.post_build,
         })
     }
 }
@@ -274,6 +289,30 @@ impl BuildBackendSpec {
             }
         }
     }
+
+    /// Whether this build backend produces native (architecture-dependent) artifacts.
+    pub(crate) fn builds_native_code(&self) -> bool {
+        match self {
+            Self::Builtin(spec) => spec.modules.values().any(|module| match module {
+                ModuleSpec::SourcePath(path) => !is_lua_source(path),
+                ModuleSpec::SourcePaths(_) => true,
+                ModuleSpec::ModulePaths(paths) => {
+                    paths.sources.iter().any(|path| !is_lua_source(path))
+                }
+            }),
+            Self::Make(_)
+            | Self::CMake(_)
+            | Self::Command(_)
+            | Self::LuaRock(_)
+            | Self::RustMlua(_)
+            | Self::TreesitterParser(_) => true,
+            Self::Source => false,
+        }
+    }
+}
+
+fn is_lua_source(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "lua")
 }
 
 impl IntoLua for BuildBackendSpec {
@@ -382,6 +421,9 @@ pub struct InstallSpec {
     /// Configuration files.
     #[serde(default)]
     pub conf: HashMap<String, PathBuf>,
+    /// Documentation files.
+    #[serde(default)]
+    pub doc: HashMap<String, PathBuf>,
     /// Lua command-line scripts.
     // TODO(vhyrro): The String component should be checked to ensure that it consists of a single
     // path component, such that targets like `my.binary` are not allowed.
@@ -394,6 +436,7 @@ impl UserData for InstallSpec {
         methods.add_method("lua", |_, this, _: ()| Ok(this.lua.clone()));
         methods.add_method("lib", |_, this, _: ()| Ok(this.lib.clone()));
         methods.add_method("conf", |_, this, _: ()| Ok(this.conf.clone()));
+        methods.add_method("doc", |_, this, _: ()| Ok(this.doc.clone()));
         methods.add_method("bin", |_, this, _: ()| Ok(this.bin.clone()));
     }
 }
@@ -502,6 +545,20 @@ impl DisplayAsLuaKV for InstallSpec {
             });
         }
 
+        let mut doc_entries = Vec::new();
+        self.doc.iter().for_each(|(key, value)| {
+            doc_entries.push(DisplayLuaKV {
+                key: key.clone(),
+                value: DisplayLuaValue::String(value.to_slash_lossy().to_string()),
+            });
+        });
+        if !doc_entries.is_empty() {
+            result.push(DisplayLuaKV {
+                key: "doc".to_string(),
+                value: DisplayLuaValue::Table(doc_entries),
+            });
+        }
+
         DisplayLuaKV {
             key: "install".to_string(),
             value: DisplayLuaValue::Table(result),
@@ -543,6 +600,10 @@ pub(crate) struct BuildSpecInternal {
     pub(crate) copy_directories: Option<Vec<PathBuf>>,
     #[serde(default)]
     pub(crate) patches: Option<HashMap<PathBuf, String>>,
+    #[serde(default)]
+    pub(crate) pre_build: Option<String>,
+    #[serde(default)]
+    pub(crate) post_build: Option<String>,
     // rust-mlua fields
     #[serde(default)]
     pub(crate) target_path: Option<PathBuf>,
@@ -694,6 +755,8 @@ fn override_build_spec_internal(
             _ => None,
         },
         patches: override_opt(&override_spec.patches, &base.patches),
+        pre_build: override_opt(&override_spec.pre_build, &base.pre_build),
+        post_build: override_opt(&override_spec.post_build, &base.post_build),
         target_path: override_opt(&override_spec.target_path, &base.target_path),
         default_features: override_opt(&override_spec.default_features, &base.default_features),
         features: override_opt(&override_spec.features, &base.features),
@@ -881,6 +944,18 @@ impl DisplayAsLuaKV for BuildSpecInternal {
                 ),
             });
         }
+        if let Some(pre_build) = &self.pre_build {
+            result.push(DisplayLuaKV {
+                key: "pre_build".to_string(),
+                value: DisplayLuaValue::String(pre_build.clone()),
+            });
+        }
+        if let Some(post_build) = &self.post_build {
+            result.push(DisplayLuaKV {
+                key: "post_build".to_string(),
+                value: DisplayLuaValue::String(post_build.clone()),
+            });
+        }
         if let Some(target_path) = &self.target_path {
             result.push(DisplayLuaKV {
                 key: "target_path".to_string(),
@@ -381,6 +381,8 @@ build = {{
                 install: InstallSpec::default(),
                 copy_directories: Vec::new(),
                 patches: HashMap::new(),
+                pre_build: None,
+                post_build: None,
             }),
             source: PerPlatform::new(source.clone()),
             test: PerPlatform::default(),
@@ -494,6 +496,10 @@ pub enum LuaVersionError {
     LuaVersionUnsupported(LuaVersion, PackageName, PackageVersion),
     #[error(transparent)]
     LuaVersionUnset(#[from] LuaVersionUnset),
+    #[error("error reading {0}:\n{1}")]
+    ReadLuaVersionFile(PathBuf, io::Error),
+    #[error("unrecognized Lua version '{1}' in {0}. Allowed versions: '5.1', '5.2', '5.3', '5.4', 'jit', 'jit52' or a full Lua version, e.g. '5.4.6'.")]
+    InvalidLuaVersionFile(PathBuf, String),
 }
 
 impl HasIntegrity for RemoteLuaRockspec {
@@ -696,6 +702,7 @@ mod tests {
 
     use std::path::PathBuf;
 
+    use crate::config::ConfigBuilder;
     use crate::git::GitSource;
     use crate::lua_rockspec::PlatformIdentifier;
     use crate::package::PackageSpec;
@@ -1456,6 +1463,41 @@ mod tests {
         assert!(!deploy_spec.wrap_bin_scripts);
     }
 
+    #[tokio::test]
+    pub async fn dependencies_for_target_platform() {
+        let rockspec_content = "
+        package = 'foo'\n
+        version = '1.0.0-1'\n
+        dependencies = {\n
+            'lua >= 5.1',\n
+            platforms = {\n
+                linux = { 'luaposix' },\n
+                macosx = { 'macfoo' },\n
+                windows = { 'winapi' },\n
+            },\n
+        }\n
+        source = { url = 'https://github.com/example-project/foo/archive/1.0.0.zip' }\n
+        ";
+        let rockspec = RemoteLuaRockspec::new(rockspec_content).unwrap();
+        let dependency_names = |target: &str| {
+            let config = ConfigBuilder::default()
+                .target_platform(Some(target.parse().unwrap()))
+                .build()
+                .unwrap();
+            rockspec
+                .dependencies()
+                .for_target_platform(&config)
+                .iter()
+                .map(|dep| dep.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(dependency_names("x86_64-unknown-linux-gnu").contains(&"luaposix".into()));
+        assert!(!dependency_names("x86_64-unknown-linux-gnu").contains(&"macfoo".into()));
+        assert!(dependency_names("aarch64-apple-darwin").contains(&"macfoo".into()));
+        assert!(dependency_names("x86_64-pc-windows-msvc").contains(&"winapi".into()));
+        assert!(!dependency_names("x86_64-pc-windows-msvc").contains(&"luaposix".into()));
+    }
+
     #[tokio::test]
     pub async fn parse_scm_rockspec() {
         let rockspec_content = "
@@ -1707,4 +1749,57 @@ mod tests {
         assert_eq!(rockspec2.local.version, "1.0.5".parse().unwrap());
         assert_eq!(rockspec2.local.source, PerPlatform::new(source_spec.into()));
     }
+
+    #[tokio::test]
+    pub async fn parse_build_hooks() {
+        let rockspec_content = "
+        rockspec_format = '3.0'\n
+        package = 'foo'\n
+        version = '1.0.0-1'\n
+        source = {\n
+            url = 'https://example.com/foo.zip',\n
+        }\n
+        build = {\n
+            type = 'builtin',\n
+            pre_build = 'touch $(LUADIR)/pre',\n
+            post_build = 'touch $(LUADIR)/post',\n
+            platforms = {\n
+                windows = {\n
+                    pre_build = 'type nul > $(LUADIR)/pre',\n
+                },\n
+            },\n
+        }\n
+        "
+        .to_string();
+        let rockspec = RemoteLuaRockspec::new(&rockspec_content).unwrap();
+        let build_spec = rockspec.local.build.default;
+        assert_eq!(build_spec.pre_build, Some("touch $(LUADIR)/pre".into()));
+        assert_eq!(build_spec.post_build, Some("touch $(LUADIR)/post".into()));
+        let windows_build_spec = rockspec
+            .local
+            .build
+            .per_platform
+            .get(&PlatformIdentifier::Windows)
+            .unwrap();
+        assert_eq!(
+            windows_build_spec.pre_build,
+            Some("type nul > $(LUADIR)/pre".into())
+        );
+        assert_eq!(
+            windows_build_spec.post_build,
+            Some("touch $(LUADIR)/post".into())
+        );
+
+        let rockspec_content = "
+        package = 'foo'\n
+        version = '1.0.0-1'\n
+        source = {\n
+            url = 'https://example.com/foo.zip',\n
+        }\n
+        "
+        .to_string();
+        let rockspec = RemoteLuaRockspec::new(&rockspec_content).unwrap();
+        assert_eq!(rockspec.local.build.default.pre_build, None);
+        assert_eq!(rockspec.local.build.default.post_build, None);
+    }
 }
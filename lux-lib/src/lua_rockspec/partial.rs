@@ -12,7 +12,7 @@ use super::{
     PlatformSupport, RockDescription, TestSpecInternal,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PartialLuaRockspec {
     pub(crate) rockspec_format: Option<RockspecFormat>,
     pub(crate) package: Option<PackageName>,
@@ -10,6 +10,9 @@ use serde::{
     Deserialize, Deserializer,
 };
 use serde_enum_str::{Deserialize_enum_str, Serialize_enum_str};
+use target_lexicon::{Environment, OperatingSystem, Triple, Vendor};
+
+use crate::config::Config;
 
 use super::{DisplayAsLuaKV, DisplayLuaKV, DisplayLuaValue};
 
@@ -88,7 +91,30 @@ fn target_identifier() -> PlatformIdentifier {
     }
 }
 
+impl From<&Triple> for PlatformIdentifier {
+    fn from(triple: &Triple) -> Self {
+        match triple.operating_system {
+            _ if triple.environment == Environment::Msvc => PlatformIdentifier::Windows,
+            OperatingSystem::Windows => PlatformIdentifier::Windows,
+            OperatingSystem::Linux => PlatformIdentifier::Linux,
+            OperatingSystem::Darwin(_) | OperatingSystem::MacOSX(_) => PlatformIdentifier::MacOSX,
+            _ if triple.vendor == Vendor::Apple => PlatformIdentifier::MacOSX,
+            OperatingSystem::Freebsd => PlatformIdentifier::FreeBSD,
+            _ => PlatformIdentifier::Unix,
+        }
+    }
+}
+
 impl PlatformIdentifier {
+    /// The identifier of the configured `target_platform`,
+    /// or of the platform lux was built with if none is configured.
+    pub(crate) fn target_platform(config: &Config) -> Self {
+        match config.target_platform() {
+            Some(triple) => triple.into(),
+            None => target_identifier(),
+        }
+    }
+
     /// Get identifiers that are a subset of this identifier.
     /// For example, Unix is a subset of Linux
     pub fn get_subsets(&self) -> Vec<Self> {
@@ -314,6 +340,12 @@ impl<T> PerPlatform<T> {
         self.for_platform_identifier(&target_identifier())
     }
 
+    /// Like [`PerPlatform::current_platform`], but respects the configured
+    /// `target_platform` override, e.g. for resolving the dependencies of another platform.
+    pub fn for_target_platform(&self, config: &Config) -> &T {
+        self.for_platform_identifier(&PlatformIdentifier::target_platform(config))
+    }
+
     fn for_platform_identifier(&self, identifier: &PlatformIdentifier) -> &T {
         self.get(identifier)
     }
@@ -348,6 +380,25 @@ impl<T> PerPlatform<T> {
     }
 }
 
+impl<T> PerPlatform<T>
+where
+    T: PartialOverride,
+    T: Clone,
+{
+    /// Construct a `PerPlatform` from a base value and raw per-platform overrides,
+    /// applying each override to the base value.
+    pub(crate) fn with_overrides(
+        default: T,
+        mut per_platform: HashMap<PlatformIdentifier, T>,
+    ) -> Result<Self, T::Err> {
+        apply_per_platform_overrides(&mut per_platform, &default)?;
+        Ok(Self {
+            default,
+            per_platform,
+        })
+    }
+}
+
 impl<U, E> PerPlatform<Result<U, E>>
 where
     E: std::error::Error,
@@ -89,6 +89,8 @@ pub(crate) enum DisplayLuaValue {
     String(String),
     List(Vec<Self>),
     Table(Vec<DisplayLuaKV>),
+    /// A list with additional key-value entries, e.g. `{ "foo", platforms = { ... } }`
+    ListWithEntries(Vec<Self>, Vec<DisplayLuaKV>),
 }
 
 pub(crate) struct DisplayLuaKV {
@@ -121,6 +123,16 @@ impl Display for DisplayLuaValue {
 
                 write!(buf, "}}")?;
             }
+            DisplayLuaValue::ListWithEntries(l, t) => {
+                writeln!(buf, "{{")?;
+                for item in l {
+                    writeln!(buf, "{item},")?;
+                }
+                for item in t {
+                    writeln!(buf, "{item},")?;
+                }
+                write!(buf, "}}")?;
+            }
         };
         let output = match stylua_lib::format_code(
             &buf,
@@ -10,9 +10,10 @@ use thiserror::Error;
 
 use crate::{
     build::{
+        check_binary_collisions,
         external_dependency::{ExternalDependencyError, ExternalDependencyInfo},
         utils::recursive_copy_dir,
-        BuildBehaviour,
+        BinaryCollisionError, BuildBehaviour,
     },
     config::Config,
     hash::HasIntegrity,
@@ -53,12 +54,17 @@ pub enum InstallBinaryRockError {
         "the entry {0} listed in the `rock_manifest` is neither a file nor a directory: {1:?}"
     )]
     NotAFileOrDirectory(String, std::fs::Metadata),
+    #[error("the binary rock {0} is labeled as architecture-independent, but its rockspec declares a native build")]
+    ArchDependentAllRock(String),
+    #[error(transparent)]
+    BinaryCollision(#[from] BinaryCollisionError),
 }
 
 pub(crate) struct BinaryRockInstall<'a> {
     rockspec: &'a RemoteLuaRockspec,
     rock_bytes: Bytes,
     source: RemotePackageSource,
+    source_url: Option<RemotePackageSourceUrl>,
     pin: PinnedState,
     opt: OptState,
     entry_type: tree::EntryType,
@@ -83,6 +89,7 @@ impl<'a> BinaryRockInstall<'a> {
             rockspec,
             rock_bytes,
             source,
+            source_url: None,
             config,
             tree,
             progress,
@@ -110,6 +117,27 @@ impl<'a> BinaryRockInstall<'a> {
         Self { behaviour, ..self }
     }
 
+    /// The full URL the packed rock was downloaded from.
+    pub(crate) fn source_url(self, source_url: RemotePackageSourceUrl) -> Self {
+        Self {
+            source_url: Some(source_url),
+            ..self
+        }
+    }
+
+    /// The file name of the packed rock, if it was built for the `all` architecture.
+    fn all_arch_rock_file_name(&self) -> Option<String> {
+        let file_name = self
+            .source_url
+            .as_ref()?
+            .packed_rock_url()?
+            .path_segments()?
+            .next_back()?;
+        file_name
+            .ends_with(".all.rock")
+            .then(|| file_name.to_string())
+    }
+
     pub(crate) async fn install(self) -> Result<LocalPackage, InstallBinaryRockError> {
         let rockspec = self.rockspec;
         self.progress.map(|p| {
@@ -125,16 +153,30 @@ impl<'a> BinaryRockInstall<'a> {
 
         rockspec.validate_lua_version_from_config(self.config)?;
 
+        if self.config.strict_binary_arch() {
+            if let Some(file_name) = self.all_arch_rock_file_name() {
+                let builds_native_code = rockspec
+                    .build()
+                    .current_platform()
+                    .build_backend
+                    .as_ref()
+                    .is_some_and(|build_backend| build_backend.builds_native_code());
+                if builds_native_code {
+                    return Err(InstallBinaryRockError::ArchDependentAllRock(file_name));
+                }
+            }
+        }
+
         let hashes = LocalPackageHashes {
             rockspec: rockspec.hash()?,
             source: self.rock_bytes.hash()?,
         };
-        let source_url = match &self.source {
+        let source_url = self.source_url.or_else(|| match &self.source {
             RemotePackageSource::LuarocksBinaryRock(url) => {
                 Some(RemotePackageSourceUrl::Url { url: url.clone() })
             }
             _ => None,
-        };
+        });
         let mut package = LocalPackage::from(
             &PackageSpec::new(rockspec.package().clone(), rockspec.version().clone()),
             self.constraint,
@@ -146,7 +188,12 @@ impl<'a> BinaryRockInstall<'a> {
         package.spec.pinned = self.pin;
         package.spec.opt = self.opt;
         match self.tree.lockfile()?.get(&package.id()) {
-            Some(package) if self.behaviour == BuildBehaviour::NoForce => Ok(package.clone()),
+            Some(package)
+                if self.behaviour == BuildBehaviour::NoForce
+                    && !package.requires_rebuild(self.tree.version()) =>
+            {
+                Ok(package.clone())
+            }
             _ => {
                 let unpack_dir = TempDir::new("lux-cli-rock").unwrap().into_path();
                 let cursor = Cursor::new(self.rock_bytes);
@@ -167,6 +214,9 @@ impl<'a> BinaryRockInstall<'a> {
                     tree::EntryType::DependencyOnly => self.tree.dependency(&package)?,
                 };
                 let rock_manifest = RockManifest::new(&rock_manifest_content)?;
+                if !rock_manifest.lib.entries.is_empty() {
+                    package.lua_version = Some(self.tree.version().clone());
+                }
                 install_manifest_entries(
                     &rock_manifest.lib.entries,
                     &unpack_dir.join("lib"),
@@ -179,6 +229,20 @@ impl<'a> BinaryRockInstall<'a> {
                     &output_paths.src,
                 )
                 .await?;
+                let binaries = rock_manifest
+                    .bin
+                    .entries
+                    .keys()
+                    .filter_map(|binary| binary.file_name())
+                    .map(|file_name| file_name.to_string_lossy().to_string())
+                    .collect::<Vec<_>>();
+                check_binary_collisions(
+                    package.name(),
+                    binaries.iter().map(String::as_str),
+                    self.tree,
+                    self.progress,
+                    self.config,
+                )?;
                 install_manifest_entries(
                     &rock_manifest.bin.entries,
                     &unpack_dir.join("bin"),
@@ -206,6 +270,10 @@ impl<'a> BinaryRockInstall<'a> {
                 if rockspec_path.is_file() {
                     tokio::fs::copy(&rockspec_path, output_paths.rockspec_path()).await?;
                     tokio::fs::remove_file(&rockspec_path).await?;
+                } else {
+                    // Packed rocks aren't required to contain their rockspec.
+                    let Ok(rockspec_str) = rockspec.to_lua_remote_rockspec_string();
+                    tokio::fs::write(output_paths.rockspec_path(), rockspec_str).await?;
                 }
                 Ok(package)
             }
@@ -243,7 +311,7 @@ mod test {
     use io::Read;
 
     use crate::{
-        config::ConfigBuilder,
+        config::{ConfigBuilder, LuaVersion},
         operations::{unpack_rockspec, DownloadedPackedRockBytes, Pack, Uninstall},
         progress::MultiProgress,
     };
@@ -297,6 +365,158 @@ mod test {
         assert!(foo_bar_module.is_file());
     }
 
+    #[tokio::test]
+    async fn strict_binary_arch_rejects_native_all_rock() {
+        let content = std::fs::read("resources/test/sample-project-0.1.0-1.all.rock").unwrap();
+        let rock_bytes = Bytes::copy_from_slice(&content);
+        let rockspec_content =
+            std::fs::read_to_string("resources/test/lua-cjson-2.1.0-1.rockspec").unwrap();
+        let rockspec = RemoteLuaRockspec::new(&rockspec_content).unwrap();
+        let install_root = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(install_root.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .strict_binary_arch(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let result = BinaryRockInstall::new(
+            &rockspec,
+            RemotePackageSource::Test,
+            rock_bytes,
+            tree::EntryType::Entrypoint,
+            &config,
+            &tree,
+            &bar,
+        )
+        .source_url(RemotePackageSourceUrl::Url {
+            url: "https://test.org/lua-cjson-2.1.0-1.all.rock"
+                .parse()
+                .unwrap(),
+        })
+        .install()
+        .await;
+        assert!(matches!(
+            result,
+            Err(InstallBinaryRockError::ArchDependentAllRock(file_name))
+                if file_name == "lua-cjson-2.1.0-1.all.rock"
+        ));
+    }
+
+    #[tokio::test]
+    async fn strict_binary_arch_accepts_pure_lua_all_rock() {
+        let content = std::fs::read("resources/test/sample-project-0.1.0-1.all.rock").unwrap();
+        let rock = DownloadedPackedRockBytes {
+            name: "sample-project".into(),
+            version: "0.1.0-1".parse().unwrap(),
+            bytes: Bytes::copy_from_slice(&content),
+            file_name: "sample-project-0.1.0-1.all.rock".into(),
+            url: "https://test.org/sample-project-0.1.0-1.all.rock"
+                .parse()
+                .unwrap(),
+        };
+        let rockspec = unpack_rockspec(&rock).await.unwrap();
+        let install_root = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(install_root.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .strict_binary_arch(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let local_package = BinaryRockInstall::new(
+            &rockspec,
+            RemotePackageSource::Test,
+            rock.bytes,
+            tree::EntryType::Entrypoint,
+            &config,
+            &tree,
+            &bar,
+        )
+        .source_url(RemotePackageSourceUrl::Url { url: rock.url })
+        .install()
+        .await
+        .unwrap();
+        let rock_layout = tree.entrypoint_layout(&local_package);
+        assert!(rock_layout.src.join("foo").join("bar.lua").is_file());
+    }
+
+    #[tokio::test]
+    async fn binary_rock_binary_collision() {
+        let content = std::fs::read("resources/test/sample-project-0.1.0-1.all.rock").unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(content)).unwrap();
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).unwrap();
+            if file.name() == "rock_manifest" {
+                let mut manifest = String::new();
+                file.read_to_string(&mut manifest).unwrap();
+                let manifest = manifest.replace(
+                    "bin = {\n}",
+                    "bin = {\n['busted'] = \"d41d8cd98f00b204e9800998ecf8427e\",\n}",
+                );
+                writer.start_file("rock_manifest", options).unwrap();
+                io::Write::write_all(&mut writer, manifest.as_bytes()).unwrap();
+            } else {
+                writer.raw_copy_file(file).unwrap();
+            }
+        }
+        writer.start_file("bin/busted", options).unwrap();
+        let rock_bytes = Bytes::from(writer.finish().unwrap().into_inner());
+        let rock = DownloadedPackedRockBytes {
+            name: "sample-project".into(),
+            version: "0.1.0-1".parse().unwrap(),
+            bytes: rock_bytes,
+            file_name: "sample-project-0.1.0-1.all.rock".into(),
+            url: "https://test.org".parse().unwrap(),
+        };
+        let rockspec = unpack_rockspec(&rock).await.unwrap();
+        let install_root = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(install_root.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .deny_binary_collisions(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let mut provider = LocalPackage::mock("busted", "2.2.0-1");
+        provider.spec.binaries.push("bin/busted".into());
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&provider);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+        let result = BinaryRockInstall::new(
+            &rockspec,
+            RemotePackageSource::Test,
+            rock.bytes,
+            tree::EntryType::Entrypoint,
+            &config,
+            &tree,
+            &bar,
+        )
+        .install()
+        .await;
+        assert!(matches!(
+            result,
+            Err(InstallBinaryRockError::BinaryCollision(
+                BinaryCollisionError::Collision { binary, .. }
+            )) if binary == "busted"
+        ));
+    }
+
     /// This relatively large integration test case tests the following:
     ///
     /// - Install a packed rock that was packed using luarocks 3.11 from the test resources.
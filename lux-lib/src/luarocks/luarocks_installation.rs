@@ -13,6 +13,7 @@ use crate::{
     build::{self, BuildError},
     config::{Config, LuaVersion, LuaVersionUnset},
     lua_installation::LuaInstallation,
+    lua_rockspec::LuaVersionError,
     operations::UnpackError,
     path::{Paths, PathsError},
     progress::{Progress, ProgressBar},
@@ -68,6 +69,8 @@ pub enum LuaRocksInstallError {
     BuildError(#[from] BuildError),
     #[error(transparent)]
     Request(#[from] reqwest::Error),
+    #[error("invalid luarocks download URL: {0}")]
+    Url(#[from] url::ParseError),
     #[error(transparent)]
     UnpackError(#[from] UnpackError),
     #[error("luarocks integrity mismatch.\nExpected: {expected}\nBut got: {got}")]
@@ -77,7 +80,7 @@ pub enum LuaRocksInstallError {
 #[derive(Error, Debug)]
 pub enum ExecLuaRocksError {
     #[error(transparent)]
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
     #[error("could not write luarocks config: {0}")]
     WriteLuarocksConfigError(io::Error),
     #[error("could not write luarocks config: {0}")]
@@ -148,8 +151,17 @@ impl LuaRocksInstallation {
     ) -> Result<(), LuaRocksInstallError> {
         use crate::{hash::HasIntegrity, operations};
         use std::io::Cursor;
-        let url = "https://luarocks.github.io/luarocks/releases/luarocks-3.11.1-windows-64.zip";
-        let response = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+        let url: url::Url =
+            "https://luarocks.github.io/luarocks/releases/luarocks-3.11.1-windows-64.zip"
+                .parse()?;
+        let response = self
+            .config
+            .download(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
         let hash = response.hash()?;
         let expected_hash: Integrity = "sha256-xx26PQPhIwXpzNAixiHIhpq6PRJNkkniFK7VwW82gqM="
             .parse()
@@ -227,7 +239,7 @@ variables = {{
 }}
 "#,
             lua_version_str,
-            LuaVersion::from(&self.config)?,
+            self.config.resolve_lua_version(None)?,
             self.config.make_cmd(),
         );
         let luarocks_config_content =
@@ -1,22 +1,21 @@
 use itertools::Itertools;
 use mlua::{Lua, LuaSerdeExt};
-use reqwest::{header::ToStrError, Client};
+use reqwest::{header::ToStrError, Method, StatusCode};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::string::FromUtf8Error;
 use std::time::SystemTime;
-use std::{cmp::Ordering, collections::HashMap};
 use thiserror::Error;
-use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::{fs, io};
 use url::Url;
 use zip::ZipArchive;
 
-use crate::config::LuaVersionUnset;
+use crate::config::display_request_error;
 use crate::package::{RemotePackageType, RemotePackageTypeFilterSpec};
 use crate::progress::{Progress, ProgressBar};
 use crate::{
-    config::{Config, LuaVersion},
+    config::Config,
+    lua_rockspec::LuaVersionError,
     package::{PackageName, PackageReq, PackageSpec, PackageVersion, RemotePackage},
     remote_package_source::RemotePackageSource,
 };
@@ -25,7 +24,7 @@ use crate::{
 pub enum ManifestFromServerError {
     #[error(transparent)]
     Io(#[from] io::Error),
-    #[error("failed to pull manifest: {0}")]
+    #[error("failed to pull manifest: {}", display_request_error(.0))]
     Request(#[from] reqwest::Error),
     #[error("failed to parse manifest: {0}")]
     FromUtf8(#[from] FromUtf8Error),
@@ -40,27 +39,35 @@ pub enum ManifestFromServerError {
     #[error("failed to unzip manifest file {0}:\n{1}")]
     ZipExtract(Url, zip::result::ZipError),
     #[error(transparent)]
-    LuaVersion(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
 }
 
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(url = %url)))]
 async fn get_manifest(
     url: Url,
     manifest_version: String,
     target: &Path,
-    client: &Client,
+    unversioned_fallback: bool,
+    config: &Config,
 ) -> Result<String, ManifestFromServerError> {
-    let response = client.get(url.clone()).send().await?;
+    let response = config.request(Method::GET, url.clone()).send().await?;
     if response.status().is_client_error() {
-        let url = fallback_unzipped_url(&url)?;
-        let manifest_bytes = client
-            .get(url)
+        let response = match config
+            .request(Method::GET, fallback_unzipped_url(&url)?)
             .send()
             .await?
-            .error_for_status()?
-            .bytes()
-            .await?;
+        {
+            response if unversioned_fallback && response.status() == StatusCode::NOT_FOUND => {
+                config
+                    .request(Method::GET, fallback_unversioned_url(&url)?)
+                    .send()
+                    .await?
+            }
+            response => response,
+        };
+        let manifest_bytes = response.error_for_status()?.bytes().await?;
         let manifest = String::from_utf8(manifest_bytes.to_vec())?;
-        tokio::fs::write(&target, &manifest).await?;
+        write_manifest_cache(target, &manifest).await?;
         Ok(manifest)
     } else {
         let manifest_bytes = response.error_for_status()?.bytes().await?;
@@ -73,27 +80,44 @@ async fn get_manifest(
             .extract_unwrapped_root_dir(&temp, zip::read::root_dir_common_filter)
             .map_err(|err| ManifestFromServerError::ZipExtract(url.clone(), err))?;
 
-        let mut extracted_manifest =
-            File::open(temp.path().join(format!("manifest-{manifest_version}"))).await?;
-        let mut target = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(target)
-            .await?;
-
-        io::copy(&mut extracted_manifest, &mut target).await?;
-
-        let mut manifest = String::new();
-
-        target.seek(io::SeekFrom::Start(0)).await?;
-        target.read_to_string(&mut manifest).await?;
-
+        let manifest =
+            fs::read_to_string(temp.path().join(format!("manifest-{manifest_version}"))).await?;
+        write_manifest_cache(target, &manifest).await?;
         Ok(manifest)
     }
 }
 
+/// Write a manifest to the cache, so that concurrent processes refreshing
+/// the same manifest can't interleave their writes.
+/// The manifest is written to a temporary file, which is then renamed to the cache path
+/// while holding an advisory lock on `<cache>.lock`.
+/// We don't lock the cache file itself, because renaming replaces it.
+async fn write_manifest_cache(cache: &Path, manifest: &str) -> io::Result<()> {
+    let cache = cache.to_path_buf();
+    let manifest = manifest.to_string();
+    tokio::task::spawn_blocking(move || {
+        let cache_dir = cache
+            .parent()
+            .expect("manifest cache has a parent directory");
+        let mut lock_path = cache.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path)?;
+        lock_file.lock()?;
+        // The temporary directory is created next to the cache,
+        // so that it is on the same file system and can be renamed.
+        let temp = tempdir::TempDir::new_in(cache_dir, "lux-manifest")?;
+        let temp_manifest = temp.path().join("manifest");
+        std::fs::write(&temp_manifest, manifest)?;
+        std::fs::rename(&temp_manifest, &cache)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
 /// Look up the manifest from a cache, or get the manifest from the server
 /// if the cache doesn't exist or is outdated.
 async fn manifest_from_cache_or_server(
@@ -101,24 +125,39 @@ async fn manifest_from_cache_or_server(
     config: &Config,
     bar: &Progress<ProgressBar>,
 ) -> Result<String, ManifestFromServerError> {
-    let manifest_version = LuaVersion::from(config)?.version_compatibility_str();
+    let manifest_version = config
+        .resolve_lua_version(None)?
+        .version_compatibility_str();
     let url = mk_manifest_url(server_url, &manifest_version, config)?;
 
     // Stores a path to the manifest cache (this allows us to operate on a manifest without
     // needing to pull it from the luarocks servers each time).
-    let cache = mk_manifest_cache(&url, config).await?;
-
-    let client = Client::new();
+    let cache = mk_manifest_cache(&url, &manifest_version, config).await?;
+    let unversioned_fallback = serves_unversioned_manifest(server_url, config);
 
     // Read the metadata of the local cache and attempt to get the last modified date.
     if let Ok(metadata) = fs::metadata(&cache).await {
         let last_modified_local: SystemTime = metadata.modified()?;
 
         // Ask the server for the last modified date of its manifest.
-        let response = match client.head(url.clone()).send().await? {
+        let response = match config.request(Method::HEAD, url.clone()).send().await? {
             response if response.status().is_client_error() => {
-                let url = fallback_unzipped_url(&url)?;
-                client.head(url).send().await?.error_for_status()?
+                match config
+                    .request(Method::HEAD, fallback_unzipped_url(&url)?)
+                    .send()
+                    .await?
+                {
+                    response
+                        if unversioned_fallback && response.status() == StatusCode::NOT_FOUND =>
+                    {
+                        config
+                            .request(Method::HEAD, fallback_unversioned_url(&url)?)
+                            .send()
+                            .await?
+                            .error_for_status()?
+                    }
+                    response => response.error_for_status()?,
+                }
             }
             response => response.error_for_status()?,
         };
@@ -134,7 +173,14 @@ async fn manifest_from_cache_or_server(
                     bar.set_message(format!("📥 Downloading updated manifest from {}", &url))
                 });
 
-                return get_manifest(url, manifest_version.clone(), &cache, &client).await;
+                return get_manifest(
+                    url,
+                    manifest_version.clone(),
+                    &cache,
+                    unversioned_fallback,
+                    config,
+                )
+                .await;
             }
 
             // Else return the cached manifest.
@@ -146,7 +192,52 @@ async fn manifest_from_cache_or_server(
     // TODO(#337): switch to something that can report progress
     bar.map(|bar| bar.set_message(format!("📥 Downloading manifest from {}", &url)));
 
-    get_manifest(url, manifest_version.clone(), &cache, &client).await
+    get_manifest(
+        url,
+        manifest_version.clone(),
+        &cache,
+        unversioned_fallback,
+        config,
+    )
+    .await
+}
+
+/// Look up the manifest from a cache or the server, and parse it.
+/// If the manifest is corrupt, e.g. because a download was interrupted,
+/// the cache is deleted and the manifest is downloaded again, once.
+async fn manifest_metadata_from_cache_or_server(
+    server_url: &Url,
+    config: &Config,
+    bar: &Progress<ProgressBar>,
+) -> Result<ManifestMetadata, ManifestError> {
+    let metadata = manifest_from_cache_or_server(server_url, config, bar)
+        .await
+        .map_err(ManifestError::from)
+        .and_then(|manifest| Ok(ManifestMetadata::new(&manifest)?));
+    match metadata {
+        Err(err) if err.is_corrupt_manifest() => {
+            remove_manifest_cache(server_url, config).await?;
+            let manifest = manifest_from_server_only(server_url, config, bar).await?;
+            Ok(ManifestMetadata::new(&manifest)?)
+        }
+        metadata => metadata,
+    }
+}
+
+/// Delete the cached manifest, if it exists.
+async fn remove_manifest_cache(
+    server_url: &Url,
+    config: &Config,
+) -> Result<(), ManifestFromServerError> {
+    let manifest_version = config
+        .resolve_lua_version(None)?
+        .version_compatibility_str();
+    let url = mk_manifest_url(server_url, &manifest_version, config)?;
+    let cache = mk_manifest_cache(&url, &manifest_version, config).await?;
+    match fs::remove_file(&cache).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
 }
 
 /// Get the manifest from the server, ignoring the cache.
@@ -156,12 +247,21 @@ pub(crate) async fn manifest_from_server_only(
     config: &Config,
     bar: &Progress<ProgressBar>,
 ) -> Result<String, ManifestFromServerError> {
-    let manifest_version = LuaVersion::from(config)?.version_compatibility_str();
+    let manifest_version = config
+        .resolve_lua_version(None)?
+        .version_compatibility_str();
     let url = mk_manifest_url(server_url, &manifest_version, config)?;
-    let cache = mk_manifest_cache(&url, config).await?;
-    let client = Client::new();
+    let cache = mk_manifest_cache(&url, &manifest_version, config).await?;
+    let unversioned_fallback = serves_unversioned_manifest(server_url, config);
     bar.map(|bar| bar.set_message(format!("📥 Downloading manifest from {}", &url)));
-    get_manifest(url, manifest_version.clone(), &cache, &client).await
+    get_manifest(
+        url,
+        manifest_version.clone(),
+        &cache,
+        unversioned_fallback,
+        config,
+    )
+    .await
 }
 
 fn mk_manifest_url(
@@ -179,13 +279,22 @@ fn mk_manifest_url(
     Ok(url)
 }
 
-async fn mk_manifest_cache(url: &Url, config: &Config) -> io::Result<PathBuf> {
-    let cache = config.cache_dir().join(
-        // Convert the url to a directory name so we don't create too many subdirectories
-        url.to_string()
-            .replace(&[':', '*', '?', '"', '<', '>', '|', '/', '\\'][..], "_")
-            .trim_end_matches(".zip"),
-    );
+/// Manifests are cached per Lua version, so that switching between Lua versions
+/// never compares one version's manifest against the other's modification time.
+async fn mk_manifest_cache(
+    url: &Url,
+    manifest_version: &str,
+    config: &Config,
+) -> io::Result<PathBuf> {
+    let cache = config
+        .cache_dir()
+        .join(format!("lua-{manifest_version}"))
+        .join(
+            // Convert the url to a directory name so we don't create too many subdirectories
+            url.to_string()
+                .replace(&[':', '*', '?', '"', '<', '>', '|', '/', '\\'][..], "_")
+                .trim_end_matches(".zip"),
+        );
     // Ensure all intermediate directories for the cache file are created (e.g. `~/.cache/lux/manifest`)
     fs::create_dir_all(cache.parent().unwrap()).await?;
     Ok(cache)
@@ -217,6 +326,24 @@ pub enum ManifestError {
     Server(#[from] ManifestFromServerError),
 }
 
+impl ManifestError {
+    /// Whether the manifest could not be parsed or unpacked,
+    /// e.g. because it was truncated, so that downloading it again may succeed.
+    fn is_corrupt_manifest(&self) -> bool {
+        match self {
+            Self::Lua(_) => true,
+            Self::Server(err) => match err {
+                ManifestFromServerError::FromUtf8(_)
+                | ManifestFromServerError::ZipRead(..)
+                | ManifestFromServerError::ZipExtract(..) => true,
+                // Reading a cached manifest that is not valid UTF-8
+                ManifestFromServerError::Io(err) => err.kind() == io::ErrorKind::InvalidData,
+                _ => false,
+            },
+        }
+    }
+}
+
 impl ManifestMetadata {
     pub fn new(manifest: &String) -> Result<Self, ManifestLuaError> {
         let lua = Lua::new();
@@ -235,73 +362,115 @@ impl ManifestMetadata {
         self.repository.contains_key(rock_name)
     }
 
+    /// Find the latest version that matches the requirement,
+    /// along with its preferred source type.
     pub fn latest_match(
         &self,
         lua_package_req: &PackageReq,
         filter: Option<RemotePackageTypeFilterSpec>,
     ) -> Option<(PackageSpec, RemotePackageType)> {
+        let (package, rock_types) = self.latest_matches(lua_package_req, filter)?;
+        Some((package, rock_types.into_iter().next()?))
+    }
+
+    /// Find the latest version that matches the requirement,
+    /// returning all of its available source types, ordered by preference.
+    pub(crate) fn latest_matches(
+        &self,
+        lua_package_req: &PackageReq,
+        filter: Option<RemotePackageTypeFilterSpec>,
+    ) -> Option<(PackageSpec, Vec<RemotePackageType>)> {
         let filter = filter.unwrap_or_default();
         if !self.has_rock(lua_package_req.name()) {
             return None;
         }
 
-        let (version, rock_type) = self.repository[lua_package_req.name()]
+        let (version, rock_types) = self.repository[lua_package_req.name()]
             .iter()
             .filter(|(version, _)| lua_package_req.version_req().matches(version))
-            .flat_map(|(version, rock_types)| {
-                rock_types.iter().filter_map(move |rock_type| {
-                    let include = match rock_type {
-                        RemotePackageType::Rockspec => filter.rockspec,
-                        RemotePackageType::Src => filter.src,
-                        RemotePackageType::Binary => filter.binary,
-                    };
-                    if include {
-                        Some((version, rock_type))
-                    } else {
-                        None
-                    }
-                })
+            .map(|(version, rock_types)| {
+                let rock_types = rock_types
+                    .iter()
+                    .filter(|rock_type| filter_includes(&filter, rock_type))
+                    .cloned()
+                    .sorted_by(|a, b| b.cmp(a))
+                    .collect_vec();
+                (version, rock_types)
             })
-            .max_by(
-                |(version_a, type_a), (version_b, type_b)| match version_a.cmp(version_b) {
-                    Ordering::Equal => type_a.cmp(type_b),
-                    ordering => ordering,
-                },
-            )?;
+            .filter(|(_, rock_types)| !rock_types.is_empty())
+            .max_by(|(version_a, _), (version_b, _)| version_a.cmp(version_b))?;
 
         Some((
             PackageSpec::new(lua_package_req.name().clone(), version.clone()),
-            rock_type.clone(),
+            rock_types,
         ))
     }
 
+    /// All available versions of a package, filtered by source type,
+    /// sorted from newest to oldest.
+    pub fn versions_of(
+        &self,
+        name: &PackageName,
+        filter: Option<RemotePackageTypeFilterSpec>,
+    ) -> Vec<PackageVersion> {
+        let filter = filter.unwrap_or_default();
+        self.repository
+            .get(name)
+            .map(|versions| {
+                versions
+                    .iter()
+                    .filter(|(_, rock_types)| {
+                        rock_types
+                            .iter()
+                            .any(|rock_type| filter_includes(&filter, rock_type))
+                    })
+                    .map(|(version, _)| version.clone())
+                    .sorted()
+                    .rev()
+                    .collect_vec()
+            })
+            .unwrap_or_default()
+    }
+
     /// Construct a `ManifestMetadata` from an intermediate representation,
     /// silently skipping entries for versions we don't know how to parse.
+    /// Version strings that parse to the same version (e.g. in manifests merged from mirrors)
+    /// are merged, so that all of their package types are considered.
     fn from_intermediate(intermediate: IntermediateManifest) -> Self {
         let repository = intermediate
             .repository
             .into_iter()
             .map(|(name, package_map)| {
-                (
-                    name,
-                    package_map
+                let mut versions: HashMap<PackageVersion, Vec<RemotePackageType>> = HashMap::new();
+                for (version_str, entries) in package_map {
+                    let Ok(version) = PackageVersion::parse(version_str.as_str()) else {
+                        continue;
+                    };
+                    let rock_types = versions.entry(version).or_default();
+                    for rock_type in entries
                         .into_iter()
-                        .filter_map(|(version_str, entries)| {
-                            let version = PackageVersion::parse(version_str.as_str()).ok()?;
-                            let entries = entries
-                                .into_iter()
-                                .filter_map(|entry| RemotePackageType::try_from(entry).ok())
-                                .collect_vec();
-                            Some((version, entries))
-                        })
-                        .collect(),
-                )
+                        .filter_map(|entry| RemotePackageType::try_from(entry).ok())
+                    {
+                        if !rock_types.contains(&rock_type) {
+                            rock_types.push(rock_type);
+                        }
+                    }
+                }
+                (name, versions)
             })
             .collect();
         Self { repository }
     }
 }
 
+fn filter_includes(filter: &RemotePackageTypeFilterSpec, rock_type: &RemotePackageType) -> bool {
+    match rock_type {
+        RemotePackageType::Rockspec => filter.rockspec,
+        RemotePackageType::Src => filter.src,
+        RemotePackageType::Binary => filter.binary,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Manifest {
     server_url: Url,
@@ -316,22 +485,18 @@ impl Manifest {
         }
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "manifest", skip_all, fields(server = %server_url))
+    )]
     pub async fn from_config(
         server_url: Url,
         config: &Config,
         progress: &Progress<ProgressBar>,
     ) -> Result<Self, ManifestError> {
-        let content =
-            crate::manifest::manifest_from_cache_or_server(&server_url, config, progress).await?;
-        match ManifestMetadata::new(&content) {
-            Ok(metadata) => Ok(Self::new(server_url, metadata)),
-            Err(_) => {
-                let manifest =
-                    crate::manifest::manifest_from_server_only(&server_url, config, progress)
-                        .await?;
-                Ok(Self::new(server_url, ManifestMetadata::new(&manifest)?))
-            }
-        }
+        let metadata =
+            manifest_metadata_from_cache_or_server(&server_url, config, progress).await?;
+        Ok(Self::new(server_url, metadata))
     }
 
     pub fn server_url(&self) -> &Url {
@@ -348,21 +513,43 @@ impl Manifest {
         package_req: &PackageReq,
         filter: Option<RemotePackageTypeFilterSpec>,
     ) -> Option<RemotePackage> {
-        match self.metadata().latest_match(package_req, filter) {
-            None => None,
-            Some((package, package_type)) => {
-                let remote_source = match package_type {
-                    RemotePackageType::Rockspec => {
-                        RemotePackageSource::LuarocksRockspec(self.server_url().clone())
-                    }
-                    RemotePackageType::Src => {
-                        RemotePackageSource::LuarocksSrcRock(self.server_url().clone())
-                    }
-                    RemotePackageType::Binary => {
-                        RemotePackageSource::LuarocksBinaryRock(self.server_url().clone())
-                    }
-                };
-                Some(RemotePackage::new(package, remote_source, None))
+        let (package, package_type) = self.metadata().latest_match(package_req, filter)?;
+        Some(RemotePackage::new(
+            package,
+            self.remote_source(package_type),
+            None,
+        ))
+    }
+
+    /// Find all source variants of the latest version that matches the requirement,
+    /// ordered by preference (binary > rockspec > src).
+    /// This allows falling back to the next source if e.g. a binary rock is broken.
+    pub fn find_all(
+        &self,
+        package_req: &PackageReq,
+        filter: Option<RemotePackageTypeFilterSpec>,
+    ) -> Vec<RemotePackage> {
+        match self.metadata().latest_matches(package_req, filter) {
+            None => Vec::new(),
+            Some((package, package_types)) => package_types
+                .into_iter()
+                .map(|package_type| {
+                    RemotePackage::new(package.clone(), self.remote_source(package_type), None)
+                })
+                .collect_vec(),
+        }
+    }
+
+    fn remote_source(&self, package_type: RemotePackageType) -> RemotePackageSource {
+        match package_type {
+            RemotePackageType::Rockspec => {
+                RemotePackageSource::LuarocksRockspec(self.server_url().clone())
+            }
+            RemotePackageType::Src => {
+                RemotePackageSource::LuarocksSrcRock(self.server_url().clone())
+            }
+            RemotePackageType::Binary => {
+                RemotePackageSource::LuarocksBinaryRock(self.server_url().clone())
             }
         }
     }
@@ -405,14 +592,37 @@ fn fallback_unzipped_url(url: &Url) -> Result<Url, url::ParseError> {
     url.to_string().trim_end_matches(".zip").parse()
 }
 
+/// Whether we fall back to the unversioned `manifest` if a server doesn't serve
+/// a manifest for the Lua version. Only dev servers, like the luarocks dev server,
+/// are known to serve unversioned manifests, which may list rocks for any Lua version.
+fn serves_unversioned_manifest(server_url: &Url, config: &Config) -> bool {
+    config
+        .enabled_dev_servers()
+        .is_ok_and(|dev_servers| dev_servers.contains(server_url))
+}
+
+/// Given a URL to a versioned manifest, create a URL to the unversioned `manifest`
+/// in the same directory.
+fn fallback_unversioned_url(url: &Url) -> Result<Url, url::ParseError> {
+    url.join("manifest")
+}
+
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{path::PathBuf, time::Duration};
 
-    use httptest::{matchers::request, responders::status_code, Expectation, Server};
+    use httptest::{
+        matchers::request,
+        responders::{delay_and_then, status_code},
+        Expectation, Server,
+    };
     use serial_test::serial;
 
-    use crate::{config::ConfigBuilder, package::PackageReq, progress::MultiProgress};
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        package::PackageReq,
+        progress::MultiProgress,
+    };
 
     use super::*;
 
@@ -440,6 +650,59 @@ mod tests {
         server
     }
 
+    #[tokio::test]
+    async fn unversioned_manifest_fallback() {
+        let fixtures =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/dev-manifest");
+        let server = Server::run();
+        for (path, status) in [
+            ("/manifest-5.1.zip", 404),
+            ("/manifest-5.1", 404),
+            ("/dev/manifest-5.1.zip", 403),
+            ("/dev/manifest-5.1", 403),
+        ] {
+            server.expect(
+                Expectation::matching(request::path(path))
+                    .times(0..)
+                    .respond_with(status_code(status)),
+            );
+        }
+        for path in ["/manifest", "/dev/manifest"] {
+            server.expect(
+                Expectation::matching(request::path(path))
+                    .times(0..)
+                    .respond_with(
+                        status_code(200)
+                            .body(std::fs::read(fixtures.join("dev-manifest")).unwrap()),
+                    ),
+            );
+        }
+        let server_url: Url = server.url_str("/").parse().unwrap();
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .server(Some(server_url.clone()))
+            .dev(Some(true))
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+
+        // Only dev servers are known to serve unversioned manifests.
+        assert!(manifest_from_cache_or_server(&server_url, &config, &bar)
+            .await
+            .is_err());
+        // We only fall back if the versioned manifest is not found.
+        assert!(
+            manifest_from_cache_or_server(&server_url.join("dev/").unwrap(), &config, &bar)
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     #[serial]
     pub async fn get_manifest_luajit() {
@@ -484,6 +747,36 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    #[serial]
+    pub async fn get_manifest_timeout() {
+        let cache_dir = assert_fs::TempDir::new().unwrap().to_path_buf();
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::path("/manifest-5.1.zip"))
+                .times(..)
+                .respond_with(delay_and_then(Duration::from_secs(5), status_code(200))),
+        );
+        let mut url_str = server.url_str(""); // Remove trailing "/"
+        url_str.pop();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .cache_dir(Some(cache_dir))
+            .lua_version(Some(crate::config::LuaVersion::Lua51))
+            .request_timeout(Some(Duration::from_millis(200)))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+
+        let result = manifest_from_server_only(&Url::parse(&url_str).unwrap(), &config, &bar).await;
+        let err = result.unwrap_err();
+        assert!(matches!(&err, ManifestFromServerError::Request(err) if err.is_timeout()));
+        assert!(err.to_string().contains("timed out"));
+    }
+
     #[tokio::test]
     #[serial]
     pub async fn get_cached_manifest() {
@@ -513,6 +806,137 @@ mod tests {
         assert_eq!(result, manifest_content);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    pub async fn concurrent_manifest_cache_refresh() {
+        let server = start_test_server("manifest-5.1".into());
+        let mut url_str = server.url_str(""); // Remove trailing "/"
+        url_str.pop();
+        let server_url = Url::parse(&url_str).unwrap();
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .lua_version(Some(crate::config::LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let bar = Progress::no_progress();
+
+        let refreshes = (0..4).map(|_| manifest_from_server_only(&server_url, &config, &bar));
+        let manifests = futures::future::try_join_all(refreshes).await.unwrap();
+
+        let url = mk_manifest_url(&server_url, "5.1", &config).unwrap();
+        let cache = mk_manifest_cache(&url, "5.1", &config).await.unwrap();
+        let cached = fs::read_to_string(&cache).await.unwrap();
+        assert!(manifests.iter().all(|manifest| manifest == &cached));
+        assert!(ManifestMetadata::new(&cached).is_ok());
+        // No temporary files are left behind
+        let mut entries = std::fs::read_dir(cache.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect_vec();
+        entries.sort();
+        let cache_file_name = cache.file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(
+            entries,
+            vec![cache_file_name.clone(), format!("{cache_file_name}.lock")]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    pub async fn recover_from_corrupt_manifest_cache() {
+        let server = start_test_server("manifest-5.1".into());
+        let mut url_str = server.url_str(""); // Remove trailing "/"
+        url_str.pop();
+        let server_url = Url::parse(&url_str).unwrap();
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .lua_version(Some(crate::config::LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let url = mk_manifest_url(&server_url, "5.1", &config).unwrap();
+        let cache = mk_manifest_cache(&url, "5.1", &config).await.unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+
+        // A truncated manifest and one that was cut off mid UTF-8 character.
+        // The cache is newer than the server's manifest, so it would be used as is.
+        for corrupt_cache in [&b"repository = {\n   ['lua-cjson'] = {"[..], &[0xf0, 0x9f]] {
+            fs::write(&cache, corrupt_cache).await.unwrap();
+            let manifest = Manifest::from_config(server_url.clone(), &config, &bar)
+                .await
+                .unwrap();
+            assert!(manifest.find(&"lua-cjson".parse().unwrap(), None).is_some());
+            let cached = fs::read_to_string(&cache).await.unwrap();
+            assert!(ManifestMetadata::new(&cached).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    pub async fn manifest_cache_per_lua_version() {
+        let server = Server::run();
+        for version in ["5.1", "5.4"] {
+            server.expect(
+                Expectation::matching(request::path(format!("/manifest-{version}.zip")))
+                    .times(1..)
+                    .respond_with(status_code(404)),
+            );
+            server.expect(
+                Expectation::matching(request::path(format!("/manifest-{version}")))
+                    .times(1..)
+                    .respond_with(
+                        status_code(200)
+                            .append_header("Last-Modified", "Sat, 20 Jan 2024 13:14:12 GMT")
+                            .body(format!("repository = {{}} -- {version}")),
+                    ),
+            );
+        }
+        let mut url_str = server.url_str(""); // Remove trailing "/"
+        url_str.pop();
+        let server_url = Url::parse(&url_str).unwrap();
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let mk_config = |lua_version| {
+            ConfigBuilder::new()
+                .unwrap()
+                .cache_dir(Some(cache_dir.to_path_buf()))
+                .lua_version(Some(lua_version))
+                .no_progress(Some(true))
+                .build()
+                .unwrap()
+        };
+        let config_51 = mk_config(LuaVersion::Lua51);
+        let config_54 = mk_config(LuaVersion::Lua54);
+        let progress = MultiProgress::new(&config_51);
+        let bar = progress.map(MultiProgress::new_bar);
+
+        let url_51 = mk_manifest_url(&server_url, "5.1", &config_51).unwrap();
+        let url_54 = mk_manifest_url(&server_url, "5.4", &config_54).unwrap();
+        let cache_51 = mk_manifest_cache(&url_51, "5.1", &config_51).await.unwrap();
+        let cache_54 = mk_manifest_cache(&url_54, "5.4", &config_54).await.unwrap();
+        assert!(cache_51.starts_with(cache_dir.join("lua-5.1")));
+        assert!(cache_54.starts_with(cache_dir.join("lua-5.4")));
+
+        for _ in 0..2 {
+            for (config, version, cache) in [
+                (&config_51, "5.1", &cache_51),
+                (&config_54, "5.4", &cache_54),
+            ] {
+                let manifest = manifest_from_cache_or_server(&server_url, config, &bar)
+                    .await
+                    .unwrap();
+                let expected = format!("repository = {{}} -- {version}");
+                assert_eq!(manifest, expected);
+                assert_eq!(fs::read_to_string(cache).await.unwrap(), expected);
+            }
+        }
+    }
+
     #[tokio::test]
     pub async fn parse_metadata_from_empty_manifest() {
         let manifest = "
@@ -542,4 +966,174 @@ mod tests {
         let package_req: PackageReq = "30log > 1.3.0".parse().unwrap();
         assert!(metadata.latest_match(&package_req, None).is_none());
     }
+
+    #[tokio::test]
+    pub async fn latest_match_specrev() {
+        let mut test_manifest_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_manifest_path.push("resources/test/manifest-5.1");
+        let manifest = String::from_utf8(fs::read(&test_manifest_path).await.unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+
+        // The manifest has both 30log 0.2-1 and 0.2-2
+        let package_req: PackageReq = "30log@0.2".parse().unwrap();
+        let (package, _) = metadata.latest_match(&package_req, None).unwrap();
+        assert_eq!(package.version(), &"0.2-2".parse().unwrap());
+
+        let package_req: PackageReq = "30log@0.2-1".parse().unwrap();
+        let (package, rock_types) = metadata.latest_matches(&package_req, None).unwrap();
+        assert_eq!(package.version(), &"0.2-1".parse().unwrap());
+        assert!(rock_types.contains(&RemotePackageType::Src));
+
+        let manifest = Manifest::new(Url::parse("https://example.com").unwrap(), metadata);
+        let package = manifest.find(&package_req, None).unwrap();
+        assert_eq!(package.package.version(), &"0.2-1".parse().unwrap());
+
+        let package_req: PackageReq = "30log@0.2-3".parse().unwrap();
+        assert!(manifest.find(&package_req, None).is_none());
+    }
+
+    #[tokio::test]
+    pub async fn latest_match_normalized_name() {
+        let mut test_manifest_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_manifest_path.push("resources/test/manifest-5.1");
+        let manifest = String::from_utf8(fs::read(&test_manifest_path).await.unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+
+        assert!(metadata.has_rock(&"Colorbox-Nvim".into()));
+        let package_req: PackageReq = "ColorBox.nvim".parse().unwrap();
+        let (package, _) = metadata.latest_match(&package_req, None).unwrap();
+        assert_eq!(package.name().to_string(), "colorbox.nvim");
+    }
+
+    #[tokio::test]
+    pub async fn find_all_sources_ordered_by_preference() {
+        let mut test_manifest_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_manifest_path.push("resources/test/manifest-5.1");
+        let content = String::from_utf8(fs::read(&test_manifest_path).await.unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&content).unwrap();
+        let server_url = Url::parse("https://example.com").unwrap();
+        let manifest = Manifest::new(server_url.clone(), metadata);
+
+        let package_req: PackageReq = "lua-cjson 2.1.0-1".parse().unwrap();
+        let packages = manifest.find_all(&package_req, None);
+        assert!(packages
+            .iter()
+            .all(|package| package.package.version() == &"2.1.0-1".parse().unwrap()));
+        let sources = packages
+            .iter()
+            .map(|package| package.source.clone())
+            .collect_vec();
+        assert_eq!(
+            sources,
+            vec![
+                RemotePackageSource::LuarocksRockspec(server_url.clone()),
+                RemotePackageSource::LuarocksSrcRock(server_url.clone()),
+            ]
+        );
+        assert_eq!(
+            manifest
+                .find(&package_req, None)
+                .map(|package| package.source),
+            sources.first().cloned()
+        );
+        assert!(manifest
+            .find_all(&"does-not-exist".parse().unwrap(), None)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn versions_of_sorted_descending() {
+        let mut test_manifest_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_manifest_path.push("resources/test/manifest-5.1");
+        let manifest = String::from_utf8(fs::read(&test_manifest_path).await.unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+
+        let versions = metadata.versions_of(&"lua-cjson".into(), None);
+        assert!(versions.len() > 1);
+        assert_eq!(versions.first().unwrap(), &"2.1.0-1".parse().unwrap());
+        assert!(versions.windows(2).all(|pair| pair[0] > pair[1]));
+
+        let src_versions = metadata.versions_of(
+            &"lua-cjson".into(),
+            Some(RemotePackageTypeFilterSpec::src_only()),
+        );
+        assert!(src_versions
+            .iter()
+            .all(|version| versions.contains(version)));
+
+        assert!(metadata
+            .versions_of(&"does-not-exist".into(), None)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn find_all_includes_src_fallback_for_all_arch_binary() {
+        let manifest = r#"
+            repository = {
+                ["foo"] = {
+                    ["1.0-1"] = {
+                        { arch = "all" },
+                        { arch = "src" },
+                    },
+                },
+            }
+            "#
+        .to_string();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+        let server_url = Url::parse("https://example.com").unwrap();
+        let manifest = Manifest::new(server_url.clone(), metadata);
+        let sources = manifest
+            .find_all(&"foo".parse().unwrap(), None)
+            .into_iter()
+            .map(|package| package.source)
+            .collect_vec();
+        assert_eq!(
+            sources,
+            vec![
+                RemotePackageSource::LuarocksBinaryRock(server_url.clone()),
+                RemotePackageSource::LuarocksSrcRock(server_url),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn merge_duplicate_version_entries() {
+        let manifest = r#"
+            repository = {
+                ["foo"] = {
+                    ["1.0-1"] = {
+                        { arch = "rockspec" },
+                        { arch = "linux-x86_64" },
+                    },
+                    ["1.0-01"] = {
+                        { arch = "src" },
+                        { arch = "macosx-aarch64" },
+                    },
+                },
+            }
+            "#
+        .to_string();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+        let versions = &metadata.repository[&"foo".into()];
+        assert_eq!(versions.len(), 1);
+        let rock_types = &versions[&"1.0-1".parse().unwrap()];
+        assert_eq!(rock_types.len(), 3);
+        for rock_type in [
+            RemotePackageType::Rockspec,
+            RemotePackageType::Src,
+            RemotePackageType::Binary,
+        ] {
+            assert!(rock_types.contains(&rock_type));
+        }
+
+        let server_url = Url::parse("https://example.com").unwrap();
+        let manifest = Manifest::new(server_url.clone(), metadata);
+        let sources = manifest
+            .find_all(&"foo".parse().unwrap(), None)
+            .into_iter()
+            .map(|package| package.source)
+            .collect_vec();
+        assert_eq!(sources.len(), 3);
+        assert!(sources.contains(&RemotePackageSource::LuarocksSrcRock(server_url)));
+    }
 }
@@ -7,7 +7,7 @@ use std::{
 
 use crate::{
     build::utils,
-    config::{Config, LuaVersion},
+    config::{display_request_error, Config, LuaVersion},
     hash::HasIntegrity,
     operations::{self, UnpackError},
     progress::{Progress, ProgressBar},
@@ -21,13 +21,9 @@ use thiserror::Error;
 use tokio::{fs, process::Command};
 use url::Url;
 
-const LUA51_VERSION: &str = "5.1.5";
 const LUA51_HASH: &str = "sha256-JkD8VqeV8p0o7xXhPDSkfiI5YLAkDoywqC2bBzhpUzM=";
-const LUA52_VERSION: &str = "5.2.4";
 const LUA52_HASH: &str = "sha256-ueLkqtZ4mztjoFbUQveznw7Pyjrg8fwK5OlhRAG2n0s=";
-const LUA53_VERSION: &str = "5.3.6";
 const LUA53_HASH: &str = "sha256-/F/Wm7hzYyPwJmcrG3I12mE9cXfnJViJOgvc0yBGbWA=";
-const LUA54_VERSION: &str = "5.4.8";
 const LUA54_HASH: &str = "sha256-TxjdrhVOeT5G7qtyfFnvHAwMK3ROe5QhlxDXb1MGKa4=";
 // XXX: there's no tag with lua 5.2 compatibility, so we have to use the v2.1 branch for now
 // this is unstable and might break the build.
@@ -44,7 +40,7 @@ pub struct BuildLua<'a> {
 
 #[derive(Debug, Error)]
 pub enum BuildLuaError {
-    #[error(transparent)]
+    #[error("{}", display_request_error(.0))]
     Request(#[from] reqwest::Error),
     #[error(transparent)]
     Io(#[from] io::Error),
@@ -286,13 +282,14 @@ async fn do_build_lua(args: BuildLua<'_>) -> Result<(), BuildLuaError> {
         .expect("failed to create lua_installation temp directory")
         .into_path();
 
-    let (source_integrity, pkg_version): (Integrity, &str) = match lua_version {
-        LuaVersion::Lua51 => (LUA51_HASH.parse().unwrap(), LUA51_VERSION),
-        LuaVersion::Lua52 => (LUA52_HASH.parse().unwrap(), LUA52_VERSION),
-        LuaVersion::Lua53 => (LUA53_HASH.parse().unwrap(), LUA53_VERSION),
-        LuaVersion::Lua54 => (LUA54_HASH.parse().unwrap(), LUA54_VERSION),
+    let source_integrity: Integrity = match lua_version {
+        LuaVersion::Lua51 => LUA51_HASH.parse().unwrap(),
+        LuaVersion::Lua52 => LUA52_HASH.parse().unwrap(),
+        LuaVersion::Lua53 => LUA53_HASH.parse().unwrap(),
+        LuaVersion::Lua54 => LUA54_HASH.parse().unwrap(),
         LuaVersion::LuaJIT | LuaVersion::LuaJIT52 => unreachable!(),
     };
+    let pkg_version = lua_version.lua_src_version();
 
     let file_name = format!("lua-{pkg_version}.tar.gz");
 
@@ -302,8 +299,9 @@ async fn do_build_lua(args: BuildLua<'_>) -> Result<(), BuildLuaError> {
 
     progress.map(|p| p.set_message(format!("📥 Downloading {}", &source_url)));
 
-    let response = reqwest::Client::new()
-        .get(source_url)
+    let response = args
+        .config
+        .download(source_url)
         .send()
         .await?
         .error_for_status()?
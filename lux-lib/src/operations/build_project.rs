@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{io, sync::Arc};
 
 use bon::Builder;
 use itertools::Itertools;
@@ -9,11 +9,13 @@ use crate::{
     config::Config,
     lockfile::LocalPackage,
     lua_installation::{LuaInstallation, LuaInstallationError},
+    lua_rockspec::LuaVersionError,
     luarocks::luarocks_installation::{LuaRocksError, LuaRocksInstallError, LuaRocksInstallation},
     progress::{MultiProgress, Progress},
     project::{project_toml::LocalProjectTomlValidationError, Project, ProjectTreeError},
+    remote_package_source::RemotePackageSource,
     rockspec::Rockspec,
-    tree::{self, TreeError},
+    tree::{self, Tree, TreeError},
 };
 
 use super::{Install, InstallError, PackageInstallSpec, Sync, SyncError};
@@ -29,6 +31,10 @@ pub enum BuildProjectError {
     #[error(transparent)]
     Tree(#[from] TreeError),
     #[error(transparent)]
+    LuaVersion(#[from] LuaVersionError),
+    #[error("error creating a staging directory:\n{0}")]
+    CreateStagingDir(io::Error),
+    #[error(transparent)]
     LuaRocks(#[from] LuaRocksError),
     #[error(transparent)]
     LuaRocksInstall(#[from] LuaRocksInstallError),
@@ -59,6 +65,18 @@ pub struct BuildProject<'a> {
     /// Build only the dependencies
     only_deps: bool,
 
+    /// Install the project in editable mode,
+    /// symlinking its Lua sources into the tree instead of copying them.
+    #[builder(default)]
+    editable: bool,
+
+    /// Check that the project builds, without installing it.
+    /// The project is compiled into a temporary staging tree, so that neither
+    /// the project tree nor its lockfile are touched.
+    /// Dependencies are not installed in this mode.
+    #[builder(default)]
+    no_install: bool,
+
     progress: Option<Arc<Progress<MultiProgress>>>,
 }
 
@@ -76,26 +94,61 @@ impl<State: build_project_builder::State + build_project_builder::IsComplete>
         let progress = Arc::clone(&progress_arc);
 
         let project_toml = project.toml().into_local()?;
+
+        if args.no_install {
+            if args.only_deps {
+                return Ok(None);
+            }
+            let staging_dir =
+                tempdir::TempDir::new("lux-check").map_err(BuildProjectError::CreateStagingDir)?;
+            let staging_tree = Tree::new(
+                staging_dir.path().to_path_buf(),
+                project.lua_version(config)?,
+                config,
+            )?;
+            let lua = LuaInstallation::new(
+                &project.lua_version(config)?,
+                config,
+                &progress.map(|progress| progress.new_bar()),
+            )
+            .await?;
+            let package = Build::new()
+                .rockspec(&project_toml)
+                .lua(&lua)
+                .tree(&staging_tree)
+                .entry_type(tree::EntryType::Entrypoint)
+                .config(config)
+                .progress(&progress.map(|p| p.new_bar()))
+                .behaviour(BuildBehaviour::Force)
+                .no_install(true)
+                .build()
+                .await?;
+            return Ok(Some(package));
+        }
+
         let project_tree = project.tree(config)?;
 
         let dependencies = project_toml
             .dependencies()
-            .current_platform()
+            .for_target_platform(config)
             .iter()
             .cloned()
             .collect_vec();
 
         let build_dependencies = project_toml
             .build_dependencies()
-            .current_platform()
+            .for_target_platform(config)
             .iter()
             .cloned()
             .collect_vec();
 
         let build_tree = project.build_tree(config)?;
-        let lua =
-            LuaInstallation::new_from_config(config, &progress.map(|progress| progress.new_bar()))
-                .await?;
+        let lua = LuaInstallation::new(
+            &project.lua_version(config)?,
+            config,
+            &progress.map(|progress| progress.new_bar()),
+        )
+        .await?;
         let luarocks = LuaRocksInstallation::new(config, build_tree.clone())?;
 
         if args.no_lock {
@@ -114,6 +167,7 @@ impl<State: build_project_builder::State + build_project_builder::IsComplete>
                     .pin(*dep.pin())
                     .opt(*dep.opt())
                     .maybe_source(dep.source().clone())
+                    .maybe_rockspec_url(dep.rockspec_url().cloned())
                     .build()
                 })
                 .collect();
@@ -141,6 +195,7 @@ impl<State: build_project_builder::State + build_project_builder::IsComplete>
                     .pin(*dep.pin())
                     .opt(*dep.opt())
                     .maybe_source(dep.source().clone())
+                    .maybe_rockspec_url(dep.rockspec_url().cloned())
                     .build()
                 })
                 .collect_vec();
@@ -179,6 +234,10 @@ impl<State: build_project_builder::State + build_project_builder::IsComplete>
                 .config(config)
                 .progress(&progress.map(|p| p.new_bar()))
                 .behaviour(BuildBehaviour::Force)
+                .maybe_source(
+                    args.editable
+                        .then(|| RemotePackageSource::LocalPath(project.root().to_path_buf())),
+                )
                 .build()
                 .await?;
 
@@ -260,4 +319,30 @@ mod tests {
         assert!(bin_dir.join("foo").is_file());
         assert!(bin_dir.join("bar").is_file());
     }
+
+    #[tokio::test]
+    async fn build_check_leaves_tree_untouched() {
+        let project_root =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-projects/init/");
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        temp_dir.copy_from(&project_root, &["**"]).unwrap();
+        let project_root = temp_dir.path();
+        let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_version(lua_version)
+            .build()
+            .unwrap();
+        let project = Project::from_exact(project_root).unwrap().unwrap();
+        let package = BuildProject::new(&project, &config)
+            .no_lock(false)
+            .only_deps(false)
+            .no_install(true)
+            .build()
+            .await
+            .unwrap();
+        assert!(package.is_some());
+        assert!(!project.default_tree_root_dir().exists());
+        assert!(!project.lockfile_path().exists());
+    }
 }
@@ -5,19 +5,20 @@ use std::{
 };
 
 use bon::Builder;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use reqwest::{header::RANGE, Method, StatusCode};
 use thiserror::Error;
 use url::{ParseError, Url};
 
 use crate::{
-    config::Config,
+    config::{display_request_error, Config},
     git::GitSource,
     lockfile::RemotePackageSourceUrl,
-    lua_rockspec::{LuaRockspecError, RemoteLuaRockspec, RockSourceSpec},
+    lua_rockspec::{LuaRockspecError, PlatformIdentifier, RemoteLuaRockspec, RockSourceSpec},
     luarocks,
     package::{
         PackageName, PackageReq, PackageSpec, PackageSpecFromPackageReqError, PackageVersion,
-        RemotePackageTypeFilterSpec,
+        PackageVersionReq, RemotePackage, RemotePackageTypeFilterSpec,
     },
     progress::{Progress, ProgressBar},
     remote_package_db::{RemotePackageDB, RemotePackageDBError, SearchError},
@@ -25,12 +26,17 @@ use crate::{
     rockspec::Rockspec,
 };
 
+/// A callback that receives the number of bytes downloaded so far
+/// and the total number of bytes, if the server reports it.
+pub type DownloadProgressFn<'a> = dyn FnMut(u64, Option<u64>) + Send + 'a;
+
 /// Builder for a rock downloader.
 pub struct Download<'a> {
     package_req: &'a PackageReq,
     package_db: Option<&'a RemotePackageDB>,
     config: &'a Config,
     progress: &'a Progress<ProgressBar>,
+    on_progress: Option<Box<DownloadProgressFn<'a>>>,
 }
 
 impl<'a> Download<'a> {
@@ -45,6 +51,7 @@ impl<'a> Download<'a> {
             package_db: None,
             config,
             progress,
+            on_progress: None,
         }
     }
 
@@ -57,57 +64,172 @@ impl<'a> Download<'a> {
         }
     }
 
+    /// Sets a callback that is invoked with the number of bytes downloaded so far
+    /// and the total number of bytes, if known, while downloading a packed rock.
+    /// Unlike the `progress` bar, this lets frontends render the progress themselves.
+    pub fn on_progress(self, on_progress: impl FnMut(u64, Option<u64>) + Send + 'a) -> Self {
+        Self {
+            on_progress: Some(Box::new(on_progress)),
+            ..self
+        }
+    }
+
     /// Download the package's Rockspec.
-    pub async fn download_rockspec(self) -> Result<DownloadedRockspec, SearchAndDownloadError> {
+    pub async fn download_rockspec(mut self) -> Result<DownloadedRockspec, SearchAndDownloadError> {
+        let on_progress = self.on_progress.as_deref_mut();
         match self.package_db {
-            Some(db) => download_rockspec(self.package_req, db, self.progress).await,
+            Some(db) => {
+                download_rockspec(
+                    self.package_req,
+                    db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
+            }
             None => {
                 let db = RemotePackageDB::from_config(self.config, self.progress).await?;
-                download_rockspec(self.package_req, &db, self.progress).await
+                download_rockspec(
+                    self.package_req,
+                    &db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
             }
         }
     }
 
+    /// Download the package's Rockspec directly from `<url>/<name>-<version>.rockspec`,
+    /// without searching a package database.
+    /// Useful if the server hosting the rockspec is already known.
+    /// The package requirement must specify an exact version.
+    pub async fn download_rockspec_from_url(
+        mut self,
+        url: &Url,
+    ) -> Result<DownloadedRockspec, SearchAndDownloadError> {
+        let package: PackageSpec = self.package_req.clone().try_into()?;
+        self.progress
+            .map(|p| p.set_message(format!("📥 Downloading rockspec for {}", self.package_req)));
+        let remote_package = RemotePackage::new(
+            package,
+            RemotePackageSource::LuarocksRockspec(url.clone()),
+            None,
+        );
+        Ok(download_remote_package(
+            remote_package,
+            self.config,
+            self.progress,
+            self.on_progress.as_deref_mut(),
+        )
+        .await?
+        .into_rockspec_download())
+    }
+
     /// Download a `.src.rock` to a file.
     /// `destination_dir` defaults to the current working directory if not set.
     pub async fn download_src_rock_to_file(
-        self,
+        mut self,
         destination_dir: Option<PathBuf>,
     ) -> Result<DownloadedPackedRock, SearchAndDownloadError> {
+        let on_progress = self.on_progress.as_deref_mut();
         match self.package_db {
             Some(db) => {
-                download_src_rock_to_file(self.package_req, destination_dir, db, self.progress)
-                    .await
+                download_src_rock_to_file(
+                    self.package_req,
+                    destination_dir,
+                    db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
             }
             None => {
                 let db = RemotePackageDB::from_config(self.config, self.progress).await?;
-                download_src_rock_to_file(self.package_req, destination_dir, &db, self.progress)
-                    .await
+                download_src_rock_to_file(
+                    self.package_req,
+                    destination_dir,
+                    &db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
             }
         }
     }
 
     /// Search for a `.src.rock` and download it to memory.
     pub async fn search_and_download_src_rock(
-        self,
+        mut self,
     ) -> Result<DownloadedPackedRockBytes, SearchAndDownloadError> {
+        let on_progress = self.on_progress.as_deref_mut();
         match self.package_db {
-            Some(db) => search_and_download_src_rock(self.package_req, db, self.progress).await,
+            Some(db) => {
+                search_and_download_src_rock(
+                    self.package_req,
+                    db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
+            }
             None => {
                 let db = RemotePackageDB::from_config(self.config, self.progress).await?;
-                search_and_download_src_rock(self.package_req, &db, self.progress).await
+                search_and_download_src_rock(
+                    self.package_req,
+                    &db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Check whether the package's source is still available on the server it was found in,
+    /// without downloading it.
+    /// Returns `false` if the package can't be found or its source no longer exists.
+    pub async fn verify_available(self) -> Result<bool, SearchAndDownloadError> {
+        match self.package_db {
+            Some(db) => verify_available(self.package_req, db, self.config, self.progress).await,
+            None => {
+                let db = RemotePackageDB::from_config(self.config, self.progress).await?;
+                verify_available(self.package_req, &db, self.config, self.progress).await
             }
         }
     }
 
     pub(crate) async fn download_remote_rock(
-        self,
+        mut self,
     ) -> Result<RemoteRockDownload, SearchAndDownloadError> {
+        let on_progress = self.on_progress.as_deref_mut();
         match self.package_db {
-            Some(db) => download_remote_rock(self.package_req, db, self.progress).await,
+            Some(db) => {
+                download_remote_rock(
+                    self.package_req,
+                    db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
+            }
             None => {
                 let db = RemotePackageDB::from_config(self.config, self.progress).await?;
-                download_remote_rock(self.package_req, &db, self.progress).await
+                download_remote_rock(
+                    self.package_req,
+                    &db,
+                    self.config,
+                    self.progress,
+                    on_progress,
+                )
+                .await
             }
         }
     }
@@ -142,6 +264,7 @@ pub(crate) enum RemoteRockDownload {
     BinaryRock {
         rockspec_download: DownloadedRockspec,
         packed_rock: Bytes,
+        source_url: RemotePackageSourceUrl,
     },
     SrcRock {
         rockspec_download: DownloadedRockspec,
@@ -165,6 +288,17 @@ impl RemoteRockDownload {
             } => rockspec_download,
         }
     }
+    fn into_rockspec_download(self) -> DownloadedRockspec {
+        match self {
+            Self::RockspecOnly { rockspec_download }
+            | Self::BinaryRock {
+                rockspec_download, ..
+            }
+            | Self::SrcRock {
+                rockspec_download, ..
+            } => rockspec_download,
+        }
+    }
     // Instead of downloading a rockspec, generate one from a `PackageReq` and a `RockSourceSpec`.
     pub(crate) fn from_package_req_and_source_spec(
         package_req: PackageReq,
@@ -196,7 +330,7 @@ impl RemoteRockDownload {
 
 #[derive(Error, Debug)]
 pub enum DownloadRockspecError {
-    #[error("failed to download rockspec: {0}")]
+    #[error("failed to download rockspec: {}", display_request_error(.0))]
     Request(#[from] reqwest::Error),
     #[error("failed to convert rockspec response: {0}")]
     ResponseConversion(#[from] FromUtf8Error),
@@ -210,45 +344,98 @@ pub enum DownloadRockspecError {
 async fn download_rockspec(
     package_req: &PackageReq,
     package_db: &RemotePackageDB,
+    config: &Config,
     progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
 ) -> Result<DownloadedRockspec, SearchAndDownloadError> {
-    let rockspec = match download_remote_rock(package_req, package_db, progress).await? {
-        RemoteRockDownload::RockspecOnly {
-            rockspec_download: rockspec,
-        } => rockspec,
-        RemoteRockDownload::BinaryRock {
-            rockspec_download: rockspec,
-            ..
-        } => rockspec,
-        RemoteRockDownload::SrcRock {
-            rockspec_download: rockspec,
-            ..
-        } => rockspec,
-    };
+    let rockspec =
+        match download_remote_rock(package_req, package_db, config, progress, on_progress).await? {
+            RemoteRockDownload::RockspecOnly {
+                rockspec_download: rockspec,
+            } => rockspec,
+            RemoteRockDownload::BinaryRock {
+                rockspec_download: rockspec,
+                ..
+            } => rockspec,
+            RemoteRockDownload::SrcRock {
+                rockspec_download: rockspec,
+                ..
+            } => rockspec,
+        };
     Ok(rockspec)
 }
 
+/// Download a rockspec from its full URL, e.g. `https://example.com/foo-1.0.0-1.rockspec`,
+/// without searching a package database.
+/// The package name and version are read from the rockspec.
+pub async fn download_rockspec_at_url(
+    url: &Url,
+    config: &Config,
+    progress: &Progress<ProgressBar>,
+) -> Result<DownloadedRockspec, SearchAndDownloadError> {
+    progress.map(|p| p.set_message(format!("📥 Downloading rockspec {url}")));
+    let bytes = config
+        .request(Method::GET, url.clone())
+        .send()
+        .await
+        .map_err(DownloadRockspecError::from)?
+        .error_for_status()
+        .map_err(DownloadRockspecError::from)?
+        .bytes()
+        .await
+        .map_err(DownloadRockspecError::from)?;
+    let content = String::from_utf8(bytes.into())?;
+    Ok(DownloadedRockspec {
+        rockspec: RemoteLuaRockspec::new(&content)?,
+        source: RemotePackageSource::RockspecContent(content),
+        source_url: None,
+    })
+}
+
 async fn download_remote_rock(
     package_req: &PackageReq,
     package_db: &RemotePackageDB,
+    config: &Config,
     progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
 ) -> Result<RemoteRockDownload, SearchAndDownloadError> {
     let remote_package = package_db.find(package_req, None, progress)?;
     progress.map(|p| p.set_message(format!("📥 Downloading rockspec for {package_req}")));
+    download_remote_package(remote_package, config, progress, on_progress).await
+}
+
+/// Download a package from the source it was found in.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "download",
+        skip_all,
+        fields(
+            package = %remote_package.package.name(),
+            version = %remote_package.package.version(),
+        )
+    )
+)]
+pub(crate) async fn download_remote_package(
+    remote_package: RemotePackage,
+    config: &Config,
+    progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
+) -> Result<RemoteRockDownload, SearchAndDownloadError> {
     match &remote_package.source {
         RemotePackageSource::LuarocksRockspec(url) => {
-            let package = &remote_package.package;
-            let rockspec_name = format!("{}-{}.rockspec", package.name(), package.version());
-            let bytes = reqwest::Client::new()
-                .get(format!("{}/{}", &url, rockspec_name))
+            let url =
+                rockspec_url(url, &remote_package.package).map_err(DownloadSrcRockError::from)?;
+            let bytes = config
+                .request(Method::GET, url)
                 .send()
                 .await
-                .map_err(DownloadRockspecError::Request)?
+                .map_err(DownloadRockspecError::from)?
                 .error_for_status()
-                .map_err(DownloadRockspecError::Request)?
+                .map_err(DownloadRockspecError::from)?
                 .bytes()
                 .await
-                .map_err(DownloadRockspecError::Request)?;
+                .map_err(DownloadRockspecError::from)?;
             let content = String::from_utf8(bytes.into())?;
             let rockspec = DownloadedRockspec {
                 rockspec: RemoteLuaRockspec::new(&content)?,
@@ -260,24 +447,55 @@ async fn download_remote_rock(
             })
         }
         RemotePackageSource::RockspecContent(content) => {
-            let rockspec = DownloadedRockspec {
-                rockspec: RemoteLuaRockspec::new(content)?,
-                source: remote_package.source,
-                source_url: remote_package.source_url,
+            let rockspec = match &remote_package.source_url {
+                // Packages installed from a local rockspec file record the file as their source URL.
+                // We read the rockspec from that file if it still exists,
+                // and fetch the source declared in the rockspec.
+                Some(RemotePackageSourceUrl::File { path })
+                    if path.extension().is_some_and(|ext| ext == "rockspec") =>
+                {
+                    let content = tokio::fs::read_to_string(path)
+                        .await
+                        .unwrap_or_else(|_| content.clone());
+                    DownloadedRockspec {
+                        rockspec: RemoteLuaRockspec::new(&content)?,
+                        source: RemotePackageSource::RockspecContent(content),
+                        source_url: None,
+                    }
+                }
+                _ => DownloadedRockspec {
+                    rockspec: RemoteLuaRockspec::new(content)?,
+                    source: remote_package.source,
+                    source_url: remote_package.source_url,
+                },
             };
             Ok(RemoteRockDownload::RockspecOnly {
                 rockspec_download: rockspec,
             })
         }
         RemotePackageSource::LuarocksBinaryRock(url) => {
-            // prioritise lockfile source_url
-            let url = if let Some(RemotePackageSourceUrl::Url { url }) = &remote_package.source_url
-            {
-                url
-            } else {
-                url
+            let rock = match lockfile_packed_rock_url(&remote_package) {
+                Some(rock_url) => {
+                    download_packed_rock_from_url(
+                        &remote_package.package,
+                        rock_url,
+                        config,
+                        progress,
+                        on_progress,
+                    )
+                    .await?
+                }
+                None => {
+                    download_binary_rock(
+                        &remote_package.package,
+                        archive_server_url(&remote_package, url),
+                        config,
+                        progress,
+                        on_progress,
+                    )
+                    .await?
+                }
             };
-            let rock = download_binary_rock(&remote_package.package, url, progress).await?;
             let rockspec = DownloadedRockspec {
                 rockspec: unpack_rockspec(&rock).await?,
                 source: remote_package.source,
@@ -286,17 +504,32 @@ async fn download_remote_rock(
             Ok(RemoteRockDownload::BinaryRock {
                 rockspec_download: rockspec,
                 packed_rock: rock.bytes,
+                source_url: RemotePackageSourceUrl::Url { url: rock.url },
             })
         }
         RemotePackageSource::LuarocksSrcRock(url) => {
-            // prioritise lockfile source_url
-            let url = if let Some(RemotePackageSourceUrl::Url { url }) = &remote_package.source_url
-            {
-                url.clone()
-            } else {
-                url.clone()
+            let rock = match lockfile_packed_rock_url(&remote_package) {
+                Some(rock_url) => {
+                    download_packed_rock_from_url(
+                        &remote_package.package,
+                        rock_url,
+                        config,
+                        progress,
+                        on_progress,
+                    )
+                    .await?
+                }
+                None => {
+                    download_src_rock(
+                        &remote_package.package,
+                        archive_server_url(&remote_package, url),
+                        config,
+                        progress,
+                        on_progress,
+                    )
+                    .await?
+                }
             };
-            let rock = download_src_rock(&remote_package.package, &url, progress).await?;
             let rockspec = DownloadedRockspec {
                 rockspec: unpack_rockspec(&rock).await?,
                 source: remote_package.source,
@@ -305,12 +538,136 @@ async fn download_remote_rock(
             Ok(RemoteRockDownload::SrcRock {
                 rockspec_download: rockspec,
                 src_rock: rock.bytes,
-                source_url: RemotePackageSourceUrl::Url { url },
+                source_url: RemotePackageSourceUrl::Url { url: rock.url },
             })
         }
-        RemotePackageSource::Local => Err(SearchAndDownloadError::LocalSource),
+        RemotePackageSource::Local | RemotePackageSource::LocalPath(_) => {
+            Err(SearchAndDownloadError::LocalSource)
+        }
+        #[cfg(test)]
+        RemotePackageSource::Test => unimplemented!(),
+    }
+}
+
+async fn verify_available(
+    package_req: &PackageReq,
+    package_db: &RemotePackageDB,
+    config: &Config,
+    progress: &Progress<ProgressBar>,
+) -> Result<bool, SearchAndDownloadError> {
+    let remote_package = match package_db.find(package_req, None, progress) {
+        Ok(remote_package) => remote_package,
+        Err(SearchError::RockNotFound(_) | SearchError::RockNotFoundInLockfile(_)) => {
+            return Ok(false)
+        }
+        Err(err) => return Err(err.into()),
+    };
+    progress.map(|p| p.set_message(format!("🔎 Checking availability of {package_req}")));
+    verify_remote_package_available(&remote_package, config).await
+}
+
+/// Check whether a package is available from the source it was found in,
+/// without downloading it.
+async fn verify_remote_package_available(
+    remote_package: &RemotePackage,
+    config: &Config,
+) -> Result<bool, SearchAndDownloadError> {
+    let package = &remote_package.package;
+    let (server_url, exts) = match &remote_package.source {
+        RemotePackageSource::LuarocksRockspec(url) => {
+            let url = rockspec_url(url, package).map_err(DownloadSrcRockError::from)?;
+            return Ok(is_available(url, config)
+                .await
+                .map_err(DownloadRockspecError::from)?);
+        }
+        RemotePackageSource::RockspecContent(_) => return Ok(true),
+        RemotePackageSource::LuarocksBinaryRock(url) => (
+            archive_server_url(remote_package, url),
+            vec![binary_rock_ext(), "all.rock".into()],
+        ),
+        RemotePackageSource::LuarocksSrcRock(url) => (
+            archive_server_url(remote_package, url),
+            vec!["src.rock".into()],
+        ),
+        RemotePackageSource::Local | RemotePackageSource::LocalPath(_) => {
+            return Err(SearchAndDownloadError::LocalSource)
+        }
         #[cfg(test)]
         RemotePackageSource::Test => unimplemented!(),
+    };
+    if let Some(rock_url) = lockfile_packed_rock_url(remote_package) {
+        return Ok(is_available(rock_url.clone(), config)
+            .await
+            .map_err(DownloadSrcRockError::from)?);
+    }
+    for ext in exts {
+        let url = packed_rock_url(server_url, package, &ext).map_err(DownloadSrcRockError::from)?;
+        if is_available(url, config)
+            .await
+            .map_err(DownloadSrcRockError::from)?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The server URL to download packed rocks from.
+/// A server URL recorded in the lockfile takes priority over the one the package was found on.
+fn archive_server_url<'a>(remote_package: &'a RemotePackage, server_url: &'a Url) -> &'a Url {
+    match &remote_package.source_url {
+        Some(RemotePackageSourceUrl::Url { url }) => url,
+        _ => server_url,
+    }
+}
+
+/// The URL of a package's rockspec on a server.
+fn rockspec_url(server_url: &Url, package: &PackageSpec) -> Result<Url, ParseError> {
+    let rockspec_name = format!("{}-{}.rockspec", package.name(), package.version());
+    format!(
+        "{}/{}",
+        server_url.as_str().trim_end_matches('/'),
+        rockspec_name
+    )
+    .parse()
+}
+
+/// The URL of a package's packed rock with the given extension on a server.
+fn packed_rock_url(server_url: &Url, package: &PackageSpec, ext: &str) -> Result<Url, ParseError> {
+    server_url.join(&mk_packed_rock_name(package.name(), package.version(), ext))
+}
+
+/// The packed rock extension for the current platform, e.g. `linux-x86_64.rock`.
+fn binary_rock_ext() -> String {
+    format!("{}.rock", luarocks::current_platform_luarocks_identifier())
+}
+
+/// The full URL of the packed rock recorded in the lockfile, if any.
+fn lockfile_packed_rock_url(remote_package: &RemotePackage) -> Option<&Url> {
+    remote_package
+        .source_url
+        .as_ref()
+        .and_then(RemotePackageSourceUrl::packed_rock_url)
+}
+
+/// Send a `HEAD` request to the URL, falling back to a ranged 1-byte `GET`
+/// if the server doesn't allow `HEAD` requests.
+/// Client errors mean the resource is unavailable, while server errors are propagated.
+async fn is_available(url: Url, config: &Config) -> Result<bool, reqwest::Error> {
+    let response = config.request(Method::HEAD, url.clone()).send().await?;
+    let response = if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+        config
+            .request(Method::GET, url)
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .await?
+    } else {
+        response
+    };
+    if response.status().is_client_error() {
+        Ok(false)
+    } else {
+        Ok(response.error_for_status()?.status().is_success())
     }
 }
 
@@ -342,53 +699,91 @@ pub enum SearchAndDownloadError {
     MissingCheckoutRef(String),
     #[error("cannot download from a local rock source.")]
     LocalSource,
+    #[error("dependency resolution was cancelled.")]
+    Cancelled,
+    #[error("dependency resolution exceeded the maximum depth of {max_depth} at {package}")]
+    ResolutionTooDeep {
+        package: PackageName,
+        max_depth: usize,
+    },
+    #[error("conflicting version requirements for {package}: {req_a} and {req_b}")]
+    VersionConflict {
+        package: PackageName,
+        req_a: Box<PackageVersionReq>,
+        req_b: Box<PackageVersionReq>,
+    },
+    #[error("{package} does not support the target platform ({platform})")]
+    UnsupportedPlatform {
+        package: PackageSpec,
+        platform: PlatformIdentifier,
+    },
 }
 
 async fn search_and_download_src_rock(
     package_req: &PackageReq,
     package_db: &RemotePackageDB,
+    config: &Config,
     progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
 ) -> Result<DownloadedPackedRockBytes, SearchAndDownloadError> {
-    let filter = Some(RemotePackageTypeFilterSpec {
-        rockspec: false,
-        binary: false,
-        src: true,
-    });
+    let filter = Some(RemotePackageTypeFilterSpec::src_only());
     let remote_package = package_db.find(package_req, filter, progress)?;
     Ok(download_src_rock(
         &remote_package.package,
         unsafe { &remote_package.source.url() },
+        config,
         progress,
+        on_progress,
     )
     .await?)
 }
 
 #[derive(Error, Debug)]
 pub enum DownloadSrcRockError {
-    #[error("failed to download source rock: {0}")]
+    #[error("failed to download source rock: {}", display_request_error(.0))]
     Request(#[from] reqwest::Error),
     #[error("failed to parse source rock URL: {0}")]
     Parse(#[from] ParseError),
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(package = %package.name(), version = %package.version(), server = %server_url)
+    )
+)]
 pub(crate) async fn download_src_rock(
     package: &PackageSpec,
     server_url: &Url,
+    config: &Config,
     progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
 ) -> Result<DownloadedPackedRockBytes, DownloadSrcRockError> {
-    ArchiveDownload::new(package, server_url, "src.rock", progress)
+    ArchiveDownload::new(package, server_url, "src.rock", config, progress)
+        .maybe_on_progress(on_progress)
         .download()
         .await
 }
 
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(package = %package.name(), version = %package.version(), server = %server_url)
+    )
+)]
 pub(crate) async fn download_binary_rock(
     package: &PackageSpec,
     server_url: &Url,
+    config: &Config,
     progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
 ) -> Result<DownloadedPackedRockBytes, DownloadSrcRockError> {
-    let ext = format!("{}.rock", luarocks::current_platform_luarocks_identifier());
-    ArchiveDownload::new(package, server_url, &ext, progress)
+    let ext = binary_rock_ext();
+    ArchiveDownload::new(package, server_url, &ext, config, progress)
         .fallback_ext("all.rock")
+        .maybe_on_progress(on_progress)
         .download()
         .await
 }
@@ -397,11 +792,14 @@ async fn download_src_rock_to_file(
     package_req: &PackageReq,
     destination_dir: Option<PathBuf>,
     package_db: &RemotePackageDB,
+    config: &Config,
     progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
 ) -> Result<DownloadedPackedRock, SearchAndDownloadError> {
     progress.map(|p| p.set_message(format!("📥 Downloading {package_req}")));
 
-    let rock = search_and_download_src_rock(package_req, package_db, progress).await?;
+    let rock = search_and_download_src_rock(package_req, package_db, config, progress, on_progress)
+        .await?;
     let full_rock_name = mk_packed_rock_name(&rock.name, &rock.version, "src.rock");
     tokio::fs::write(
         destination_dir
@@ -420,7 +818,7 @@ async fn download_src_rock_to_file(
 
 #[derive(Builder)]
 #[builder(start_fn = new, finish_fn(name = _build, vis = ""))]
-struct ArchiveDownload<'a> {
+struct ArchiveDownload<'a, 'b> {
     #[builder(start_fn)]
     package: &'a PackageSpec,
 
@@ -430,13 +828,18 @@ struct ArchiveDownload<'a> {
     #[builder(start_fn)]
     ext: &'a str,
 
+    #[builder(start_fn)]
+    config: &'a Config,
+
     #[builder(start_fn)]
     progress: &'a Progress<ProgressBar>,
 
     fallback_ext: Option<&'a str>,
+
+    on_progress: Option<&'a mut DownloadProgressFn<'b>>,
 }
 
-impl<State> ArchiveDownloadBuilder<'_, State>
+impl<State> ArchiveDownloadBuilder<'_, '_, State>
 where
     State: archive_download_builder::State,
 {
@@ -455,27 +858,37 @@ where
             ))
         });
         let full_rock_name = mk_packed_rock_name(package.name(), package.version(), ext);
-        let url = server_url.join(&full_rock_name)?;
-        let response = reqwest::Client::new().get(url.clone()).send().await?;
-        let bytes = if response.status().is_success() {
-            response.bytes().await
+        let url = packed_rock_url(server_url, package, ext)?;
+        let config = args.config;
+        let on_progress = args.on_progress;
+        let response = config.download(url.clone()).send().await?;
+        let (bytes, full_rock_name, url) = if response.status().is_success() {
+            (
+                read_response_body(response, on_progress).await?,
+                full_rock_name,
+                url,
+            )
         } else {
             match args.fallback_ext {
                 Some(ext) => {
                     let full_rock_name =
                         mk_packed_rock_name(package.name(), package.version(), ext);
-                    let url = server_url.join(&full_rock_name)?;
-                    reqwest::Client::new()
-                        .get(url.clone())
+                    let url = packed_rock_url(server_url, package, ext)?;
+                    let response = config
+                        .download(url.clone())
                         .send()
                         .await?
-                        .error_for_status()?
-                        .bytes()
-                        .await
+                        .error_for_status()?;
+                    let bytes = read_response_body(response, on_progress).await?;
+                    (bytes, full_rock_name, url)
                 }
-                None => response.error_for_status()?.bytes().await,
+                None => (
+                    read_response_body(response.error_for_status()?, on_progress).await?,
+                    full_rock_name,
+                    url,
+                ),
             }
-        }?;
+        };
         Ok(DownloadedPackedRockBytes {
             name: package.name().clone(),
             version: package.version().clone(),
@@ -486,6 +899,53 @@ where
     }
 }
 
+/// Download a packed rock from the exact URL it was previously downloaded from.
+async fn download_packed_rock_from_url(
+    package: &PackageSpec,
+    url: &Url,
+    config: &Config,
+    progress: &Progress<ProgressBar>,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
+) -> Result<DownloadedPackedRockBytes, DownloadSrcRockError> {
+    let file_name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+        .to_string();
+    progress.map(|p| p.set_message(format!("📥 Downloading {file_name}")));
+    let response = config
+        .download(url.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    let bytes = read_response_body(response, on_progress).await?;
+    Ok(DownloadedPackedRockBytes {
+        name: package.name().clone(),
+        version: package.version().clone(),
+        bytes,
+        file_name,
+        url: url.clone(),
+    })
+}
+
+/// Read a response body, reporting the number of bytes read so far to `on_progress`.
+async fn read_response_body(
+    mut response: reqwest::Response,
+    on_progress: Option<&mut DownloadProgressFn<'_>>,
+) -> Result<Bytes, reqwest::Error> {
+    let Some(on_progress) = on_progress else {
+        return response.bytes().await;
+    };
+    let total = response.content_length();
+    let mut bytes = BytesMut::new();
+    on_progress(0, total);
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        on_progress(bytes.len() as u64, total);
+    }
+    Ok(bytes.freeze())
+}
+
 fn mk_packed_rock_name(name: &PackageName, version: &PackageVersion, ext: &str) -> String {
     format!("{name}-{version}.{ext}")
 }
@@ -510,3 +970,286 @@ pub(crate) async fn unpack_rockspec(
     let rockspec = RemoteLuaRockspec::new(&content)?;
     Ok(rockspec)
 }
+
+#[cfg(test)]
+mod tests {
+    use httptest::{
+        all_of,
+        matchers::{contains, request},
+        responders::status_code,
+        Expectation, Server,
+    };
+
+    use crate::{config::ConfigBuilder, progress::MultiProgress};
+
+    use super::*;
+
+    const ROCKSPEC: &str = r#"
+package = "foo"
+version = "1.0.0-1"
+source = {
+    url = "https://example.com/foo-1.0.0.tar.gz",
+}
+"#;
+
+    #[tokio::test]
+    async fn download_rockspec_from_url() {
+        // Any manifest request would fail, as the server doesn't expect it.
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::path("/foo-1.0.0-1.rockspec"))
+                .respond_with(status_code(200).body(ROCKSPEC)),
+        );
+        let url = Url::parse(&server.url_str("/")).unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let package_req = PackageReq::parse("foo==1.0.0-1").unwrap();
+        let downloaded = Download::new(&package_req, &config, &bar)
+            .download_rockspec_from_url(&url)
+            .await
+            .unwrap();
+        assert_eq!(downloaded.rockspec.package().to_string(), "foo");
+        assert_eq!(downloaded.rockspec.version().to_string(), "1.0.0-1");
+        assert_eq!(
+            downloaded.source,
+            RemotePackageSource::LuarocksRockspec(url)
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_remote_package_available() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("HEAD"),
+                request::path("/foo-1.0.0-1.rockspec"),
+            ])
+            .respond_with(status_code(200)),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("HEAD"),
+                request::path("/foo-1.0.0-1.src.rock"),
+            ])
+            .respond_with(status_code(404)),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("HEAD"),
+                request::path("/bar-1.0.0-1.src.rock"),
+            ])
+            .respond_with(status_code(405)),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("GET"),
+                request::path("/bar-1.0.0-1.src.rock"),
+                request::headers(contains(("range", "bytes=0-0"))),
+            ])
+            .respond_with(status_code(206).body("x")),
+        );
+        let url = Url::parse(&server.url_str("/")).unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let foo = PackageSpec::parse("foo".into(), "1.0.0-1".into()).unwrap();
+        let bar = PackageSpec::parse("bar".into(), "1.0.0-1".into()).unwrap();
+
+        let rockspec = RemotePackage::new(
+            foo.clone(),
+            RemotePackageSource::LuarocksRockspec(url.clone()),
+            None,
+        );
+        assert!(super::verify_remote_package_available(&rockspec, &config)
+            .await
+            .unwrap());
+        let missing_src_rock =
+            RemotePackage::new(foo, RemotePackageSource::LuarocksSrcRock(url.clone()), None);
+        assert!(
+            !super::verify_remote_package_available(&missing_src_rock, &config)
+                .await
+                .unwrap()
+        );
+        let head_not_allowed =
+            RemotePackage::new(bar, RemotePackageSource::LuarocksSrcRock(url), None);
+        assert!(
+            super::verify_remote_package_available(&head_not_allowed, &config)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn download_remote_package_records_full_url() {
+        let server = Server::run();
+        let src_rock = std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/luatest-0.2-1.src.rock"),
+        )
+        .unwrap();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("GET"),
+                request::path("/files/luatest-0.2-1.src.rock"),
+            ])
+            .times(2)
+            .respond_with(status_code(200).body(src_rock)),
+        );
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("HEAD"),
+                request::path("/files/luatest-0.2-1.src.rock"),
+            ])
+            .respond_with(status_code(200)),
+        );
+        let manifest_server_url = Url::parse(&server.url_str("/manifests/")).unwrap();
+        let file_server_url = Url::parse(&server.url_str("/files/")).unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let luatest = PackageSpec::parse("luatest".into(), "0.2-1".into()).unwrap();
+
+        let remote_package = RemotePackage::new(
+            luatest.clone(),
+            RemotePackageSource::LuarocksSrcRock(file_server_url.clone()),
+            None,
+        );
+        let download = download_remote_package(remote_package, &config, &bar, None)
+            .await
+            .unwrap();
+        let expected_url = file_server_url.join("luatest-0.2-1.src.rock").unwrap();
+        let source_url = match download {
+            RemoteRockDownload::SrcRock { source_url, .. } => source_url,
+            _ => panic!("expected a src rock download"),
+        };
+        assert_eq!(
+            source_url,
+            RemotePackageSourceUrl::Url {
+                url: expected_url.clone()
+            }
+        );
+
+        // Reinstalling from a lockfile uses the recorded URL,
+        // even if the package's server has a different layout.
+        let remote_package = RemotePackage::new(
+            luatest,
+            RemotePackageSource::LuarocksSrcRock(manifest_server_url),
+            Some(source_url),
+        );
+        assert!(
+            super::verify_remote_package_available(&remote_package, &config)
+                .await
+                .unwrap()
+        );
+        let download = download_remote_package(remote_package, &config, &bar, None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            download,
+            RemoteRockDownload::SrcRock {
+                source_url: RemotePackageSourceUrl::Url { url },
+                ..
+            } if url == expected_url
+        ));
+    }
+
+    #[tokio::test]
+    async fn download_remote_package_from_rockspec_file() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let rockspec_path = temp.join("foo-1.0.0-1.rockspec");
+        let updated_rockspec = ROCKSPEC.replace("foo-1.0.0.tar.gz", "foo-1.0.0-updated.tar.gz");
+        std::fs::write(&rockspec_path, &updated_rockspec).unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let foo = PackageSpec::parse("foo".into(), "1.0.0-1".into()).unwrap();
+
+        let remote_package = RemotePackage::new(
+            foo.clone(),
+            RemotePackageSource::RockspecContent(ROCKSPEC.into()),
+            Some(RemotePackageSourceUrl::File {
+                path: rockspec_path.clone(),
+            }),
+        );
+        let download = download_remote_package(remote_package, &config, &bar, None)
+            .await
+            .unwrap();
+        let rockspec_download = download.rockspec_download();
+        assert_eq!(
+            rockspec_download.source,
+            RemotePackageSource::RockspecContent(updated_rockspec.clone())
+        );
+        assert_eq!(rockspec_download.source_url, None);
+
+        // Falls back to the recorded rockspec if the file no longer exists.
+        std::fs::remove_file(&rockspec_path).unwrap();
+        let remote_package = RemotePackage::new(
+            foo,
+            RemotePackageSource::RockspecContent(ROCKSPEC.into()),
+            Some(RemotePackageSourceUrl::File {
+                path: rockspec_path,
+            }),
+        );
+        let download = download_remote_package(remote_package, &config, &bar, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            download.rockspec_download().source,
+            RemotePackageSource::RockspecContent(ROCKSPEC.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn download_remote_package_reports_progress() {
+        let server = Server::run();
+        let src_rock = std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/luatest-0.2-1.src.rock"),
+        )
+        .unwrap();
+        let len = src_rock.len() as u64;
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("GET"),
+                request::path("/luatest-0.2-1.src.rock"),
+            ])
+            .respond_with(status_code(200).body(src_rock)),
+        );
+        let server_url = Url::parse(&server.url_str("/")).unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let progress = MultiProgress::new(&config);
+        let bar = progress.map(MultiProgress::new_bar);
+        let luatest = PackageSpec::parse("luatest".into(), "0.2-1".into()).unwrap();
+        let remote_package = RemotePackage::new(
+            luatest,
+            RemotePackageSource::LuarocksSrcRock(server_url),
+            None,
+        );
+
+        let mut reports = Vec::new();
+        let mut on_progress = |downloaded, total| reports.push((downloaded, total));
+        download_remote_package(remote_package, &config, &bar, Some(&mut on_progress))
+            .await
+            .unwrap();
+        assert_eq!(reports.first(), Some(&(0, Some(len))));
+        assert_eq!(reports.last(), Some(&(len, Some(len))));
+        assert!(reports.is_sorted());
+    }
+}
@@ -2,7 +2,7 @@ use std::io;
 use tokio::process::Command;
 
 use crate::{
-    config::{Config, LuaVersion, LuaVersionUnset},
+    config::{Config, LuaVersionUnset},
     lua_rockspec::LuaVersionError,
     operations::Install,
     package::{PackageReq, PackageVersionReqError},
@@ -84,11 +84,7 @@ pub enum ExecError {
 }
 
 async fn exec(run: Exec<'_>) -> Result<(), ExecError> {
-    let lua_version = run
-        .project
-        .map(|project| project.lua_version(run.config))
-        .transpose()?
-        .unwrap_or(LuaVersion::from(run.config)?.clone());
+    let lua_version = run.config.resolve_lua_version(run.project)?;
 
     let user_tree = run.config.user_tree(lua_version)?;
     let mut paths = Paths::new(&user_tree)?;
@@ -143,7 +139,7 @@ pub enum InstallCmdError {
     PackageVersionReqError(#[from] PackageVersionReqError),
     RemotePackageDBError(#[from] RemotePackageDBError),
     Tree(#[from] TreeError),
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
 }
 
 /// Ensure that a command is installed.
@@ -154,7 +150,7 @@ pub async fn install_command(command: &str, config: &Config) -> Result<(), Insta
         tree::EntryType::Entrypoint,
     )
     .build();
-    let tree = config.user_tree(LuaVersion::from(config)?.clone())?;
+    let tree = config.user_tree(config.resolve_lua_version(None)?)?;
     Install::new(config)
         .package(install_spec)
         .tree(tree)
@@ -12,7 +12,7 @@ use std::path::PathBuf;
 use thiserror::Error;
 
 use crate::build::utils::recursive_copy_dir;
-use crate::config::Config;
+use crate::config::{display_request_error, Config};
 use crate::git::GitSource;
 use crate::hash::HasIntegrity;
 use crate::lockfile::RemotePackageSourceUrl;
@@ -105,7 +105,7 @@ pub enum FetchSrcError {
     GitUrlParse(#[from] GitUrlParseError),
     #[error(transparent)]
     Io(#[from] io::Error),
-    #[error(transparent)]
+    #[error("{}", display_request_error(.0))]
     Request(#[from] reqwest::Error),
     #[error(transparent)]
     Unpack(#[from] UnpackError),
@@ -201,8 +201,9 @@ async fn do_fetch_src<R: Rockspec>(
         RockSourceSpec::Url(url) => {
             progress.map(|p| p.set_message(format!("📥 Downloading {}", url.to_owned())));
 
-            let response = reqwest::Client::new()
-                .get(url.clone())
+            let response = fetch
+                .config
+                .download(url.clone())
                 .send()
                 .await?
                 .error_for_status()?
@@ -279,7 +280,14 @@ async fn do_fetch_src_rock(
     let dest_dir = fetch.dest_dir;
     let config = fetch.config;
     let progress = fetch.progress;
-    let src_rock = operations::download_src_rock(package, config.server(), progress).await?;
+    let src_rock = operations::download_src_rock(
+        package,
+        config.select_server().await,
+        config,
+        progress,
+        None,
+    )
+    .await?;
     let hash = src_rock.bytes.hash()?;
     let cursor = Cursor::new(src_rock.bytes);
     let mime_type = infer::get(cursor.get_ref()).map(|file_type| file_type.mime_type());
@@ -4,6 +4,7 @@ use crate::project::Project;
 use crate::project::ProjectError;
 use crate::project::ProjectTreeError;
 use crate::project::LUX_DIR_NAME;
+use crate::tree::RockLayout;
 use bon::Builder;
 use itertools::Itertools;
 use path_slash::PathBufExt;
@@ -64,11 +65,15 @@ struct Workspace {
 
 async fn do_generate_luarc(args: GenLuaRc<'_>) -> Result<(), GenLuaRcError> {
     let config = args.config;
-    if !config.generate_luarc() {
+    let project = args.project;
+    if !project
+        .toml()
+        .generate_luarc()
+        .unwrap_or(config.generate_luarc())
+    {
         return Ok(());
     }
-    let project = args.project;
-    let lockfile = project.lockfile()?;
+    let lockfile = project.lockfile_for(config)?;
     let luarc_path = project.luarc_path();
 
     // read the existing .luarc file or initialise a new one if it doesn't exist
@@ -83,8 +88,7 @@ async fn do_generate_luarc(args: GenLuaRc<'_>) -> Result<(), GenLuaRcError> {
         .values()
         .map(|dependency| dependency_tree.installed_rock_layout(dependency))
         .filter_map(Result::ok)
-        .map(|rock_layout| rock_layout.src)
-        .filter(|dir| dir.is_dir())
+        .flat_map(library_dirs)
         .map(|dependency_dir| {
             diff_paths(dependency_dir, project.root())
                 .expect("tree root should be a subpath of the project root")
@@ -97,8 +101,7 @@ async fn do_generate_luarc(args: GenLuaRc<'_>) -> Result<(), GenLuaRcError> {
         .values()
         .map(|dependency| test_dependency_tree.installed_rock_layout(dependency))
         .filter_map(Result::ok)
-        .map(|rock_layout| rock_layout.src)
-        .filter(|dir| dir.is_dir())
+        .flat_map(library_dirs)
         .map(|test_dependency_dir| {
             diff_paths(test_dependency_dir, project.root())
                 .expect("test tree root should be a subpath of the project root")
@@ -118,6 +121,14 @@ async fn do_generate_luarc(args: GenLuaRc<'_>) -> Result<(), GenLuaRcError> {
     Ok(())
 }
 
+/// The directories of an installed rock that should be added to the library path.
+/// Besides the Lua sources, native rocks may ship Lua stubs in their `lib` directory.
+fn library_dirs(rock_layout: RockLayout) -> impl Iterator<Item = PathBuf> {
+    [rock_layout.src, rock_layout.lib]
+        .into_iter()
+        .filter(|dir| dir.is_dir())
+}
+
 fn update_luarc_content(
     prev_contents: &str,
     extra_paths: Vec<PathBuf>,
@@ -140,6 +151,7 @@ fn update_luarc_content(
 
 #[cfg(test)]
 mod test {
+    use assert_fs::prelude::PathChild;
 
     use super::*;
 
@@ -205,4 +217,31 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_library_dirs() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let rock_path = temp.child("rock").to_path_buf();
+        let rock_layout = || RockLayout {
+            rock_path: rock_path.clone(),
+            etc: rock_path.join("etc"),
+            lib: rock_path.join("lib"),
+            src: rock_path.join("src"),
+            bin: temp.child("bin").to_path_buf(),
+            conf: rock_path.join("etc/conf"),
+            doc: rock_path.join("etc/doc"),
+        };
+
+        std::fs::create_dir_all(rock_path.join("src")).unwrap();
+        assert_eq!(
+            library_dirs(rock_layout()).collect_vec(),
+            vec![rock_path.join("src")]
+        );
+
+        std::fs::create_dir_all(rock_path.join("lib")).unwrap();
+        assert_eq!(
+            library_dirs(rock_layout()).collect_vec(),
+            vec![rock_path.join("src"), rock_path.join("lib")]
+        );
+    }
 }
@@ -5,7 +5,7 @@ use crate::{
     config::{Config, LuaVersionUnset},
     lockfile::{
         FlushLockfileError, LocalPackage, LocalPackageId, LockConstraint, Lockfile, OptState,
-        PinnedState, ReadWrite,
+        PinnedState, ReadWrite, RemotePackageSourceUrl,
     },
     lua_installation::{LuaInstallation, LuaInstallationError},
     lua_rockspec::BuildBackendSpec,
@@ -13,8 +13,12 @@ use crate::{
         install_binary_rock::{BinaryRockInstall, InstallBinaryRockError},
         luarocks_installation::{LuaRocksError, LuaRocksInstallError, LuaRocksInstallation},
     },
-    operations::resolve::{Resolve, ResolveDependenciesError},
-    package::{PackageName, PackageNameList},
+    operations::{
+        download::download_remote_package,
+        resolve::{PackageInstallData, Resolve, ResolveDependenciesError},
+        SearchAndDownloadError,
+    },
+    package::{PackageName, PackageNameList, PackageSpec},
     progress::{MultiProgress, Progress, ProgressBar},
     project::{Project, ProjectTreeError},
     remote_package_db::{RemotePackageDB, RemotePackageDBError, RemotePackageDbIntegrityError},
@@ -22,6 +26,7 @@ use crate::{
     tree::{self, Tree, TreeError},
 };
 
+pub use crate::operations::install::plan::{InstallPlan, PlannedPackage};
 pub use crate::operations::install::spec::PackageInstallSpec;
 
 use bon::Builder;
@@ -29,9 +34,11 @@ use bytes::Bytes;
 use futures::StreamExt;
 use itertools::Itertools;
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 
 use super::{DownloadedRockspec, RemoteRockDownload};
 
+pub mod plan;
 pub mod spec;
 
 /// A rocks package installer, providing fine-grained control
@@ -48,6 +55,13 @@ pub struct Install<'a> {
     tree: Tree,
     package_db: Option<RemotePackageDB>,
     progress: Option<Arc<Progress<MultiProgress>>>,
+    /// Cancels dependency resolution, e.g. when the user interrupts the installation.
+    cancellation_token: Option<CancellationToken>,
+    /// Resolve each package to a single version, failing with a
+    /// `SearchAndDownloadError::VersionConflict` if dependents require disjoint versions.
+    /// By default, multiple versions of a package may be installed side by side.
+    #[builder(default)]
+    unified: bool,
 }
 
 impl<'a, State> InstallBuilder<'a, State>
@@ -95,19 +109,59 @@ where
     /// Install the packages.
     pub async fn install(self) -> Result<Vec<LocalPackage>, InstallError> {
         let install_built = self._build();
-        let progress = match install_built.progress {
-            Some(p) => p,
-            None => MultiProgress::new_arc(install_built.config),
-        };
-        let package_db = match install_built.package_db {
+        let config = install_built.config;
+        let tree = install_built.tree.clone();
+        let progress = install_built.progress();
+        let resolved = install_built.resolve(progress.clone()).await?;
+        install_impl(resolved, config, &tree, progress).await
+    }
+
+    /// Compute which packages would be installed, and from which sources,
+    /// without building or installing them.
+    /// Note that dependency resolution downloads the rockspecs and packed rocks.
+    pub async fn plan(self) -> Result<InstallPlan, InstallError> {
+        let install_built = self._build();
+        let progress = install_built.progress();
+        let resolved = install_built.resolve(progress).await?;
+        Ok(InstallPlan::new(
+            resolved.dependencies.values(),
+            &resolved.build_dependencies,
+        ))
+    }
+}
+
+/// The packages to install, as determined by dependency resolution.
+struct Resolved {
+    package_db: Arc<RemotePackageDB>,
+    /// Build dependencies, in the order in which they must be installed.
+    build_dependencies: Vec<PackageInstallData>,
+    dependencies: HashMap<LocalPackageId, PackageInstallData>,
+}
+
+impl Install<'_> {
+    fn progress(&self) -> Arc<Progress<MultiProgress>> {
+        match &self.progress {
+            Some(p) => p.clone(),
+            None => MultiProgress::new_arc(self.config),
+        }
+    }
+
+    async fn resolve(
+        self,
+        progress_arc: Arc<Progress<MultiProgress>>,
+    ) -> Result<Resolved, InstallError> {
+        let config = self.config;
+        let tree = self.tree;
+        let package_db = match self.package_db {
             Some(db) => db,
             None => {
-                let bar = progress.map(|p| p.new_bar());
-                RemotePackageDB::from_config(install_built.config, &bar).await?
+                let bar = progress_arc.map(|p| p.new_bar());
+                RemotePackageDB::from_config(config, &bar).await?
             }
         };
+        let package_db = Arc::new(package_db);
 
-        let duplicate_entrypoints = install_built
+        let duplicate_entrypoints = self
             .packages
             .iter()
             .filter(|pkg| pkg.entry_type == tree::EntryType::Entrypoint)
@@ -122,14 +176,40 @@ where
             )));
         }
 
-        install_impl(
-            install_built.packages,
-            Arc::new(package_db),
-            install_built.config,
-            &install_built.tree,
-            progress,
-        )
-        .await
+        let (dep_tx, mut dep_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (build_dep_tx, mut build_dep_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let lockfile = tree.lockfile()?;
+        let build_lockfile = tree.build_tree(config)?.lockfile()?;
+
+        Resolve::new()
+            .dependencies_tx(dep_tx)
+            .build_dependencies_tx(build_dep_tx)
+            .packages(self.packages)
+            .package_db(package_db.clone())
+            .lockfile(Arc::new(lockfile))
+            .build_lockfile(Arc::new(build_lockfile))
+            .lua_version(tree.version())
+            .config(config)
+            .progress(progress_arc)
+            .maybe_cancellation_token(self.cancellation_token)
+            .maybe_unified_version_reqs(self.unified.then(Arc::default))
+            .get_all_dependencies()
+            .await?;
+
+        let mut build_dependencies = Vec::with_capacity(build_dep_rx.len());
+        while let Some(build_dep) = build_dep_rx.recv().await {
+            build_dependencies.push(build_dep);
+        }
+        let mut dependencies = HashMap::with_capacity(dep_rx.len());
+        while let Some(dep) = dep_rx.recv().await {
+            dependencies.insert(dep.spec.id(), dep);
+        }
+        Ok(Resolved {
+            package_db,
+            build_dependencies,
+            dependencies,
+        })
     }
 }
 
@@ -163,34 +243,33 @@ pub enum InstallError {
     ProjectTreeError(#[from] ProjectTreeError),
     #[error("cannot install duplicate entrypoints:\n{0}")]
     DuplicateEntrypoints(PackageNameList),
+    #[error("failed to download alternative source for {0}:\n{1}")]
+    DownloadAlternateSource(PackageName, SearchAndDownloadError),
+}
+
+impl InstallError {
+    /// Whether this error was caused by building or installing a downloaded rock,
+    /// in which case installing from a different source may succeed.
+    fn is_build_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::BuildError(..) | Self::InstallBinaryRockError(..)
+        )
+    }
 }
 
-// TODO(vhyrro): This function has too many arguments. Refactor it.
-#[allow(clippy::too_many_arguments)]
 async fn install_impl(
-    packages: Vec<PackageInstallSpec>,
-    package_db: Arc<RemotePackageDB>,
+    resolved: Resolved,
     config: &Config,
     tree: &Tree,
     progress_arc: Arc<Progress<MultiProgress>>,
 ) -> Result<Vec<LocalPackage>, InstallError> {
-    let (dep_tx, mut dep_rx) = tokio::sync::mpsc::unbounded_channel();
-    let (build_dep_tx, mut build_dep_rx) = tokio::sync::mpsc::unbounded_channel();
-
+    let Resolved {
+        package_db,
+        build_dependencies,
+        dependencies: all_packages,
+    } = resolved;
     let lockfile = tree.lockfile()?;
-    let build_lockfile = tree.build_tree(config)?.lockfile()?;
-
-    Resolve::new()
-        .dependencies_tx(dep_tx)
-        .build_dependencies_tx(build_dep_tx)
-        .packages(packages)
-        .package_db(package_db.clone())
-        .lockfile(Arc::new(lockfile.clone()))
-        .build_lockfile(Arc::new(build_lockfile.clone()))
-        .config(config)
-        .progress(progress_arc.clone())
-        .get_all_dependencies()
-        .await?;
 
     let lua = Arc::new(
         LuaInstallation::new_from_config(config, &progress_arc.map(|progress| progress.new_bar()))
@@ -198,7 +277,7 @@ async fn install_impl(
     );
 
     // We have to install transitive build dependencies sequentially
-    while let Some(build_dep_spec) = build_dep_rx.recv().await {
+    for build_dep_spec in build_dependencies {
         let rockspec = build_dep_spec.downloaded_rock.rockspec();
         let bar = progress_arc.map(|p| {
             p.add(ProgressBar::from(format!(
@@ -227,81 +306,25 @@ async fn install_impl(
         build_lockfile.add_entrypoint(&pkg);
     }
 
-    let mut all_packages = HashMap::with_capacity(dep_rx.len());
-    while let Some(dep) = dep_rx.recv().await {
-        all_packages.insert(dep.spec.id(), dep);
-    }
-
     let installed_packages =
         futures::stream::iter(all_packages.clone().into_values().map(|install_spec| {
             let progress_arc = progress_arc.clone();
-            let downloaded_rock = install_spec.downloaded_rock;
+            let package_db = package_db.clone();
             let config = config.clone();
             let tree = tree.clone();
             let lua = lua.clone();
 
             tokio::spawn({
                 async move {
-                    let pkg = match downloaded_rock {
-                        RemoteRockDownload::RockspecOnly { rockspec_download } => {
-                            install_rockspec(
-                                rockspec_download,
-                                None,
-                                install_spec.spec.constraint(),
-                                install_spec.build_behaviour,
-                                install_spec.pin,
-                                install_spec.opt,
-                                install_spec.entry_type,
-                                &lua,
-                                &tree,
-                                &config,
-                                progress_arc,
-                            )
-                            .await?
-                        }
-                        RemoteRockDownload::BinaryRock {
-                            rockspec_download,
-                            packed_rock,
-                        } => {
-                            install_binary_rock(
-                                rockspec_download,
-                                packed_rock,
-                                install_spec.spec.constraint(),
-                                install_spec.build_behaviour,
-                                install_spec.pin,
-                                install_spec.opt,
-                                install_spec.entry_type,
-                                &config,
-                                &tree,
-                                progress_arc,
-                            )
-                            .await?
-                        }
-                        RemoteRockDownload::SrcRock {
-                            rockspec_download,
-                            src_rock,
-                            source_url,
-                        } => {
-                            let src_rock_source = SrcRockSource {
-                                bytes: src_rock,
-                                source_url,
-                            };
-                            install_rockspec(
-                                rockspec_download,
-                                Some(src_rock_source),
-                                install_spec.spec.constraint(),
-                                install_spec.build_behaviour,
-                                install_spec.pin,
-                                install_spec.opt,
-                                install_spec.entry_type,
-                                &lua,
-                                &tree,
-                                &config,
-                                progress_arc,
-                            )
-                            .await?
-                        }
-                    };
+                    let pkg = install_with_fallback(
+                        &install_spec,
+                        &package_db,
+                        &lua,
+                        &tree,
+                        &config,
+                        progress_arc,
+                    )
+                    .await?;
 
                     Ok::<_, InstallError>((pkg.id(), (pkg, install_spec.entry_type)))
                 }
@@ -354,6 +377,148 @@ async fn install_impl(
         .collect_vec())
 }
 
+async fn install_downloaded_rock(
+    downloaded_rock: RemoteRockDownload,
+    install_spec: &PackageInstallData,
+    lua: &LuaInstallation,
+    tree: &Tree,
+    config: &Config,
+    progress_arc: Arc<Progress<MultiProgress>>,
+) -> Result<LocalPackage, InstallError> {
+    match downloaded_rock {
+        RemoteRockDownload::RockspecOnly { rockspec_download } => {
+            install_rockspec(
+                rockspec_download,
+                None,
+                install_spec.spec.constraint(),
+                install_spec.build_behaviour,
+                install_spec.pin,
+                install_spec.opt,
+                install_spec.entry_type,
+                lua,
+                tree,
+                config,
+                progress_arc,
+            )
+            .await
+        }
+        RemoteRockDownload::BinaryRock {
+            rockspec_download,
+            packed_rock,
+            source_url,
+        } => {
+            install_binary_rock(
+                rockspec_download,
+                packed_rock,
+                source_url,
+                install_spec.spec.constraint(),
+                install_spec.build_behaviour,
+                install_spec.pin,
+                install_spec.opt,
+                install_spec.entry_type,
+                config,
+                tree,
+                progress_arc,
+            )
+            .await
+        }
+        RemoteRockDownload::SrcRock {
+            rockspec_download,
+            src_rock,
+            source_url,
+        } => {
+            let src_rock_source = SrcRockSource {
+                bytes: src_rock,
+                source_url,
+            };
+            install_rockspec(
+                rockspec_download,
+                Some(src_rock_source),
+                install_spec.spec.constraint(),
+                install_spec.build_behaviour,
+                install_spec.pin,
+                install_spec.opt,
+                install_spec.entry_type,
+                lua,
+                tree,
+                config,
+                progress_arc,
+            )
+            .await
+        }
+    }
+}
+
+/// Install a downloaded rock, falling back to the other sources
+/// for the same package version if building or installing it fails,
+/// e.g. due to an ABI mismatch in a binary rock.
+/// If none of the alternate sources succeed, the original error is returned.
+async fn install_with_fallback(
+    install_spec: &PackageInstallData,
+    package_db: &RemotePackageDB,
+    lua: &LuaInstallation,
+    tree: &Tree,
+    config: &Config,
+    progress_arc: Arc<Progress<MultiProgress>>,
+) -> Result<LocalPackage, InstallError> {
+    let downloaded_rock = install_spec.downloaded_rock.clone();
+    let failed_source = downloaded_rock.rockspec_download().source.clone();
+    let rockspec = downloaded_rock.rockspec();
+    let package_spec = PackageSpec::new(rockspec.package().clone(), rockspec.version().clone());
+    let err = match install_downloaded_rock(
+        downloaded_rock,
+        install_spec,
+        lua,
+        tree,
+        config,
+        progress_arc.clone(),
+    )
+    .await
+    {
+        Err(err) if err.is_build_failure() => err,
+        result => return result,
+    };
+    // Only fall back to other sources for the same version.
+    let alternate_sources = package_db
+        .find_all(
+            &package_spec.into_package_req(),
+            None,
+            &Progress::no_progress(),
+        )
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|remote_package| remote_package.source != failed_source);
+    let bar = progress_arc.map(|p| p.new_bar());
+    for remote_package in alternate_sources {
+        bar.map(|p| {
+            p.println(format!(
+                "⚠️ {err}\n🔁 Retrying {} from {}",
+                remote_package.package, remote_package.source
+            ))
+        });
+        let package = remote_package.package.name().clone();
+        let result = match download_remote_package(remote_package, config, &bar, None).await {
+            Ok(downloaded_rock) => {
+                install_downloaded_rock(
+                    downloaded_rock,
+                    install_spec,
+                    lua,
+                    tree,
+                    config,
+                    progress_arc.clone(),
+                )
+                .await
+            }
+            Err(err) => Err(InstallError::DownloadAlternateSource(package, err)),
+        };
+        match result {
+            Ok(pkg) => return Ok(pkg),
+            Err(alternate_err) => bar.map(|p| p.println(format!("⚠️ {alternate_err}"))),
+        };
+    }
+    Err(err)
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn install_rockspec(
     rockspec_download: DownloadedRockspec,
@@ -411,6 +576,7 @@ async fn install_rockspec(
 async fn install_binary_rock(
     rockspec_download: DownloadedRockspec,
     packed_rock: Bytes,
+    source_url: RemotePackageSourceUrl,
     constraint: LockConstraint,
     behaviour: BuildBehaviour,
     pin: PinnedState,
@@ -442,6 +608,7 @@ async fn install_binary_rock(
     .opt(opt)
     .constraint(constraint)
     .behaviour(behaviour)
+    .source_url(source_url)
     .install()
     .await
     .map_err(|err| InstallError::InstallBinaryRockError(package, err))?;
@@ -450,3 +617,213 @@ async fn install_binary_rock(
 
     Ok(pkg)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use httptest::{matchers::request, responders::status_code, Expectation, Server};
+    use url::Url;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackageSpec,
+        lua_rockspec::RemoteLuaRockspec,
+        manifest::{Manifest, ManifestMetadata},
+        remote_package_source::RemotePackageSource,
+        rockspec::RockBinaries,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn fall_back_to_src_rock_if_binary_rock_fails() {
+        let server = Server::run();
+        let src_rock = std::fs::read(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/luatest-0.2-1.src.rock"),
+        )
+        .unwrap();
+        server.expect(
+            Expectation::matching(request::path("/luatest-0.2-1.src.rock"))
+                .times(1..)
+                .respond_with(status_code(200).body(src_rock)),
+        );
+        server.expect(
+            Expectation::matching(request::path("/missing/luatest-0.2-1.src.rock"))
+                .times(1..)
+                .respond_with(status_code(404)),
+        );
+        let manifest = r#"
+commands = {}
+modules = {}
+repository = {
+   luatest = {
+      ['0.2-1'] = {
+         {
+            arch = "all"
+         },
+         {
+            arch = "src"
+         }
+      }
+   }
+}
+"#;
+        let server_url = Url::parse(&server.url_str("/")).unwrap();
+        let missing_url = server_url.join("missing/").unwrap();
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let lua = LuaInstallation::without_headers(LuaVersion::Lua51);
+        let rockspec = RemoteLuaRockspec::new(
+            r#"
+package = "luatest"
+version = "0.2-1"
+source = {
+   url = "git+https://github.com/mblayman/luatest"
+}
+build = {
+   type = "builtin",
+}
+"#,
+        )
+        .unwrap();
+        let package_spec = PackageSpec::new(rockspec.package().clone(), rockspec.version().clone());
+        let corrupt_binary_rock = |server_url: &Url| PackageInstallData {
+            build_behaviour: BuildBehaviour::NoForce,
+            pin: PinnedState::Unpinned,
+            opt: OptState::Required,
+            downloaded_rock: RemoteRockDownload::BinaryRock {
+                rockspec_download: DownloadedRockspec {
+                    rockspec: rockspec.clone(),
+                    source: RemotePackageSource::LuarocksBinaryRock(server_url.clone()),
+                    source_url: None,
+                },
+                packed_rock: Bytes::from_static(b"not a zip archive"),
+                source_url: RemotePackageSourceUrl::Url {
+                    url: server_url.join("luatest-0.2-1.all.rock").unwrap(),
+                },
+            },
+            spec: LocalPackageSpec::new(
+                package_spec.name(),
+                package_spec.version(),
+                LockConstraint::Unconstrained,
+                Vec::new(),
+                &PinnedState::Unpinned,
+                &OptState::Required,
+                RockBinaries::default(),
+            ),
+            entry_type: tree::EntryType::Entrypoint,
+        };
+
+        let package_db: RemotePackageDB = Manifest::new(
+            server_url.clone(),
+            ManifestMetadata::new(&manifest.to_string()).unwrap(),
+        )
+        .into();
+        let pkg = install_with_fallback(
+            &corrupt_binary_rock(&server_url),
+            &package_db,
+            &lua,
+            &tree,
+            &config,
+            MultiProgress::new_arc(&config),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pkg.name(), package_spec.name());
+        assert_eq!(pkg.version(), package_spec.version());
+        assert_eq!(pkg.source, RemotePackageSource::LuarocksSrcRock(server_url));
+
+        // If all alternate sources fail, the original error is returned
+        let package_db: RemotePackageDB = Manifest::new(
+            missing_url.clone(),
+            ManifestMetadata::new(&manifest.to_string()).unwrap(),
+        )
+        .into();
+        let result = install_with_fallback(
+            &corrupt_binary_rock(&missing_url),
+            &package_db,
+            &lua,
+            &tree,
+            &config,
+            MultiProgress::new_arc(&config),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(InstallError::InstallBinaryRockError(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn plan_install() {
+        let fixture_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/version-conflict");
+        let server = Server::run();
+        for rockspec in ["modern-dep-1.0-1.rockspec", "common-dep-1.0-1.rockspec"] {
+            let content = std::fs::read_to_string(fixture_dir.join(rockspec)).unwrap();
+            server.expect(
+                Expectation::matching(request::path(format!("/{rockspec}")))
+                    .respond_with(status_code(200).body(content)),
+            );
+        }
+        let server_url = Url::parse(&server.url_str("/")).unwrap();
+        let manifest = std::fs::read_to_string(fixture_dir.join("manifest")).unwrap();
+        let package_db: RemotePackageDB = Manifest::new(
+            server_url.clone(),
+            ManifestMetadata::new(&manifest).unwrap(),
+        )
+        .into();
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let plan = Install::new(&config)
+            .package(
+                PackageInstallSpec::new("modern-dep".parse().unwrap(), tree::EntryType::Entrypoint)
+                    .build(),
+            )
+            .package_db(package_db)
+            .tree(tree.clone())
+            .plan()
+            .await
+            .unwrap();
+        let packages = plan
+            .packages()
+            .iter()
+            .map(|package| (package.package().to_string(), package.entry_type()))
+            .collect_vec();
+        assert_eq!(
+            packages,
+            vec![
+                ("common-dep 1.0-1".into(), tree::EntryType::DependencyOnly),
+                ("modern-dep 1.0-1".into(), tree::EntryType::Entrypoint),
+            ]
+        );
+        let source = RemotePackageSource::LuarocksRockspec(server_url.clone());
+        assert!(
+            plan.packages()
+                .iter()
+                .all(|package| package.to_string()
+                    == format!("{} (from {source})", package.package()))
+        );
+        assert!(plan.build_dependencies().is_empty());
+        assert_eq!(plan.download_size(), 0);
+        assert!(tree.lockfile().unwrap().rocks().is_empty());
+    }
+}
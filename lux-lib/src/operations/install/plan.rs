@@ -0,0 +1,126 @@
+use std::fmt::Display;
+
+use itertools::Itertools;
+
+use crate::{
+    build::BuildBehaviour,
+    operations::{resolve::PackageInstallData, RemoteRockDownload},
+    package::PackageSpec,
+    remote_package_source::RemotePackageSource,
+    tree,
+};
+
+/// A package that would be installed, as determined by dependency resolution.
+#[derive(Debug, Clone)]
+pub struct PlannedPackage {
+    package: PackageSpec,
+    source: RemotePackageSource,
+    entry_type: tree::EntryType,
+    build_behaviour: BuildBehaviour,
+    download_size: Option<u64>,
+}
+
+impl PlannedPackage {
+    pub fn package(&self) -> &PackageSpec {
+        &self.package
+    }
+
+    pub fn entry_type(&self) -> tree::EntryType {
+        self.entry_type
+    }
+
+    /// Whether the package would be rebuilt if it is already installed.
+    pub fn build_behaviour(&self) -> BuildBehaviour {
+        self.build_behaviour
+    }
+
+    /// The size of the packed rock that would be installed, in bytes.
+    /// `None` if the package would be built from its rockspec,
+    /// in which case the size of its source is only known once it has been fetched.
+    pub fn download_size(&self) -> Option<u64> {
+        self.download_size
+    }
+}
+
+impl From<&PackageInstallData> for PlannedPackage {
+    fn from(install_data: &PackageInstallData) -> Self {
+        let download_size = match &install_data.downloaded_rock {
+            RemoteRockDownload::RockspecOnly { .. } => None,
+            RemoteRockDownload::BinaryRock { packed_rock, .. } => Some(packed_rock.len() as u64),
+            RemoteRockDownload::SrcRock { src_rock, .. } => Some(src_rock.len() as u64),
+        };
+        Self {
+            package: install_data.spec.to_package(),
+            source: install_data
+                .downloaded_rock
+                .rockspec_download()
+                .source
+                .clone(),
+            entry_type: install_data.entry_type,
+            build_behaviour: install_data.build_behaviour,
+            download_size,
+        }
+    }
+}
+
+impl Display for PlannedPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (from {})", self.package, self.source)
+    }
+}
+
+/// The packages that an [`Install`](super::Install) would install,
+/// computed without building or installing anything.
+#[derive(Debug, Clone, Default)]
+pub struct InstallPlan {
+    packages: Vec<PlannedPackage>,
+    build_dependencies: Vec<PlannedPackage>,
+}
+
+impl InstallPlan {
+    pub(crate) fn new<'a>(
+        packages: impl IntoIterator<Item = &'a PackageInstallData>,
+        build_dependencies: impl IntoIterator<Item = &'a PackageInstallData>,
+    ) -> Self {
+        Self {
+            packages: packages
+                .into_iter()
+                .map(PlannedPackage::from)
+                .sorted_by(|a, b| {
+                    (a.package.name(), a.package.version())
+                        .cmp(&(b.package.name(), b.package.version()))
+                })
+                .collect_vec(),
+            build_dependencies: build_dependencies
+                .into_iter()
+                .map(PlannedPackage::from)
+                .collect_vec(),
+        }
+    }
+
+    /// The packages that would be installed into the tree, sorted by name and version.
+    pub fn packages(&self) -> &[PlannedPackage] {
+        &self.packages
+    }
+
+    /// The build dependencies that would be installed into the build tree,
+    /// in the order in which they would be installed.
+    pub fn build_dependencies(&self) -> &[PlannedPackage] {
+        &self.build_dependencies
+    }
+
+    /// Whether there is nothing to install, e.g. because all packages are already installed.
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.build_dependencies.is_empty()
+    }
+
+    /// The total size of the packed rocks that would be installed, in bytes.
+    /// This excludes the sources of packages that would be built from their rockspecs.
+    pub fn download_size(&self) -> u64 {
+        self.packages
+            .iter()
+            .chain(&self.build_dependencies)
+            .filter_map(PlannedPackage::download_size)
+            .sum()
+    }
+}
@@ -1,4 +1,5 @@
 use bon::Builder;
+use url::Url;
 
 use crate::{
     build::BuildBehaviour,
@@ -9,7 +10,7 @@ use crate::{
 };
 
 /// Specifies how to install a package
-#[derive(Debug, Builder)]
+#[derive(Debug, Clone, Builder)]
 #[builder(start_fn = new, finish_fn(name = build, vis = "pub"))]
 pub struct PackageInstallSpec {
     #[builder(start_fn)]
@@ -18,11 +19,18 @@ pub struct PackageInstallSpec {
     pub(crate) entry_type: tree::EntryType,
     #[builder(default)]
     pub(crate) build_behaviour: BuildBehaviour,
+    /// Rebuild this package if it is already installed, as with [`BuildBehaviour::Force`],
+    /// but without forcing a rebuild of its dependencies.
+    #[builder(default)]
+    pub(crate) reinstall: bool,
     #[builder(default)]
     pub(crate) pin: PinnedState,
     #[builder(default)]
     pub(crate) opt: OptState,
     pub(crate) source: Option<RockSourceSpec>,
+    /// URL of the rockspec to install the package from,
+    /// instead of searching a package database.
+    pub(crate) rockspec_url: Option<Url>,
     /// Optional constraint, carried over from a previous install,
     /// e.g. defined in a lockfile.
     pub(crate) constraint: Option<LockConstraint>,
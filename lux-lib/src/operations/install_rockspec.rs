@@ -0,0 +1,231 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bon::Builder;
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::{
+    build::{Build, BuildBehaviour, BuildError},
+    config::Config,
+    lockfile::{LocalPackage, OptState, PinnedState, RemotePackageSourceUrl},
+    lua_installation::{LuaInstallation, LuaInstallationError},
+    lua_rockspec::{BuildBackendSpec, LuaRockspecError, LuaVersionError, RemoteLuaRockspec},
+    luarocks::luarocks_installation::{LuaRocksError, LuaRocksInstallError, LuaRocksInstallation},
+    progress::{MultiProgress, Progress},
+    remote_package_source::RemotePackageSource,
+    rockspec::{LuaVersionCompatibility, Rockspec},
+    tree::{self, RockMatches, Tree, TreeError},
+};
+
+use super::{Install, InstallError, PackageInstallSpec};
+
+/// Installs a rock from a local `.rockspec` file,
+/// including its dependencies and build dependencies.
+#[derive(Builder)]
+#[builder(start_fn = new, finish_fn(name = _build, vis = ""))]
+pub struct InstallRockspec<'a> {
+    #[builder(start_fn)]
+    rockspec_path: &'a Path,
+    #[builder(start_fn)]
+    config: &'a Config,
+
+    /// The tree to install into.
+    /// Defaults to the user tree for the rockspec's Lua version.
+    tree: Option<Tree>,
+    /// Whether to pin the installed package and dependencies.
+    #[builder(default)]
+    pin: PinnedState,
+    progress: Option<Arc<Progress<MultiProgress>>>,
+}
+
+#[derive(Error, Debug)]
+pub enum InstallRockspecError {
+    #[error("{0} is not a valid rockspec")]
+    NotARockspec(PathBuf),
+    #[error("error reading {0}:\n{1}")]
+    Read(PathBuf, io::Error),
+    #[error(transparent)]
+    Rockspec(#[from] LuaRockspecError),
+    #[error(transparent)]
+    LuaVersion(#[from] LuaVersionError),
+    #[error(transparent)]
+    LuaInstallation(#[from] LuaInstallationError),
+    #[error(transparent)]
+    Tree(#[from] TreeError),
+    #[error(transparent)]
+    LuaRocks(#[from] LuaRocksError),
+    #[error(transparent)]
+    LuaRocksInstall(#[from] LuaRocksInstallError),
+    #[error("error installing dependencies:\n{0}")]
+    InstallDependencies(InstallError),
+    #[error("error installing build dependencies:\n{0}")]
+    InstallBuildDependencies(InstallError),
+    #[error("error building rockspec:\n{0}")]
+    Build(#[from] BuildError),
+}
+
+impl<State> InstallRockspecBuilder<'_, State>
+where
+    State: install_rockspec_builder::State + install_rockspec_builder::IsComplete,
+{
+    /// Install the rockspec, returning the installed package.
+    pub async fn install(self) -> Result<LocalPackage, InstallRockspecError> {
+        do_install_rockspec(self._build()).await
+    }
+}
+
+async fn do_install_rockspec(
+    args: InstallRockspec<'_>,
+) -> Result<LocalPackage, InstallRockspecError> {
+    let config = args.config;
+    let pin = args.pin;
+    let path = args.rockspec_path;
+
+    if path.extension().is_none_or(|ext| ext != "rockspec") {
+        return Err(InstallRockspecError::NotARockspec(path.to_path_buf()));
+    }
+
+    let progress_arc = args
+        .progress
+        .unwrap_or_else(|| MultiProgress::new_arc(config));
+    let progress = Arc::clone(&progress_arc);
+
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|err| InstallRockspecError::Read(path.to_path_buf(), err))?;
+    let rockspec = RemoteLuaRockspec::new(&content)?;
+    let lua_version = rockspec.lua_version_matches(config)?;
+    let tree = match args.tree {
+        Some(tree) => tree,
+        None => config.user_tree(lua_version.clone())?,
+    };
+    let lua = LuaInstallation::new(
+        &lua_version,
+        config,
+        &progress.map(|progress| progress.new_bar()),
+    )
+    .await?;
+
+    // Ensure all dependencies and build dependencies are installed first
+
+    let build_tree = tree.build_tree(config)?;
+    let build_dependencies_to_install = rockspec
+        .build_dependencies()
+        .for_target_platform(config)
+        .iter()
+        .filter(|dep| {
+            // Exclude luarocks build backends that we have implemented in lux
+            !matches!(
+                dep.name().to_string().as_str(),
+                "luarocks-build-rust-mlua" | "luarocks-build-treesitter-parser"
+            )
+        })
+        .filter(|dep| {
+            build_tree
+                .match_rocks(dep.package_req())
+                .is_ok_and(|rock_match| !rock_match.is_found())
+        })
+        .map(|dep| {
+            PackageInstallSpec::new(dep.package_req().clone(), tree::EntryType::Entrypoint)
+                .build_behaviour(BuildBehaviour::NoForce)
+                .pin(pin)
+                .opt(OptState::Required)
+                .maybe_source(dep.source().clone())
+                .maybe_rockspec_url(dep.rockspec_url().cloned())
+                .build()
+        })
+        .collect_vec();
+
+    if !build_dependencies_to_install.is_empty() {
+        Install::new(config)
+            .packages(build_dependencies_to_install)
+            .tree(build_tree.clone())
+            .progress(progress_arc.clone())
+            .install()
+            .await
+            .map_err(InstallRockspecError::InstallBuildDependencies)?;
+    }
+
+    let dependencies = rockspec.dependencies().for_target_platform(config);
+
+    // Dependencies are installed as entrypoints, so that we can look them up
+    // and demote them once the rockspec's package has been built.
+    let dependencies_to_install = dependencies
+        .iter()
+        .filter(|dep| {
+            tree.match_rocks(dep.package_req())
+                .is_ok_and(|rock_match| !rock_match.is_found())
+        })
+        .map(|dep| {
+            PackageInstallSpec::new(dep.package_req().clone(), tree::EntryType::Entrypoint)
+                .build_behaviour(BuildBehaviour::NoForce)
+                .pin(pin)
+                .opt(OptState::Required)
+                .maybe_source(dep.source().clone())
+                .maybe_rockspec_url(dep.rockspec_url().cloned())
+                .build()
+        })
+        .collect_vec();
+
+    let installed_dependencies = if dependencies_to_install.is_empty() {
+        Vec::new()
+    } else {
+        Install::new(config)
+            .packages(dependencies_to_install)
+            .tree(tree.clone())
+            .progress(progress_arc.clone())
+            .install()
+            .await
+            .map_err(InstallRockspecError::InstallDependencies)?
+    };
+
+    if let Some(BuildBackendSpec::LuaRock(_)) = &rockspec.build().current_platform().build_backend {
+        let luarocks = LuaRocksInstallation::new(config, build_tree)?;
+        let bar = progress.map(|p| p.new_bar());
+        luarocks.ensure_installed(&lua, &bar).await?;
+    }
+
+    let mut package = Build::new()
+        .rockspec(&rockspec)
+        .tree(&tree)
+        .lua(&lua)
+        .entry_type(tree::EntryType::Entrypoint)
+        .config(config)
+        .progress(&progress.map(|p| p.new_bar()))
+        .pin(pin)
+        .behaviour(BuildBehaviour::Force)
+        .source(RemotePackageSource::RockspecContent(content))
+        .build()
+        .await?;
+    // Record the rockspec file, so that the package can be reinstalled from it.
+    package.source_url = Some(RemotePackageSourceUrl::File {
+        path: std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    });
+
+    let lockfile = tree.lockfile()?;
+    let dependencies = dependencies
+        .iter()
+        .filter_map(|dep| match tree.match_rocks(dep.package_req()) {
+            Ok(RockMatches::Single(id)) => lockfile.get(&id).cloned(),
+            Ok(RockMatches::Many(ids)) => ids.iter().find_map(|id| lockfile.get(id).cloned()),
+            _ => None,
+        })
+        .collect_vec();
+    let mut lockfile = lockfile.write_guard();
+    lockfile.add_entrypoint(&package);
+    for dependency in dependencies {
+        lockfile.add_dependency(&package, &dependency);
+        if installed_dependencies
+            .iter()
+            .any(|installed| installed.id() == dependency.id())
+        {
+            lockfile.remove_entrypoint(&dependency);
+        }
+    }
+
+    Ok(package)
+}
@@ -7,6 +7,8 @@ mod exec;
 mod fetch;
 mod gen_luarc;
 pub mod install;
+mod install_rockspec;
+mod outdated;
 mod pack;
 mod pin;
 mod resolve;
@@ -25,6 +27,8 @@ pub use exec::*;
 pub use fetch::*;
 pub use gen_luarc::*;
 pub use install::*;
+pub use install_rockspec::*;
+pub use outdated::*;
 pub use pack::*;
 pub use pin::*;
 pub use run::*;
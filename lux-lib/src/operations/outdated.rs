@@ -0,0 +1,116 @@
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{
+    lockfile::{LockConstraint, PinnedState},
+    package::{PackageName, PackageReq, PackageVersion, PackageVersionReq},
+    remote_package_db::RemotePackageDB,
+    tree::{Tree, TreeError},
+};
+
+/// An installed entrypoint for which a newer version is available.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutdatedRock {
+    pub name: PackageName,
+    /// The currently installed version.
+    pub installed: PackageVersion,
+    /// The latest available version, ignoring the constraint the rock was installed with.
+    pub latest: PackageVersion,
+    /// The latest available version that satisfies the constraint the rock was installed with,
+    /// if it is newer than the installed version.
+    pub latest_compatible: Option<PackageVersion>,
+}
+
+/// Find the entrypoints in a tree that have newer versions available.
+/// Pinned rocks are skipped.
+pub fn outdated(tree: &Tree, db: &RemotePackageDB) -> Result<Vec<OutdatedRock>, TreeError> {
+    let lockfile = tree.lockfile()?;
+    Ok(lockfile
+        .rocks()
+        .values()
+        .filter(|package| {
+            package.pinned() == PinnedState::Unpinned && lockfile.is_entrypoint(&package.id())
+        })
+        .filter_map(|package| {
+            let installed = package.version();
+            let latest = db
+                .latest_version(package.name())
+                .filter(|latest| latest > installed)?;
+            let version_req = match package.constraint() {
+                LockConstraint::Unconstrained => PackageVersionReq::any(),
+                LockConstraint::Constrained(version_req) => version_req,
+            };
+            let latest_compatible = db
+                .latest_match(
+                    &PackageReq {
+                        name: package.name().clone(),
+                        version_req,
+                    },
+                    None,
+                )
+                .map(|package| package.version().clone())
+                .filter(|latest_compatible| latest_compatible > installed);
+            Some(OutdatedRock {
+                name: package.name().clone(),
+                installed: installed.clone(),
+                latest,
+                latest_compatible,
+            })
+        })
+        .sorted_by(|a, b| a.name.cmp(&b.name))
+        .collect_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use url::Url;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackage,
+        manifest::{Manifest, ManifestMetadata},
+    };
+
+    use super::*;
+
+    #[test]
+    fn outdated_entrypoints() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let mut lua_cjson = LocalPackage::mock("lua-cjson", "1.0.1-1");
+        lua_cjson.spec.constraint = Some("< 2.0.0".into());
+        let mut pinned = LocalPackage::mock("30log", "0.2-1");
+        pinned.spec.pinned = PinnedState::Pinned;
+        let dependency = LocalPackage::mock("30log", "0.3-0");
+
+        let mut lockfile = tree.lockfile().unwrap().write_guard();
+        lockfile.add_entrypoint(&lua_cjson);
+        lockfile.add_entrypoint(&pinned);
+        lockfile.add_dependency(&lua_cjson, &dependency);
+        drop(lockfile);
+
+        let test_manifest_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/manifest-5.1");
+        let content = String::from_utf8(std::fs::read(&test_manifest_path).unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&content).unwrap();
+        let package_db = Manifest::new(Url::parse("https://example.com").unwrap(), metadata).into();
+
+        assert_eq!(
+            outdated(&tree, &package_db).unwrap(),
+            vec![OutdatedRock {
+                name: "lua-cjson".into(),
+                installed: "1.0.1-1".parse().unwrap(),
+                latest: "2.1.0-1".parse().unwrap(),
+                latest_compatible: Some("1.0.4-1".parse().unwrap()),
+            }]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::build::utils;
 use crate::build::utils::c_dylib_extension;
+use crate::config::Config;
 use crate::lockfile::LocalPackage;
 use crate::luarocks;
 use crate::luarocks::rock_manifest::DirOrFileEntry;
@@ -10,8 +11,16 @@ use crate::luarocks::rock_manifest::RockManifestDoc;
 use crate::luarocks::rock_manifest::RockManifestLib;
 use crate::luarocks::rock_manifest::RockManifestLua;
 use crate::luarocks::rock_manifest::RockManifestRoot;
+use crate::operations::FetchSrc;
+use crate::operations::FetchSrcError;
+use crate::progress::Progress;
+use crate::progress::ProgressBar;
+use crate::project::Project;
+use crate::rockspec::Rockspec;
 use crate::tree::RockLayout;
 use crate::tree::Tree;
+use crate::upload::helpers::generate_remote_rockspec;
+use crate::upload::helpers::GenerateRockspecError;
 use bon::{builder, Builder};
 use clean_path::Clean;
 use itertools::Itertools;
@@ -63,6 +72,63 @@ pub enum PackError {
     MissingRockspec,
 }
 
+/// A source rock packer, bundling a project's generated rockspec
+/// and its source into a `.src.rock` archive.
+#[derive(Builder)]
+#[builder(start_fn = new, finish_fn(name = _build, vis = ""))]
+pub struct PackSrcRock<'a> {
+    #[builder(start_fn)]
+    dest_dir: PathBuf,
+    #[builder(start_fn)]
+    project: &'a Project,
+    #[builder(start_fn)]
+    config: &'a Config,
+    #[builder(start_fn)]
+    progress: &'a Progress<ProgressBar>,
+}
+
+impl<State> PackSrcRockBuilder<'_, State>
+where
+    State: pack_src_rock_builder::State + pack_src_rock_builder::IsComplete,
+{
+    /// Pack the project into a `<name>-<version>.src.rock`,
+    /// returning the path to the archive.
+    pub async fn pack(self) -> Result<PathBuf, PackSrcRockError> {
+        do_pack_src_rock(self._build()).await
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("failed to pack source rock: {0}")]
+pub enum PackSrcRockError {
+    GenerateRockspec(#[from] GenerateRockspecError),
+    FetchSrc(#[from] FetchSrcError),
+    Pack(#[from] PackError),
+    Io(#[from] io::Error),
+    Zip(#[from] zip::result::ZipError),
+}
+
+async fn do_pack_src_rock(args: PackSrcRock<'_>) -> Result<PathBuf, PackSrcRockError> {
+    let (rockspec, rockspec_content) = generate_remote_rockspec(args.project, None)?;
+    let package = rockspec.package();
+    let version = rockspec.version();
+    let temp_dir = TempDir::new(&format!("lux-pack-{package}"))?;
+    FetchSrc::new(temp_dir.path(), &rockspec, args.config, args.progress)
+        .fetch()
+        .await?;
+    // `unpack_rockspec` expects a <package>-<version>.rockspec in the archive root
+    let rockspec_entry = temp_dir
+        .path()
+        .join(format!("{package}-{version}.rockspec"));
+    tokio::fs::write(&rockspec_entry, rockspec_content).await?;
+    let output_path = args.dest_dir.join(format!("{package}-{version}.src.rock"));
+    let file = File::create(&output_path)?;
+    let mut zip = ZipWriter::new(file);
+    add_rock_entries(&mut zip, &temp_dir.path().to_path_buf(), "".into())?;
+    zip.finish()?;
+    Ok(output_path)
+}
+
 async fn do_pack(args: Pack) -> Result<PathBuf, PackError> {
     let package = args.package;
     let tree = args.tree;
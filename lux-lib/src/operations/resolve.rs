@@ -1,4 +1,8 @@
-use std::{fmt::Display, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
 
 use async_recursion::async_recursion;
 use bon::Builder;
@@ -6,21 +10,29 @@ use futures::StreamExt;
 use itertools::Itertools;
 use thiserror::Error;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     build::BuildBehaviour,
-    config::Config,
+    config::{Config, LuaVersion},
     lockfile::{
         LocalPackageId, LocalPackageSpec, Lockfile, LockfilePermissions, OptState, PinnedState,
     },
-    package::PackageName,
-    progress::{MultiProgress, Progress},
+    lua_rockspec::PlatformIdentifier,
+    package::{
+        PackageName, PackageReq, PackageSpec, PackageVersion, PackageVersionReq,
+        RemotePackageTypeFilterSpec,
+    },
+    progress::{MultiProgress, Progress, ProgressBar},
     remote_package_db::RemotePackageDB,
     rockspec::Rockspec,
     tree,
 };
 
-use super::{Download, PackageInstallSpec, RemoteRockDownload, SearchAndDownloadError};
+use super::{
+    download_remote_package, download_rockspec_at_url, Download, PackageInstallSpec,
+    RemoteRockDownload, SearchAndDownloadError,
+};
 
 #[derive(Error, Debug)]
 pub enum ResolveDependenciesError {
@@ -39,6 +51,69 @@ impl Display for DependencyCycle {
     }
 }
 
+/// The combined version requirements of each package during unified resolution,
+/// in which each package is resolved to a single version.
+#[derive(Debug, Default)]
+pub(crate) struct UnifiedVersionReqs(Mutex<UnifiedVersionReqsState>);
+
+#[derive(Debug, Default)]
+struct UnifiedVersionReqsState {
+    version_reqs: HashMap<PackageName, PackageVersionReq>,
+    resolved: HashMap<PackageName, HashSet<PackageVersion>>,
+}
+
+impl UnifiedVersionReqs {
+    /// Register a package requirement, returning the requirement combined with
+    /// all requirements registered for the same package so far.
+    fn register(&self, req: &PackageReq) -> Result<PackageReq, SearchAndDownloadError> {
+        let version_reqs = &mut self.0.lock().unwrap().version_reqs;
+        let version_req = match version_reqs.get(req.name()) {
+            Some(registered) => registered.intersect(req.version_req()).ok_or_else(|| {
+                SearchAndDownloadError::VersionConflict {
+                    package: req.name().clone(),
+                    req_a: Box::new(registered.clone()),
+                    req_b: Box::new(req.version_req().clone()),
+                }
+            })?,
+            None => req.version_req().clone(),
+        };
+        version_reqs.insert(req.name().clone(), version_req.clone());
+        Ok(PackageReq {
+            name: req.name().clone(),
+            version_req,
+        })
+    }
+
+    /// Record the version a package was resolved to.
+    fn resolved(&self, name: &PackageName, version: &PackageVersion) {
+        self.0
+            .lock()
+            .unwrap()
+            .resolved
+            .entry(name.clone())
+            .or_default()
+            .insert(version.clone());
+    }
+
+    /// Whether a package was resolved to a version that doesn't match
+    /// a requirement that was registered later, or to more than one version.
+    /// In that case, the packages have to be resolved again with the combined requirements.
+    fn requires_re_resolve(&self) -> bool {
+        let state = self.0.lock().unwrap();
+        state.resolved.iter().any(|(name, versions)| {
+            versions.len() > 1
+                || state.version_reqs.get(name).is_some_and(|version_req| {
+                    versions.iter().any(|version| !version_req.matches(version))
+                })
+        })
+    }
+
+    /// Forget the resolved versions, keeping the combined requirements.
+    fn clear_resolved(&self) {
+        self.0.lock().unwrap().resolved.clear();
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct PackageInstallData {
     pub build_behaviour: BuildBehaviour,
@@ -62,8 +137,18 @@ where
     parent_packages: Option<Arc<Vec<PackageName>>>,
     lockfile: Arc<Lockfile<P>>,
     build_lockfile: Arc<Lockfile<P>>,
+    /// The Lua version of the trees to install into.
+    /// Installed packages with native libraries built against a different version are reinstalled.
+    lua_version: &'a LuaVersion,
     config: &'a Config,
     progress: Arc<Progress<MultiProgress>>,
+    /// If set, each package is resolved to a single version,
+    /// and resolution fails if dependents require conflicting versions.
+    /// Otherwise, multiple versions of a package may be installed side by side.
+    unified_version_reqs: Option<Arc<UnifiedVersionReqs>>,
+    /// Cancels the resolution, which stops spawning new downloads.
+    #[builder(default)]
+    cancellation_token: CancellationToken,
 }
 
 impl<P, State> ResolveBuilder<'_, P, State>
@@ -75,7 +160,56 @@ where
         self,
     ) -> Result<Vec<LocalPackageId>, ResolveDependenciesError> {
         let args = self._build();
-        do_get_all_dependencies(args).await
+        match &args.unified_version_reqs {
+            Some(unified_version_reqs) if args.parent_packages.is_none() => {
+                let unified_version_reqs = unified_version_reqs.clone();
+                do_get_all_unified_dependencies(args, unified_version_reqs).await
+            }
+            _ => do_get_all_dependencies(args).await,
+        }
+    }
+}
+
+/// Resolve the packages until each package is resolved to a single version
+/// that matches all of its requirements, so that the result doesn't depend on
+/// the order in which the requirements are registered.
+async fn do_get_all_unified_dependencies<P>(
+    args: Resolve<'_, P>,
+    unified_version_reqs: Arc<UnifiedVersionReqs>,
+) -> Result<Vec<LocalPackageId>, ResolveDependenciesError>
+where
+    P: LockfilePermissions + Send + Sync + 'static,
+{
+    loop {
+        let (dependencies_tx, mut dependencies_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (build_dependencies_tx, mut build_dependencies_rx) =
+            tokio::sync::mpsc::unbounded_channel();
+        let ids = do_get_all_dependencies(Resolve {
+            dependencies_tx,
+            build_dependencies_tx,
+            packages: args.packages.clone(),
+            package_db: args.package_db.clone(),
+            parent_packages: None,
+            lockfile: args.lockfile.clone(),
+            build_lockfile: args.build_lockfile.clone(),
+            lua_version: args.lua_version,
+            config: args.config,
+            progress: args.progress.clone(),
+            unified_version_reqs: Some(unified_version_reqs.clone()),
+            cancellation_token: args.cancellation_token.clone(),
+        })
+        .await?;
+        if unified_version_reqs.requires_re_resolve() {
+            unified_version_reqs.clear_resolved();
+            continue;
+        }
+        while let Ok(dependency) = dependencies_rx.try_recv() {
+            args.dependencies_tx.send(dependency).unwrap();
+        }
+        while let Ok(build_dependency) = build_dependencies_rx.try_recv() {
+            args.build_dependencies_tx.send(build_dependency).unwrap();
+        }
+        return Ok(ids);
     }
 }
 
@@ -94,8 +228,11 @@ where
     let package_db = args.package_db;
     let lockfile = args.lockfile;
     let build_lockfile = args.build_lockfile;
+    let lua_version = args.lua_version;
     let config = args.config;
     let progress = args.progress;
+    let cancellation_token = args.cancellation_token;
+    let unified_version_reqs = args.unified_version_reqs;
     futures::stream::iter(
         packages
             .into_iter()
@@ -104,24 +241,32 @@ where
                 |PackageInstallSpec {
                      package,
                      build_behaviour,
+                     reinstall,
                      ..
                  }| {
                     *build_behaviour == BuildBehaviour::Force
-                        || lockfile.has_rock(package, None).is_none()
+                        || *reinstall
+                        || lockfile
+                            .has_rock(package, None)
+                            .is_none_or(|installed| installed.requires_rebuild(lua_version))
                 },
             )
             .map(
-                // NOTE: we propagate build_behaviour, pin and opt to all dependencies
+                // NOTE: we propagate build_behaviour, pin and opt to all dependencies,
+                // but reinstall only applies to the package itself
                 |PackageInstallSpec {
                      package,
                      build_behaviour,
+                     reinstall,
                      pin,
                      opt,
                      entry_type,
                      constraint,
                      source,
+                     rockspec_url,
                  }| {
                     let config = config.clone();
+                    let lua_version = lua_version.clone();
                     let dependencies_tx = dependencies_tx.clone();
                     let build_dependencies_tx = build_dependencies_tx.clone();
                     let parent_packages = Arc::clone(&parent_packages);
@@ -130,22 +275,73 @@ where
                     let build_dep_progress = Arc::clone(&progress);
                     let lockfile = Arc::clone(&lockfile);
                     let build_lockfile = Arc::clone(&build_lockfile);
+                    let cancellation_token = cancellation_token.clone();
+                    let unified_version_reqs = unified_version_reqs.clone();
+
+                    #[cfg(feature = "tracing")]
+                    let span = tracing::info_span!("resolve", package = %package);
+
+                    let resolve_package = async move {
+                        if cancellation_token.is_cancelled() {
+                            return Err(SearchAndDownloadError::Cancelled.into());
+                        }
+
+                        let max_depth = config.max_resolution_depth();
+                        if parent_packages.len() >= max_depth {
+                            return Err(SearchAndDownloadError::ResolutionTooDeep {
+                                package: package.name().clone(),
+                                max_depth,
+                            }
+                            .into());
+                        }
+
+                        let download_req = match &unified_version_reqs {
+                            Some(version_reqs) => version_reqs.register(&package)?,
+                            None => package.clone(),
+                        };
 
-                    tokio::spawn(async move {
                         let bar = progress.map(|p| p.new_bar());
+                        let _clear_on_cancel = ClearOnCancel {
+                            bar: &bar,
+                            cancellation_token: cancellation_token.clone(),
+                        };
 
                         let downloaded_rock = if let Some(source) = source {
                             RemoteRockDownload::from_package_req_and_source_spec(
                                 package.clone(),
                                 source,
                             )?
+                        } else if let Some(rockspec_url) = rockspec_url {
+                            let downloaded_rock = RemoteRockDownload::RockspecOnly {
+                                rockspec_download: download_rockspec_at_url(
+                                    &rockspec_url,
+                                    &config,
+                                    &bar,
+                                )
+                                .await?,
+                            };
+                            check_supported_platform(&downloaded_rock, &config)?;
+                            downloaded_rock
                         } else {
-                            Download::new(&package, &config, &bar)
-                                .package_db(&package_db)
-                                .download_remote_rock()
-                                .await?
+                            let download = download_supported_remote_rock(
+                                &download_req,
+                                &package_db,
+                                &config,
+                                &bar,
+                            );
+                            tokio::select! {
+                                downloaded_rock = download => downloaded_rock?,
+                                _ = cancellation_token.cancelled() => {
+                                    return Err(SearchAndDownloadError::Cancelled.into());
+                                }
+                            }
                         };
 
+                        if let Some(version_reqs) = &unified_version_reqs {
+                            let rockspec = downloaded_rock.rockspec();
+                            version_reqs.resolved(rockspec.package(), rockspec.version());
+                        }
+
                         let constraint = constraint.unwrap_or(package.version_req().clone().into());
 
                         let rockspec = downloaded_rock.rockspec();
@@ -166,7 +362,7 @@ where
                         if !matches!(downloaded_rock, RemoteRockDownload::BinaryRock { .. }) {
                             let build_dependencies = rockspec
                                 .build_dependencies()
-                                .current_platform()
+                                .for_target_platform(&config)
                                 .iter()
                                 .filter(|dep| {
                                     // Exclude luarocks build backends that we have implemented in lux
@@ -185,6 +381,7 @@ where
                                         .pin(pin)
                                         .opt(opt)
                                         .maybe_source(dep.source().clone())
+                                        .maybe_rockspec_url(dep.rockspec_url().cloned())
                                         .build()
                                 })
                                 .collect_vec();
@@ -205,15 +402,17 @@ where
                                 .package_db(package_db.clone())
                                 .lockfile(build_lockfile.clone())
                                 .build_lockfile(build_lockfile.clone())
+                                .lua_version(&lua_version)
                                 .config(&config)
                                 .progress(build_dep_progress)
+                                .cancellation_token(cancellation_token.clone())
                                 .get_all_dependencies()
                                 .await?;
                         }
 
                         let dependencies = rockspec
                             .dependencies()
-                            .current_platform()
+                            .for_target_platform(&config)
                             .iter()
                             .map(|dep| {
                                 // If we're forcing a rebuild, retain the `EntryType`
@@ -234,6 +433,7 @@ where
                                     .pin(pin)
                                     .opt(opt)
                                     .maybe_source(dep.source().clone())
+                                    .maybe_rockspec_url(dep.rockspec_url().cloned())
                                     .build()
                             })
                             .collect_vec();
@@ -252,8 +452,11 @@ where
                             .package_db(package_db)
                             .lockfile(lockfile)
                             .build_lockfile(build_lockfile)
+                            .lua_version(&lua_version)
                             .config(&config)
                             .progress(progress)
+                            .maybe_unified_version_reqs(unified_version_reqs)
+                            .cancellation_token(cancellation_token)
                             .get_all_dependencies()
                             .await?;
 
@@ -269,7 +472,11 @@ where
                         );
 
                         let install_spec = PackageInstallData {
-                            build_behaviour,
+                            build_behaviour: if reinstall {
+                                BuildBehaviour::Force
+                            } else {
+                                build_behaviour
+                            },
                             pin,
                             opt,
                             spec: local_spec.clone(),
@@ -280,7 +487,12 @@ where
                         dependencies_tx.send(install_spec).unwrap();
 
                         Ok::<_, ResolveDependenciesError>(local_spec.id())
-                    })
+                    };
+
+                    #[cfg(feature = "tracing")]
+                    let resolve_package = tracing::Instrument::instrument(resolve_package, span);
+
+                    tokio::spawn(resolve_package)
                 },
             ),
     )
@@ -291,3 +503,546 @@ where
     .flatten()
     .try_collect()
 }
+
+/// Clears a package's progress bar when it goes out of scope after the resolution
+/// has been cancelled, so that cancelled downloads don't leave stale bars behind.
+struct ClearOnCancel<'a> {
+    bar: &'a Progress<ProgressBar>,
+    cancellation_token: CancellationToken,
+}
+
+impl Drop for ClearOnCancel<'_> {
+    fn drop(&mut self) {
+        if self.cancellation_token.is_cancelled() {
+            self.bar.map(|b| b.finish_and_clear());
+        }
+    }
+}
+
+/// Download the latest version of a package that matches the requirement
+/// and supports the target platform.
+/// If the latest match doesn't support the target platform, the rockspecs of
+/// older matching versions are checked, and the latest supported one is downloaded.
+async fn download_supported_remote_rock(
+    package_req: &PackageReq,
+    package_db: &RemotePackageDB,
+    config: &Config,
+    progress: &Progress<ProgressBar>,
+) -> Result<RemoteRockDownload, SearchAndDownloadError> {
+    let downloaded_rock = Download::new(package_req, config, progress)
+        .package_db(package_db)
+        .download_remote_rock()
+        .await?;
+    let unsupported_platform_err = match check_supported_platform(&downloaded_rock, config) {
+        Ok(()) => return Ok(downloaded_rock),
+        Err(err) => err,
+    };
+    let latest_version = downloaded_rock.rockspec().version().clone();
+    let older_versions = package_db
+        .versions_of(
+            package_req.name(),
+            Some(RemotePackageTypeFilterSpec::rockspec_only()),
+        )
+        .into_iter()
+        .filter(|version| version < &latest_version && package_req.version_req().matches(version));
+    for version in older_versions {
+        let package_req = PackageReq {
+            name: package_req.name().clone(),
+            version_req: PackageVersionReq::Exact(version),
+        };
+        let remote_package = package_db.find(
+            &package_req,
+            Some(RemotePackageTypeFilterSpec::rockspec_only()),
+            progress,
+        )?;
+        progress.map(|p| {
+            p.set_message(format!(
+                "📥 Downloading rockspec for {}",
+                remote_package.package
+            ))
+        });
+        let rockspec_download =
+            download_remote_package(remote_package, config, progress, None).await?;
+        if check_supported_platform(&rockspec_download, config).is_ok() {
+            return Download::new(&package_req, config, progress)
+                .package_db(package_db)
+                .download_remote_rock()
+                .await;
+        }
+    }
+    Err(unsupported_platform_err)
+}
+
+/// Check the `supported_platforms` of a downloaded rock's rockspec
+/// against the configured target platform.
+fn check_supported_platform(
+    downloaded_rock: &RemoteRockDownload,
+    config: &Config,
+) -> Result<(), SearchAndDownloadError> {
+    let rockspec = downloaded_rock.rockspec();
+    let platform = PlatformIdentifier::target_platform(config);
+    if rockspec.supported_platforms().is_supported(&platform) {
+        Ok(())
+    } else {
+        Err(SearchAndDownloadError::UnsupportedPlatform {
+            package: PackageSpec::new(rockspec.package().clone(), rockspec.version().clone()),
+            platform,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use httptest::{matchers::request, responders::status_code, Expectation, Server};
+    use url::Url;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackage,
+        lua_rockspec::PlatformIdentifier,
+        manifest::{Manifest, ManifestMetadata},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancelled_resolve() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let test_manifest_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/manifest-5.1");
+        let content = String::from_utf8(std::fs::read(&test_manifest_path).unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&content).unwrap();
+        let package_db: RemotePackageDB =
+            Manifest::new(Url::parse("https://example.com").unwrap(), metadata).into();
+
+        let (dependencies_tx, mut dependencies_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (build_dependencies_tx, _) = tokio::sync::mpsc::unbounded_channel();
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+
+        let result = Resolve::new()
+            .dependencies_tx(dependencies_tx)
+            .build_dependencies_tx(build_dependencies_tx)
+            .packages(vec![PackageInstallSpec::new(
+                "lua-cjson".parse().unwrap(),
+                tree::EntryType::Entrypoint,
+            )
+            .build()])
+            .package_db(Arc::new(package_db))
+            .lockfile(Arc::new(tree.lockfile().unwrap()))
+            .build_lockfile(Arc::new(
+                tree.build_tree(&config).unwrap().lockfile().unwrap(),
+            ))
+            .lua_version(tree.version())
+            .config(&config)
+            .progress(MultiProgress::new_arc(&config))
+            .cancellation_token(cancellation_token)
+            .get_all_dependencies()
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(ResolveDependenciesError::SearchAndDownload(
+                SearchAndDownloadError::Cancelled
+            ))
+        ));
+        assert!(dependencies_rx.try_recv().is_err());
+    }
+
+    async fn resolve_version_conflict_fixture(
+        packages: &[&str],
+        unified_version_reqs: Option<Arc<UnifiedVersionReqs>>,
+        max_resolution_depth: Option<usize>,
+    ) -> (
+        Result<Vec<LocalPackageId>, ResolveDependenciesError>,
+        Vec<PackageInstallData>,
+    ) {
+        let fixture_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/version-conflict");
+        let server = Server::run();
+        for rockspec in [
+            "any-dep-1.0-1.rockspec",
+            "legacy-dep-1.0-1.rockspec",
+            "modern-dep-1.0-1.rockspec",
+            "common-dep-0.9-1.rockspec",
+            "common-dep-1.0-1.rockspec",
+        ] {
+            let content = std::fs::read_to_string(fixture_dir.join(rockspec)).unwrap();
+            server.expect(
+                Expectation::matching(request::path(format!("/{rockspec}")))
+                    .times(..)
+                    .respond_with(status_code(200).body(content)),
+            );
+        }
+        let manifest = std::fs::read_to_string(fixture_dir.join("manifest")).unwrap();
+        let package_db: RemotePackageDB = Manifest::new(
+            Url::parse(&server.url_str("/")).unwrap(),
+            ManifestMetadata::new(&manifest).unwrap(),
+        )
+        .into();
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .max_resolution_depth(max_resolution_depth)
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let (dependencies_tx, mut dependencies_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (build_dependencies_tx, _) = tokio::sync::mpsc::unbounded_channel();
+
+        let result = Resolve::new()
+            .dependencies_tx(dependencies_tx)
+            .build_dependencies_tx(build_dependencies_tx)
+            .packages(
+                packages
+                    .iter()
+                    .map(|package| {
+                        PackageInstallSpec::new(
+                            package.parse().unwrap(),
+                            tree::EntryType::Entrypoint,
+                        )
+                        .build()
+                    })
+                    .collect_vec(),
+            )
+            .package_db(Arc::new(package_db))
+            .lockfile(Arc::new(tree.lockfile().unwrap()))
+            .build_lockfile(Arc::new(
+                tree.build_tree(&config).unwrap().lockfile().unwrap(),
+            ))
+            .lua_version(tree.version())
+            .config(&config)
+            .progress(MultiProgress::new_arc(&config))
+            .maybe_unified_version_reqs(unified_version_reqs)
+            .get_all_dependencies()
+            .await;
+
+        let mut resolved = Vec::new();
+        while let Ok(package) = dependencies_rx.try_recv() {
+            resolved.push(package);
+        }
+        (result, resolved)
+    }
+
+    #[tokio::test]
+    async fn resolve_multiple_versions_side_by_side() {
+        let (result, resolved) =
+            resolve_version_conflict_fixture(&["legacy-dep", "modern-dep"], None, None).await;
+        assert_eq!(result.unwrap().len(), 2);
+        let common_dep_versions = resolved
+            .iter()
+            .filter(|package| package.spec.name().to_string() == "common-dep")
+            .map(|package| package.spec.version().to_string())
+            .sorted()
+            .collect_vec();
+        assert_eq!(common_dep_versions, vec!["0.9-1", "1.0-1"]);
+    }
+
+    #[tokio::test]
+    async fn unified_resolve_version_conflict() {
+        let (result, _) = resolve_version_conflict_fixture(
+            &["legacy-dep", "modern-dep"],
+            Some(Arc::default()),
+            None,
+        )
+        .await;
+        match result {
+            Err(ResolveDependenciesError::SearchAndDownload(
+                SearchAndDownloadError::VersionConflict {
+                    package,
+                    req_a,
+                    req_b,
+                },
+            )) => {
+                assert_eq!(package.to_string(), "common-dep");
+                let reqs = [req_a.to_string(), req_b.to_string()];
+                assert!(reqs.contains(&"<1.0".to_string()), "{reqs:?}");
+                assert!(reqs.contains(&">=1.0".to_string()), "{reqs:?}");
+            }
+            result => panic!("expected a version conflict, but got {result:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn unified_resolve_independent_of_order() {
+        for packages in [["any-dep", "legacy-dep"], ["legacy-dep", "any-dep"]] {
+            let (result, resolved) =
+                resolve_version_conflict_fixture(&packages, Some(Arc::default()), None).await;
+            assert!(result.is_ok(), "{result:?}");
+            let common_dep_versions = resolved
+                .iter()
+                .filter(|package| package.spec.name().to_string() == "common-dep")
+                .map(|package| package.spec.version().to_string())
+                .unique()
+                .collect_vec();
+            assert_eq!(common_dep_versions, vec!["0.9-1"], "{packages:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_too_deep() {
+        let (result, resolved) =
+            resolve_version_conflict_fixture(&["legacy-dep", "modern-dep"], None, Some(1)).await;
+        match result {
+            Err(ResolveDependenciesError::SearchAndDownload(
+                SearchAndDownloadError::ResolutionTooDeep { package, max_depth },
+            )) => {
+                assert_eq!(package.to_string(), "common-dep");
+                assert_eq!(max_depth, 1);
+            }
+            result => panic!("expected the resolution to be too deep, but got {result:?}"),
+        }
+        assert!(resolved
+            .iter()
+            .all(|package| package.spec.name().to_string() != "common-dep"));
+    }
+
+    #[tokio::test]
+    async fn reinstall_without_dependencies() {
+        let fixture_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/version-conflict");
+        let server = Server::run();
+        for rockspec in ["modern-dep-1.0-1.rockspec", "common-dep-1.0-1.rockspec"] {
+            let content = std::fs::read_to_string(fixture_dir.join(rockspec)).unwrap();
+            server.expect(
+                Expectation::matching(request::path(format!("/{rockspec}")))
+                    .times(..)
+                    .respond_with(status_code(200).body(content)),
+            );
+        }
+        let server_url = Url::parse(&server.url_str("/")).unwrap();
+        let manifest = std::fs::read_to_string(fixture_dir.join("manifest")).unwrap();
+        let package_db: Arc<RemotePackageDB> = Arc::new(
+            Manifest::new(
+                server_url.clone(),
+                ManifestMetadata::new(&manifest).unwrap(),
+            )
+            .into(),
+        );
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let installed = |name: &str| LocalPackage::mock(name, "1.0-1");
+        let (modern_dep, common_dep) = (installed("modern-dep"), installed("common-dep"));
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&modern_dep);
+                lockfile.add_dependency(&modern_dep, &common_dep);
+                Ok::<_, std::io::Error>(())
+            })
+            .unwrap();
+
+        let resolve = |reinstall: bool| {
+            let (dependencies_tx, mut dependencies_rx) = tokio::sync::mpsc::unbounded_channel();
+            let (build_dependencies_tx, _) = tokio::sync::mpsc::unbounded_channel();
+            let package_db = package_db.clone();
+            let config = &config;
+            let tree = &tree;
+            async move {
+                Resolve::new()
+                    .dependencies_tx(dependencies_tx)
+                    .build_dependencies_tx(build_dependencies_tx)
+                    .packages(vec![PackageInstallSpec::new(
+                        "modern-dep".parse().unwrap(),
+                        tree::EntryType::Entrypoint,
+                    )
+                    .reinstall(reinstall)
+                    .build()])
+                    .package_db(package_db)
+                    .lockfile(Arc::new(tree.lockfile().unwrap()))
+                    .build_lockfile(Arc::new(
+                        tree.build_tree(config).unwrap().lockfile().unwrap(),
+                    ))
+                    .lua_version(tree.version())
+                    .config(config)
+                    .progress(MultiProgress::new_arc(config))
+                    .get_all_dependencies()
+                    .await
+                    .unwrap();
+                let mut resolved = Vec::new();
+                while let Ok(package) = dependencies_rx.try_recv() {
+                    resolved.push(package);
+                }
+                resolved
+            }
+        };
+
+        assert!(resolve(false).await.is_empty());
+
+        let resolved = resolve(true).await;
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].spec.name().to_string(), "modern-dep");
+        assert_eq!(resolved[0].build_behaviour, BuildBehaviour::Force);
+    }
+
+    #[test]
+    fn unified_version_reqs_intersect() {
+        let version_reqs = UnifiedVersionReqs::default();
+        let req = version_reqs
+            .register(&"common-dep>=1.0".parse().unwrap())
+            .unwrap();
+        assert_eq!(req.version_req().to_string(), ">=1.0");
+        let req = version_reqs
+            .register(&"common-dep<2.0".parse().unwrap())
+            .unwrap();
+        assert!(req.version_req().matches(&"1.5-1".parse().unwrap()));
+        assert!(!req.version_req().matches(&"2.0-1".parse().unwrap()));
+        assert!(version_reqs
+            .register(&"common-dep<1.0".parse().unwrap())
+            .is_err());
+        assert!(version_reqs
+            .register(&"other-dep<1.0".parse().unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn unified_version_reqs_re_resolve() {
+        for reqs in [
+            ["common-dep>=1.0", "common-dep<2.0"],
+            ["common-dep<2.0", "common-dep>=1.0"],
+        ] {
+            let version_reqs = UnifiedVersionReqs::default();
+            let name: PackageName = "common-dep".into();
+            let req = version_reqs.register(&reqs[0].parse().unwrap()).unwrap();
+            let version = if req.version_req().matches(&"2.0-1".parse().unwrap()) {
+                "2.0-1"
+            } else {
+                "1.5-1"
+            };
+            version_reqs.resolved(&name, &version.parse().unwrap());
+            let req = version_reqs.register(&reqs[1].parse().unwrap()).unwrap();
+            assert!(req.version_req().matches(&"1.5-1".parse().unwrap()));
+            assert!(!req.version_req().matches(&"2.0-1".parse().unwrap()));
+            version_reqs.resolved(&name, &"1.5-1".parse().unwrap());
+            // The version resolved for the first requirement has to be re-resolved,
+            // unless it already matches the second requirement.
+            assert_eq!(
+                version_reqs.requires_re_resolve(),
+                version == "2.0-1",
+                "{reqs:?}"
+            );
+            version_reqs.clear_resolved();
+            let req = version_reqs.register(&reqs[0].parse().unwrap()).unwrap();
+            assert!(!req.version_req().matches(&"2.0-1".parse().unwrap()));
+            version_reqs.resolved(&name, &"1.5-1".parse().unwrap());
+            assert!(!version_reqs.requires_re_resolve());
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_skips_unsupported_platform() {
+        let server = Server::run();
+        for (version, supported_platforms) in [("0.9-1", "{}"), ("1.0-1", r#"{"!linux"}"#)] {
+            let content = format!(
+                r#"
+                package = "common-dep"
+                version = "{version}"
+                supported_platforms = {supported_platforms}
+                source = {{
+                   url = "https://example.com/common-dep-{version}.tar.gz",
+                }}
+                "#
+            );
+            server.expect(
+                Expectation::matching(request::path(format!("/common-dep-{version}.rockspec")))
+                    .times(..)
+                    .respond_with(status_code(200).body(content)),
+            );
+        }
+        let fixture_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/version-conflict");
+        let manifest = std::fs::read_to_string(fixture_dir.join("manifest")).unwrap();
+        let package_db: Arc<RemotePackageDB> = Arc::new(
+            Manifest::new(
+                Url::parse(&server.url_str("/")).unwrap(),
+                ManifestMetadata::new(&manifest).unwrap(),
+            )
+            .into(),
+        );
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .target_platform(Some("x86_64-unknown-linux-gnu".parse().unwrap()))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let resolve = |package_req: &str| {
+            let (dependencies_tx, mut dependencies_rx) = tokio::sync::mpsc::unbounded_channel();
+            let (build_dependencies_tx, _) = tokio::sync::mpsc::unbounded_channel();
+            let package_db = package_db.clone();
+            let package_req = package_req.parse().unwrap();
+            let config = &config;
+            let tree = &tree;
+            async move {
+                let result = Resolve::new()
+                    .dependencies_tx(dependencies_tx)
+                    .build_dependencies_tx(build_dependencies_tx)
+                    .packages(vec![PackageInstallSpec::new(
+                        package_req,
+                        tree::EntryType::Entrypoint,
+                    )
+                    .build()])
+                    .package_db(package_db)
+                    .lockfile(Arc::new(tree.lockfile().unwrap()))
+                    .build_lockfile(Arc::new(
+                        tree.build_tree(config).unwrap().lockfile().unwrap(),
+                    ))
+                    .lua_version(tree.version())
+                    .config(config)
+                    .progress(MultiProgress::new_arc(config))
+                    .get_all_dependencies()
+                    .await;
+                let mut resolved = Vec::new();
+                while let Ok(package) = dependencies_rx.try_recv() {
+                    resolved.push(package.spec.version().to_string());
+                }
+                (result, resolved)
+            }
+        };
+
+        let (result, resolved) = resolve("common-dep").await;
+        assert!(result.is_ok(), "{result:?}");
+        assert_eq!(resolved, vec!["0.9-1"]);
+
+        let (result, resolved) = resolve("common-dep >= 1.0").await;
+        match result {
+            Err(ResolveDependenciesError::SearchAndDownload(
+                SearchAndDownloadError::UnsupportedPlatform { package, platform },
+            )) => {
+                assert_eq!(package.to_string(), "common-dep 1.0-1");
+                assert_eq!(platform, PlatformIdentifier::Linux);
+            }
+            result => panic!("expected an unsupported platform error, but got {result:?}"),
+        }
+        assert!(resolved.is_empty());
+    }
+}
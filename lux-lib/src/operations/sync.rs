@@ -6,7 +6,7 @@ use crate::{
     lockfile::{FlushLockfileError, LocalPackage, LocalPackageLockType, LockfileIntegrityError},
     luarocks::luarocks_installation::LUAROCKS_VERSION,
     operations::{self, GenLuaRcError},
-    package::{PackageName, PackageReq},
+    package::{DependencySet, PackageName, PackageReq},
     progress::{MultiProgress, Progress},
     project::{
         project_toml::LocalProjectTomlValidationError, Project, ProjectError, ProjectTreeError,
@@ -57,18 +57,18 @@ where
 
     pub async fn sync_test_dependencies(mut self) -> Result<SyncReport, SyncError> {
         let toml = self.project.toml().into_local()?;
+        let declared: DependencySet = toml
+            .test_dependencies()
+            .for_target_platform(self.config)
+            .iter()
+            .map(|dep| dep.package_req())
+            .collect();
         for test_dep in toml
             .test()
             .current_platform()
             .test_dependencies(self.project)
             .iter()
-            .filter(|test_dep| {
-                !toml
-                    .test_dependencies()
-                    .current_platform()
-                    .iter()
-                    .any(|dep| dep.name() == test_dep.name())
-            })
+            .filter(|test_dep| !declared.contains_name(test_dep.name()))
             .cloned()
         {
             self.extra_packages.push(test_dep);
@@ -143,7 +143,7 @@ async fn do_sync(
         SyncError::FailedToCreateDirectory(tree.root().to_string_lossy().to_string(), err)
     })?;
 
-    let mut project_lockfile = args.project.lockfile()?.write_guard();
+    let mut project_lockfile = args.project.lockfile_for(args.config)?.write_guard();
     let dest_lockfile = tree.lockfile()?;
 
     let progress = args.progress.unwrap_or(MultiProgress::new_arc(args.config));
@@ -154,21 +154,21 @@ async fn do_sync(
             .toml()
             .into_local()?
             .dependencies()
-            .current_platform()
+            .for_target_platform(args.config)
             .clone(),
         LocalPackageLockType::Build => args
             .project
             .toml()
             .into_local()?
             .build_dependencies()
-            .current_platform()
+            .for_target_platform(args.config)
             .clone(),
         LocalPackageLockType::Test => args
             .project
             .toml()
             .into_local()?
             .test_dependencies()
-            .current_platform()
+            .for_target_platform(args.config)
             .clone(),
     }
     .into_iter()
@@ -189,7 +189,10 @@ async fn do_sync(
         removed: Vec::new(),
     };
     for (id, local_package) in project_lockfile.rocks(lock_type) {
-        if dest_lockfile.get(id).is_none() {
+        if dest_lockfile
+            .get(id)
+            .is_none_or(|installed| installed.requires_rebuild(tree.version()))
+        {
             let entry_type = if project_lockfile.is_entrypoint(&local_package.id(), lock_type) {
                 tree::EntryType::Entrypoint
             } else {
@@ -270,6 +273,7 @@ async fn do_sync(
                     .pin(*dep.pin())
                     .opt(*dep.opt())
                     .maybe_source(dep.source.clone())
+                    .maybe_rockspec_url(dep.rockspec_url.clone())
                     .build()
             })
             .collect();
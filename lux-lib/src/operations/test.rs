@@ -1,4 +1,10 @@
-use std::{io, ops::Deref, path::PathBuf, process::Command, sync::Arc};
+use std::{
+    io,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
 
 use crate::{
     build::BuildBehaviour,
@@ -37,6 +43,10 @@ pub struct Test<'a> {
 
     #[builder(field)]
     args: Vec<String>,
+    /// Environment variables to set for the test runner.
+    /// These take precedence over the `test_env` from the `Config`.
+    #[builder(field)]
+    env_vars: Vec<(String, String)>,
 
     no_lock: Option<bool>,
 
@@ -56,6 +66,23 @@ impl<State: test_builder::State> TestBuilder<'_, State> {
         self
     }
 
+    pub fn env_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env_vars.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn env_vars(
+        mut self,
+        env_vars: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        self.env_vars.extend(
+            env_vars
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
     pub async fn run(self) -> Result<(), RunTestsError>
     where
         State: test_builder::IsComplete,
@@ -147,17 +174,18 @@ async fn run_tests(test: Test<'_>) -> Result<(), RunTestsError> {
     let test_tree_paths = Paths::new(&test_tree)?;
     paths.prepend(&test_tree_paths);
 
+    let lua_version = test.project.lua_version(&config)?;
     let mut command = match &test_spec {
         ValidatedTestSpec::Busted(_) => Command::new(BUSTED_EXE),
         ValidatedTestSpec::BustedNlua(_) => Command::new(BUSTED_EXE),
         ValidatedTestSpec::Command(spec) => Command::new(spec.command.clone()),
         ValidatedTestSpec::LuaScript(_) => {
-            let lua_version = test.project.lua_version(&config)?;
-            let lua_binary = LuaBinary::new(lua_version, &config);
+            let lua_binary = LuaBinary::new(lua_version.clone(), &config);
             let lua_bin_path: PathBuf = lua_binary.try_into()?;
             Command::new(lua_bin_path)
         }
     };
+    let lua_bin: Option<PathBuf> = LuaBinary::new(lua_version, &config).try_into().ok();
     let mut command = command
         .current_dir(test.project.root().deref())
         .args(test_spec.args())
@@ -183,6 +211,7 @@ async fn run_tests(test: Test<'_>) -> Result<(), RunTestsError> {
             .env("XDG_STATE_HOME", xdg_state_home)
             .env("XDG_DATA_HOME", xdg_data_home);
     }
+    set_runner_env(command, lua_bin.as_deref(), &config, test.env_vars);
     let status = match command.status() {
         Ok(status) => Ok(status),
         Err(err) => Err(RunTestsError::RunCommandFailure("busted".into(), err)),
@@ -194,6 +223,20 @@ async fn run_tests(test: Test<'_>) -> Result<(), RunTestsError> {
     }
 }
 
+/// Export the Lua interpreter as `LUA`, so that test runners like busted can pick it up,
+/// followed by the user's environment variables.
+fn set_runner_env<'a>(
+    command: &'a mut Command,
+    lua_bin: Option<&Path>,
+    config: &Config,
+    env_vars: Vec<(String, String)>,
+) -> &'a mut Command {
+    if let Some(lua_bin) = lua_bin {
+        command.env("LUA", lua_bin);
+    }
+    command.envs(config.test_env()).envs(env_vars)
+}
+
 #[derive(Error, Debug)]
 #[error("error installing test dependencies: {0}")]
 pub enum InstallTestDependenciesError {
@@ -212,7 +255,7 @@ async fn ensure_test_dependencies(
     progress: Arc<Progress<MultiProgress>>,
 ) -> Result<(), InstallTestDependenciesError> {
     let test_tree = project.test_tree(config)?;
-    let rockspec_dependencies = rockspec.test_dependencies().current_platform();
+    let rockspec_dependencies = rockspec.test_dependencies().for_target_platform(config);
     let test_dependencies = rockspec
         .test()
         .current_platform()
@@ -260,6 +303,7 @@ async fn ensure_test_dependencies(
                         .pin(*dep.pin())
                         .opt(*dep.opt())
                         .maybe_source(dep.source.clone())
+                        .maybe_rockspec_url(dep.rockspec_url.clone())
                         .build()
                     })
                 }),
@@ -321,4 +365,35 @@ mod tests {
 
         Test::new(project, &config).run().await.unwrap();
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runner_env() {
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .test_env(Some(
+                [("FOO", "config"), ("BAR", "config")]
+                    .into_iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect(),
+            ))
+            .build()
+            .unwrap();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(r#"printf '%s %s %s' "$LUA" "$FOO" "$BAR""#);
+        let output = set_runner_env(
+            &mut command,
+            Some(Path::new("/usr/bin/nlua")),
+            &config,
+            vec![("BAR".into(), "test".into())],
+        )
+        .output()
+        .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "/usr/bin/nlua config test"
+        );
+    }
 }
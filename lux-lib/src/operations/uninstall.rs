@@ -1,11 +1,14 @@
+use std::collections::HashSet;
 use std::io;
 use std::sync::Arc;
 
-use crate::config::{LuaVersion, LuaVersionUnset};
 use crate::lockfile::{FlushLockfileError, LocalPackage, LocalPackageId};
+use crate::lua_rockspec::LuaVersionError;
+use crate::package::PackageReq;
 use crate::progress::{MultiProgress, Progress, ProgressBar};
-use crate::tree::TreeError;
+use crate::tree::{RockMatches, TreeError};
 use crate::{config::Config, tree::Tree};
+use bon::Builder;
 use futures::StreamExt;
 use itertools::Itertools;
 use thiserror::Error;
@@ -13,7 +16,7 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub enum RemoveError {
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
     Io(#[from] io::Error),
     #[error(transparent)]
     Tree(#[from] TreeError),
@@ -72,11 +75,96 @@ impl<'a> Uninstall<'a> {
         };
         let tree = self
             .config
-            .user_tree(LuaVersion::from(self.config)?.clone())?;
+            .user_tree(self.config.resolve_lua_version(None)?)?;
         remove(self.packages, tree, self.config, &Arc::clone(&progress)).await
     }
 }
 
+/// Uninstalls a single entrypoint rock from a tree,
+/// along with the dependencies that are no longer needed by any other entrypoint.
+#[derive(Builder)]
+#[builder(start_fn = new, finish_fn(name = _build, vis = ""))]
+pub struct UninstallPackage<'a> {
+    #[builder(start_fn)]
+    package: &'a PackageReq,
+    #[builder(start_fn)]
+    tree: &'a Tree,
+    #[builder(start_fn)]
+    config: &'a Config,
+
+    progress: Option<Arc<Progress<MultiProgress>>>,
+}
+
+#[derive(Error, Debug)]
+pub enum UninstallPackageError {
+    #[error("{0} is not installed")]
+    NotFound(PackageReq),
+    #[error("{0} matches multiple installed rocks: {ids}", ids = .1.iter().join(", "))]
+    Ambiguous(PackageReq, Vec<LocalPackageId>),
+    #[error("{0} is a dependency of other installed rocks")]
+    IsDependency(PackageReq),
+    #[error(transparent)]
+    Tree(#[from] TreeError),
+    #[error(transparent)]
+    Remove(#[from] RemoveError),
+}
+
+impl<State> UninstallPackageBuilder<'_, State>
+where
+    State: uninstall_package_builder::State + uninstall_package_builder::IsComplete,
+{
+    /// Uninstall the package, returning the removed packages.
+    pub async fn uninstall(self) -> Result<Vec<LocalPackage>, UninstallPackageError> {
+        do_uninstall_package(self._build()).await
+    }
+}
+
+async fn do_uninstall_package(
+    args: UninstallPackage<'_>,
+) -> Result<Vec<LocalPackage>, UninstallPackageError> {
+    let req = args.package;
+    let tree = args.tree;
+    let id = match tree.match_rocks(req)? {
+        RockMatches::NotFound(req) => return Err(UninstallPackageError::NotFound(req)),
+        RockMatches::Single(id) => id,
+        RockMatches::Many(ids) => return Err(UninstallPackageError::Ambiguous(req.clone(), ids)),
+    };
+
+    let lockfile = tree.lockfile()?;
+    let package = lockfile
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| UninstallPackageError::NotFound(req.clone()))?;
+
+    // Determine what becomes orphaned without modifying the lockfile on disk,
+    // as the installed rock layouts depend on the current entry types.
+    // Rocks that were already unreachable were not orphaned by this operation, so we keep them.
+    let already_unreachable: HashSet<LocalPackageId> =
+        lockfile.unreachable_rocks().into_iter().collect();
+    let mut pruned = lockfile.clone().into_temporary();
+    pruned.remove_entrypoint(&package);
+    let orphans = pruned
+        .unreachable_rocks()
+        .into_iter()
+        .filter(|orphan| orphan == &id || !already_unreachable.contains(orphan))
+        .collect_vec();
+    if !orphans.contains(&id) {
+        return Err(UninstallPackageError::IsDependency(req.clone()));
+    }
+    let removed = orphans
+        .iter()
+        .filter_map(|id| lockfile.get(id))
+        .cloned()
+        .collect_vec();
+
+    let progress = args
+        .progress
+        .unwrap_or_else(|| MultiProgress::new_arc(args.config));
+    remove(orphans, tree.clone(), args.config, &progress).await?;
+
+    Ok(removed)
+}
+
 // TODO: Remove dependencies recursively too!
 async fn remove(
     package_ids: Vec<LocalPackageId>,
@@ -150,3 +238,79 @@ async fn remove_package(
     bar.map(|p| p.finish_and_clear());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ConfigBuilder, LuaVersion};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn uninstall_package_prunes_orphaned_dependencies() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let foo = LocalPackage::mock("foo", "1.0.0-1");
+        let bar = LocalPackage::mock("bar", "1.0.0-1");
+        let shared = LocalPackage::mock("shared", "1.0.0-1");
+        let only_foo = LocalPackage::mock("only-foo", "1.0.0-1");
+        let stale = LocalPackage::mock("stale", "1.0.0-1");
+
+        let mut lockfile = tree.lockfile().unwrap().write_guard();
+        lockfile.add_entrypoint(&stale);
+        lockfile.remove_entrypoint(&stale);
+        lockfile.add_entrypoint(&foo);
+        lockfile.add_entrypoint(&bar);
+        lockfile.add_dependency(&foo, &shared);
+        lockfile.add_dependency(&foo, &only_foo);
+        lockfile.add_dependency(&bar, &shared);
+        drop(lockfile);
+
+        for package in [&foo, &bar, &shared, &only_foo, &stale] {
+            let layout = tree.installed_rock_layout(package).unwrap();
+            std::fs::create_dir_all(&layout.rock_path).unwrap();
+            std::fs::create_dir_all(&layout.etc).unwrap();
+        }
+
+        let err = UninstallPackage::new(&"shared".parse().unwrap(), &tree, &config)
+            .uninstall()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UninstallPackageError::IsDependency(_)));
+
+        let removed = UninstallPackage::new(&"foo".parse().unwrap(), &tree, &config)
+            .uninstall()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|package| package.id())
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            removed,
+            [foo.id(), only_foo.id()].into_iter().sorted().collect_vec()
+        );
+        assert!(!tree.root_for(&foo).exists());
+        assert!(!tree.root_for(&only_foo).exists());
+        assert!(tree.root_for(&shared).exists());
+        assert!(tree.root_for(&stale).exists());
+
+        let lockfile = tree.lockfile().unwrap();
+        assert!(lockfile.get(&foo.id()).is_none());
+        assert!(lockfile.get(&only_foo.id()).is_none());
+        assert!(lockfile.is_dependency(&shared.id()));
+        assert!(lockfile.is_entrypoint(&bar.id()));
+        assert!(lockfile.get(&stale.id()).is_some());
+
+        let err = UninstallPackage::new(&"foo".parse().unwrap(), &tree, &config)
+            .uninstall()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UninstallPackageError::NotFound(_)));
+    }
+}
@@ -1,15 +1,18 @@
 use std::{io, sync::Arc};
 
 use bon::Builder;
+use git_url_parse::{GitUrl, GitUrlParseError};
 use itertools::Itertools;
 use thiserror::Error;
 
 use crate::{
-    config::{Config, LuaVersion, LuaVersionUnset},
+    config::Config,
+    git::utils::{self as git_utils, GitError},
     lockfile::{
-        LocalPackage, LocalPackageLockType, Lockfile, PinnedState, ProjectLockfile, ReadOnly,
-        ReadWrite,
+        LocalPackage, LocalPackageLock, LocalPackageLockType, Lockfile, PinnedState,
+        ProjectLockfile, ReadOnly, ReadWrite, RemotePackageSourceUrl,
     },
+    lua_rockspec::LuaVersionError,
     package::{PackageReq, RockConstraintUnsatisfied},
     progress::{MultiProgress, Progress},
     project::{Project, ProjectError, ProjectTreeError},
@@ -33,7 +36,7 @@ pub enum UpdateError {
     #[error("error loading project: {0}")]
     Project(#[from] ProjectError),
     #[error(transparent)]
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
     #[error(transparent)]
     Io(#[from] io::Error),
     #[error(transparent)]
@@ -42,6 +45,10 @@ pub enum UpdateError {
     ProjectTree(#[from] ProjectTreeError),
     #[error("error syncing the project tree: {0}")]
     Sync(#[from] SyncError),
+    #[error("error parsing git source URL: {0}")]
+    GitUrlParse(#[from] GitUrlParseError),
+    #[error("error resolving the latest commit of a git source: {0}")]
+    Git(#[from] GitError),
 }
 
 /// A rocks package updater, providing fine-grained control
@@ -124,7 +131,7 @@ async fn update_project(
     package_db: RemotePackageDB,
     progress: Arc<Progress<MultiProgress>>,
 ) -> Result<Vec<LocalPackage>, UpdateError> {
-    let mut project_lockfile = project.lockfile()?.write_guard();
+    let mut project_lockfile = project.lockfile_for(args.config)?.write_guard();
     let tree = project.tree(args.config)?;
 
     let dep_report = super::Sync::new(&project, args.config)
@@ -206,9 +213,33 @@ async fn update_dependency_tree(
         .into_iter()
         .filter(|pkg| is_included(pkg, packages))
         .collect_vec();
+    let dev_dependencies = dev_git_packages(&lockfile)
+        .into_iter()
+        .filter(|pkg| is_included(pkg, packages))
+        .collect_vec();
+    let updated_dependencies = update(
+        dependencies,
+        package_db.clone(),
+        tree.clone(),
+        &lockfile,
+        config,
+        progress.clone(),
+    )
+    .await?
+    .into_iter()
+    .chain(
+        update_dev_git_packages(
+            dev_dependencies,
+            package_db,
+            tree.clone(),
+            &lockfile,
+            config,
+            progress,
+        )
+        .await?,
+    )
+    .collect_vec();
     let updated_lockfile = tree.lockfile()?;
-    let updated_dependencies =
-        update(dependencies, package_db, tree, &lockfile, config, progress).await?;
     if !updated_dependencies.is_empty() {
         project_lockfile.sync(updated_lockfile.local_pkg_lock(), &lock_type);
     }
@@ -234,13 +265,38 @@ async fn update_install_tree(
 ) -> Result<Vec<LocalPackage>, UpdateError> {
     let tree = args
         .config
-        .user_tree(LuaVersion::from(args.config)?.clone())?;
+        .user_tree(args.config.resolve_lua_version(None)?)?;
     let lockfile = tree.lockfile()?;
     let packages = updatable_packages(&lockfile)
         .into_iter()
         .filter(|pkg| is_included(pkg, &args.packages))
         .collect_vec();
-    update(packages, package_db, tree, &lockfile, args.config, progress).await
+    let dev_packages = dev_git_packages(&lockfile)
+        .into_iter()
+        .filter(|pkg| is_included(pkg, &args.packages))
+        .collect_vec();
+    let updated_packages = update(
+        packages,
+        package_db.clone(),
+        tree.clone(),
+        &lockfile,
+        args.config,
+        progress.clone(),
+    )
+    .await?;
+    let updated_dev_packages = update_dev_git_packages(
+        dev_packages,
+        package_db,
+        tree,
+        &lockfile,
+        args.config,
+        progress,
+    )
+    .await?;
+    Ok(updated_packages
+        .into_iter()
+        .chain(updated_dev_packages)
+        .collect_vec())
 }
 
 async fn update(
@@ -290,12 +346,94 @@ async fn update(
     }
 }
 
+/// Reinstall dev (`scm`/`dev`) packages that were installed from a git source
+/// if the remote's HEAD has moved past the commit recorded in the lockfile.
+async fn update_dev_git_packages(
+    packages: Vec<(LocalPackage, PackageReq)>,
+    package_db: RemotePackageDB,
+    tree: Tree,
+    lockfile: &Lockfile<ReadOnly>,
+    config: &Config,
+    progress: Arc<Progress<MultiProgress>>,
+) -> Result<Vec<LocalPackage>, UpdateError> {
+    let mut updatable = Vec::new();
+    for (package, req) in packages {
+        if let Some(RemotePackageSourceUrl::Git { url, checkout_ref }) = &package.source_url {
+            let git_url: GitUrl = url.parse()?;
+            match git_utils::latest_commit_sha(&git_url)? {
+                Some(latest_sha) if &latest_sha != checkout_ref => {
+                    let mut updated_package = package.clone();
+                    updated_package.source_url = Some(RemotePackageSourceUrl::Git {
+                        url: url.clone(),
+                        checkout_ref: latest_sha,
+                    });
+                    updatable.push((package, updated_package, req));
+                }
+                _ => {}
+            }
+        }
+    }
+    if updatable.is_empty() {
+        return Ok(Vec::new());
+    }
+    Uninstall::new(config)
+        .packages(updatable.iter().map(|(package, _, _)| package.id()))
+        .progress(progress.clone())
+        .remove()
+        .await?;
+    // Resolve against the updated lock entries, so that the new `checkout_ref`
+    // is fetched and its source hash is recorded when rebuilding.
+    // Dependencies that aren't installed yet are resolved with the `package_db`.
+    let package_db = RemotePackageDB::from_lock_with_fallback(
+        updatable
+            .iter()
+            .map(|(_, updated_package, _)| updated_package.clone())
+            .collect::<LocalPackageLock>(),
+        package_db,
+    );
+    let updated_packages = Install::new(config)
+        .packages(
+            updatable
+                .into_iter()
+                .map(|(package, updated_package, req)| {
+                    PackageInstallSpec::new(req, entry_type(&package, lockfile))
+                        .pin(PinnedState::Unpinned)
+                        .opt(package.opt())
+                        .constraint(updated_package.constraint())
+                        .build()
+                })
+                .collect(),
+        )
+        .tree(tree)
+        .package_db(package_db)
+        .progress(progress)
+        .install()
+        .await?;
+    Ok(updated_packages)
+}
+
+/// Unpinned dev packages with a git source, which track the remote's HEAD.
+fn dev_git_packages(lockfile: &Lockfile<ReadOnly>) -> Vec<(LocalPackage, PackageReq)> {
+    lockfile
+        .rocks()
+        .values()
+        .filter(|package| package.pinned() == PinnedState::Unpinned && is_dev_git_package(package))
+        .map(|package| (package.clone(), package.to_package().into_package_req()))
+        .collect_vec()
+}
+
+fn is_dev_git_package(package: &LocalPackage) -> bool {
+    package.version().is_dev()
+        && matches!(package.source_url, Some(RemotePackageSourceUrl::Git { .. }))
+}
+
 fn updatable_packages(lockfile: &Lockfile<ReadOnly>) -> Vec<(LocalPackage, PackageReq)> {
     lockfile
         .rocks()
         .values()
         .filter(|package| {
             package.pinned() == PinnedState::Unpinned
+                && !is_dev_git_package(package)
                 && match package.source() {
                     RemotePackageSource::LuarocksRockspec(_) => true,
                     RemotePackageSource::LuarocksSrcRock(_) => true,
@@ -304,6 +442,7 @@ fn updatable_packages(lockfile: &Lockfile<ReadOnly>) -> Vec<(LocalPackage, Packa
                     // Git sources can be updated with the --toml flag
                     RemotePackageSource::RockspecContent(_) => false,
                     RemotePackageSource::Local => false,
+                    RemotePackageSource::LocalPath(_) => false,
                     #[cfg(test)]
                     RemotePackageSource::Test => false,
                 }
@@ -316,13 +455,197 @@ fn mk_install_spec(
     (package, req): &(LocalPackage, PackageReq),
     lockfile: &Lockfile<ReadOnly>,
 ) -> PackageInstallSpec {
-    let entry_type = if lockfile.is_entrypoint(&package.id()) {
+    PackageInstallSpec::new(req.clone(), entry_type(package, lockfile))
+        .pin(PinnedState::Unpinned)
+        .opt(package.opt())
+        .build()
+}
+
+fn entry_type(package: &LocalPackage, lockfile: &Lockfile<ReadOnly>) -> tree::EntryType {
+    if lockfile.is_entrypoint(&package.id()) {
         tree::EntryType::Entrypoint
     } else {
         tree::EntryType::DependencyOnly
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::{FileWriteStr, PathChild};
+    use git2::{IndexAddOption, Repository, Signature};
+    use httptest::{matchers::request, responders::status_code, Expectation, Server};
+    use url::Url;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        manifest::{Manifest, ManifestMetadata},
     };
-    PackageInstallSpec::new(req.clone(), entry_type)
-        .pin(PinnedState::Unpinned)
-        .opt(package.opt())
-        .build()
+
+    use super::*;
+
+    fn commit_all(repo: &Repository, message: &str) -> String {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("name", "email").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            message,
+            &tree,
+            &parent.iter().collect_vec(),
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn update_dev_git_package_to_latest_commit() {
+        if std::env::var("LUX_SKIP_IMPURE_TESTS").unwrap_or("0".into()) == "1" {
+            println!("Skipping impure test");
+            return;
+        }
+        let upstream = assert_fs::TempDir::new().unwrap();
+        let repo = Repository::init(&upstream).unwrap();
+        upstream.child("foo.lua").write_str("return 1").unwrap();
+        let first_sha = commit_all(&repo, "initial");
+        upstream.child("foo.lua").write_str("return 2").unwrap();
+        let latest_sha = commit_all(&repo, "update");
+        let git_url = format!("file://{}", upstream.path().display());
+
+        // The dependency isn't installed, so it has to be resolved with the fallback package DB
+        let dependency_src = assert_fs::TempDir::new().unwrap();
+        dependency_src
+            .child("dep.lua")
+            .write_str("return true")
+            .unwrap();
+        let dependency_rockspec = format!(
+            r#"
+package = "dep"
+version = "1.0-1"
+source = {{
+   url = "file://{}"
+}}
+build = {{
+   type = "builtin",
+   modules = {{
+      dep = "dep.lua",
+   }},
+}}
+"#,
+            dependency_src.path().display()
+        );
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::path("/dep-1.0-1.rockspec"))
+                .respond_with(status_code(200).body(dependency_rockspec)),
+        );
+        let manifest = r#"
+commands = {}
+modules = {}
+repository = {
+   dep = {
+      ['1.0-1'] = {
+         {
+            arch = "rockspec"
+         }
+      }
+   }
+}
+"#;
+        let server_url = Url::parse(&server.url_str("/")).unwrap();
+        let package_db: RemotePackageDB = Manifest::new(
+            server_url,
+            ManifestMetadata::new(&manifest.to_string()).unwrap(),
+        )
+        .into();
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let rockspec = r#"
+package = "foo"
+version = "scm-1"
+source = {
+   url = "git+https://example.com/foo.git"
+}
+dependencies = {
+   "dep",
+}
+build = {
+   type = "builtin",
+   modules = {
+      foo = "foo.lua",
+   },
+}
+"#;
+        let mut dev_package = LocalPackage::mock("foo", "scm-1");
+        dev_package.source = RemotePackageSource::RockspecContent(rockspec.into());
+        dev_package.source_url = Some(RemotePackageSourceUrl::Git {
+            url: git_url.clone(),
+            checkout_ref: first_sha.clone(),
+        });
+        let mut pinned_dev_package = LocalPackage::mock("bar", "scm-1");
+        pinned_dev_package.spec.pinned = PinnedState::Pinned;
+        pinned_dev_package.source_url = Some(RemotePackageSourceUrl::Git {
+            url: git_url,
+            checkout_ref: first_sha,
+        });
+        let package = LocalPackage::mock("baz", "1.0.0-1");
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&dev_package);
+                lockfile.add_entrypoint(&pinned_dev_package);
+                lockfile.add_entrypoint(&package);
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+
+        let lockfile = tree.lockfile().unwrap();
+        let dev_packages = dev_git_packages(&lockfile);
+        assert_eq!(dev_packages.len(), 1);
+        let updated_packages = update_dev_git_packages(
+            dev_packages,
+            package_db,
+            tree.clone(),
+            &lockfile,
+            &config,
+            MultiProgress::new_arc(&config),
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated_packages.len(), 2);
+
+        let lockfile = tree.lockfile().unwrap();
+        let updated_package = lockfile
+            .rocks()
+            .values()
+            .find(|pkg| pkg.name().to_string() == "foo")
+            .unwrap();
+        assert!(matches!(
+            &updated_package.source_url,
+            Some(RemotePackageSourceUrl::Git { checkout_ref, .. }) if checkout_ref == &latest_sha
+        ));
+        assert!(lockfile.is_entrypoint(&updated_package.id()));
+        assert!(lockfile
+            .rocks()
+            .values()
+            .any(|pkg| pkg.name().to_string() == "dep"));
+        assert_eq!(
+            lockfile.get(&pinned_dev_package.id()),
+            Some(&pinned_dev_package)
+        );
+        assert_eq!(lockfile.get(&package.id()), Some(&package));
+    }
 }
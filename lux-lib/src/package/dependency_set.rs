@@ -0,0 +1,139 @@
+use itertools::Itertools;
+
+use super::{PackageName, PackageReq};
+
+/// A set of package requirements, e.g. the dependencies declared in a `lux.toml`.
+/// Two requirements are considered equal if they have the same name and version constraint.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencySet(Vec<PackageReq>);
+
+impl DependencySet {
+    pub fn new(package_reqs: impl IntoIterator<Item = PackageReq>) -> Self {
+        package_reqs.into_iter().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PackageReq> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether the set contains a requirement with the same name and version constraint.
+    pub fn contains(&self, package_req: &PackageReq) -> bool {
+        self.0.contains(package_req)
+    }
+
+    /// Whether the set contains a requirement for a package with this name,
+    /// regardless of its version constraint.
+    pub fn contains_name(&self, name: &PackageName) -> bool {
+        self.0.iter().any(|req| req.name() == name)
+    }
+
+    /// The requirements in this set that are not in `other`.
+    /// A requirement whose constraint differs from the one in `other` is included.
+    pub fn difference(&self, other: &DependencySet) -> DependencySet {
+        self.iter()
+            .filter(|req| !other.contains(req))
+            .cloned()
+            .collect()
+    }
+
+    /// The requirements in this set for packages that `other` has no requirement for.
+    pub fn difference_by_name(&self, other: &DependencySet) -> DependencySet {
+        self.iter()
+            .filter(|req| !other.contains_name(req.name()))
+            .cloned()
+            .collect()
+    }
+
+    /// The requirements that are in both this set and `other`.
+    pub fn intersection(&self, other: &DependencySet) -> DependencySet {
+        self.iter()
+            .filter(|req| other.contains(req))
+            .cloned()
+            .collect()
+    }
+
+    pub fn into_vec(self) -> Vec<PackageReq> {
+        self.0
+    }
+}
+
+impl FromIterator<PackageReq> for DependencySet {
+    fn from_iter<T: IntoIterator<Item = PackageReq>>(iter: T) -> Self {
+        let mut package_reqs = Vec::new();
+        for req in iter {
+            if !package_reqs.contains(&req) {
+                package_reqs.push(req);
+            }
+        }
+        Self(package_reqs)
+    }
+}
+
+impl<'a> FromIterator<&'a PackageReq> for DependencySet {
+    fn from_iter<T: IntoIterator<Item = &'a PackageReq>>(iter: T) -> Self {
+        iter.into_iter().cloned().collect()
+    }
+}
+
+impl IntoIterator for DependencySet {
+    type Item = PackageReq;
+    type IntoIter = std::vec::IntoIter<PackageReq>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DependencySet {
+    type Item = &'a PackageReq;
+    type IntoIter = std::slice::Iter<'a, PackageReq>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl From<Vec<PackageReq>> for DependencySet {
+    fn from(package_reqs: Vec<PackageReq>) -> Self {
+        package_reqs.into_iter().collect()
+    }
+}
+
+impl std::fmt::Display for DependencySet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.iter().join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(reqs: &[&str]) -> DependencySet {
+        reqs.iter()
+            .map(|req| req.parse::<PackageReq>().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn dependency_set_operations() {
+        let declared = set(&["foo >= 1.0", "bar", "baz == 2.0"]);
+        let locked = set(&["foo >= 1.0", "baz == 1.0", "qux"]);
+
+        assert_eq!(declared.difference(&locked), set(&["bar", "baz == 2.0"]));
+        assert_eq!(locked.difference(&declared), set(&["baz == 1.0", "qux"]));
+        assert_eq!(declared.difference_by_name(&locked), set(&["bar"]));
+        assert_eq!(declared.intersection(&locked), set(&["foo >= 1.0"]));
+        assert!(declared.contains_name(&"baz".into()));
+        assert!(!declared.contains(&"baz == 1.0".parse().unwrap()));
+        assert_eq!(set(&["foo", "foo"]).len(), 1);
+    }
+}
@@ -1,21 +1,31 @@
+use bon::Builder;
 use itertools::Itertools;
 use mlua::{ExternalResult, FromLua, IntoLua, LuaSerdeExt};
 use serde::{de, Deserialize, Deserializer, Serialize};
-use std::{cmp::Ordering, fmt::Display, str::FromStr};
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 use thiserror::Error;
+use url::Url;
 
+mod dependency_set;
 mod outdated;
 mod version;
 
+pub use dependency_set::*;
 pub use outdated::*;
 pub use version::{
     PackageVersion, PackageVersionParseError, PackageVersionReq, PackageVersionReqError, SpecRev,
-    VersionReqToVersionError,
+    VersionReqToVersionError, MAX_SPECREV,
 };
 
 pub(crate) use version::SpecRevIterator;
 
 use crate::{
+    git::shorthand::{self, GitUrlShorthand},
     lockfile::RemotePackageSourceUrl,
     lua_rockspec::{DisplayAsLuaKV, DisplayLuaKV, DisplayLuaValue},
     package::version::HasModRev,
@@ -33,7 +43,7 @@ pub enum PackageSpecFromPackageReqError {
     },
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct PackageSpec {
     name: PackageName,
@@ -46,8 +56,8 @@ impl PackageSpec {
     }
     pub fn parse(name: String, version: String) -> Result<Self, PackageVersionParseError> {
         Ok(Self::new(
-            PackageName::new(name),
-            PackageVersion::parse(&version)?,
+            PackageName::new(trim_input(&name).to_string()),
+            PackageVersion::parse(trim_input(&version))?,
         ))
     }
     pub fn name(&self) -> &PackageName {
@@ -164,16 +174,50 @@ impl PartialOrd for RemotePackageType {
     }
 }
 
-#[derive(Clone)]
+/// Filters the types of remote packages to consider when searching for a package.
+///
+/// `RemotePackageTypeFilterSpec::default()` includes all package types.
+/// When constructing a filter with `RemotePackageTypeFilterSpec::builder()`,
+/// package types are excluded unless they are explicitly included.
+///
+/// If a package version is available as multiple types,
+/// they are prioritised as follows: binary > rockspec > src.
+#[derive(Clone, Debug, PartialEq, Eq, Builder)]
 pub struct RemotePackageTypeFilterSpec {
     /// Include Rockspec
+    #[builder(default)]
     pub rockspec: bool,
     /// Include Src
+    #[builder(default)]
     pub src: bool,
     /// Include Binary
+    #[builder(default)]
     pub binary: bool,
 }
 
+impl RemotePackageTypeFilterSpec {
+    /// Only include rockspecs.
+    pub fn rockspec_only() -> Self {
+        Self::builder().rockspec(true).build()
+    }
+
+    /// Only include source rocks.
+    pub fn src_only() -> Self {
+        Self::builder().src(true).build()
+    }
+
+    /// Only include binary rocks.
+    pub fn binary_only() -> Self {
+        Self::builder().binary(true).build()
+    }
+
+    /// Include all package types, preferring binary rocks if available.
+    /// This is equivalent to the default filter.
+    pub fn prefer_binary() -> Self {
+        Self::default()
+    }
+}
+
 impl Default for RemotePackageTypeFilterSpec {
     fn default() -> Self {
         Self {
@@ -200,7 +244,7 @@ impl FromStr for PackageSpec {
     type Err = ParseRemotePackageError;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let (name, version) = s
+        let (name, version) = trim_input(s)
             .split_once('@')
             .ok_or_else(|| ParseRemotePackageError::InvalidInput(s.to_string()))?;
 
@@ -357,6 +401,7 @@ impl FromStr for PackageReq {
     type Err = PackageReqParseError;
 
     fn from_str(str: &str) -> Result<Self, PackageReqParseError> {
+        let str = trim_input(str);
         let rock_name_str = str
             .chars()
             .peeking_take_while(|t| t.is_alphanumeric() || matches!(t, '-' | '_' | '.'))
@@ -383,6 +428,66 @@ impl FromStr for PackageReq {
     }
 }
 
+/// Trim surrounding whitespace and matching surrounding quotes,
+/// which are easily left over when passing arguments from shell scripts.
+fn trim_input(input: &str) -> &str {
+    let input = input.trim();
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| input.strip_prefix(quote)?.strip_suffix(quote))
+        .map(str::trim)
+        .unwrap_or(input)
+}
+
+/// Used for parsing alternatives between a git URL shorthand, a rockspec URL
+/// and a package requirement.
+/// Git sources can be specified with a host prefix, e.g. "github:owner/repo",
+/// or as a full git URL, e.g. "https://github.com/owner/repo.git".
+/// URLs ending in `.rockspec` are parsed as rockspec URLs.
+#[derive(Debug, Clone)]
+pub enum PackageReqOrGitShorthand {
+    PackageReq(PackageReq),
+    GitShorthand(GitUrlShorthand),
+    RockspecUrl(Url),
+}
+
+#[derive(Error, Debug)]
+pub enum PackageReqOrGitShorthandParseError {
+    #[error("invalid git URL shorthand '{0}': {1}")]
+    InvalidGitShorthand(String, shorthand::ParseError),
+    #[error("invalid git URL '{0}': {1}")]
+    InvalidGitUrl(String, git_url_parse::GitUrlParseError),
+    #[error("invalid rockspec URL '{0}': {1}")]
+    InvalidRockspecUrl(String, url::ParseError),
+    #[error(transparent)]
+    PackageReq(#[from] PackageReqParseError),
+}
+
+impl FromStr for PackageReqOrGitShorthand {
+    type Err = PackageReqOrGitShorthandParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = trim_input(s);
+        if shorthand::has_git_host_prefix(s) {
+            GitUrlShorthand::parse_with_prefix(s)
+                .map(Self::GitShorthand)
+                .map_err(|err| {
+                    PackageReqOrGitShorthandParseError::InvalidGitShorthand(s.into(), err)
+                })
+        } else if s.contains("://") && s.ends_with(".rockspec") {
+            Url::parse(s).map(Self::RockspecUrl).map_err(|err| {
+                PackageReqOrGitShorthandParseError::InvalidRockspecUrl(s.into(), err)
+            })
+        } else if s.contains("://") || s.starts_with("git@") {
+            s.parse::<git_url_parse::GitUrl>()
+                .map(|url| Self::GitShorthand(url.into()))
+                .map_err(|err| PackageReqOrGitShorthandParseError::InvalidGitUrl(s.into(), err))
+        } else {
+            Ok(Self::PackageReq(PackageReq::parse(s)?))
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for PackageReq {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -393,8 +498,10 @@ impl<'de> Deserialize<'de> for PackageReq {
     }
 }
 
-/// A luarocks package name, which is always lowercase
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+/// A luarocks package name, which is always lowercase.
+/// Comparisons treat `.` and `-` as equivalent separators,
+/// so that e.g. `plenary.nvim` and `Plenary-nvim` refer to the same package.
+#[derive(Debug, Clone)]
 pub struct PackageName(String);
 
 impl IntoLua for PackageName {
@@ -405,7 +512,70 @@ impl IntoLua for PackageName {
 
 impl PackageName {
     pub fn new(name: String) -> Self {
-        Self(name.to_lowercase())
+        Self(name.trim().to_lowercase())
+    }
+
+    /// The canonical form of this name, used for equality, ordering and hashing.
+    fn canonical(&self) -> impl Iterator<Item = char> + '_ {
+        self.0.chars().map(|c| if c == '.' { '-' } else { c })
+    }
+
+    /// The canonical form of this name as a string, e.g. for hashing into ids.
+    pub(crate) fn normalized(&self) -> String {
+        self.canonical().collect()
+    }
+}
+
+impl PartialEq for PackageName {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical().eq(other.canonical())
+    }
+}
+
+impl Eq for PackageName {}
+
+impl PartialOrd for PackageName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PackageName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.canonical().cmp(other.canonical())
+    }
+}
+
+impl Hash for PackageName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().for_each(|c| c.hash(state));
+        state.write_u8(0xff);
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PackageNameParseError {
+    #[error("package name cannot be empty")]
+    Empty,
+    #[error("invalid character '{1}' in package name '{0}'")]
+    InvalidCharacter(String, char),
+}
+
+impl FromStr for PackageName {
+    type Err = PackageNameParseError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let name = str.trim();
+        if name.is_empty() {
+            return Err(PackageNameParseError::Empty);
+        }
+        match name
+            .chars()
+            .find(|c| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        {
+            Some(c) => Err(PackageNameParseError::InvalidCharacter(name.to_string(), c)),
+            None => Ok(Self::new(name.to_string())),
+        }
     }
 }
 
@@ -467,12 +637,170 @@ impl Display for PackageNameList {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_package_req_or_git_shorthand() {
+        let git_url: git_url_parse::GitUrl = match "github:nvim-neorocks/lux".parse().unwrap() {
+            PackageReqOrGitShorthand::GitShorthand(shorthand) => shorthand.into(),
+            PackageReqOrGitShorthand::PackageReq(req) => {
+                panic!("expected git shorthand, got {req}")
+            }
+            PackageReqOrGitShorthand::RockspecUrl(url) => {
+                panic!("expected git shorthand, got {url}")
+            }
+        };
+        assert_eq!(git_url.host, Some("github.com".into()));
+        assert_eq!(git_url.owner, Some("nvim-neorocks".into()));
+        assert_eq!(git_url.name, "lux");
+
+        let shorthand = match "https://gitlab.com/owner/repo.git".parse().unwrap() {
+            PackageReqOrGitShorthand::GitShorthand(shorthand) => shorthand,
+            PackageReqOrGitShorthand::PackageReq(req) => panic!("expected git URL, got {req}"),
+            PackageReqOrGitShorthand::RockspecUrl(url) => panic!("expected git URL, got {url}"),
+        };
+        assert_eq!(shorthand.to_string(), "gitlab:owner/repo");
+
+        match "neorg@1.0.0".parse().unwrap() {
+            PackageReqOrGitShorthand::PackageReq(req) => {
+                assert_eq!(req.name().to_string(), "neorg")
+            }
+            PackageReqOrGitShorthand::GitShorthand(url) => {
+                panic!("expected package requirement, got {url}")
+            }
+            PackageReqOrGitShorthand::RockspecUrl(url) => {
+                panic!("expected package requirement, got {url}")
+            }
+        }
+
+        assert!(matches!(
+            "https://luarocks.org/busted-2.2.0-1.rockspec".parse().unwrap(),
+            PackageReqOrGitShorthand::RockspecUrl(url)
+                if url.as_str() == "https://luarocks.org/busted-2.2.0-1.rockspec"
+        ));
+
+        assert!(matches!(
+            "github:lux".parse::<PackageReqOrGitShorthand>(),
+            Err(PackageReqOrGitShorthandParseError::InvalidGitShorthand(..))
+        ));
+    }
+
+    #[test]
+    fn parse_padded_and_quoted_input() {
+        let expected = PackageSpec::parse("neorg".into(), "8.8.1-1".into()).unwrap();
+        for input in [
+            " neorg @ 8.8.1-1 ",
+            "\"neorg@8.8.1-1\"",
+            "' neorg@8.8.1-1 '",
+        ] {
+            assert_eq!(input.parse::<PackageSpec>().unwrap(), expected);
+            let req = PackageReq::parse(input).unwrap();
+            assert_eq!(req.name(), expected.name());
+            assert!(req.matches(&expected));
+        }
+        assert_eq!(
+            PackageSpec::parse(" \"neorg\" ".into(), "'8.8.1-1'".into()).unwrap(),
+            expected
+        );
+        let req = PackageReq::parse("\"neorg >= 8.0\"").unwrap();
+        assert_eq!(req.name(), expected.name());
+        assert!(req.matches(&expected));
+        assert!(matches!(
+            " 'github:nvim-neorocks/lux' ".parse().unwrap(),
+            PackageReqOrGitShorthand::GitShorthand(_)
+        ));
+        // Mismatched quotes are not stripped
+        PackageReq::parse("\"neorg'").unwrap_err();
+    }
+
+    #[test]
+    fn remote_package_type_filter_spec() {
+        assert_eq!(
+            RemotePackageTypeFilterSpec::builder().build(),
+            RemotePackageTypeFilterSpec {
+                rockspec: false,
+                src: false,
+                binary: false,
+            }
+        );
+        assert_eq!(
+            RemotePackageTypeFilterSpec::builder()
+                .rockspec(true)
+                .binary(true)
+                .build(),
+            RemotePackageTypeFilterSpec {
+                rockspec: true,
+                src: false,
+                binary: true,
+            }
+        );
+        assert_eq!(
+            RemotePackageTypeFilterSpec::src_only(),
+            RemotePackageTypeFilterSpec {
+                rockspec: false,
+                src: true,
+                binary: false,
+            }
+        );
+        assert_eq!(
+            RemotePackageTypeFilterSpec::prefer_binary(),
+            RemotePackageTypeFilterSpec::default()
+        );
+    }
+
     #[tokio::test]
     async fn parse_name() {
         let mut package_name: PackageName = "neorg".into();
         assert_eq!(package_name.to_string(), "neorg");
         package_name = "LuaFileSystem".into();
         assert_eq!(package_name.to_string(), "luafilesystem");
+        package_name = " plenary.nvim ".into();
+        assert_eq!(package_name.to_string(), "plenary.nvim");
+    }
+
+    #[test]
+    fn parse_name_from_str() {
+        let package_name: PackageName = "Plenary.nvim".parse().unwrap();
+        assert_eq!(package_name.to_string(), "plenary.nvim");
+        assert!(matches!(
+            "".parse::<PackageName>(),
+            Err(PackageNameParseError::Empty)
+        ));
+        assert!(matches!(
+            "  ".parse::<PackageName>(),
+            Err(PackageNameParseError::Empty)
+        ));
+        assert!(matches!(
+            "neorg >= 1.0.0".parse::<PackageName>(),
+            Err(PackageNameParseError::InvalidCharacter(_, ' '))
+        ));
+        assert!(matches!(
+            "foo/bar".parse::<PackageName>(),
+            Err(PackageNameParseError::InvalidCharacter(_, '/'))
+        ));
+    }
+
+    #[test]
+    fn package_name_normalization() {
+        let canonical = PackageName::from("plenary.nvim");
+        for variant in [
+            "Plenary.nvim",
+            "PLENARY.NVIM",
+            "plenary-nvim",
+            "Plenary-Nvim",
+        ] {
+            let name = PackageName::from(variant);
+            assert_eq!(name, canonical, "{variant} should equal {canonical}");
+            assert_eq!(name.cmp(&canonical), Ordering::Equal);
+        }
+        assert_ne!(PackageName::from("plenary_nvim"), canonical);
+        assert_ne!(PackageName::from("plenarynvim"), canonical);
+
+        let grouped: std::collections::HashMap<PackageName, usize> =
+            ["Plenary.nvim", "plenary.nvim", "plenary-nvim", "neorg"]
+                .into_iter()
+                .map(PackageName::from)
+                .counts();
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped.get(&canonical), Some(&3));
     }
 
     #[tokio::test]
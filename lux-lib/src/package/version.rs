@@ -1,6 +1,7 @@
 use std::{
     cmp::{self, Ordering},
     fmt::Display,
+    ops::Bound,
     str::FromStr,
 };
 
@@ -74,6 +75,10 @@ impl PackageVersion {
         matches!(self, PackageVersion::SemVer(_))
     }
 
+    pub(crate) fn is_dev(&self) -> bool {
+        matches!(self, PackageVersion::DevVer(_))
+    }
+
     pub(crate) fn default_dev_version() -> Self {
         Self::DevVer(DevVer::default())
     }
@@ -129,6 +134,7 @@ impl TryFrom<PackageVersionReq> for PackageVersion {
                 modrev,
                 specrev: 1.into(),
             })),
+            PackageVersionReq::Exact(version) => Ok(version),
             PackageVersionReq::Any => Err(VersionReqToVersionError::Any),
         }
     }
@@ -283,15 +289,21 @@ impl FromLua for SpecRev {
     }
 }
 
-/// Iterates `SpecRev`s upwards, starting from `1`.
+/// The highest `SpecRev` a `SpecRevIterator` yields, unless capped lower.
+pub const MAX_SPECREV: u16 = u16::MAX;
+
+/// Iterates `SpecRev`s upwards, starting from `1`, up to and including a maximum.
 pub(crate) struct SpecRevIterator {
-    current: u16,
+    next: Option<u16>,
+    max: u16,
 }
 
 impl SpecRevIterator {
-    pub fn new() -> Self {
+    /// Iterate `SpecRev`s up to and including `max`.
+    pub fn with_max(max: u16) -> Self {
         SpecRevIterator {
-            current: SpecRev::default().0,
+            next: Some(SpecRev::default().0),
+            max,
         }
     }
 }
@@ -300,12 +312,9 @@ impl Iterator for SpecRevIterator {
     type Item = SpecRev;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.current += 1;
-        if self.current == 0 {
-            None // overflow
-        } else {
-            Some(SpecRev(self.current))
-        }
+        let current = self.next.filter(|current| *current <= self.max)?;
+        self.next = current.checked_add(1);
+        Some(SpecRev(current))
     }
 }
 
@@ -504,8 +513,14 @@ pub(crate) trait HasModRev {
 }
 
 #[derive(Error, Debug)]
-#[error(transparent)]
-pub struct PackageVersionReqError(#[from] Error);
+pub enum PackageVersionReqError {
+    #[error(transparent)]
+    SemVer(#[from] Error),
+    #[error("invalid version requirement '{0}': {1}\nNOTE: '^' matches versions compatible with the given version, like in Cargo or npm (e.g. '^1.2.3' means '>= 1.2.3, < 2.0.0'), whereas luarocks' '~>' only allows the last given component to change (e.g. '~> 1.2.3' means '>= 1.2.3, < 1.2.4')")]
+    Caret(String, Error),
+    #[error("invalid version requirement '{0}': {1}\nNOTE: '~>' only allows the last given component to change, like in luarocks (e.g. '~> 1.2' means '>= 1.2, < 1.3'), whereas '^' matches versions compatible with the given version (e.g. '^1.2' means '>= 1.2.0, < 2.0.0')")]
+    Pessimistic(String, Error),
+}
 
 /// **SemVer version** requirement as defined by <https://semver.org>.
 /// or a **Dev** version requirement, which can be one of "dev", "scm", or "git"
@@ -517,6 +532,9 @@ pub enum PackageVersionReq {
     DevVer(DevVersion),
     /// A PackageVersionReq that matches a arbitrary string version.
     StringVer(String),
+    /// A PackageVersionReq that matches exactly one version, including its specrev,
+    /// for example "==1.2.3-2" or "@1.2.3-2".
+    Exact(PackageVersion),
     /// A PackageVersionReq that has no version constraint.
     Any,
 }
@@ -537,6 +555,7 @@ impl IntoLua for PackageVersionReq {
             }
             PackageVersionReq::DevVer(dev) => table.set("dev", dev)?,
             PackageVersionReq::StringVer(dev) => table.set("stringver", dev)?,
+            PackageVersionReq::Exact(version) => table.set("exact", version)?,
             PackageVersionReq::Any => table.set("any", true)?,
         }
 
@@ -563,6 +582,9 @@ impl PackageVersionReq {
             (PackageVersionReq::StringVer(req), PackageVersion::StringVer(ver)) => {
                 req == &ver.modrev
             }
+            // Compare the full versions (including the specrev),
+            // ignoring how many components a SemVer version was written with.
+            (PackageVersionReq::Exact(req), ver) => req.cmp(ver) == Ordering::Equal,
             (PackageVersionReq::Any, _) => true,
             _ => false,
         }
@@ -571,6 +593,58 @@ impl PackageVersionReq {
     pub fn is_any(&self) -> bool {
         matches!(self, PackageVersionReq::Any)
     }
+
+    /// Combine two requirements into one that matches only versions matched by both.
+    /// Returns `None` if the requirements are provably disjoint.
+    ///
+    /// `Any` acts as the identity. SemVer requirements are combined by concatenating
+    /// their comparators, so the result may still match no versions if disjointness
+    /// can't be determined (e.g. with pre-release comparators).
+    pub fn intersect(&self, other: &Self) -> Option<PackageVersionReq> {
+        match (self, other) {
+            (PackageVersionReq::Any, req) | (req, PackageVersionReq::Any) => Some(req.clone()),
+            (PackageVersionReq::Exact(version), req) | (req, PackageVersionReq::Exact(version)) => {
+                req.matches(version)
+                    .then(|| PackageVersionReq::Exact(version.clone()))
+            }
+            (PackageVersionReq::SemVer(a), PackageVersionReq::SemVer(b)) => {
+                let version_req = VersionReq {
+                    comparators: a
+                        .comparators
+                        .iter()
+                        .chain(b.comparators.iter())
+                        .unique()
+                        .cloned()
+                        .collect(),
+                };
+                (!is_provably_empty(&version_req)).then_some(PackageVersionReq::SemVer(version_req))
+            }
+            (PackageVersionReq::DevVer(a), PackageVersionReq::DevVer(b)) if a == b => {
+                Some(self.clone())
+            }
+            (PackageVersionReq::StringVer(a), PackageVersionReq::StringVer(b)) if a == b => {
+                Some(self.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// A canonical form of this requirement, with the comparators of a SemVer requirement
+    /// deduplicated and sorted, so that logically equal requirements have the same representation.
+    pub(crate) fn canonical(&self) -> Self {
+        match self {
+            PackageVersionReq::SemVer(version_req) => PackageVersionReq::SemVer(VersionReq {
+                comparators: version_req
+                    .comparators
+                    .iter()
+                    .unique()
+                    .sorted_by_cached_key(|comparator| comparator.to_string())
+                    .cloned()
+                    .collect(),
+            }),
+            req => req.clone(),
+        }
+    }
 }
 
 impl Display for PackageVersionReq {
@@ -580,13 +654,12 @@ impl Display for PackageVersionReq {
                 let mut str = version_req.to_string();
                 if str.starts_with("=") {
                     str = str.replacen("=", "==", 1);
-                } else if str.starts_with("^") {
-                    str = str.replacen("^", "~>", 1);
                 }
                 str.fmt(f)
             }
             PackageVersionReq::DevVer(name_req) => write!(f, "=={}", &name_req),
             PackageVersionReq::StringVer(name_req) => write!(f, "=={}", &name_req),
+            PackageVersionReq::Exact(version) => write!(f, "=={}", &version),
             PackageVersionReq::Any => f.write_str("any"),
         }
     }
@@ -607,12 +680,20 @@ impl FromStr for PackageVersionReq {
     type Err = PackageVersionReqError;
 
     fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some(version) = parse_exact_version_with_specrev(text) {
+            return Ok(PackageVersionReq::Exact(version));
+        }
+
         let text = correct_version_req_str(text);
 
         let trimmed = text.trim_start_matches('=').trim_start_matches('@').trim();
 
         match parse_version_req(&text) {
-            Ok(_) => Ok(PackageVersionReq::SemVer(parse_version_req(&text)?)),
+            Ok(version_req) => Ok(PackageVersionReq::SemVer(version_req)),
+            // Operators can't be part of a string version, so we don't fall back to one.
+            Err(
+                err @ (PackageVersionReqError::Caret(..) | PackageVersionReqError::Pessimistic(..)),
+            ) => Err(err),
             Err(_) => match trimmed {
                 "scm" => Ok(PackageVersionReq::DevVer(DevVersion::Scm)),
                 "dev" => Ok(PackageVersionReq::DevVer(DevVersion::Dev)),
@@ -622,6 +703,126 @@ impl FromStr for PackageVersionReq {
     }
 }
 
+/// Whether no version can satisfy all comparators of a SemVer requirement.
+/// Pre-release comparators are not taken into account, so we assume they can be satisfied.
+fn is_provably_empty(version_req: &VersionReq) -> bool {
+    if version_req
+        .comparators
+        .iter()
+        .any(|comparator| !comparator.pre.is_empty())
+    {
+        return false;
+    }
+    let (lower, upper) = version_req.comparators.iter().map(comparator_bounds).fold(
+        (Bound::Unbounded, Bound::Unbounded),
+        |(lower, upper), (comparator_lower, comparator_upper)| {
+            (
+                max_lower_bound(lower, comparator_lower),
+                min_upper_bound(upper, comparator_upper),
+            )
+        },
+    );
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (Bound::Included(lower), Bound::Excluded(upper))
+        | (Bound::Excluded(lower), Bound::Included(upper))
+        | (Bound::Excluded(lower), Bound::Excluded(upper)) => lower >= upper,
+        _ => false,
+    }
+}
+
+/// The range of versions matched by a comparator, ignoring pre-release versions.
+fn comparator_bounds(comparator: &Comparator) -> (Bound<Version>, Bound<Version>) {
+    let major = comparator.major;
+    let minor = comparator.minor.unwrap_or(0);
+    let patch = comparator.patch.unwrap_or(0);
+    let version = Version::new(major, minor, patch);
+    // The first version after all versions matched by a partial version, e.g. 1.3.0 for 1.2
+    let next = match (comparator.minor, comparator.patch) {
+        (None, _) => Version::new(major + 1, 0, 0),
+        (Some(minor), None) => Version::new(major, minor + 1, 0),
+        (Some(minor), Some(patch)) => Version::new(major, minor, patch + 1),
+    };
+    let is_partial = comparator.patch.is_none();
+    match comparator.op {
+        Op::Exact | Op::Wildcard => (Bound::Included(version), Bound::Excluded(next)),
+        Op::Greater if is_partial => (Bound::Included(next), Bound::Unbounded),
+        Op::Greater => (Bound::Excluded(version), Bound::Unbounded),
+        Op::GreaterEq => (Bound::Included(version), Bound::Unbounded),
+        Op::Less => (Bound::Unbounded, Bound::Excluded(version)),
+        Op::LessEq if is_partial => (Bound::Unbounded, Bound::Excluded(next)),
+        Op::LessEq => (Bound::Unbounded, Bound::Included(version)),
+        Op::Tilde => {
+            let upper = match comparator.minor {
+                None => Version::new(major + 1, 0, 0),
+                Some(minor) => Version::new(major, minor + 1, 0),
+            };
+            (Bound::Included(version), Bound::Excluded(upper))
+        }
+        Op::Caret => {
+            let upper = match (major, comparator.minor, comparator.patch) {
+                (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+                (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+                _ => Version::new(major + 1, 0, 0),
+            };
+            (Bound::Included(version), Bound::Excluded(upper))
+        }
+        // `Op` is non-exhaustive, so we can't make any assumptions about new operators.
+        _ => (Bound::Unbounded, Bound::Unbounded),
+    }
+}
+
+fn max_lower_bound(a: Bound<Version>, b: Bound<Version>) -> Bound<Version> {
+    match (&a, &b) {
+        (Bound::Unbounded, _) => b,
+        (_, Bound::Unbounded) => a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            match x.cmp(y) {
+                Ordering::Greater => a,
+                Ordering::Less => b,
+                Ordering::Equal if matches!(a, Bound::Excluded(_)) => a,
+                Ordering::Equal => b,
+            }
+        }
+    }
+}
+
+fn min_upper_bound(a: Bound<Version>, b: Bound<Version>) -> Bound<Version> {
+    match (&a, &b) {
+        (Bound::Unbounded, _) => b,
+        (_, Bound::Unbounded) => a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            match x.cmp(y) {
+                Ordering::Less => a,
+                Ordering::Greater => b,
+                Ordering::Equal if matches!(a, Bound::Excluded(_)) => a,
+                Ordering::Equal => b,
+            }
+        }
+    }
+}
+
+/// Parses an exact requirement with an explicit specrev, e.g. "==1.2.3-2", "@scm-2" or "1.2.3-2".
+/// Like luarocks, we only take the specrev into account if it is specified.
+fn parse_exact_version_with_specrev(text: &str) -> Option<PackageVersion> {
+    let version_str = text
+        .trim()
+        .trim_start_matches("==")
+        .trim_start_matches(['=', '@'])
+        .trim();
+    let (modrev, specrev) = version_str.rsplit_once('-')?;
+    if modrev.is_empty()
+        || specrev.is_empty()
+        || !specrev.chars().all(|c| c.is_ascii_digit())
+        || !version_str
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return None;
+    }
+    PackageVersion::parse(version_str).ok()
+}
+
 fn correct_version_req_str(text: &str) -> String {
     text.chars()
         .chunk_by(|t| t.is_alphanumeric() || matches!(t, '-' | '_' | '.'))
@@ -696,17 +897,20 @@ fn parse_version(s: &str) -> Result<Version, Error> {
 }
 
 /// Transform LuaRocks constraints into constraints that can be parsed by the semver crate.
-fn parse_version_req(version_constraints: &str) -> Result<VersionReq, Error> {
+fn parse_version_req(version_constraints: &str) -> Result<VersionReq, PackageVersionReqError> {
     let unescaped = decode_html_entities(version_constraints)
         .to_string()
         .as_str()
         .to_owned();
     let transformed = match unescaped {
-        s if s.starts_with("~>") => parse_pessimistic_version_constraint(s)?,
+        s if s.starts_with("~>") => parse_pessimistic_version_constraint(&s)
+            .map_err(|err| PackageVersionReqError::Pessimistic(s.clone(), err))?,
+        s if s.starts_with("^") => parse_caret_version_constraint(&s)
+            .map_err(|err| PackageVersionReqError::Caret(s.clone(), err))?,
         s if s.starts_with("@") => format!("={}", &s[1..]),
         // The semver crate only understands "= version", unlike luarocks which understands "== version".
         s if s.starts_with("==") => s[1..].to_string(),
-        s if s // semver parses no constraint prefix as ^ (equivalent to ~>)
+        s if s // semver parses no constraint prefix as ^
             .find(|c: char| c.is_alphanumeric())
             .is_some_and(|idx| idx == 0) =>
         {
@@ -719,7 +923,7 @@ fn parse_version_req(version_constraints: &str) -> Result<VersionReq, Error> {
     Ok(version_req)
 }
 
-fn parse_pessimistic_version_constraint(version_constraint: String) -> Result<String, Error> {
+fn parse_pessimistic_version_constraint(version_constraint: &str) -> Result<String, Error> {
     // pessimistic operator
     let min_version_str = &version_constraint[2..].trim();
     let min_version = Version::parse(&correct_version_string(min_version_str))?;
@@ -742,6 +946,26 @@ fn parse_pessimistic_version_constraint(version_constraint: String) -> Result<St
     Ok(format!(">= {min_version}, < {max_version}"))
 }
 
+/// Caret operator, as in Cargo or npm: allows updates that don't change
+/// the left-most non-zero component, e.g. `^1.2.3` means `>= 1.2.3, < 2.0.0`
+/// and `^0.2.3` means `>= 0.2.3, < 0.3.0`.
+fn parse_caret_version_constraint(version_constraint: &str) -> Result<String, Error> {
+    let min_version_str = &version_constraint[1..].trim();
+    let min_version = Version::parse(&correct_version_string(min_version_str))?;
+
+    let max_version = match (
+        min_version.major,
+        min_version.minor,
+        min_version_str.matches('.').count(),
+    ) {
+        (0, 0, 2..) => Version::new(0, 0, min_version.patch + 1),
+        (0, minor, 1..) => Version::new(0, minor + 1, 0),
+        (major, _, _) => Version::new(major + 1, 0, 0),
+    };
+
+    Ok(format!(">= {min_version}, < {max_version}"))
+}
+
 /// ┻━┻ ︵╰(°□°╰) Luarocks allows for an arbitrary number of version digits
 /// This function attempts to correct a non-semver compliant version string,
 /// by swapping the third '.' out with a '-', converting the non-semver
@@ -780,6 +1004,19 @@ fn append_minor_patch_if_missing(version: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn specrev_iterator_bounds() {
+        assert_eq!(
+            SpecRevIterator::with_max(3).collect_vec(),
+            vec![SpecRev(1), SpecRev(2), SpecRev(3)]
+        );
+        assert_eq!(SpecRevIterator::with_max(0).next(), None);
+        assert_eq!(
+            SpecRevIterator::with_max(MAX_SPECREV).last(),
+            Some(SpecRev(MAX_SPECREV))
+        );
+    }
+
     #[tokio::test]
     async fn parse_semver_version() {
         assert_eq!(
@@ -932,6 +1169,89 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn parse_exact_version_req_with_specrev() {
+        let version: PackageVersion = "1.2.3-2".parse().unwrap();
+        for req in ["1.2.3-2", "==1.2.3-2", "== 1.2.3-2", "@1.2.3-2"] {
+            assert_eq!(
+                PackageVersionReq::parse(req).unwrap(),
+                PackageVersionReq::Exact(version.clone())
+            );
+        }
+        let req = PackageVersionReq::parse("@1.2.3-2").unwrap();
+        assert_eq!(req.to_string(), "==1.2.3-2");
+        assert!(req.matches(&version));
+        assert!(!req.matches(&"1.2.3-1".parse().unwrap()));
+        assert!(!req.matches(&"1.2.4-2".parse().unwrap()));
+        assert_eq!(PackageVersion::try_from(req).unwrap(), version);
+
+        let req = PackageVersionReq::parse("1.2-2").unwrap();
+        assert!(req.matches(&"1.2.0-2".parse().unwrap()));
+
+        let req = PackageVersionReq::parse("@scm-2").unwrap();
+        assert!(req.matches(&"scm-2".parse().unwrap()));
+        assert!(!req.matches(&"scm-1".parse().unwrap()));
+
+        // Without a specrev, any specrev of the modrev matches
+        let req = PackageVersionReq::parse("@1.2.3").unwrap();
+        assert!(req.matches(&"1.2.3-1".parse().unwrap()));
+        assert!(req.matches(&version));
+    }
+
+    #[tokio::test]
+    async fn package_version_req_intersect() {
+        let intersect = |a: &str, b: &str| {
+            PackageVersionReq::parse(a)
+                .unwrap()
+                .intersect(&PackageVersionReq::parse(b).unwrap())
+        };
+
+        let req = intersect(">=1.0.0", "<2.0.0").unwrap();
+        assert!(req.matches(&"1.5.0-1".parse().unwrap()));
+        assert!(!req.matches(&"2.0.0-1".parse().unwrap()));
+        assert!(!req.matches(&"0.9.0-1".parse().unwrap()));
+        assert_eq!(
+            req.canonical(),
+            PackageVersionReq::parse(">=1.0.0, <2.0.0")
+                .unwrap()
+                .canonical()
+        );
+
+        // `Any` is the identity
+        let req = PackageVersionReq::parse("~> 1.2").unwrap();
+        assert_eq!(req.intersect(&PackageVersionReq::Any), Some(req.clone()));
+        assert_eq!(PackageVersionReq::Any.intersect(&req), Some(req));
+        assert_eq!(
+            PackageVersionReq::Any.intersect(&PackageVersionReq::Any),
+            Some(PackageVersionReq::Any)
+        );
+
+        // Disjoint ranges
+        assert_eq!(intersect(">=2.0.0", "<2.0.0"), None);
+        assert_eq!(intersect(">2.0.0", "<=2.0.0"), None);
+        assert_eq!(intersect("~> 1.2", "~> 1.3"), None);
+        assert_eq!(intersect("==1.0.0", "==1.0.1"), None);
+        assert_eq!(intersect(">1", "<2"), None);
+        assert!(intersect(">=2.0.0", "<=2.0.0").is_some());
+        assert!(intersect(">1", "<=2").is_some());
+
+        // Exact requirements
+        assert_eq!(
+            intersect("==1.2.3-2", ">=1.0.0"),
+            Some(PackageVersionReq::parse("==1.2.3-2").unwrap())
+        );
+        assert_eq!(intersect(">=1.0.0", "==0.9.0-1"), None);
+        assert_eq!(intersect("==1.2.3-2", "==1.2.3-1"), None);
+
+        // Dev and string versions
+        assert_eq!(
+            intersect("scm", "==scm"),
+            Some(PackageVersionReq::DevVer(DevVersion::Scm))
+        );
+        assert_eq!(intersect("scm", "dev"), None);
+        assert_eq!(intersect("scm", ">=1.0.0"), None);
+    }
+
     #[tokio::test]
     async fn package_version_req_semver_roundtrips() {
         let req = PackageVersionReq::parse("==0.7.1").unwrap();
@@ -951,6 +1271,55 @@ mod tests {
 
         let req = PackageVersionReq::parse("~> 0.7.1").unwrap();
         assert_eq!(req.to_string(), ">=0.7.1, <0.7.2");
+
+        let req = PackageVersionReq::parse("^0.7.1").unwrap();
+        assert_eq!(req.to_string(), ">=0.7.1, <0.8.0");
+    }
+
+    #[tokio::test]
+    async fn package_version_req_caret_and_pessimistic() {
+        let matches = |req: &str, version: &str| {
+            PackageVersionReq::parse(req)
+                .unwrap()
+                .matches(&version.parse().unwrap())
+        };
+
+        assert!(matches("^1.2.3", "1.2.3-1"));
+        assert!(matches("^1.2.3", "1.9.0-1"));
+        assert!(!matches("^1.2.3", "2.0.0-1"));
+        assert!(!matches("^1.2.3", "1.2.2-1"));
+        assert!(matches("^1.2", "1.5.0-1"));
+        assert!(matches("^1", "1.5.0-1"));
+        assert!(matches("^0.2.3", "0.2.9-1"));
+        assert!(!matches("^0.2.3", "0.3.0-1"));
+        assert!(matches("^0.0.3", "0.0.3-1"));
+        assert!(!matches("^0.0.3", "0.0.4-1"));
+
+        assert!(matches("~> 1.2.3", "1.2.3-1"));
+        assert!(!matches("~> 1.2.3", "1.2.4-1"));
+        assert!(matches("~> 1.2", "1.2.9-1"));
+        assert!(!matches("~> 1.2", "1.3.0-1"));
+        assert!(matches("~> 1", "1.9.0-1"));
+        assert!(!matches("~> 1", "2.0.0-1"));
+
+        for req in ["^1.2.3", "^0.2", "~> 1.2.3", "~> 1.2"] {
+            let parsed = PackageVersionReq::parse(req).unwrap();
+            assert_eq!(
+                PackageVersionReq::parse(&parsed.to_string()).unwrap(),
+                parsed
+            );
+        }
+        assert_eq!(
+            PackageVersionReq::parse("^1.2.3").unwrap().to_string(),
+            ">=1.2.3, <2.0.0"
+        );
+
+        let err = PackageVersionReq::parse("^foo").unwrap_err();
+        assert!(matches!(err, PackageVersionReqError::Caret(..)));
+        assert!(err.to_string().contains("'~>'"));
+        let err = PackageVersionReq::parse("~> foo").unwrap_err();
+        assert!(matches!(err, PackageVersionReqError::Pessimistic(..)));
+        assert!(err.to_string().contains("'^'"));
     }
 
     #[tokio::test]
@@ -3,9 +3,11 @@ use lets_find_up::{find_up_with, FindUpKind, FindUpOptions};
 use mlua::{ExternalResult, UserData};
 use path_slash::PathBufExt;
 use project_toml::{
-    LocalProjectTomlValidationError, PartialProjectToml, RemoteProjectTomlValidationError,
+    LocalProjectTomlValidationError, MergePrecedence, PartialProjectToml,
+    RemoteProjectTomlValidationError,
 };
 use std::{
+    collections::HashSet,
     io,
     ops::Deref,
     path::{Path, PathBuf},
@@ -13,33 +15,35 @@ use std::{
 };
 use thiserror::Error;
 use toml_edit::{DocumentMut, Item};
+use url::Url;
 
 use crate::{
     build,
-    config::{Config, LuaVersion},
+    config::{tree::RockLayoutConfig, Config, LuaVersion},
     git::{
         self,
         shorthand::GitUrlShorthand,
         utils::{GitError, SemVerTagOrSha},
     },
-    lockfile::{LockfileError, ProjectLockfile, ReadOnly},
+    lockfile::{LocalPackageLockType, LockfileError, ProjectLockfile, ReadOnly},
     lua::lua_runtime,
     lua_rockspec::{
         LocalLuaRockspec, LuaRockspecError, LuaVersionError, PartialLuaRockspec,
-        PartialRockspecError, RemoteLuaRockspec,
+        PartialRockspecError, PlatformIdentifier, RemoteLuaRockspec,
     },
+    operations::{self, SearchAndDownloadError},
     package::SpecRev,
-    progress::Progress,
+    progress::{Progress, ProgressBar},
     remote_package_db::RemotePackageDB,
     rockspec::{
         lua_dependency::{DependencyType, LuaDependencySpec, LuaDependencyType},
-        LuaVersionCompatibility,
+        Rockspec,
     },
     tree::{Tree, TreeError},
 };
 use crate::{
     lockfile::PinnedState,
-    package::{PackageName, PackageReq},
+    package::{PackageName, PackageReq, PackageVersion},
 };
 
 pub(crate) mod gen;
@@ -51,6 +55,7 @@ pub const EXTRA_ROCKSPEC: &str = "extra.rockspec";
 pub(crate) const LUX_DIR_NAME: &str = ".lux";
 const LUARC: &str = ".luarc.json";
 const EMMYRC: &str = ".emmyrc.json";
+const LUA_VERSION_FILE: &str = ".lua-version";
 
 #[derive(Error, Debug)]
 #[error(transparent)]
@@ -102,13 +107,20 @@ pub enum ProjectEditError {
     ExpectedString(toml_edit::Value),
     #[error(transparent)]
     GitUrlShorthandParse(#[from] git::shorthand::ParseError),
+    #[error(transparent)]
+    Lockfile(#[from] LockfileError),
+    #[error("failed to download rockspec {0}:\n{1}")]
+    DownloadRockspec(Url, Box<SearchAndDownloadError>),
 }
 
 #[derive(Error, Debug)]
-#[error(transparent)]
 pub enum ProjectTreeError {
+    #[error(transparent)]
     Tree(#[from] TreeError),
+    #[error(transparent)]
     LuaVersionError(#[from] LuaVersionError),
+    #[error("the project's {0} and {1} trees would share the root {2}")]
+    SharedTreeRoot(&'static str, &'static str, PathBuf),
 }
 
 #[derive(Error, Debug)]
@@ -283,7 +295,9 @@ impl Project {
             };
 
             if let Some(extra_rockspec) = project.extra_rockspec()? {
-                project.toml = project.toml.merge(extra_rockspec);
+                project.toml = project
+                    .toml
+                    .merge(extra_rockspec, MergePrecedence::ExtraOverrides);
             }
 
             Ok(Some(project))
@@ -317,7 +331,9 @@ impl Project {
                 };
 
                 if let Some(extra_rockspec) = project.extra_rockspec()? {
-                    project.toml = project.toml.merge(extra_rockspec);
+                    project.toml = project
+                        .toml
+                        .merge(extra_rockspec, MergePrecedence::ExtraOverrides);
                 }
 
                 std::fs::create_dir_all(root).map_err(|err| {
@@ -365,14 +381,39 @@ impl Project {
 
     /// Get the `lux.lock` lockfile in the project root.
     pub fn lockfile(&self) -> Result<ProjectLockfile<ReadOnly>, ProjectError> {
-        Ok(ProjectLockfile::new(self.lockfile_path())?)
+        Ok(ProjectLockfile::new_unchecked(self.lockfile_path())?)
+    }
+
+    /// Get the `lux.lock` lockfile in the project root.
+    /// Fails if the lockfile's rock layout doesn't match the configured entrypoint layout.
+    pub fn lockfile_for(&self, config: &Config) -> Result<ProjectLockfile<ReadOnly>, ProjectError> {
+        Ok(ProjectLockfile::new(
+            self.lockfile_path(),
+            config.entrypoint_layout().clone(),
+        )?)
     }
 
     /// Get the `lux.lock` lockfile in the project root, if present.
     pub fn try_lockfile(&self) -> Result<Option<ProjectLockfile<ReadOnly>>, ProjectError> {
+        self.try_load_lockfile(None)
+    }
+
+    /// Get the `lux.lock` lockfile in the project root, if present.
+    /// Fails if the lockfile's rock layout doesn't match the configured entrypoint layout.
+    pub fn try_lockfile_for(
+        &self,
+        config: &Config,
+    ) -> Result<Option<ProjectLockfile<ReadOnly>>, ProjectError> {
+        self.try_load_lockfile(Some(config.entrypoint_layout()))
+    }
+
+    fn try_load_lockfile(
+        &self,
+        expected_rock_layout: Option<&RockLayoutConfig>,
+    ) -> Result<Option<ProjectLockfile<ReadOnly>>, ProjectError> {
         let path = self.lockfile_path();
         if path.is_file() {
-            Ok(Some(ProjectLockfile::load(path)?))
+            Ok(Some(ProjectLockfile::load(path, expected_rock_layout)?))
         } else {
             Ok(None)
         }
@@ -420,11 +461,9 @@ impl Project {
         lua_version: LuaVersion,
         config: &Config,
     ) -> Result<Tree, ProjectTreeError> {
-        Ok(Tree::new(
-            self.default_tree_root_dir(),
-            lua_version,
-            config,
-        )?)
+        let tree = Tree::new(self.default_tree_root_dir(), lua_version, config)?;
+        validate_tree_roots(&tree)?;
+        Ok(tree)
     }
 
     pub fn test_tree(&self, config: &Config) -> Result<Tree, ProjectTreeError> {
@@ -435,8 +474,37 @@ impl Project {
         Ok(self.tree(config)?.build_tree(config)?)
     }
 
+    /// The Lua version to use for this project.
+    /// A `.lua-version` file at the project root takes precedence over the config,
+    /// but must still satisfy the `lua` constraint in the `lux.toml`.
+    /// See [`Config::resolve_lua_version`].
     pub fn lua_version(&self, config: &Config) -> Result<LuaVersion, LuaVersionError> {
-        self.toml().lua_version_matches(config)
+        config.resolve_lua_version(Some(self))
+    }
+
+    /// Read the Lua version from a `.lua-version` file (as used by tools like `luaenv`), if present.
+    pub(crate) fn lua_version_file(&self) -> Result<Option<LuaVersion>, LuaVersionError> {
+        let path = self.root().join(LUA_VERSION_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| LuaVersionError::ReadLuaVersionFile(path.clone(), err))?;
+        let version_str = content.trim();
+        LuaVersion::from_str(version_str)
+            .ok()
+            .or_else(|| {
+                version_str
+                    .starts_with("luajit")
+                    .then_some(LuaVersion::LuaJIT)
+            })
+            .or_else(|| {
+                PackageVersion::parse(version_str)
+                    .ok()
+                    .and_then(|version| LuaVersion::from_version(version).ok())
+            })
+            .map(Some)
+            .ok_or_else(|| LuaVersionError::InvalidLuaVersionFile(path, version_str.to_string()))
     }
 
     pub async fn add(
@@ -460,20 +528,8 @@ impl Project {
             | DependencyType::Build(ref deps)
             | DependencyType::Test(ref deps) => {
                 for dep in deps {
-                    let dep_version_str = if dep.version_req().is_any() {
-                        package_db
-                            .latest_version(dep.name())
-                            // This condition should never be reached, as the package should
-                            // have been found in the database or an error should have been
-                            // reported prior.
-                            // Still worth making an error message for this in the future,
-                            // though.
-                            .expect("unable to query latest version for package")
-                            .to_string()
-                    } else {
-                        dep.version_req().to_string()
-                    };
-                    table[dep.name().to_string()] = toml_edit::value(dep_version_str);
+                    table[dep.name().to_string()] =
+                        toml_edit::value(dependency_version_str(dep, package_db));
                 }
             }
             DependencyType::External(ref deps) => {
@@ -496,6 +552,41 @@ impl Project {
         Ok(())
     }
 
+    /// Add dependencies that are only required on the given platform,
+    /// i.e. to a `[dependencies.platform.<platform>]` table.
+    pub async fn add_for_platform(
+        &mut self,
+        dependencies: Vec<PackageReq>,
+        platform: &PlatformIdentifier,
+        package_db: &RemotePackageDB,
+    ) -> Result<(), ProjectEditError> {
+        let mut project_toml =
+            toml_edit::DocumentMut::from_str(&tokio::fs::read_to_string(self.toml_path()).await?)?;
+
+        prepare_dependency_tables(&mut project_toml);
+        let platform_tables = &mut project_toml["dependencies"]["platform"];
+        if !platform_tables.is_table() {
+            let mut table = toml_edit::table().into_table().unwrap();
+            table.set_implicit(true);
+            *platform_tables = toml_edit::Item::Table(table);
+        }
+        let table = &mut platform_tables[platform.to_string()];
+        if !table.is_table() {
+            *table = toml_edit::table();
+        }
+
+        for dep in dependencies {
+            table[dep.name().to_string()] =
+                toml_edit::value(dependency_version_str(&dep, package_db));
+        }
+
+        let toml_content = project_toml.to_string();
+        tokio::fs::write(self.toml_path(), &toml_content).await?;
+        self.toml = PartialProjectToml::new(&toml_content, self.root.clone())?;
+
+        Ok(())
+    }
+
     pub async fn add_git(
         &mut self,
         dependencies: LuaDependencyType<GitUrlShorthand>,
@@ -543,6 +634,52 @@ impl Project {
         Ok(())
     }
 
+    /// Add dependencies from the URLs of their rockspecs,
+    /// without searching a package database.
+    /// The package name and version are read from each rockspec,
+    /// and the dependency is pinned to that exact version.
+    pub async fn add_rockspecs(
+        &mut self,
+        dependencies: LuaDependencyType<Url>,
+        config: &Config,
+        progress: &Progress<ProgressBar>,
+    ) -> Result<(), ProjectEditError> {
+        let mut project_toml =
+            toml_edit::DocumentMut::from_str(&tokio::fs::read_to_string(self.toml_path()).await?)?;
+
+        prepare_dependency_tables(&mut project_toml);
+        let table = match dependencies {
+            LuaDependencyType::Regular(_) => &mut project_toml["dependencies"],
+            LuaDependencyType::Build(_) => &mut project_toml["build_dependencies"],
+            LuaDependencyType::Test(_) => &mut project_toml["test_dependencies"],
+        };
+
+        match dependencies {
+            LuaDependencyType::Regular(ref urls)
+            | LuaDependencyType::Build(ref urls)
+            | LuaDependencyType::Test(ref urls) => {
+                for url in urls {
+                    let rockspec = operations::download_rockspec_at_url(url, config, progress)
+                        .await
+                        .map_err(|err| {
+                            ProjectEditError::DownloadRockspec(url.clone(), Box::new(err))
+                        })?
+                        .rockspec;
+                    let mut dep_entry = toml_edit::table();
+                    dep_entry["version"] = Item::Value(format!("=={}", rockspec.version()).into());
+                    dep_entry["rockspec"] = Item::Value(url.to_string().into());
+                    table[rockspec.package().to_string()] = dep_entry;
+                }
+            }
+        }
+
+        let toml_content = project_toml.to_string();
+        tokio::fs::write(self.toml_path(), &toml_content).await?;
+        self.toml = PartialProjectToml::new(&toml_content, self.root.clone())?;
+
+        Ok(())
+    }
+
     pub async fn remove(
         &mut self,
         dependencies: DependencyType<PackageName>,
@@ -673,6 +810,7 @@ impl Project {
     ) -> Result<(), ProjectEditError> {
         if let Some(dependencies) = &self.toml().dependencies {
             let packages = dependencies
+                .default
                 .iter()
                 .map(|dep| dep.name())
                 .cloned()
@@ -723,9 +861,12 @@ impl Project {
                         .dependencies
                         .take()
                         .unwrap_or_default()
-                        .into_iter()
-                        .map(|dep| LuaDependencySpec { pin, ..dep })
-                        .collect(),
+                        .map(|deps| {
+                            deps.iter()
+                                .cloned()
+                                .map(|dep| LuaDependencySpec { pin, ..dep })
+                                .collect()
+                        }),
                 )
             }
             LuaDependencyType::Build(ref _deps) => {
@@ -786,11 +927,136 @@ impl Project {
         Ok(())
     }
 
+    /// Add the entrypoints of the project lockfile that are missing from `lux.toml`,
+    /// e.g. after a manual install, with a `==<version>` constraint.
+    /// Dependencies that are already declared in `lux.toml` for the target platform
+    /// (see [`Config::target_platform`]) are left untouched.
+    /// Dependencies that are only declared for other platforms are added to the
+    /// target platform's table.
+    /// If the lockfile has multiple versions of a package, the latest one is used.
+    pub async fn sync_toml_from_lockfile(
+        &mut self,
+        config: &Config,
+    ) -> Result<(), ProjectEditError> {
+        let lockfile_path = self.lockfile_path();
+        if !lockfile_path.is_file() {
+            return Ok(());
+        }
+        let lockfile = ProjectLockfile::load(lockfile_path, None)?;
+
+        let mut project_toml =
+            toml_edit::DocumentMut::from_str(&tokio::fs::read_to_string(self.toml_path()).await?)?;
+
+        let target_platform = PlatformIdentifier::target_platform(config);
+        let target_platforms = target_platform
+            .get_subsets()
+            .into_iter()
+            .chain(std::iter::once(target_platform.clone()))
+            .map(|platform| platform.to_string())
+            .collect_vec();
+
+        prepare_dependency_tables(&mut project_toml);
+        for (deps, table_name) in [
+            (LocalPackageLockType::Regular, "dependencies"),
+            (LocalPackageLockType::Build, "build_dependencies"),
+            (LocalPackageLockType::Test, "test_dependencies"),
+        ] {
+            let table = &mut project_toml[table_name];
+            let mut declared = declared_dependency_names(table);
+            let mut declared_for_other_platforms = HashSet::new();
+            if let Some(platform_tables) = table.get("platform").and_then(Item::as_table_like) {
+                for (platform, platform_table) in platform_tables.iter() {
+                    let names = declared_dependency_names(platform_table);
+                    if target_platforms.iter().any(|target| target == platform) {
+                        declared.extend(names);
+                    } else {
+                        declared_for_other_platforms.extend(names);
+                    }
+                }
+            }
+            let latest_entrypoints = lockfile
+                .entrypoints(&deps)
+                .into_iter()
+                .filter(|package| !declared.contains(package.name()))
+                .into_grouping_map_by(|package| package.name().clone())
+                .max_by(|_, a, b| a.version().cmp(b.version()));
+            for (name, package) in latest_entrypoints
+                .into_iter()
+                .sorted_by(|a, b| a.0.cmp(&b.0))
+            {
+                let version = toml_edit::value(format!("=={}", package.version()));
+                if declared_for_other_platforms.contains(&name) {
+                    let platform_table = &mut table["platform"][target_platform.to_string()];
+                    if !platform_table.is_table() {
+                        *platform_table = toml_edit::table();
+                    }
+                    platform_table[name.to_string()] = version;
+                } else {
+                    table[name.to_string()] = version;
+                }
+            }
+        }
+
+        let toml_content = project_toml.to_string();
+        tokio::fs::write(self.toml_path(), &toml_content).await?;
+        self.toml = PartialProjectToml::new(&toml_content, self.root.clone())?;
+
+        Ok(())
+    }
+
     pub fn project_files(&self) -> Vec<PathBuf> {
         build::utils::project_files(&self.root().0)
     }
 }
 
+/// Make sure the dependency, test dependency and build dependency trees have distinct roots,
+/// so that their lockfiles and installed rocks don't collide.
+fn validate_tree_roots(tree: &Tree) -> Result<(), ProjectTreeError> {
+    let roots = [
+        ("dependency", tree.root()),
+        ("test dependency", tree.test_tree_root()),
+        ("build dependency", tree.build_tree_root()),
+    ];
+    match roots
+        .iter()
+        .tuple_combinations()
+        .find(|((_, a), (_, b))| a == b)
+    {
+        Some(((a, root), (b, _))) => Err(ProjectTreeError::SharedTreeRoot(a, b, root.clone())),
+        None => Ok(()),
+    }
+}
+
+fn dependency_version_str(dep: &PackageReq, package_db: &RemotePackageDB) -> String {
+    if dep.version_req().is_any() {
+        package_db
+            .latest_version(dep.name())
+            // This condition should never be reached, as the package should
+            // have been found in the database or an error should have been
+            // reported prior.
+            // Still worth making an error message for this in the future,
+            // though.
+            .expect("unable to query latest version for package")
+            .to_string()
+    } else {
+        dep.version_req().to_string()
+    }
+}
+
+/// The names of the dependencies declared in a `lux.toml` dependency table,
+/// excluding the `platform` overrides.
+fn declared_dependency_names(table: &Item) -> HashSet<PackageName> {
+    table
+        .as_table_like()
+        .map(|tbl| {
+            tbl.iter()
+                .filter(|(name, _)| *name != "platform")
+                .map(|(name, _)| PackageName::new(name.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn prepare_dependency_tables(project_toml: &mut DocumentMut) {
     if !project_toml.contains_table("dependencies") {
         let mut table = toml_edit::table().into_table().unwrap();
@@ -828,12 +1094,133 @@ mod tests {
 
     use super::*;
     use crate::{
+        config::ConfigBuilder,
         lua_rockspec::ExternalDependencySpec,
         manifest::{Manifest, ManifestMetadata},
         package::PackageReq,
         rockspec::Rockspec,
     };
 
+    #[test]
+    fn project_tree_roots_are_distinct() {
+        let sample_project: PathBuf = "resources/test/sample-projects/no-build-spec/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let project = Project::from(&project_root).unwrap().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_version(Some(LuaVersion::Lua51))
+            .build()
+            .unwrap();
+        let tree = project.tree(&config).unwrap();
+        let test_tree = project.test_tree(&config).unwrap();
+        let build_tree = project.build_tree(&config).unwrap();
+        assert_ne!(tree.root(), test_tree.root());
+        assert_ne!(tree.root(), build_tree.root());
+        assert_ne!(test_tree.root(), build_tree.root());
+        assert_eq!(tree.test_tree_root(), test_tree.root());
+        assert_eq!(tree.build_tree_root(), build_tree.root());
+        assert!(build_tree
+            .root()
+            .starts_with(tree.root().join("build_dependencies")));
+
+        // The test tree's own test tree is itself
+        assert!(matches!(
+            validate_tree_roots(&test_tree),
+            Err(ProjectTreeError::SharedTreeRoot("dependency", "test dependency", root))
+                if root == test_tree.root()
+        ));
+    }
+
+    #[test]
+    fn test_lua_version_file() {
+        let sample_project: PathBuf = "resources/test/sample-projects/no-build-spec/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let project = Project::from(&project_root).unwrap().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_version(Some(LuaVersion::Lua51))
+            .build()
+            .unwrap();
+        assert_eq!(project.lua_version(&config).unwrap(), LuaVersion::Lua51);
+
+        let lua_version_file = project_root.join(LUA_VERSION_FILE);
+        std::fs::write(&lua_version_file, "5.2\n").unwrap();
+        assert_eq!(project.lua_version(&config).unwrap(), LuaVersion::Lua52);
+        std::fs::write(&lua_version_file, "5.4.6").unwrap();
+        assert_eq!(project.lua_version(&config).unwrap(), LuaVersion::Lua54);
+        std::fs::write(&lua_version_file, "luajit-2.1.0-beta3").unwrap();
+        assert_eq!(project.lua_version(&config).unwrap(), LuaVersion::LuaJIT);
+        std::fs::write(&lua_version_file, "foo").unwrap();
+        assert!(matches!(
+            project.lua_version(&config),
+            Err(LuaVersionError::InvalidLuaVersionFile(_, version)) if version == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_lua_version() {
+        let sample_project: PathBuf = "resources/test/sample-projects/no-build-spec/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let lux_toml = project_root.join(PROJECT_TOML);
+        let content = std::fs::read_to_string(&lux_toml)
+            .unwrap()
+            .replace(r#"lua = ">=5.1""#, r#"lua = ">=5.3""#);
+        std::fs::write(&lux_toml, content).unwrap();
+        let project = Project::from(&project_root).unwrap().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .lua_version(Some(LuaVersion::Lua54))
+            .build()
+            .unwrap();
+        assert_eq!(config.resolve_lua_version(None).unwrap(), LuaVersion::Lua54);
+        assert_eq!(
+            config.resolve_lua_version(Some(&project)).unwrap(),
+            LuaVersion::Lua54
+        );
+
+        // The `.lua-version` file takes precedence over the config,
+        // but must satisfy the lux.toml's constraint
+        let lua_version_file = project_root.join(LUA_VERSION_FILE);
+        std::fs::write(&lua_version_file, "5.3").unwrap();
+        assert_eq!(
+            config.resolve_lua_version(Some(&project)).unwrap(),
+            LuaVersion::Lua53
+        );
+        assert_eq!(config.resolve_lua_version(None).unwrap(), LuaVersion::Lua54);
+        std::fs::write(&lua_version_file, "5.2").unwrap();
+        assert!(matches!(
+            config.resolve_lua_version(Some(&project)),
+            Err(LuaVersionError::LuaVersionUnsupported(
+                LuaVersion::Lua52,
+                ..
+            ))
+        ));
+
+        // A constraint that only allows a single version determines the version
+        std::fs::remove_file(&lua_version_file).unwrap();
+        let content = std::fs::read_to_string(&lux_toml)
+            .unwrap()
+            .replace(r#"lua = ">=5.3""#, r#"lua = "==5.1""#);
+        std::fs::write(&lux_toml, content).unwrap();
+        let project = Project::from(&project_root).unwrap().unwrap();
+        assert_eq!(
+            config.resolve_lua_version(Some(&project)).unwrap(),
+            LuaVersion::Lua51
+        );
+        let luajit_config = ConfigBuilder::new()
+            .unwrap()
+            .lua_version(Some(LuaVersion::LuaJIT))
+            .build()
+            .unwrap();
+        assert_eq!(
+            luajit_config.resolve_lua_version(Some(&project)).unwrap(),
+            LuaVersion::LuaJIT
+        );
+    }
+
     #[tokio::test]
     async fn test_add_various_dependencies() {
         let sample_project: PathBuf = "resources/test/sample-projects/no-build-spec/".into();
@@ -914,6 +1301,97 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_add_platform_dependencies() {
+        let sample_project: PathBuf = "resources/test/sample-projects/no-build-spec/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let project_root: PathBuf = project_root.path().into();
+        let mut project = Project::from(&project_root).unwrap().unwrap();
+
+        let test_manifest_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/manifest-5.1");
+        let content = String::from_utf8(std::fs::read(&test_manifest_path).unwrap()).unwrap();
+        let metadata = ManifestMetadata::new(&content).unwrap();
+        let package_db = Manifest::new(Url::parse("https://example.com").unwrap(), metadata).into();
+
+        let linux_dependency = PackageReq::new("lua-cjson".into(), None).unwrap();
+        project
+            .add_for_platform(
+                vec![linux_dependency.clone()],
+                &PlatformIdentifier::Linux,
+                &package_db,
+            )
+            .await
+            .unwrap();
+
+        let project = Project::from(&project_root).unwrap().unwrap();
+        let validated_toml = project.toml().into_remote(None).unwrap();
+        let dependencies = validated_toml.dependencies();
+        assert!(dependencies
+            .get(&PlatformIdentifier::Linux)
+            .iter()
+            .any(|dep| dep.name() == linux_dependency.name()
+                && dep.version_req() == &"2.1.0-1".parse().unwrap()));
+        assert!(!dependencies
+            .get(&PlatformIdentifier::Windows)
+            .iter()
+            .any(|dep| dep.name() == linux_dependency.name()));
+    }
+
+    #[tokio::test]
+    async fn test_add_rockspec_dependencies() {
+        let rockspec_content = std::fs::read_to_string(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/lua-cjson-2.1.0-1.rockspec"),
+        )
+        .unwrap();
+        let server = httptest::Server::run();
+        server.expect(
+            httptest::Expectation::matching(httptest::matchers::request::path(
+                "/rockspecs/lua-cjson-2.1.0-1.rockspec",
+            ))
+            .respond_with(httptest::responders::status_code(200).body(rockspec_content)),
+        );
+        let rockspec_url =
+            Url::parse(&server.url_str("/rockspecs/lua-cjson-2.1.0-1.rockspec")).unwrap();
+
+        let sample_project: PathBuf = "resources/test/sample-projects/no-build-spec/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let project_root: PathBuf = project_root.path().into();
+        let mut project = Project::from(&project_root).unwrap().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .no_progress(Some(true))
+            .build()
+            .unwrap();
+
+        project
+            .add_rockspecs(
+                LuaDependencyType::Test(vec![rockspec_url.clone()]),
+                &config,
+                &Progress::no_progress(),
+            )
+            .await
+            .unwrap();
+
+        let check = |project: &Project| {
+            let test_dependencies = project.toml().test_dependencies.clone().unwrap();
+            let lua_cjson = test_dependencies
+                .iter()
+                .find(|dep| dep.name() == &"lua-cjson".into())
+                .unwrap();
+            // The version is read from the rockspec, not from its file name
+            assert_eq!(lua_cjson.version_req(), &"==2.1.0.10-1".parse().unwrap());
+            assert_eq!(lua_cjson.rockspec_url(), Some(&rockspec_url));
+        };
+        check(&project);
+        // check again after reloading lux.toml
+        let reloaded_project = Project::from(&project_root).unwrap().unwrap();
+        check(&reloaded_project);
+    }
+
     #[tokio::test]
     async fn test_remove_dependencies() {
         let sample_project: PathBuf = "resources/test/sample-projects/dependencies/".into();
@@ -933,6 +1411,7 @@ mod tests {
                     .dependencies
                     .clone()
                     .unwrap_or_default()
+                    .default
                     .iter()
                     .any(|dep| dep.name() == name));
             }
@@ -943,6 +1422,103 @@ mod tests {
         check(&reloaded_project);
     }
 
+    #[tokio::test]
+    async fn test_sync_toml_from_lockfile() {
+        let sample_project: PathBuf = "resources/test/sample-projects/busted-with-lockfile/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let project_root: PathBuf = project_root.path().into();
+        let toml_path = project_root.join("lux.toml");
+        let toml_content = std::fs::read_to_string(&toml_path).unwrap().replace(
+            "[dependencies]\n",
+            "[dependencies]\nlua-cjson = \">=2.1.0\"\n",
+        );
+        std::fs::write(&toml_path, toml_content).unwrap();
+        let mut project = Project::from(&project_root).unwrap().unwrap();
+        let config = ConfigBuilder::new().unwrap().build().unwrap();
+        project.sync_toml_from_lockfile(&config).await.unwrap();
+        let check = |project: &Project| {
+            let test_dependencies = project.toml().test_dependencies.clone().unwrap();
+            let busted = test_dependencies
+                .iter()
+                .find(|dep| dep.name() == &"busted".into())
+                .unwrap();
+            assert_eq!(busted.version_req(), &"==2.2.0-1".parse().unwrap());
+            let dependencies = project.toml().dependencies.clone().unwrap().default;
+            assert_eq!(dependencies.len(), 1);
+            let lua_cjson = dependencies.first().unwrap();
+            assert_eq!(lua_cjson.name(), &"lua-cjson".into());
+            assert_eq!(lua_cjson.version_req(), &">=2.1.0".parse().unwrap());
+        };
+        check(&project);
+        // check again after reloading lux.toml
+        let reloaded_project = Project::from(&project_root).unwrap().unwrap();
+        check(&reloaded_project);
+
+        // Syncing again doesn't change anything
+        let toml_content = std::fs::read_to_string(&toml_path).unwrap();
+        project.sync_toml_from_lockfile(&config).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&toml_path).unwrap(), toml_content);
+    }
+
+    #[tokio::test]
+    async fn test_sync_toml_from_lockfile_platform_dependencies() {
+        let sample_project: PathBuf = "resources/test/sample-projects/busted-with-lockfile/".into();
+        let project_root = assert_fs::TempDir::new().unwrap();
+        project_root.copy_from(&sample_project, &["**"]).unwrap();
+        let project_root: PathBuf = project_root.path().into();
+        // Turn the locked test dependencies into regular dependencies,
+        // which can be declared per platform.
+        let lockfile_path = project_root.join("lux.lock");
+        let lockfile_content = std::fs::read_to_string(&lockfile_path)
+            .unwrap()
+            .replace("\"test_dependencies\"", "\"dependencies\"");
+        std::fs::write(&lockfile_path, lockfile_content).unwrap();
+        let toml_path = project_root.join("lux.toml");
+        let current_platform = PlatformIdentifier::default();
+        let other_platform = if current_platform == PlatformIdentifier::Windows {
+            PlatformIdentifier::Linux
+        } else {
+            PlatformIdentifier::Windows
+        };
+        let toml_content = std::fs::read_to_string(&toml_path).unwrap()
+            + &format!("\n[dependencies.platform.{other_platform}]\nbusted = \">=2.0.0\"\n");
+        std::fs::write(&toml_path, &toml_content).unwrap();
+
+        // busted is already declared for the configured target platform
+        let other_target = if other_platform == PlatformIdentifier::Windows {
+            "x86_64-pc-windows-msvc"
+        } else {
+            "x86_64-unknown-linux-gnu"
+        };
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .target_platform(Some(other_target.parse().unwrap()))
+            .build()
+            .unwrap();
+        let mut project = Project::from(&project_root).unwrap().unwrap();
+        project.sync_toml_from_lockfile(&config).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&toml_path).unwrap(), toml_content);
+
+        let config = ConfigBuilder::new().unwrap().build().unwrap();
+        project.sync_toml_from_lockfile(&config).await.unwrap();
+
+        let dependencies = project.toml().dependencies.clone().unwrap();
+        assert!(dependencies.default.is_empty());
+        let busted = dependencies
+            .get(&current_platform)
+            .iter()
+            .find(|dep| dep.name() == &"busted".into())
+            .unwrap();
+        assert_eq!(busted.version_req(), &"==2.2.0-1".parse().unwrap());
+        let busted = dependencies
+            .get(&other_platform)
+            .iter()
+            .find(|dep| dep.name() == &"busted".into())
+            .unwrap();
+        assert_eq!(busted.version_req(), &">=2.0.0".parse().unwrap());
+    }
+
     #[tokio::test]
     async fn test_extra_rockspec_parsing() {
         let sample_project: PathBuf = "resources/test/sample-projects/extra-rockspec/".into();
@@ -988,6 +1564,7 @@ mod tests {
                     .dependencies
                     .clone()
                     .unwrap_or_default()
+                    .default
                     .iter()
                     .any(|dep| dep.name() == name && dep.pin == pin));
             }
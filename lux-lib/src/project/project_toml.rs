@@ -16,6 +16,7 @@ use crate::package::PackageNameList;
 use crate::package::SpecRev;
 use crate::rockspec::lua_dependency::LuaDependencySpec;
 use std::io;
+use std::sync::OnceLock;
 use std::{collections::HashMap, path::PathBuf};
 
 use itertools::Itertools;
@@ -26,15 +27,16 @@ use serde::de;
 use serde::{Deserialize, Deserializer};
 use ssri::Integrity;
 use thiserror::Error;
+use url::Url;
 
 use crate::{
     config::{Config, LuaVersion},
     lua_rockspec::{
-        BuildSpec, BuildSpecInternal, BuildSpecInternalError, DisplayAsLuaKV, ExternalDependencies,
-        ExternalDependencySpec, FromPlatformOverridable, LuaVersionError, PartialLuaRockspec,
-        PerPlatform, PlatformIdentifier, PlatformSupport, PlatformValidationError,
-        RemoteRockSource, RockDescription, RockSourceError, RockspecFormat, TestSpec,
-        TestSpecDecodeError, TestSpecInternal,
+        BuildSpec, BuildSpecInternal, BuildSpecInternalError, DisplayAsLuaKV, DisplayLuaKV,
+        DisplayLuaValue, ExternalDependencies, ExternalDependencySpec, FromPlatformOverridable,
+        LuaVersionError, PartialLuaRockspec, PerPlatform, PlatformIdentifier, PlatformSupport,
+        PlatformValidationError, RemoteRockSource, RockDescription, RockSourceError,
+        RockspecFormat, TestSpec, TestSpecDecodeError, TestSpecInternal,
     },
     package::{
         BuildDependencies, Dependencies, PackageName, PackageReq, PackageVersion,
@@ -70,6 +72,9 @@ struct DependencyTableEntry {
     git: Option<GitUrlShorthand>,
     #[serde(default)]
     rev: Option<String>,
+    /// URL of the rockspec to install the dependency from.
+    #[serde(default)]
+    rockspec: Option<String>,
 }
 
 fn parse_map_to_dependency_vec_opt<'de, D>(
@@ -81,52 +86,170 @@ where
     let packages: Option<HashMap<PackageName, DependencyEntry>> =
         Option::deserialize(deserializer)?;
 
-    match packages {
+    packages.map(to_dependency_vec).transpose()
+}
+
+/// Top-level `lux.toml` keys whose string values support `${VAR}` interpolation,
+/// if enabled with `[tool] interpolate_env = true`.
+const INTERPOLATED_KEYS: &[&str] = &[
+    "dependencies",
+    "build_dependencies",
+    "test_dependencies",
+    "external_dependencies",
+];
+
+fn interpolate_env_enabled(table: &toml::Table) -> bool {
+    table
+        .get("tool")
+        .and_then(|tool| tool.get("interpolate_env"))
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false)
+}
+
+fn interpolate_env_values<E>(value: &mut toml::Value, key_path: &str) -> Result<(), E>
+where
+    E: de::Error,
+{
+    match value {
+        toml::Value::String(str) => {
+            *str = interpolate_env(str)
+                .map_err(|err| E::custom(format!("error interpolating `{key_path}`: {err}")))?;
+        }
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                interpolate_env_values(value, &format!("{key_path}[{i}]"))?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                interpolate_env_values(value, &format!("{key_path}.{key}"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expand `${VAR}` and `${VAR:-fallback}` with the values of environment variables.
+/// As in POSIX shells, the fallback is also used if `VAR` is set, but empty.
+fn interpolate_env(str: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(str.len());
+    let mut rest = str;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let expr = &rest[start + 2..];
+        let end = expr
+            .find('}')
+            .ok_or_else(|| "unterminated `${`".to_string())?;
+        let (var, default) = match expr[..end].split_once(":-") {
+            Some((var, default)) => (var, Some(default)),
+            None => (&expr[..end], None),
+        };
+        if var.is_empty() || !var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid environment variable name `{var}`"));
+        }
+        match (std::env::var(var).ok(), default) {
+            (Some(value), Some(default)) if value.is_empty() => result.push_str(default),
+            (Some(value), _) => result.push_str(&value),
+            (None, Some(default)) => result.push_str(default),
+            (None, None) => return Err(format!("environment variable `{var}` is not set")),
+        }
+        rest = &expr[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Dependencies, with optional platform-specific dependencies,
+/// e.g. `[dependencies.platform.linux]`.
+#[derive(Deserialize)]
+struct PerPlatformDependencyEntries {
+    #[serde(default)]
+    platform: HashMap<PlatformIdentifier, HashMap<PackageName, DependencyEntry>>,
+    #[serde(flatten)]
+    default: HashMap<PackageName, DependencyEntry>,
+}
+
+fn parse_map_to_per_platform_dependencies_opt<'de, D>(
+    deserializer: D,
+) -> Result<Option<PerPlatform<Vec<LuaDependencySpec>>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries: Option<PerPlatformDependencyEntries> = Option::deserialize(deserializer)?;
+
+    match entries {
         None => Ok(None),
-        Some(packages) => Ok(Some(
-            packages
+        Some(entries) => {
+            let default = to_dependency_vec(entries.default)?;
+            let per_platform = entries
+                .platform
                 .into_iter()
-                .map(|(name, spec)| match spec {
-                    DependencyEntry::Simple(version_req) => {
-                        Ok(PackageReq { name, version_req }.into())
-                    }
-                    DependencyEntry::Detailed(entry) => {
-                        let source = match (entry.git, entry.rev) {
-                            (None, None) => Ok(None),
-                            (None, Some(_)) => Err(de::Error::custom(format!(
-                                "dependency {} specifies a 'rev', but missing a 'git' field",
-                                &name
-                            ))),
-                            (Some(git), Some(rev)) => Ok(Some(RockSourceSpec::Git(GitSource {
-                                url: git.into(),
-                                checkout_ref: Some(rev),
-                            }))),
-                            (Some(git), None) => Ok(Some(RockSourceSpec::Git(GitSource {
-                                url: git.into(),
-                                checkout_ref: Some(
-                                    entry
-                                        .version
-                                        .clone()
-                                        .to_string()
-                                        .trim_start_matches("=")
-                                        .to_string(),
-                                ),
-                            }))),
-                        }?;
-                        Ok(LuaDependencySpec {
-                            package_req: PackageReq {
-                                name,
-                                version_req: entry.version,
-                            },
-                            opt: OptState::from(entry.opt.unwrap_or(false)),
-                            pin: PinnedState::from(entry.pin.unwrap_or(false)),
-                            source,
+                .map(|(platform, packages)| Ok((platform, to_dependency_vec(packages)?)))
+                .try_collect::<_, _, D::Error>()?;
+            let Ok(dependencies) = PerPlatform::with_overrides(default, per_platform);
+            Ok(Some(dependencies))
+        }
+    }
+}
+
+fn to_dependency_vec<E>(
+    packages: HashMap<PackageName, DependencyEntry>,
+) -> Result<Vec<LuaDependencySpec>, E>
+where
+    E: de::Error,
+{
+    packages
+        .into_iter()
+        .map(|(name, spec)| match spec {
+            DependencyEntry::Simple(version_req) => Ok(PackageReq { name, version_req }.into()),
+            DependencyEntry::Detailed(entry) => {
+                let source = match (entry.git, entry.rev) {
+                    (None, None) => Ok(None),
+                    (None, Some(_)) => Err(E::custom(format!(
+                        "dependency {} specifies a 'rev', but missing a 'git' field",
+                        &name
+                    ))),
+                    (Some(git), Some(rev)) => Ok(Some(RockSourceSpec::Git(GitSource {
+                        url: git.into(),
+                        checkout_ref: Some(rev),
+                    }))),
+                    (Some(git), None) => Ok(Some(RockSourceSpec::Git(GitSource {
+                        url: git.into(),
+                        checkout_ref: Some(
+                            entry
+                                .version
+                                .clone()
+                                .to_string()
+                                .trim_start_matches("=")
+                                .to_string(),
+                        ),
+                    }))),
+                }?;
+                let rockspec_url = entry
+                    .rockspec
+                    .map(|url| {
+                        Url::parse(&url).map_err(|err| {
+                            E::custom(format!(
+                                "dependency {} has an invalid 'rockspec' URL {}: {}",
+                                &name, url, err
+                            ))
                         })
-                    }
+                    })
+                    .transpose()?;
+                Ok(LuaDependencySpec {
+                    package_req: PackageReq {
+                        name,
+                        version_req: entry.version,
+                    },
+                    opt: OptState::from(entry.opt.unwrap_or(false)),
+                    pin: PinnedState::from(entry.pin.unwrap_or(false)),
+                    source,
+                    rockspec_url,
                 })
-                .try_collect()?,
-        )),
-    }
+            }
+        })
+        .try_collect()
 }
 
 #[derive(Debug, Error)]
@@ -194,8 +317,11 @@ pub struct PartialProjectToml {
     pub(crate) description: Option<RockDescription>,
     #[serde(default)]
     pub(crate) supported_platforms: Option<HashMap<PlatformIdentifier, bool>>,
-    #[serde(default, deserialize_with = "parse_map_to_dependency_vec_opt")]
-    pub(crate) dependencies: Option<Vec<LuaDependencySpec>>,
+    #[serde(
+        default,
+        deserialize_with = "parse_map_to_per_platform_dependencies_opt"
+    )]
+    pub(crate) dependencies: Option<PerPlatform<Vec<LuaDependencySpec>>>,
     #[serde(default, deserialize_with = "parse_map_to_dependency_vec_opt")]
     pub(crate) build_dependencies: Option<Vec<LuaDependencySpec>>,
     #[serde(default)]
@@ -208,10 +334,17 @@ pub struct PartialProjectToml {
     pub(crate) test: Option<TestSpecInternal>,
     #[serde(default)]
     pub(crate) deploy: Option<DeploySpec>,
+    #[serde(default)]
+    pub(crate) lua_ls: Option<LuaLsSpec>,
 
     /// Used to bind the project TOML to a project root
     #[serde(skip, default = "ProjectRoot::new")]
     pub(crate) project_root: ProjectRoot,
+
+    /// The project TOML before environment variable interpolation, if enabled.
+    /// Generated rockspecs are based on this, so that secrets don't leak into them.
+    #[serde(skip)]
+    raw_template: Option<Box<PartialProjectToml>>,
 }
 
 impl UserData for PartialProjectToml {
@@ -240,9 +373,23 @@ impl HasIntegrity for PartialProjectToml {
 
 impl PartialProjectToml {
     pub(crate) fn new(str: &str, project_root: ProjectRoot) -> Result<Self, toml::de::Error> {
+        let raw_template = Self {
+            project_root: project_root.clone(),
+            ..toml::from_str(str)?
+        };
+        let mut table: toml::Table = toml::from_str(str)?;
+        if !interpolate_env_enabled(&table) {
+            return Ok(raw_template);
+        }
+        for key in INTERPOLATED_KEYS {
+            if let Some(value) = table.get_mut(*key) {
+                interpolate_env_values(value, key)?;
+            }
+        }
         Ok(Self {
             project_root,
-            ..toml::from_str(str)?
+            raw_template: Some(Box::new(raw_template)),
+            ..table.try_into()?
         })
     }
 
@@ -252,11 +399,12 @@ impl PartialProjectToml {
         let project_toml = self.clone();
 
         // Disallow `lua` to be part of the `dependencies` field
-        if project_toml
-            .dependencies
-            .as_ref()
-            .is_some_and(|deps| deps.iter().any(|dep| dep.name() == &"lua".into()))
-        {
+        if project_toml.dependencies.as_ref().is_some_and(|deps| {
+            std::iter::once(&deps.default)
+                .chain(deps.per_platform.values())
+                .flatten()
+                .any(|dep| dep.name() == &"lua".into())
+        }) {
             return Err(LocalProjectTomlValidationError::DependenciesContainLua);
         }
 
@@ -271,7 +419,8 @@ impl PartialProjectToml {
                 })
                 .collect_vec()
         };
-        let duplicate_dependencies = get_duplicates(&self.dependencies);
+        let duplicate_dependencies =
+            get_duplicates(&self.dependencies.as_ref().map(|deps| deps.default.clone()));
         if !duplicate_dependencies.is_empty() {
             return Err(LocalProjectTomlValidationError::DuplicateDependencies(
                 PackageNameList::new(duplicate_dependencies),
@@ -319,7 +468,7 @@ impl PartialProjectToml {
             )?,
             // Merge dependencies internally with lua version
             // so the output of `dependencies()` is consistent
-            dependencies: PerPlatform::new(project_toml.dependencies.unwrap_or_default()),
+            dependencies: project_toml.dependencies.unwrap_or_default(),
             build_dependencies: PerPlatform::new(
                 project_toml.build_dependencies.unwrap_or_default(),
             ),
@@ -374,6 +523,9 @@ impl PartialProjectToml {
         &self,
         specrev: Option<SpecRev>,
     ) -> Result<RemoteProjectToml, RemoteProjectTomlValidationError> {
+        if let Some(raw_template) = &self.raw_template {
+            return raw_template.into_remote(specrev);
+        }
         let version = self
             .version_template
             .try_generate(&self.project_root, specrev)?;
@@ -390,7 +542,11 @@ impl PartialProjectToml {
         let mut local = self.into_local()?;
         local.version = version;
 
-        let validated = RemoteProjectToml { source, local };
+        let validated = RemoteProjectToml {
+            source,
+            local,
+            content_hash: OnceLock::new(),
+        };
 
         Ok(validated)
     }
@@ -406,52 +562,120 @@ impl PartialProjectToml {
         self.version_template.try_generate(&self.project_root, None)
     }
 
+    /// Whether to generate a `.luarc.json` for this project.
+    /// If unset, the global `Config` value is used.
+    pub(crate) fn generate_luarc(&self) -> Option<bool> {
+        self.lua_ls
+            .as_ref()
+            .and_then(|lua_ls| lua_ls.generate_luarc)
+    }
+
     /// Merge the `ProjectToml` struct with an unvalidated `LuaRockspec`.
     /// The final merged struct can then be validated.
-    pub fn merge(self, other: PartialLuaRockspec) -> Self {
+    ///
+    /// `precedence` decides which value is kept for fields that are set in both.
+    /// Fields that are only set in one of them are always kept.
+    pub fn merge(self, other: PartialLuaRockspec, precedence: MergePrecedence) -> Self {
+        let raw_template = self
+            .raw_template
+            .map(|raw_template| Box::new(raw_template.merge(other.clone(), precedence)));
+        let other_lua = other.dependencies.as_ref().and_then(|deps| {
+            deps.iter()
+                .find(|dep| dep.name() == &"lua".into())
+                .and_then(|dep| {
+                    if dep.version_req().is_any() {
+                        None
+                    } else {
+                        Some(dep.version_req().clone())
+                    }
+                })
+        });
+        let other_dependencies = other.dependencies.map(|deps| {
+            PerPlatform::new(
+                deps.into_iter()
+                    .filter(|dep| dep.name() != &"lua".into())
+                    .collect(),
+            )
+        });
+        // The `package` is required in the `lux.toml`, and the `build` has a default,
+        // so we can't tell whether it was set explicitly, unless it differs from the default.
+        let (package, build) = match precedence {
+            MergePrecedence::ExtraOverrides => (
+                other.package.unwrap_or(self.package),
+                other.build.unwrap_or(self.build),
+            ),
+            MergePrecedence::TomlOverrides => (
+                self.package,
+                if self.build == BuildSpecInternal::default() {
+                    other.build.unwrap_or(self.build)
+                } else {
+                    self.build
+                },
+            ),
+        };
         PartialProjectToml {
-            package: other.package.unwrap_or(self.package),
+            package,
             version_template: self.version_template,
-            lua: other
-                .dependencies
-                .as_ref()
-                .and_then(|deps| {
-                    deps.iter()
-                        .find(|dep| dep.name() == &"lua".into())
-                        .and_then(|dep| {
-                            if dep.version_req().is_any() {
-                                None
-                            } else {
-                                Some(dep.version_req().clone())
-                            }
-                        })
-                })
-                .or(self.lua),
-            build: other.build.unwrap_or(self.build),
+            lua: precedence.pick(self.lua, other_lua),
+            build,
             run: self.run,
-            description: other.description.or(self.description),
-            supported_platforms: other
-                .supported_platforms
-                .map(|platform_support| platform_support.platforms().clone())
-                .or(self.supported_platforms),
-            dependencies: other
-                .dependencies
-                .map(|deps| {
-                    deps.into_iter()
-                        .filter(|dep| dep.name() != &"lua".into())
-                        .collect()
-                })
-                .or(self.dependencies),
-            build_dependencies: other.build_dependencies.or(self.build_dependencies),
-            test_dependencies: other.test_dependencies.or(self.test_dependencies),
-            external_dependencies: other.external_dependencies.or(self.external_dependencies),
+            description: precedence.pick(self.description, other.description),
+            supported_platforms: precedence.pick(
+                self.supported_platforms,
+                other
+                    .supported_platforms
+                    .map(|platform_support| platform_support.platforms().clone()),
+            ),
+            dependencies: precedence.pick(self.dependencies, other_dependencies),
+            build_dependencies: precedence.pick(self.build_dependencies, other.build_dependencies),
+            test_dependencies: precedence.pick(self.test_dependencies, other.test_dependencies),
+            external_dependencies: precedence
+                .pick(self.external_dependencies, other.external_dependencies),
             source_template: self.source_template,
-            test: other.test.or(self.test),
-            deploy: other.deploy.or(self.deploy),
-            rockspec_format: other.rockspec_format.or(self.rockspec_format),
+            test: precedence.pick(self.test, other.test),
+            deploy: precedence.pick(self.deploy, other.deploy),
+            rockspec_format: precedence.pick(self.rockspec_format, other.rockspec_format),
+            lua_ls: self.lua_ls,
 
             // Keep the project root the same, as it is not part of the lua rockspec
             project_root: self.project_root,
+            raw_template,
+        }
+    }
+
+    /// The Lua version allowed by the `lua` constraint, if it only allows one,
+    /// e.g. `lua = "==5.1"`.
+    fn single_lua_version(&self) -> Option<LuaVersion> {
+        let lua = self.lua.as_ref()?;
+        [
+            LuaVersion::Lua51,
+            LuaVersion::Lua52,
+            LuaVersion::Lua53,
+            LuaVersion::Lua54,
+        ]
+        .into_iter()
+        .filter(|version| lua.matches(&version.as_version()))
+        .exactly_one()
+        .ok()
+    }
+}
+
+/// Which value [`PartialProjectToml::merge`] keeps for fields that are set
+/// in both the `lux.toml` and the `extra.rockspec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePrecedence {
+    /// Fields in the `extra.rockspec` override those in the `lux.toml`.
+    /// This is what [`Project`](crate::project::Project) uses.
+    ExtraOverrides,
+    /// Fields in the `lux.toml` override those in the `extra.rockspec`.
+    TomlOverrides,
+}
+
+impl MergePrecedence {
+    fn pick<T>(self, toml: Option<T>, extra: Option<T>) -> Option<T> {
+        match self {
+            Self::ExtraOverrides => extra.or(toml),
+            Self::TomlOverrides => toml.or(extra),
         }
     }
 }
@@ -479,19 +703,25 @@ impl LuaVersionCompatibility for PartialProjectToml {
         Ok(())
     }
 
+    /// The configured Lua version, if the `lua` constraint allows it.
+    /// Otherwise, the only Lua version that the `lua` constraint allows, if there is one.
     fn lua_version_matches(&self, config: &Config) -> Result<LuaVersion, LuaVersionError> {
-        let version = LuaVersion::from(config)?.clone();
-        if self.supports_lua_version(&version) {
-            Ok(version)
-        } else {
-            Err(LuaVersionError::LuaVersionUnsupported(
-                version,
-                self.package.clone(),
-                self.version_template
-                    .try_generate(&self.project_root, None)
-                    .unwrap_or(PackageVersion::default_dev_version()),
-            ))
+        let configured = config.resolve_lua_version(None);
+        if let Ok(version) = &configured {
+            if self.supports_lua_version(version) {
+                return Ok(version.clone());
+            }
         }
+        if let Some(version) = self.single_lua_version() {
+            return Ok(version);
+        }
+        Err(LuaVersionError::LuaVersionUnsupported(
+            configured?,
+            self.package.clone(),
+            self.version_template
+                .try_generate(&self.project_root, None)
+                .unwrap_or(PackageVersion::default_dev_version()),
+        ))
     }
 
     fn supports_lua_version(&self, lua_version: &LuaVersion) -> bool {
@@ -528,6 +758,14 @@ pub struct RunSpec {
     pub(crate) args: Option<NonEmpty<String>>,
 }
 
+/// Project-specific settings for the lua-language-server integration.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LuaLsSpec {
+    /// Whether to generate a `.luarc.json` with the project's dependencies.
+    /// Overrides the global `generate_luarc` config setting.
+    pub(crate) generate_luarc: Option<bool>,
+}
+
 /// The `lux.toml` file, after being properly deserialized.
 /// This struct may be used to build a local version of a project.
 /// To build a rockspec, use `RemoteProjectToml`.
@@ -563,34 +801,13 @@ impl LocalProjectToml {
     /// Convert this project TOML to a Lua rockspec.
     /// Fails if there is no valid project root or if there are off-spec dependencies.
     pub fn to_lua_rockspec(&self) -> Result<LocalLuaRockspec, LuaRockspecError> {
-        if let Some(dep) = self
-            .dependencies()
-            .per_platform
-            .iter()
-            .filter_map(|(_, deps)| deps.iter().find(|dep| dep.source().is_some()))
-            .collect_vec()
-            .first()
-        {
+        if let Some(dep) = find_off_spec_dependency(self.dependencies()) {
             return Err(LuaRockspecError::OffSpecDependency(dep.name().clone()));
         }
-        if let Some(dep) = self
-            .build_dependencies()
-            .per_platform
-            .iter()
-            .filter_map(|(_, deps)| deps.iter().find(|dep| dep.source().is_some()))
-            .collect_vec()
-            .first()
-        {
+        if let Some(dep) = find_off_spec_dependency(self.build_dependencies()) {
             return Err(LuaRockspecError::OffSpecBuildDependency(dep.name().clone()));
         }
-        if let Some(dep) = self
-            .test_dependencies()
-            .per_platform
-            .iter()
-            .filter_map(|(_, deps)| deps.iter().find(|dep| dep.source().is_some()))
-            .collect_vec()
-            .first()
-        {
+        if let Some(dep) = find_off_spec_dependency(self.test_dependencies()) {
             return Err(LuaRockspecError::OffSpecTestDependency(dep.name().clone()));
         }
         LocalLuaRockspec::new(
@@ -600,6 +817,17 @@ impl LocalProjectToml {
     }
 }
 
+/// Find a dependency with a source that can't be represented in a Lua rockspec,
+/// including platform-specific dependencies.
+fn find_off_spec_dependency(
+    dependencies: &PerPlatform<Vec<LuaDependencySpec>>,
+) -> Option<&LuaDependencySpec> {
+    std::iter::once(&dependencies.default)
+        .chain(dependencies.per_platform.values())
+        .flatten()
+        .find(|dep| dep.source().is_some())
+}
+
 impl Rockspec for LocalProjectToml {
     type Error = ProjectTomlError;
 
@@ -701,18 +929,7 @@ version = "{}""#,
             template.push(self.supported_platforms.display_lua());
         }
 
-        {
-            let mut dependencies = self.internal.dependencies.clone().unwrap_or_default();
-            dependencies.insert(
-                0,
-                PackageReq {
-                    name: "lua".into(),
-                    version_req: self.lua.clone(),
-                }
-                .into(),
-            );
-            template.push(Dependencies(&dependencies).display_lua());
-        }
+        template.push(display_dependencies(&self.lua, &self.internal.dependencies));
 
         let mut build_dependencies = self
             .internal
@@ -798,6 +1015,10 @@ impl HasIntegrity for LocalProjectToml {
 pub struct RemoteProjectToml {
     local: LocalProjectToml,
     source: PerPlatform<RemoteRockSource>,
+    /// Memoized `content_hash`.
+    /// The Lua rockspec is generated from the `lux.toml`, so mutating the `Rockspec`
+    /// doesn't invalidate it.
+    content_hash: OnceLock<Integrity>,
 }
 
 impl RemoteProjectToml {
@@ -881,6 +1102,14 @@ impl Rockspec for RemoteProjectToml {
         self.local.deploy_mut()
     }
 
+    fn content_hash(&self) -> Result<Integrity, Self::Error> {
+        if let Some(content_hash) = self.content_hash.get() {
+            return Ok(content_hash.clone());
+        }
+        let content_hash = Integrity::from(&self.to_lua_remote_rockspec_string()?);
+        Ok(self.content_hash.get_or_init(|| content_hash).clone())
+    }
+
     fn to_lua_remote_rockspec_string(&self) -> Result<String, Self::Error> {
         let project_root = &self.local.internal.project_root;
         let starter = format!(
@@ -903,18 +1132,10 @@ version = "{}""#,
             template.push(self.local.supported_platforms.display_lua());
         }
 
-        {
-            let mut dependencies = self.local.internal.dependencies.clone().unwrap_or_default();
-            dependencies.insert(
-                0,
-                PackageReq {
-                    name: "lua".into(),
-                    version_req: self.local.lua.clone(),
-                }
-                .into(),
-            );
-            template.push(Dependencies(&dependencies).display_lua());
-        }
+        template.push(display_dependencies(
+            &self.local.lua,
+            &self.local.internal.dependencies,
+        ));
 
         let mut build_dependencies = self
             .local
@@ -989,9 +1210,7 @@ version = "{}""#,
 
 impl HasIntegrity for RemoteProjectToml {
     fn hash(&self) -> io::Result<Integrity> {
-        self.to_lua_rockspec()
-            .expect("unable to convert remote project to rockspec")
-            .hash()
+        self.content_hash().map_err(io::Error::other)
     }
 }
 
@@ -1067,6 +1286,51 @@ impl UserData for RemoteProjectToml {
     }
 }
 
+/// Display the rockspec `dependencies`, including the `lua` dependency
+/// and the platform-specific dependencies.
+fn display_dependencies(
+    lua: &PackageVersionReq,
+    dependencies: &Option<PerPlatform<Vec<LuaDependencySpec>>>,
+) -> DisplayLuaKV {
+    let dependencies = dependencies.clone().unwrap_or_default();
+    let default = std::iter::once(
+        PackageReq {
+            name: "lua".into(),
+            version_req: lua.clone(),
+        }
+        .into(),
+    )
+    .chain(dependencies.default.iter().cloned())
+    .collect_vec();
+    let platforms = dependencies
+        .per_platform
+        .iter()
+        .sorted_by_key(|(platform, _)| platform.to_string())
+        .map(|(platform, deps)| DisplayLuaKV {
+            key: platform.to_string(),
+            // Only display the overrides, as they are merged with the base dependencies.
+            value: DisplayLuaValue::List(
+                deps.iter()
+                    .filter(|dep| !dependencies.default.contains(dep))
+                    .map(|dep| DisplayLuaValue::String(dep.to_string()))
+                    .collect(),
+            ),
+        })
+        .collect_vec();
+    let mut display = Dependencies(&default).display_lua();
+    display.value = match display.value {
+        DisplayLuaValue::List(items) if !platforms.is_empty() => DisplayLuaValue::ListWithEntries(
+            items,
+            vec![DisplayLuaKV {
+                key: "platforms".into(),
+                value: DisplayLuaValue::Table(platforms),
+            }],
+        ),
+        value => value,
+    };
+    display
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
@@ -1074,16 +1338,24 @@ mod tests {
     use assert_fs::prelude::{PathChild, PathCopy, PathCreateDir};
     use git2::{Repository, RepositoryInitOptions};
     use git_url_parse::GitUrl;
+    use ssri::Integrity;
     use url::Url;
 
     use crate::{
         git::GitSource,
-        lua_rockspec::{PartialLuaRockspec, PerPlatform, RemoteLuaRockspec, RockSourceSpec},
+        hash::HasIntegrity,
+        lua_rockspec::{
+            LuaRockspecError, PartialLuaRockspec, PerPlatform, PlatformIdentifier,
+            RemoteLuaRockspec, RockSourceSpec,
+        },
+        package::PackageName,
         project::{Project, ProjectRoot},
         rockspec::{lua_dependency::LuaDependencySpec, Rockspec},
     };
 
-    use super::PartialProjectToml;
+    use serial_test::serial;
+
+    use super::{MergePrecedence, PartialProjectToml};
 
     #[test]
     fn project_toml_parsing() {
@@ -1363,6 +1635,83 @@ mod tests {
         assert_eq!(rockspec.format(), expected_rockspec.format());
     }
 
+    #[test]
+    fn merge_precedence() {
+        let project_toml = r#"
+        package = "my-package"
+        version = "1.0.0"
+
+        [description]
+        summary = "From lux.toml"
+
+        [dependencies]
+        foo = "1.0"
+
+        [build]
+        type = "builtin"
+        "#;
+
+        let extra_rockspec_content = r#"
+            package = "my-package-overwritten"
+
+            description = {
+                summary = "From extra.rockspec",
+            }
+
+            dependencies = {
+                "bar >=2.0",
+            }
+
+            test_dependencies = {
+                "busted >1.0",
+            }
+
+            build = {
+                type = "make",
+            }
+        "#;
+
+        let project_toml = PartialProjectToml::new(project_toml, ProjectRoot::default()).unwrap();
+        let dependency_names = |merged: &PartialProjectToml| {
+            merged
+                .dependencies
+                .as_ref()
+                .unwrap()
+                .current_platform()
+                .iter()
+                .map(|dep| dep.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let extra_rockspec = PartialLuaRockspec::new(extra_rockspec_content).unwrap();
+        let extra_build = extra_rockspec.build.clone().unwrap();
+        let merged = project_toml
+            .clone()
+            .merge(extra_rockspec, MergePrecedence::ExtraOverrides);
+        assert_eq!(merged.package().to_string(), "my-package-overwritten");
+        assert_eq!(
+            merged.description.as_ref().unwrap().summary.as_deref(),
+            Some("From extra.rockspec")
+        );
+        assert_eq!(dependency_names(&merged), vec!["bar"]);
+        assert_eq!(merged.test_dependencies.as_ref().unwrap().len(), 1);
+        assert_eq!(merged.build, extra_build);
+
+        let extra_rockspec = PartialLuaRockspec::new(extra_rockspec_content).unwrap();
+        let merged = project_toml
+            .clone()
+            .merge(extra_rockspec, MergePrecedence::TomlOverrides);
+        assert_eq!(merged.package().to_string(), "my-package");
+        assert_eq!(
+            merged.description.as_ref().unwrap().summary.as_deref(),
+            Some("From lux.toml")
+        );
+        assert_eq!(dependency_names(&merged), vec!["foo"]);
+        // Fields that are only set in the extra.rockspec are still merged
+        assert_eq!(merged.test_dependencies.as_ref().unwrap().len(), 1);
+        assert_eq!(merged.build, project_toml.build);
+    }
+
     #[test]
     fn merge_project_toml_with_partial_rockspec() {
         let project_toml = r#"
@@ -1485,7 +1834,7 @@ mod tests {
         let expected_rockspec = RemoteLuaRockspec::new(&remote_rockspec_content).unwrap();
 
         let merged = project_toml
-            .merge(partial_rockspec)
+            .merge(partial_rockspec, MergePrecedence::ExtraOverrides)
             .into_remote(None)
             .unwrap();
 
@@ -1546,6 +1895,242 @@ mod tests {
             .unwrap_err();
     }
 
+    #[test]
+    fn project_toml_platform_dependencies() {
+        let project_toml = r#"
+        package = "my-package"
+        version = "1.0.0"
+        lua = "5.1"
+
+        [source]
+        url = "https://example.com"
+
+        [dependencies]
+        foo = "1.0"
+
+        [dependencies.platform.linux]
+        bar = "2.0"
+
+        [build]
+        type = "builtin"
+        "#;
+
+        let project_toml = PartialProjectToml::new(project_toml, ProjectRoot::default()).unwrap();
+        let has_dependency = |dependencies: &Vec<LuaDependencySpec>, name: &str| {
+            dependencies
+                .iter()
+                .any(|dep| dep.name() == &PackageName::new(name.into()))
+        };
+
+        let local = project_toml.into_local().unwrap();
+        let linux_dependencies = local.dependencies().get(&PlatformIdentifier::Linux);
+        assert!(has_dependency(linux_dependencies, "foo"));
+        assert!(has_dependency(linux_dependencies, "bar"));
+        let windows_dependencies = local.dependencies().get(&PlatformIdentifier::Windows);
+        assert!(has_dependency(windows_dependencies, "foo"));
+        assert!(!has_dependency(windows_dependencies, "bar"));
+
+        // The platform-specific dependencies are carried over to the generated rockspec
+        let rockspec = project_toml
+            .into_remote(None)
+            .unwrap()
+            .to_lua_rockspec()
+            .unwrap();
+        assert!(has_dependency(
+            rockspec.dependencies().get(&PlatformIdentifier::Linux),
+            "bar"
+        ));
+        assert!(!has_dependency(
+            rockspec.dependencies().get(&PlatformIdentifier::Windows),
+            "bar"
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn project_toml_env_interpolation() {
+        std::env::set_var("LUX_TEST_INTERPOLATE_TOKEN", "secret");
+        std::env::remove_var("LUX_TEST_INTERPOLATE_UNSET");
+        let project_toml = |interpolate_env: bool, library: &str| {
+            format!(
+                r#"
+        package = "my-package"
+        version = "1.0.0"
+        lua = "5.1"
+
+        [dependencies.foo]
+        version = "1.0"
+        rockspec = "https://${{LUX_TEST_INTERPOLATE_TOKEN}}@example.com/foo-1.0-1.rockspec"
+
+        [external_dependencies.FOO]
+        library = "{library}"
+
+        [source]
+        url = "https://example.com"
+
+        [build]
+        type = "builtin"
+
+        [tool]
+        interpolate_env = {interpolate_env}
+        "#
+            )
+        };
+        let external_library = |project_toml: &PartialProjectToml| {
+            project_toml.external_dependencies.as_ref().unwrap()["FOO"]
+                .library
+                .clone()
+                .unwrap()
+        };
+
+        let toml = project_toml(true, "${LUX_TEST_INTERPOLATE_UNSET:-/usr/lib}/libfoo.so");
+        let project_toml_interpolated =
+            PartialProjectToml::new(&toml, ProjectRoot::default()).unwrap();
+        assert_eq!(
+            project_toml_interpolated
+                .dependencies
+                .as_ref()
+                .unwrap()
+                .current_platform()[0]
+                .rockspec_url()
+                .unwrap()
+                .as_str(),
+            "https://secret@example.com/foo-1.0-1.rockspec"
+        );
+        assert_eq!(
+            external_library(&project_toml_interpolated),
+            PathBuf::from("/usr/lib/libfoo.so")
+        );
+        // Generated rockspecs keep the templates
+        let rockspec = project_toml_interpolated
+            .into_remote(None)
+            .unwrap()
+            .to_lua_remote_rockspec_string()
+            .unwrap();
+        assert!(rockspec.contains("${LUX_TEST_INTERPOLATE_UNSET:-/usr/lib}/libfoo.so"));
+        assert!(!rockspec.contains("secret"));
+
+        // Interpolation is opt-in
+        let toml = project_toml(false, "${LUX_TEST_INTERPOLATE_UNSET}/libfoo.so");
+        let project_toml_raw = PartialProjectToml::new(&toml, ProjectRoot::default()).unwrap();
+        assert_eq!(
+            external_library(&project_toml_raw),
+            PathBuf::from("${LUX_TEST_INTERPOLATE_UNSET}/libfoo.so")
+        );
+
+        let toml = project_toml(true, "${LUX_TEST_INTERPOLATE_UNSET}/libfoo.so");
+        let err = PartialProjectToml::new(&toml, ProjectRoot::default()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("environment variable `LUX_TEST_INTERPOLATE_UNSET` is not set"));
+    }
+
+    #[test]
+    fn remote_project_toml_content_hash() {
+        let project_toml = r#"
+        package = "my-package"
+        version = "1.0.0"
+        lua = "5.1"
+
+        [source]
+        url = "https://example.com"
+
+        [build]
+        type = "builtin"
+        "#;
+
+        let remote = PartialProjectToml::new(project_toml, ProjectRoot::default())
+            .unwrap()
+            .into_remote(None)
+            .unwrap();
+        let content_hash = remote.content_hash().unwrap();
+        assert_eq!(
+            content_hash,
+            Integrity::from(remote.to_lua_remote_rockspec_string().unwrap())
+        );
+        assert_eq!(remote.content_hash.get(), Some(&content_hash));
+        assert_eq!(remote.hash().unwrap(), content_hash);
+        assert_eq!(
+            remote.to_lua_rockspec().unwrap().hash().unwrap(),
+            content_hash
+        );
+    }
+
+    #[test]
+    fn project_toml_build_and_test_dependencies_with_git_source() {
+        let project_toml = r#"
+        package = "my-package"
+        version = "1.0.0"
+        lua = "5.1"
+
+        [source]
+        url = "https://example.com"
+
+        [build_dependencies.foo]
+        version = "1.0.0"
+        git = "github:owner/foo"
+
+        [test_dependencies.bar]
+        version = "2.0.0"
+        git = "github:owner/bar"
+        rev = "fork"
+
+        [build]
+        type = "builtin"
+        "#;
+
+        let project_toml = PartialProjectToml::new(project_toml, ProjectRoot::default()).unwrap();
+        let local = project_toml.into_local().unwrap();
+        let git_source = |deps: &Vec<LuaDependencySpec>| match deps.first().unwrap().source() {
+            Some(RockSourceSpec::Git(GitSource { url, checkout_ref })) => {
+                (url.to_string(), checkout_ref.clone().unwrap())
+            }
+            source => panic!("expected git source, got {source:?}"),
+        };
+        assert_eq!(
+            git_source(local.build_dependencies().current_platform()),
+            ("https://github.com/owner/foo.git".into(), "1.0.0".into())
+        );
+        assert_eq!(
+            git_source(local.test_dependencies().current_platform()),
+            ("https://github.com/owner/bar.git".into(), "fork".into())
+        );
+
+        // Git sources can't be represented in a Lua rockspec
+        assert!(matches!(
+            local.to_lua_rockspec(),
+            Err(LuaRockspecError::OffSpecBuildDependency(_))
+        ));
+    }
+
+    #[test]
+    fn project_toml_lua_ls_settings() {
+        let project_toml = r#"
+        package = "my-package"
+        version = "1.0.0"
+        lua = "5.1"
+
+        [build]
+        type = "builtin"
+        "#;
+        let project = PartialProjectToml::new(project_toml, ProjectRoot::default()).unwrap();
+        assert_eq!(project.generate_luarc(), None);
+
+        let project_toml = r#"
+        package = "my-package"
+        version = "1.0.0"
+        lua = "5.1"
+
+        [build]
+        type = "builtin"
+
+        [lua_ls]
+        generate_luarc = false
+        "#;
+        let project = PartialProjectToml::new(project_toml, ProjectRoot::default()).unwrap();
+        assert_eq!(project.generate_luarc(), Some(false));
+    }
+
     #[test]
     fn project_toml_with_invalid_run_command() {
         for command in ["lua", "lua5.1", "lua5.2", "lua5.3", "lua5.4", "luajit"] {
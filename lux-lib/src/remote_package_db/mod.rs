@@ -1,14 +1,19 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     config::{Config, ConfigError},
-    lockfile::{LocalPackageLock, LockfileIntegrityError},
+    lockfile::{LocalPackageLock, Lockfile, LockfileIntegrityError, ReadOnly},
     manifest::{Manifest, ManifestError},
     package::{
         PackageName, PackageReq, PackageSpec, PackageVersion, RemotePackage,
         RemotePackageTypeFilterSpec,
     },
     progress::{Progress, ProgressBar},
+    remote_package_source::RemotePackageSource,
 };
 use itertools::Itertools;
 use mlua::{FromLua, UserData};
@@ -19,8 +24,17 @@ pub struct RemotePackageDB(Impl);
 
 #[derive(Clone, Debug)]
 enum Impl {
-    LuarocksManifests(Vec<Manifest>),
+    LuarocksManifests {
+        manifests: Vec<Manifest>,
+        /// See [`Config::rockspec_overlay_dir`].
+        rockspec_overlay_dir: Option<PathBuf>,
+    },
     Lock(LocalPackageLock),
+    /// Packages in the `lock` take precedence over the `fallback` DB.
+    LockWithFallback {
+        lock: LocalPackageLock,
+        fallback: Box<RemotePackageDB>,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -41,6 +55,8 @@ pub enum SearchError {
     RockNotFoundInLockfile(PackageReq),
     #[error("error when pulling manifest: {0}")]
     Manifest(#[from] ManifestError),
+    #[error("error reading overlay rockspec {0}: {1}")]
+    RockspecOverlay(PathBuf, io::Error),
 }
 
 #[derive(Error, Debug)]
@@ -54,6 +70,7 @@ impl RemotePackageDB {
         config: &Config,
         progress: &Progress<ProgressBar>,
     ) -> Result<Self, RemotePackageDBError> {
+        let server = config.select_server().await.clone();
         let mut manifests = Vec::new();
         for server in config.enabled_dev_servers()? {
             let manifest = Manifest::from_config(server, config, progress).await?;
@@ -63,8 +80,28 @@ impl RemotePackageDB {
             let manifest = Manifest::from_config(server.clone(), config, progress).await?;
             manifests.push(manifest);
         }
-        manifests.push(Manifest::from_config(config.server().clone(), config, progress).await?);
-        Ok(Self(Impl::LuarocksManifests(manifests)))
+        manifests.push(Manifest::from_config(server, config, progress).await?);
+        Ok(Self(Impl::LuarocksManifests {
+            manifests,
+            rockspec_overlay_dir: config.rockspec_overlay_dir().cloned(),
+        }))
+    }
+
+    /// Construct a package DB that resolves packages from the sources recorded
+    /// in a lockfile, without contacting a server.
+    /// Searching for a package that isn't in the lockfile fails with
+    /// [`SearchError::RockNotFoundInLockfile`].
+    pub fn from_lockfile(lockfile: &Lockfile<ReadOnly>) -> Self {
+        lockfile.local_pkg_lock().clone().into()
+    }
+
+    /// Construct a package DB that resolves packages from the sources recorded
+    /// in a lock, and searches the `fallback` DB for packages that aren't in the lock.
+    pub(crate) fn from_lock_with_fallback(lock: LocalPackageLock, fallback: Self) -> Self {
+        Self(Impl::LockWithFallback {
+            lock,
+            fallback: Box::new(fallback),
+        })
     }
 
     /// Find a remote package that matches the requirement, returning the latest match.
@@ -75,23 +112,68 @@ impl RemotePackageDB {
         progress: &Progress<ProgressBar>,
     ) -> Result<RemotePackage, SearchError> {
         match &self.0 {
-            Impl::LuarocksManifests(manifests) => match manifests.iter().find_map(|manifest| {
+            Impl::LuarocksManifests {
+                manifests,
+                rockspec_overlay_dir,
+            } => match manifests.iter().find_map(|manifest| {
                 progress.map(|p| p.set_message(format!("🔎 Searching {}", &manifest.server_url())));
                 manifest.find(package_req, filter.clone())
             }) {
-                Some(package) => Ok(package),
+                Some(package) => {
+                    Ok(
+                        find_overlay_rockspec(rockspec_overlay_dir.as_deref(), &package, &filter)?
+                            .unwrap_or(package),
+                    )
+                }
                 None => Err(SearchError::RockNotFound(package_req.clone())),
             },
-            Impl::Lock(lockfile) => {
-                match lockfile.has_rock(package_req, filter).map(|local_package| {
-                    RemotePackage::new(
-                        PackageSpec::new(local_package.spec.name, local_package.spec.version),
-                        local_package.source,
-                        local_package.source_url,
-                    )
-                }) {
+            Impl::Lock(lockfile) => find_in_lock(lockfile, package_req, filter)
+                .ok_or_else(|| SearchError::RockNotFoundInLockfile(package_req.clone())),
+            Impl::LockWithFallback { lock, fallback } => {
+                match find_in_lock(lock, package_req, filter.clone()) {
                     Some(package) => Ok(package),
-                    None => Err(SearchError::RockNotFoundInLockfile(package_req.clone())),
+                    None => fallback.find(package_req, filter, progress),
+                }
+            }
+        }
+    }
+
+    /// Find all source variants of the latest version that matches the requirement,
+    /// ordered by preference.
+    pub(crate) fn find_all(
+        &self,
+        package_req: &PackageReq,
+        filter: Option<RemotePackageTypeFilterSpec>,
+        progress: &Progress<ProgressBar>,
+    ) -> Result<Vec<RemotePackage>, SearchError> {
+        match &self.0 {
+            Impl::LuarocksManifests {
+                manifests,
+                rockspec_overlay_dir,
+            } => {
+                let packages = manifests
+                    .iter()
+                    .map(|manifest| {
+                        progress.map(|p| {
+                            p.set_message(format!("🔎 Searching {}", &manifest.server_url()))
+                        });
+                        manifest.find_all(package_req, filter.clone())
+                    })
+                    .find(|packages| !packages.is_empty())
+                    .ok_or_else(|| SearchError::RockNotFound(package_req.clone()))?;
+                // The other source variants were built from the upstream rockspec,
+                // so an overlay rockspec replaces all of them.
+                match find_overlay_rockspec(rockspec_overlay_dir.as_deref(), &packages[0], &filter)?
+                {
+                    Some(package) => Ok(vec![package]),
+                    None => Ok(packages),
+                }
+            }
+            Impl::Lock(_) => Ok(vec![self.find(package_req, filter, progress)?]),
+            Impl::LockWithFallback { lock, fallback } => {
+                match find_in_lock(lock, package_req, filter.clone()) {
+                    Some(package) => Ok(vec![package]),
+                    None => fallback.find_all(package_req, filter, progress),
                 }
             }
         }
@@ -100,7 +182,7 @@ impl RemotePackageDB {
     /// Search for all packages that match the requirement.
     pub fn search(&self, package_req: &PackageReq) -> Vec<(&PackageName, Vec<&PackageVersion>)> {
         match &self.0 {
-            Impl::LuarocksManifests(manifests) => manifests
+            Impl::LuarocksManifests { manifests, .. } => manifests
                 .iter()
                 .flat_map(|manifest| {
                     manifest
@@ -125,19 +207,36 @@ impl RemotePackageDB {
                         })
                 })
                 .collect(),
-            Impl::Lock(lockfile) => lockfile
-                .rocks()
+            Impl::Lock(lockfile) => search_lock(lockfile, package_req),
+            Impl::LockWithFallback { lock, fallback } => search_lock(lock, package_req)
+                .into_iter()
+                .chain(fallback.search(package_req))
+                .collect_vec(),
+        }
+    }
+
+    /// All available versions of a package, filtered by source type,
+    /// sorted from newest to oldest.
+    pub fn versions_of(
+        &self,
+        name: &PackageName,
+        filter: Option<RemotePackageTypeFilterSpec>,
+    ) -> Vec<PackageVersion> {
+        match &self.0 {
+            Impl::LuarocksManifests { manifests, .. } => manifests
                 .iter()
-                .filter_map(|(_, package)| {
-                    // NOTE: This doesn't group packages by name, but we don't care for now,
-                    // as we shouldn't need to use this function with a lockfile.
-                    let name = package.name();
-                    if name.to_string().contains(&package_req.name().to_string()) {
-                        Some((name, vec![package.version()]))
-                    } else {
-                        None
-                    }
-                })
+                .flat_map(|manifest| manifest.metadata().versions_of(name, filter.clone()))
+                .unique()
+                .sorted_by(|a, b| Ord::cmp(b, a))
+                .collect_vec(),
+            Impl::Lock(lockfile) => versions_in_lock(lockfile, name)
+                .unique()
+                .sorted_by(|a, b| Ord::cmp(b, a))
+                .collect_vec(),
+            Impl::LockWithFallback { lock, fallback } => versions_in_lock(lock, name)
+                .chain(fallback.versions_of(name, filter))
+                .unique()
+                .sorted_by(|a, b| Ord::cmp(b, a))
                 .collect_vec(),
         }
     }
@@ -161,6 +260,75 @@ impl RemotePackageDB {
     }
 }
 
+fn find_in_lock(
+    lock: &LocalPackageLock,
+    package_req: &PackageReq,
+    filter: Option<RemotePackageTypeFilterSpec>,
+) -> Option<RemotePackage> {
+    lock.has_rock(package_req, filter).map(|local_package| {
+        RemotePackage::new(
+            PackageSpec::new(local_package.spec.name, local_package.spec.version),
+            local_package.source,
+            local_package.source_url,
+        )
+    })
+}
+
+fn search_lock<'a>(
+    lock: &'a LocalPackageLock,
+    package_req: &PackageReq,
+) -> Vec<(&'a PackageName, Vec<&'a PackageVersion>)> {
+    lock.rocks()
+        .values()
+        .filter_map(|package| {
+            // NOTE: This doesn't group packages by name, but we don't care for now,
+            // as we shouldn't need to use this function with a lockfile.
+            let name = package.name();
+            if name.to_string().contains(&package_req.name().to_string()) {
+                Some((name, vec![package.version()]))
+            } else {
+                None
+            }
+        })
+        .collect_vec()
+}
+
+fn versions_in_lock<'a>(
+    lock: &'a LocalPackageLock,
+    name: &'a PackageName,
+) -> impl Iterator<Item = PackageVersion> + 'a {
+    lock.rocks()
+        .values()
+        .filter(move |package| package.name() == name)
+        .map(|package| package.version().clone())
+}
+
+/// Look up `<name>-<version>.rockspec` for a package found on a remote server
+/// in the rockspec overlay directory, if rockspecs are allowed by the `filter`.
+fn find_overlay_rockspec(
+    rockspec_overlay_dir: Option<&Path>,
+    remote_package: &RemotePackage,
+    filter: &Option<RemotePackageTypeFilterSpec>,
+) -> Result<Option<RemotePackage>, SearchError> {
+    let rockspec_overlay_dir = match rockspec_overlay_dir {
+        Some(dir) if filter.as_ref().is_none_or(|filter| filter.rockspec) => dir,
+        _ => return Ok(None),
+    };
+    let package = &remote_package.package;
+    let path =
+        rockspec_overlay_dir.join(format!("{}-{}.rockspec", package.name(), package.version()));
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content =
+        std::fs::read_to_string(&path).map_err(|err| SearchError::RockspecOverlay(path, err))?;
+    Ok(Some(RemotePackage::new(
+        package.clone(),
+        RemotePackageSource::RockspecContent(content),
+        None,
+    )))
+}
+
 impl UserData for RemotePackageDB {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
         methods.add_method("search", |_, this, package_req: PackageReq| {
@@ -183,7 +351,10 @@ impl UserData for RemotePackageDB {
 
 impl From<Manifest> for RemotePackageDB {
     fn from(manifest: Manifest) -> Self {
-        Self(Impl::LuarocksManifests(vec![manifest]))
+        Self(Impl::LuarocksManifests {
+            manifests: vec![manifest],
+            rockspec_overlay_dir: None,
+        })
     }
 }
 
@@ -192,3 +363,208 @@ impl From<LocalPackageLock> for RemotePackageDB {
         Self(Impl::Lock(lock))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use assert_fs::prelude::{FileWriteStr, PathChild};
+    use httptest::{matchers::request, responders::status_code, Expectation, Server};
+    use url::Url;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        manifest::ManifestMetadata,
+        progress::Progress,
+        remote_package_source::RemotePackageSource,
+    };
+
+    use super::*;
+
+    fn start_dev_manifest_server() -> Server {
+        let fixtures =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/dev-manifest");
+        let server = Server::run();
+        for path in [
+            "/manifest-5.1.zip",
+            "/dev/manifest-5.1.zip",
+            "/dev/manifest-5.1",
+        ] {
+            server.expect(
+                Expectation::matching(request::path(path))
+                    .times(0..)
+                    .respond_with(status_code(404)),
+            );
+        }
+        for (path, fixture) in [
+            ("/manifest-5.1", "manifest-5.1"),
+            ("/dev/manifest", "dev-manifest"),
+        ] {
+            server.expect(
+                Expectation::matching(request::path(path))
+                    .times(0..)
+                    .respond_with(
+                        status_code(200).body(std::fs::read(fixtures.join(fixture)).unwrap()),
+                    ),
+            );
+        }
+        server
+    }
+
+    #[tokio::test]
+    async fn find_in_unversioned_dev_manifest() {
+        let server = start_dev_manifest_server();
+        let server_url: Url = server.url_str("/").parse().unwrap();
+        let req: PackageReq = "foo@scm".parse().unwrap();
+
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .server(Some(server_url.clone()))
+            .dev(Some(true))
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .build()
+            .unwrap();
+        let db = RemotePackageDB::from_config(&config, &Progress::no_progress())
+            .await
+            .unwrap();
+        let package = db.find(&req, None, &Progress::no_progress()).unwrap();
+        assert_eq!(package.package.to_string(), "foo scm-1");
+        assert_eq!(
+            package.source,
+            RemotePackageSource::LuarocksRockspec(server_url.join("dev/").unwrap())
+        );
+        assert!(db
+            .find(&"bar".parse().unwrap(), None, &Progress::no_progress())
+            .is_ok());
+
+        let cache_dir = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .server(Some(server_url))
+            .cache_dir(Some(cache_dir.to_path_buf()))
+            .lua_version(Some(LuaVersion::Lua51))
+            .build()
+            .unwrap();
+        let db = RemotePackageDB::from_config(&config, &Progress::no_progress())
+            .await
+            .unwrap();
+        assert!(matches!(
+            db.find(&req, None, &Progress::no_progress()),
+            Err(SearchError::RockNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn find_in_lockfile() {
+        let lockfile = Lockfile::load(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/sample-tree/5.1/lux.lock"),
+            None,
+        )
+        .unwrap();
+        let db = RemotePackageDB::from_lockfile(&lockfile);
+        let package = db
+            .find(&"nvim-nio".parse().unwrap(), None, &Progress::no_progress())
+            .unwrap();
+        assert_eq!(package.package.to_string(), "nvim-nio 1.10.1-1");
+        assert_eq!(
+            package.source,
+            RemotePackageSource::LuarocksRockspec("https://luarocks.org/".parse().unwrap())
+        );
+        assert_eq!(
+            db.latest_version(&"say".into()),
+            Some("1.4.1-3".parse().unwrap())
+        );
+        assert!(matches!(
+            db.find(&"foo".parse().unwrap(), None, &Progress::no_progress()),
+            Err(SearchError::RockNotFoundInLockfile(_))
+        ));
+    }
+
+    #[test]
+    fn find_in_lock_with_fallback() {
+        let lockfile = Lockfile::load(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/sample-tree/5.1/lux.lock"),
+            None,
+        )
+        .unwrap();
+        let manifest_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/manifest-5.1");
+        let manifest = std::fs::read_to_string(manifest_path).unwrap();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+        let manifest = Manifest::new(Url::parse("https://example.com").unwrap(), metadata);
+        let db = RemotePackageDB::from_lock_with_fallback(
+            lockfile.local_pkg_lock().clone(),
+            manifest.into(),
+        );
+        let progress = Progress::no_progress();
+
+        let package = db
+            .find(&"nvim-nio".parse().unwrap(), None, &progress)
+            .unwrap();
+        assert_eq!(package.package.to_string(), "nvim-nio 1.10.1-1");
+        assert_eq!(
+            package.source,
+            RemotePackageSource::LuarocksRockspec("https://luarocks.org/".parse().unwrap())
+        );
+        let package = db
+            .find(&"30log@0.2-1".parse().unwrap(), None, &progress)
+            .unwrap();
+        assert_eq!(package.package.to_string(), "30log 0.2-1");
+        assert!(db
+            .versions_of(&"30log".into(), None)
+            .contains(&"0.2-1".parse().unwrap()));
+        assert!(matches!(
+            db.find(&"foo".parse().unwrap(), None, &progress),
+            Err(SearchError::RockNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn rockspec_overlay_overrides_manifest() {
+        let manifest_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/manifest-5.1");
+        let manifest = std::fs::read_to_string(manifest_path).unwrap();
+        let metadata = ManifestMetadata::new(&manifest).unwrap();
+        let manifest = Manifest::new(Url::parse("https://example.com").unwrap(), metadata);
+        let overlay_dir = assert_fs::TempDir::new().unwrap();
+        let rockspec = "package = '30log'\nversion = '0.2-1'\n";
+        overlay_dir
+            .child("30log-0.2-1.rockspec")
+            .write_str(rockspec)
+            .unwrap();
+        let db = RemotePackageDB(Impl::LuarocksManifests {
+            manifests: vec![manifest],
+            rockspec_overlay_dir: Some(overlay_dir.to_path_buf()),
+        });
+        let progress = Progress::no_progress();
+
+        let package_req: PackageReq = "30log@0.2-1".parse().unwrap();
+        let package = db.find(&package_req, None, &progress).unwrap();
+        assert_eq!(
+            package.source,
+            RemotePackageSource::RockspecContent(rockspec.into())
+        );
+        let packages = db.find_all(&package_req, None, &progress).unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].source, package.source);
+
+        let src_only = Some(RemotePackageTypeFilterSpec::src_only());
+        let package = db.find(&package_req, src_only, &progress).unwrap();
+        assert!(matches!(
+            package.source,
+            RemotePackageSource::LuarocksSrcRock(_)
+        ));
+
+        // The overlay only applies to the exact version
+        let package_req: PackageReq = "30log@0.2-2".parse().unwrap();
+        let package = db.find(&package_req, None, &progress).unwrap();
+        assert!(!matches!(
+            package.source,
+            RemotePackageSource::RockspecContent(_)
+        ));
+    }
+}
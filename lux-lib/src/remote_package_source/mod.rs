@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
 use mlua::IntoLua;
 use serde::{de, Deserialize, Deserializer, Serialize};
@@ -18,6 +18,8 @@ pub(crate) enum RemotePackageSource {
     LuarocksBinaryRock(Url),
     RockspecContent(String),
     Local,
+    /// A local project directory, installed in editable mode.
+    LocalPath(PathBuf),
     #[cfg(test)]
     Test,
 }
@@ -34,6 +36,7 @@ impl IntoLua for RemotePackageSource {
                 table.set("rockspec_content", content)?
             }
             RemotePackageSource::Local => table.set("local", true)?,
+            RemotePackageSource::LocalPath(path) => table.set("local_path", path)?,
             #[cfg(test)]
             RemotePackageSource::Test => unreachable!(),
         };
@@ -50,6 +53,7 @@ impl RemotePackageSource {
             | Self::LuarocksBinaryRock(url) => url,
             Self::RockspecContent(_) => panic!("tried to get URL from RockspecContent"),
             RemotePackageSource::Local => panic!("tried to get URL from Local"),
+            RemotePackageSource::LocalPath(_) => panic!("tried to get URL from LocalPath"),
             #[cfg(test)]
             Self::Test => unimplemented!(),
         }
@@ -72,6 +76,7 @@ impl Display for RemotePackageSource {
                 format!("rockspec{PLUS}{content}").fmt(f)
             }
             RemotePackageSource::Local => "local".fmt(f),
+            RemotePackageSource::LocalPath(path) => format!("local{PLUS}{}", path.display()).fmt(f),
             #[cfg(test)]
             RemotePackageSource::Test => "test+foo_bar".fmt(f),
         }
@@ -111,6 +116,7 @@ impl TryFrom<String> for RemotePackageSource {
                     "luarocks_src_rock" => Ok(Self::LuarocksSrcRock(Url::parse(str)?)),
                     "luarocks_rock" => Ok(Self::LuarocksBinaryRock(Url::parse(str)?)),
                     "rockspec" => Ok(Self::RockspecContent(str.into())),
+                    "local" => Ok(Self::LocalPath(PathBuf::from(str))),
                     _ => Err(RemotePackageSourceError::UnknownRemoteSourceType(
                         remote_source_type.into(),
                     )),
@@ -170,4 +176,14 @@ source = {
         let roundtripped = RemotePackageSource::try_from(format!("{source}")).unwrap();
         assert_eq!(source, roundtripped)
     }
+
+    #[test]
+    fn local_source_roundtrip() {
+        let source = RemotePackageSource::Local;
+        let roundtripped = RemotePackageSource::try_from(format!("{source}")).unwrap();
+        assert_eq!(source, roundtripped);
+        let source = RemotePackageSource::LocalPath("/home/user/my-plugin+dev".into());
+        let roundtripped = RemotePackageSource::try_from(format!("{source}")).unwrap();
+        assert_eq!(source, roundtripped)
+    }
 }
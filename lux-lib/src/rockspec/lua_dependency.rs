@@ -3,6 +3,7 @@ use std::{collections::HashMap, convert::Infallible, fmt::Display, str::FromStr}
 use mlua::{FromLua, IntoLua, LuaSerdeExt};
 use serde::{Deserialize, Deserializer};
 use thiserror::Error;
+use url::Url;
 
 use crate::{
     lockfile::{OptState, PinnedState},
@@ -24,6 +25,9 @@ pub struct LuaDependencySpec {
     pub(crate) pin: PinnedState,
     pub(crate) opt: OptState,
     pub(crate) source: Option<RockSourceSpec>,
+    /// URL of the rockspec to install this dependency from,
+    /// instead of searching a package database.
+    pub(crate) rockspec_url: Option<Url>,
 }
 
 impl LuaDependencySpec {
@@ -39,6 +43,9 @@ impl LuaDependencySpec {
     pub fn source(&self) -> &Option<RockSourceSpec> {
         &self.source
     }
+    pub fn rockspec_url(&self) -> Option<&Url> {
+        self.rockspec_url.as_ref()
+    }
     pub fn into_package_req(self) -> PackageReq {
         self.package_req
     }
@@ -60,6 +67,7 @@ impl From<PackageName> for LuaDependencySpec {
             pin: PinnedState::default(),
             opt: OptState::default(),
             source: None,
+            rockspec_url: None,
         }
     }
 }
@@ -71,6 +79,7 @@ impl From<PackageReq> for LuaDependencySpec {
             pin: PinnedState::default(),
             opt: OptState::default(),
             source: None,
+            rockspec_url: None,
         }
     }
 }
@@ -85,6 +94,7 @@ impl FromStr for LuaDependencySpec {
             pin: PinnedState::default(),
             opt: OptState::default(),
             source: None,
+            rockspec_url: None,
         })
     }
 }
@@ -140,6 +150,7 @@ impl FromLua for LuaDependencySpec {
             pin: PinnedState::default(),
             opt: OptState::default(),
             source: None,
+            rockspec_url: None,
         })
     }
 }
@@ -155,6 +166,7 @@ impl<'de> Deserialize<'de> for LuaDependencySpec {
             pin: PinnedState::default(),
             opt: OptState::default(),
             source: None,
+            rockspec_url: None,
         })
     }
 }
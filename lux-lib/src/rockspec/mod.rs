@@ -9,6 +9,7 @@ use itertools::Itertools;
 use lua_dependency::LuaDependencySpec;
 use mlua::IntoLua;
 use serde::{Deserialize, Serialize};
+use ssri::Integrity;
 pub mod lua_dependency;
 
 use crate::{
@@ -60,6 +61,12 @@ pub trait Rockspec {
 
     /// Converts the rockspec to a string that can be uploaded to a luarocks server.
     fn to_lua_remote_rockspec_string(&self) -> Result<String, Self::Error>;
+
+    /// The `Integrity` of the rockspec's Lua representation,
+    /// as returned by `to_lua_remote_rockspec_string`.
+    fn content_hash(&self) -> Result<Integrity, Self::Error> {
+        Ok(Integrity::from(&self.to_lua_remote_rockspec_string()?))
+    }
 }
 
 pub trait LuaVersionCompatibility {
@@ -101,7 +108,7 @@ impl<T: Rockspec> LuaVersionCompatibility for T {
     }
 
     fn lua_version_matches(&self, config: &Config) -> Result<LuaVersion, LuaVersionError> {
-        let version = LuaVersion::from(config)?.clone();
+        let version = config.resolve_lua_version(None)?;
         if self.supports_lua_version(&version) {
             Ok(version)
         } else {
@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 
-use crate::{lockfile::LocalPackage, package::PackageName};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{
+    lockfile::{LocalPackage, LocalPackageId, PinnedState},
+    package::{PackageName, PackageVersion},
+};
 
 use super::{Tree, TreeError};
 
@@ -9,6 +15,32 @@ use super::{Tree, TreeError};
 // references to the packages, which would allow for in-place manipulation of the lockfile.
 // Cloning isn't destructive, but it's sure expensive.
 
+/// A machine-readable listing of the rocks installed in a tree,
+/// sorted by name and version.
+/// Serializes to a stable JSON array, for consumption by scripts.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct TreeListing(Vec<ListedRock>);
+
+impl TreeListing {
+    pub fn rocks(&self) -> &Vec<ListedRock> {
+        &self.0
+    }
+}
+
+/// An installed rock in a [`TreeListing`].
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ListedRock {
+    pub name: PackageName,
+    pub version: PackageVersion,
+    pub pinned: bool,
+    /// Whether the rock was installed explicitly, rather than as a dependency.
+    pub entrypoint: bool,
+    /// The kind of source the rock was installed from, e.g. `rockspec`.
+    /// See [`SourceKind`](crate::lockfile::SourceKind).
+    pub source: String,
+}
+
 impl Tree {
     pub fn list(&self) -> Result<HashMap<PackageName, Vec<LocalPackage>>, TreeError> {
         Ok(self.lockfile()?.list())
@@ -19,4 +51,93 @@ impl Tree {
 
         Ok(rock_list.values().flatten().cloned().collect())
     }
+
+    /// Map the name of each executable installed in [`Tree::bin`]
+    /// to the package that provides it.
+    /// If several packages provide an executable with the same name,
+    /// the one that sorts first by name and version is listed.
+    pub fn bin_scripts(&self) -> Result<HashMap<String, LocalPackageId>, TreeError> {
+        let lockfile = self.lockfile()?;
+        let mut bin_scripts = HashMap::new();
+        for package in lockfile
+            .rocks()
+            .values()
+            .sorted_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())))
+        {
+            for binary in package.spec.binaries() {
+                if let Some(file_name) = binary.file_name() {
+                    bin_scripts
+                        .entry(file_name.to_string_lossy().to_string())
+                        .or_insert_with(|| package.id());
+                }
+            }
+        }
+        Ok(bin_scripts)
+    }
+
+    /// List the installed rocks in a serializable form.
+    pub fn listing(&self) -> Result<TreeListing, TreeError> {
+        let lockfile = self.lockfile()?;
+        Ok(TreeListing(
+            lockfile
+                .rocks()
+                .values()
+                .map(|package| ListedRock {
+                    name: package.name().clone(),
+                    version: package.version().clone(),
+                    pinned: package.pinned() == PinnedState::Pinned,
+                    entrypoint: lockfile.is_entrypoint(&package.id()),
+                    source: package.source_kind().to_string(),
+                })
+                .sorted_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)))
+                .collect_vec(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackage,
+    };
+
+    #[test]
+    fn bin_scripts() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let mk_package = |name: &str, binaries: &[&str]| {
+            let mut package = LocalPackage::mock(name, "1.0.0-1");
+            package
+                .spec
+                .binaries
+                .extend(binaries.iter().map(PathBuf::from));
+            package
+        };
+        let busted = mk_package("busted", &["bin/busted"]);
+        let luacheck = mk_package("luacheck", &["bin/luacheck", "bin/busted"]);
+        let penlight = mk_package("penlight", &[]);
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&luacheck);
+                lockfile.add_entrypoint(&busted);
+                lockfile.add_entrypoint(&penlight);
+                Ok::<_, std::io::Error>(())
+            })
+            .unwrap();
+
+        let bin_scripts = tree.bin_scripts().unwrap();
+        assert_eq!(bin_scripts.len(), 2);
+        assert_eq!(bin_scripts.get("busted"), Some(&busted.id()));
+        assert_eq!(bin_scripts.get("luacheck"), Some(&luacheck.id()));
+    }
 }
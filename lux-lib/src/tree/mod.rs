@@ -1,8 +1,12 @@
 use crate::{
     build::utils::format_path,
     config::{tree::RockLayoutConfig, Config, LuaVersion},
-    lockfile::{LocalPackage, LocalPackageId, Lockfile, LockfileError, OptState, ReadOnly},
-    package::PackageReq,
+    lockfile::{
+        rock_dir_name, LocalPackage, LocalPackageId, Lockfile, LockfileError, OptState, ReadOnly,
+    },
+    lua_rockspec::{LuaRockspecError, RemoteLuaRockspec},
+    package::{PackageReq, PackageSpec},
+    path::{Paths, PathsError},
     variables::{GetVariableError, HasVariables},
 };
 use std::{io, path::PathBuf};
@@ -12,6 +16,12 @@ use mlua::{ExternalResult, IntoLua};
 use thiserror::Error;
 
 mod list;
+mod snapshot;
+mod verify;
+
+pub use list::{ListedRock, TreeListing};
+pub use snapshot::TreeSnapshot;
+pub use verify::VerifyIssue;
 
 const LOCKFILE_NAME: &str = "lux.lock";
 
@@ -44,10 +54,20 @@ pub struct Tree {
 pub enum TreeError {
     #[error("unable to create directory {0}:\n{1}")]
     CreateDir(String, io::Error),
+    #[error("unable to read {0}:\n{1}")]
+    ReadFile(String, io::Error),
     #[error("unable to write to {0}:\n{1}")]
     WriteFile(String, io::Error),
+    #[error("unable to remove {0}:\n{1}")]
+    Remove(String, io::Error),
     #[error(transparent)]
     Lockfile(#[from] LockfileError),
+    #[error("unable to read rockspec {0}:\n{1}")]
+    ReadRockspec(String, io::Error),
+    #[error("unable to parse rockspec {0}:\n{1}")]
+    ParseRockspec(String, Box<LuaRockspecError>),
+    #[error("unable to restore the tree snapshot, because the following rocks were removed since it was taken:\n{}", .0.iter().join("\n"))]
+    SnapshotRocksRemoved(Vec<PackageSpec>),
 }
 
 /// Change-agnostic way of referencing various paths for a rock.
@@ -86,19 +106,28 @@ impl RockLayout {
     pub fn rockspec_path(&self) -> PathBuf {
         self.rock_path.join("package.rockspec")
     }
+
+    /// All paths that can be substituted in a rockspec's `build.build_variables`
+    /// and `build.install_variables`, paired with their variable names.
+    pub fn all_paths(&self) -> Vec<(&'static str, &PathBuf)> {
+        vec![
+            ("PREFIX", &self.rock_path),
+            ("LIBDIR", &self.lib),
+            ("LUADIR", &self.src),
+            ("BINDIR", &self.bin),
+            ("CONFDIR", &self.conf),
+            ("DOCDIR", &self.doc),
+        ]
+    }
 }
 
 impl HasVariables for RockLayout {
     fn get_variable(&self, var: &str) -> Result<Option<String>, GetVariableError> {
-        Ok(match var {
-            "PREFIX" => Some(format_path(&self.rock_path)),
-            "LIBDIR" => Some(format_path(&self.lib)),
-            "LUADIR" => Some(format_path(&self.src)),
-            "BINDIR" => Some(format_path(&self.bin)),
-            "CONFDIR" => Some(format_path(&self.conf)),
-            "DOCDIR" => Some(format_path(&self.doc)),
-            _ => None,
-        })
+        Ok(self
+            .all_paths()
+            .into_iter()
+            .find(|(name, _)| *name == var)
+            .map(|(_, path)| format_path(path)))
     }
 }
 
@@ -116,7 +145,7 @@ impl mlua::UserData for RockLayout {
 
 impl Tree {
     /// NOTE: This is exposed for use by the config module.
-    /// Use `Config::tree()`
+    /// Use `Config::tree()` or `Config::user_tree()`
     pub(crate) fn new(
         root: PathBuf,
         version: LuaVersion,
@@ -153,7 +182,7 @@ impl Tree {
         std::fs::create_dir_all(&bin_dir)
             .map_err(|err| TreeError::CreateDir(bin_dir.to_string_lossy().to_string(), err))?;
 
-        let lockfile_path = root.join(LOCKFILE_NAME);
+        let lockfile_path = path_with_version.join(LOCKFILE_NAME);
         let rock_layout_config = if lockfile_path.is_file() {
             let lockfile = Lockfile::load(lockfile_path, None)?;
             lockfile.entrypoint_layout
@@ -178,13 +207,18 @@ impl Tree {
         &self.version
     }
 
+    /// The root of the tree in which to install test dependencies
+    pub fn test_tree_root(&self) -> PathBuf {
+        self.test_tree_dir.join(self.version.to_string())
+    }
+
+    /// The root of the tree in which to install build dependencies
+    pub fn build_tree_root(&self) -> PathBuf {
+        self.build_tree_dir.join(self.version.to_string())
+    }
+
     pub fn root_for(&self, package: &LocalPackage) -> PathBuf {
-        self.root().join(format!(
-            "{}-{}@{}",
-            package.id(),
-            package.name(),
-            package.version()
-        ))
+        self.root().join(rock_dir_name(&package.id(), package))
     }
 
     pub fn bin(&self) -> PathBuf {
@@ -210,25 +244,12 @@ impl Tree {
     where
         F: Fn(&LocalPackage) -> bool,
     {
-        match self.list()?.get(req.name()) {
-            Some(packages) => {
-                let mut found_packages = packages
-                    .iter()
-                    .rev()
-                    .filter(|package| {
-                        req.version_req().matches(package.version()) && filter(package)
-                    })
-                    .map(|package| package.id())
-                    .collect_vec();
-
-                Ok(match found_packages.len() {
-                    0 => RockMatches::NotFound(req.clone()),
-                    1 => RockMatches::Single(found_packages.pop().unwrap()),
-                    2.. => RockMatches::Many(found_packages),
-                })
-            }
-            None => Ok(RockMatches::NotFound(req.clone())),
-        }
+        let mut found_packages = self.lockfile()?.find_rocks_where(req, filter);
+        Ok(match found_packages.len() {
+            0 => RockMatches::NotFound(req.clone()),
+            1 => RockMatches::Single(found_packages.pop().unwrap()),
+            2.. => RockMatches::Many(found_packages),
+        })
     }
 
     /// Get the `RockLayout` for an installed package.
@@ -241,6 +262,20 @@ impl Tree {
         }
     }
 
+    /// Read and parse the rockspec that was persisted when the package was installed.
+    pub fn installed_rockspec(
+        &self,
+        package: &LocalPackage,
+    ) -> Result<RemoteLuaRockspec, TreeError> {
+        let rockspec_path = self.installed_rock_layout(package)?.rockspec_path();
+        let content = std::fs::read_to_string(&rockspec_path).map_err(|err| {
+            TreeError::ReadRockspec(rockspec_path.to_string_lossy().to_string(), err)
+        })?;
+        RemoteLuaRockspec::new(&content).map_err(|err| {
+            TreeError::ParseRockspec(rockspec_path.to_string_lossy().to_string(), Box::new(err))
+        })
+    }
+
     /// Create a `RockLayout` for an entrypoint
     pub fn entrypoint_layout(&self, package: &LocalPackage) -> RockLayout {
         self.mk_rock_layout(package, &self.entrypoint_layout)
@@ -309,11 +344,21 @@ impl Tree {
         )?)
     }
 
-    /// Get this tree's lockfile path.
+    /// Get this tree's lockfile path, `<tree>/<lua-version>/lux.lock`.
     pub fn lockfile_path(&self) -> PathBuf {
         self.root().join(LOCKFILE_NAME)
     }
 
+    /// The `LUA_PATH` and `LUA_CPATH` for all rocks installed in this tree,
+    /// built from each rock's `RockLayout`.
+    pub fn lua_paths(&self) -> Result<(String, String), PathsError> {
+        let paths = Paths::new(self)?;
+        Ok((
+            paths.package_path().joined(),
+            paths.package_cpath().joined(),
+        ))
+    }
+
     /// The tree in which to install test dependencies
     pub fn test_tree(&self, config: &Config) -> Result<Self, TreeError> {
         let test_tree_dir = self.test_tree_dir.clone();
@@ -365,10 +410,14 @@ impl mlua::UserData for Tree {
             this.installed_rock_layout(&package)
                 .map_err(|err| mlua::Error::RuntimeError(err.to_string()))
         });
+        methods.add_method("installed_rockspec", |_, this, package: LocalPackage| {
+            this.installed_rockspec(&package).into_lua_err()
+        });
         methods.add_method("rock", |_, this, package: LocalPackage| {
             this.dependency(&package).into_lua_err()
         });
         methods.add_method("lockfile", |_, this, ()| this.lockfile().into_lua_err());
+        methods.add_method("lua_paths", |_, this, ()| this.lua_paths().into_lua_err());
     }
 }
 
@@ -419,9 +468,10 @@ impl IntoLua for RockMatches {
 mod tests {
     use assert_fs::prelude::PathCopy;
     use itertools::Itertools;
+    use path_slash::PathBufExt;
     use std::path::PathBuf;
 
-    use insta::assert_yaml_snapshot;
+    use insta::{assert_json_snapshot, assert_yaml_snapshot};
 
     use crate::{
         config::{ConfigBuilder, LuaVersion},
@@ -429,7 +479,8 @@ mod tests {
         package::{PackageName, PackageSpec, PackageVersion},
         remote_package_source::RemotePackageSource,
         rockspec::RockBinaries,
-        tree::RockLayout,
+        rockspec::Rockspec,
+        tree::{RockLayout, TreeError},
         variables,
     };
 
@@ -511,6 +562,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn installed_rockspec() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(temp.to_path_buf()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let package = LocalPackage::mock("lua-cjson", "2.1.0.10-1");
+        let layout = tree.dependency(&package).unwrap();
+        assert!(matches!(
+            tree.installed_rockspec(&package),
+            Err(TreeError::ReadRockspec(..))
+        ));
+
+        let rockspec_content = std::fs::read_to_string(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("resources/test/lua-cjson-2.1.0-1.rockspec"),
+        )
+        .unwrap();
+        std::fs::write(layout.rockspec_path(), &rockspec_content).unwrap();
+        let rockspec = tree.installed_rockspec(&package).unwrap();
+        assert_eq!(rockspec.package(), package.name());
+        assert_eq!(rockspec.version(), package.version());
+    }
+
     #[test]
     fn tree_list() {
         let tree_path =
@@ -546,6 +625,51 @@ mod tests {
         assert_yaml_snapshot!(sorted_result)
     }
 
+    #[test]
+    fn tree_listing() {
+        let tree_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-tree");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.copy_from(&tree_path, &["**"]).unwrap();
+        let tree_path = temp.to_path_buf();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_path.clone()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        assert_json_snapshot!(tree.listing().unwrap())
+    }
+
+    #[test]
+    fn tree_lua_paths() {
+        let tree_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-tree");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.copy_from(&tree_path, &["**"]).unwrap();
+        let tree_path = temp.to_path_buf();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_path.clone()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+        let (lua_path, lua_cpath) = tree.lua_paths().unwrap();
+
+        let packages = tree.as_rock_list().unwrap();
+        assert!(!packages.is_empty());
+        for package in packages {
+            let layout = tree.installed_rock_layout(&package).unwrap();
+            assert!(lua_path.contains(&*layout.src.join("?.lua").to_slash_lossy()));
+            assert!(lua_path.contains(&*layout.src.join("?").join("init.lua").to_slash_lossy()));
+            assert!(lua_cpath.contains(&*layout.lib.to_slash_lossy()));
+        }
+    }
+
     #[test]
     fn rock_layout_substitute() {
         let tree_path =
@@ -590,7 +714,7 @@ mod tests {
             "$(DOCDIR)",
         ];
         let result: Vec<String> = build_variables
-            .into_iter()
+            .iter()
             .map(|var| variables::substitute(&[&neorg], var))
             .try_collect()
             .unwrap();
@@ -605,5 +729,13 @@ mod tests {
                 neorg.doc.to_string_lossy().to_string(),
             ]
         );
+        assert_eq!(
+            neorg
+                .all_paths()
+                .into_iter()
+                .map(|(name, _)| format!("$({name})"))
+                .collect_vec(),
+            build_variables
+        );
     }
 }
@@ -0,0 +1,225 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    path::Path,
+};
+
+use itertools::Itertools;
+
+use crate::lockfile::{LocalPackage, LocalPackageId};
+
+use super::{Tree, TreeError};
+
+/// The lockfile and installed rocks of a tree at some point in time,
+/// which can be restored with [`Tree::restore`].
+#[derive(Debug, Clone)]
+pub struct TreeSnapshot {
+    lockfile: String,
+    packages: BTreeMap<LocalPackageId, LocalPackage>,
+}
+
+impl TreeSnapshot {
+    /// The IDs of the rocks that were installed when the snapshot was taken.
+    pub fn packages(&self) -> impl Iterator<Item = &LocalPackageId> {
+        self.packages.keys()
+    }
+}
+
+impl Tree {
+    /// Capture the lockfile and the IDs of the installed rocks,
+    /// e.g. to roll back an upgrade that fails.
+    pub fn snapshot(&self) -> Result<TreeSnapshot, TreeError> {
+        let packages = self.lockfile()?.rocks().clone();
+        let lockfile_path = self.lockfile_path();
+        let lockfile = std::fs::read_to_string(&lockfile_path)
+            .map_err(|err| TreeError::ReadFile(lockfile_path.to_string_lossy().to_string(), err))?;
+        Ok(TreeSnapshot { lockfile, packages })
+    }
+
+    /// Remove the rocks that were installed since the `snapshot` was taken
+    /// and restore its lockfile.
+    ///
+    /// The snapshot does not contain the files of the installed rocks,
+    /// so this fails without modifying the tree if any of the rocks
+    /// in the snapshot were removed since it was taken.
+    pub fn restore(&self, snapshot: &TreeSnapshot) -> Result<(), TreeError> {
+        let lockfile = self.lockfile()?;
+        let removed = snapshot
+            .packages
+            .iter()
+            .filter(|(id, _)| lockfile.get(id).is_none())
+            .map(|(_, package)| package.to_package())
+            .collect_vec();
+        if !removed.is_empty() {
+            return Err(TreeError::SnapshotRocksRemoved(removed));
+        }
+        let (kept, installed): (Vec<_>, Vec<_>) = lockfile
+            .rocks()
+            .iter()
+            .partition(|(id, _)| snapshot.packages.contains_key(id));
+        // Binaries with the same name as one of a kept rock's binaries were overwritten,
+        // so we don't remove them.
+        let kept_binaries: HashSet<_> = kept
+            .iter()
+            .flat_map(|(_, package)| package.spec.binaries())
+            .filter_map(|binary| binary.file_name().map(|file_name| file_name.to_os_string()))
+            .collect();
+        for (id, package) in installed {
+            let layout = if lockfile.is_entrypoint(id) {
+                self.entrypoint_layout(package)
+            } else {
+                self.dependency_layout(package)
+            };
+            remove_dir_if_exists(&layout.etc)?;
+            remove_dir_if_exists(&layout.rock_path)?;
+            for binary_file_name in package
+                .spec
+                .binaries()
+                .into_iter()
+                .filter_map(|binary| binary.file_name().map(|file_name| file_name.to_os_string()))
+                .filter(|file_name| !kept_binaries.contains(file_name))
+            {
+                remove_file_if_exists(&self.bin().join(&binary_file_name))?;
+                remove_file_if_exists(&self.unwrapped_bin().join(&binary_file_name))?;
+            }
+        }
+        let lockfile_path = self.lockfile_path();
+        std::fs::write(&lockfile_path, &snapshot.lockfile)
+            .map_err(|err| TreeError::WriteFile(lockfile_path.to_string_lossy().to_string(), err))
+    }
+}
+
+fn remove_dir_if_exists(dir: &Path) -> Result<(), TreeError> {
+    match std::fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(TreeError::Remove(dir.to_string_lossy().to_string(), err))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn remove_file_if_exists(file: &Path) -> Result<(), TreeError> {
+    match std::fs::remove_file(file) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(TreeError::Remove(file.to_string_lossy().to_string(), err))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use assert_fs::prelude::PathCopy;
+    use itertools::Itertools;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackage,
+    };
+
+    use super::TreeError;
+
+    #[test]
+    fn restore_tree_snapshot() {
+        let tree_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-tree");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.copy_from(&tree_path, &["**"]).unwrap();
+        let tree_path = temp.to_path_buf();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_path.clone()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let installed = tree.lockfile().unwrap().rocks().clone();
+        let snapshot = tree.snapshot().unwrap();
+        assert_eq!(
+            snapshot.packages().collect_vec(),
+            installed.keys().collect_vec()
+        );
+        let lockfile_content = std::fs::read_to_string(tree.lockfile_path()).unwrap();
+
+        let mut package = LocalPackage::mock("my-tool", "1.0.0-1");
+        package.spec.binaries.push(PathBuf::from("bin/my-tool"));
+        let layout = tree.entrypoint(&package).unwrap();
+        std::fs::write(layout.src.join("my-tool.lua"), "return {}").unwrap();
+        let binary = tree.bin().join("my-tool");
+        std::fs::write(&binary, "#!/bin/sh").unwrap();
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&package);
+                Ok::<_, std::io::Error>(())
+            })
+            .unwrap();
+
+        tree.restore(&snapshot).unwrap();
+
+        assert!(!layout.rock_path.exists());
+        assert!(!binary.exists());
+        assert_eq!(
+            std::fs::read_to_string(tree.lockfile_path()).unwrap(),
+            lockfile_content
+        );
+        let lockfile = tree.lockfile().unwrap();
+        assert!(lockfile.get(&package.id()).is_none());
+        assert_eq!(lockfile.rocks(), &installed);
+        let neorg = installed
+            .values()
+            .find(|package| package.name().to_string() == "neorg")
+            .unwrap();
+        assert!(tree.root_for(neorg).is_dir());
+    }
+
+    #[test]
+    fn restore_tree_snapshot_with_removed_rocks() {
+        let tree_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-tree");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.copy_from(&tree_path, &["**"]).unwrap();
+        let tree_path = temp.to_path_buf();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_path.clone()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let snapshot = tree.snapshot().unwrap();
+        let neorg = tree
+            .lockfile()
+            .unwrap()
+            .rocks()
+            .values()
+            .find(|package| package.name().to_string() == "neorg")
+            .unwrap()
+            .clone();
+        std::fs::remove_dir_all(tree.root_for(&neorg)).unwrap();
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.remove(&neorg);
+                Ok::<_, std::io::Error>(())
+            })
+            .unwrap();
+        let lockfile_content = std::fs::read_to_string(tree.lockfile_path()).unwrap();
+
+        let result = tree.restore(&snapshot);
+        assert!(matches!(
+            result,
+            Err(TreeError::SnapshotRocksRemoved(removed)) if removed == vec![neorg.to_package()]
+        ));
+        assert_eq!(
+            std::fs::read_to_string(tree.lockfile_path()).unwrap(),
+            lockfile_content
+        );
+    }
+}
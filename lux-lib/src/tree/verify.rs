@@ -0,0 +1,215 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+
+use crate::{build::utils::has_files, lockfile::LocalPackage, package::PackageSpec};
+
+use super::{Tree, TreeError};
+
+/// A filesystem-level inconsistency between a tree's lockfile and its installed rocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// A directory of the rock's `RockLayout` does not exist.
+    MissingDir { package: PackageSpec, path: PathBuf },
+    /// The rock's directories exist, but none of them contain any installed files.
+    MissingFiles { package: PackageSpec, path: PathBuf },
+}
+
+impl VerifyIssue {
+    pub fn package(&self) -> &PackageSpec {
+        match self {
+            Self::MissingDir { package, .. } | Self::MissingFiles { package, .. } => package,
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            Self::MissingDir { path, .. } | Self::MissingFiles { path, .. } => path,
+        }
+    }
+}
+
+impl Display for VerifyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDir { package, path } => {
+                write!(f, "{package}: missing directory {}", path.display())
+            }
+            Self::MissingFiles { package, path } => {
+                write!(f, "{package}: no installed files in {}", path.display())
+            }
+        }
+    }
+}
+
+impl Tree {
+    /// Check that the files of every rock in this tree's lockfile exist on disk.
+    /// This catches trees that were partially deleted, which would otherwise
+    /// leave the lockfile claiming a rock is installed.
+    pub fn verify(&self) -> Result<Vec<VerifyIssue>, TreeError> {
+        let lockfile = self.lockfile()?;
+        Ok(lockfile
+            .rocks()
+            .values()
+            .sorted_by_key(|package| (package.name().clone(), package.version().clone()))
+            .flat_map(|package| {
+                let layout = if lockfile.is_entrypoint(&package.id()) {
+                    self.entrypoint_layout(package)
+                } else {
+                    self.dependency_layout(package)
+                };
+                verify_rock(package, &layout.rock_path, &[&layout.src, &layout.lib])
+            })
+            .collect_vec())
+    }
+}
+
+fn verify_rock(package: &LocalPackage, rock_path: &Path, dirs: &[&PathBuf]) -> Vec<VerifyIssue> {
+    let spec = package.to_package();
+    if !rock_path.is_dir() {
+        return vec![VerifyIssue::MissingDir {
+            package: spec,
+            path: rock_path.to_path_buf(),
+        }];
+    }
+    let missing_dirs = dirs
+        .iter()
+        .filter(|dir| !dir.is_dir())
+        .map(|dir| VerifyIssue::MissingDir {
+            package: spec.clone(),
+            path: dir.to_path_buf(),
+        })
+        .collect_vec();
+    if !missing_dirs.is_empty() {
+        return missing_dirs;
+    }
+    // Rocks that only install binaries may have an empty `src` and `lib`,
+    // and the working copy of an editable rock may legitimately be empty.
+    let has_files = package.is_editable()
+        || !package.spec.binaries.is_empty()
+        || dirs.iter().any(|dir| has_files(dir));
+    if has_files {
+        Vec::new()
+    } else {
+        vec![VerifyIssue::MissingFiles {
+            package: spec,
+            path: rock_path.to_path_buf(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use assert_fs::prelude::PathCopy;
+
+    use crate::{
+        config::{ConfigBuilder, LuaVersion},
+        lockfile::LocalPackage,
+        remote_package_source::RemotePackageSource,
+        tree::VerifyIssue,
+    };
+
+    #[test]
+    fn verify_tree() {
+        let tree_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-tree");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.copy_from(&tree_path, &["**"]).unwrap();
+        let tree_path = temp.to_path_buf();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_path.clone()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let neorg = tree
+            .as_rock_list()
+            .unwrap()
+            .into_iter()
+            .find(|package| package.name().to_string() == "neorg")
+            .unwrap();
+        let layout = tree.installed_rock_layout(&neorg).unwrap();
+        let issues = tree.verify().unwrap();
+        assert!(issues
+            .iter()
+            .all(|issue| issue.package() != &neorg.to_package()));
+
+        std::fs::remove_dir_all(&layout.lib).unwrap();
+        let issues = tree.verify().unwrap();
+        assert!(issues.contains(&VerifyIssue::MissingDir {
+            package: neorg.to_package(),
+            path: layout.lib.clone(),
+        }));
+
+        std::fs::create_dir_all(&layout.lib).unwrap();
+        std::fs::remove_dir_all(&layout.src).unwrap();
+        std::fs::create_dir_all(&layout.src).unwrap();
+        let issues = tree.verify().unwrap();
+        assert!(issues.contains(&VerifyIssue::MissingFiles {
+            package: neorg.to_package(),
+            path: layout.rock_path.clone(),
+        }));
+
+        std::fs::remove_dir_all(&layout.rock_path).unwrap();
+        let issues = tree.verify().unwrap();
+        assert!(issues.contains(&VerifyIssue::MissingDir {
+            package: neorg.to_package(),
+            path: layout.rock_path,
+        }));
+    }
+
+    #[test]
+    fn verify_tree_editable_rock() {
+        let tree_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-tree");
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        temp.copy_from(&tree_path, &["**"]).unwrap();
+        let tree_path = temp.to_path_buf();
+
+        let config = ConfigBuilder::new()
+            .unwrap()
+            .user_tree(Some(tree_path.clone()))
+            .build()
+            .unwrap();
+        let tree = config.user_tree(LuaVersion::Lua51).unwrap();
+
+        let working_copy = assert_fs::TempDir::new().unwrap();
+        let mut package = LocalPackage::mock("my-plugin", "scm-1");
+        package.source = RemotePackageSource::LocalPath(working_copy.to_path_buf());
+        assert!(package.is_editable());
+        let layout = tree.entrypoint(&package).unwrap();
+        std::fs::remove_dir_all(&layout.src).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(working_copy.path(), &layout.src).unwrap();
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_dir(working_copy.path(), &layout.src).unwrap();
+        tree.lockfile()
+            .unwrap()
+            .map_then_flush(|lockfile| {
+                lockfile.add_entrypoint(&package);
+                Ok::<_, std::io::Error>(())
+            })
+            .unwrap();
+
+        let issues = tree.verify().unwrap();
+        assert!(issues
+            .iter()
+            .all(|issue| issue.package() != &package.to_package()));
+
+        drop(working_copy);
+        let issues = tree.verify().unwrap();
+        assert!(issues.contains(&VerifyIssue::MissingDir {
+            package: package.to_package(),
+            path: layout.src,
+        }));
+    }
+}
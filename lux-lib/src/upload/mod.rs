@@ -1,20 +1,20 @@
 use std::{env, io};
 
 use crate::operations::SearchAndDownloadError;
-use crate::package::SpecRevIterator;
+use crate::package::{PackageName, PackageVersion, SpecRev, SpecRevIterator, MAX_SPECREV};
 use crate::progress::{Progress, ProgressBar};
 use crate::project::project_toml::RemoteProjectTomlValidationError;
-use crate::remote_package_db::RemotePackageDB;
 use crate::rockspec::Rockspec;
 use crate::TOOL_VERSION;
-use crate::{config::Config, project::Project};
+use crate::{
+    config::{display_request_error, Config, ConfigError},
+    project::Project,
+};
 
 use bon::Builder;
+use itertools::Itertools;
+use reqwest::multipart::{Form, Part};
 use reqwest::StatusCode;
-use reqwest::{
-    multipart::{Form, Part},
-    Client,
-};
 use serde::Deserialize;
 use serde_enum_str::Serialize_enum_str;
 use thiserror::Error;
@@ -36,7 +36,12 @@ pub struct ProjectUpload<'a> {
     sign_protocol: SignatureProtocol,
     config: &'a Config,
     progress: &'a Progress<ProgressBar>,
-    package_db: &'a RemotePackageDB,
+    /// The namespace to upload the package to.
+    /// Defaults to the configured namespace, or the authenticated user's default namespace.
+    namespace: Option<String>,
+    /// The highest rockspec revision to try before giving up.
+    /// Defaults to [`MAX_SPECREV`](crate::package::MAX_SPECREV).
+    max_specrev: Option<u16>,
 }
 
 impl<State> ProjectUploadBuilder<'_, State>
@@ -44,12 +49,36 @@ where
     State: project_upload_builder::State + project_upload_builder::IsComplete,
 {
     /// Upload a package to a luarocks server.
-    pub async fn upload_to_luarocks(self) -> Result<(), UploadError> {
+    pub async fn upload_to_luarocks(self) -> Result<UploadedPackage, UploadError> {
         let args = self._build();
         upload_from_project(args).await
     }
 }
 
+/// A package that was successfully uploaded to a luarocks server.
+#[derive(Debug, Clone)]
+pub struct UploadedPackage {
+    package: PackageName,
+    version: PackageVersion,
+    specrev: SpecRev,
+}
+
+impl UploadedPackage {
+    pub fn package(&self) -> &PackageName {
+        &self.package
+    }
+
+    /// The uploaded version, including the rockspec revision.
+    pub fn version(&self) -> &PackageVersion {
+        &self.version
+    }
+
+    /// The rockspec revision the package was uploaded with.
+    pub fn specrev(&self) -> &SpecRev {
+        &self.specrev
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct VersionCheckResponse {
     version: String,
@@ -65,16 +94,28 @@ pub enum ToolCheckError {
     ToolOutdated(String, VersionCheckResponse),
 }
 
+/// The status of the user authenticated by an API key.
+#[derive(Deserialize, Debug, Default)]
+struct UserStatusResponse {
+    /// The authenticated user's name, if reported by the server.
+    #[serde(default)]
+    username: Option<String>,
+}
+
 #[derive(Error, Debug)]
 pub enum UserCheckError {
     #[error("error parsing user check URL: {0}")]
     ParseError(#[from] url::ParseError),
     #[error(transparent)]
-    Request(#[from] reqwest::Error),
+    Request(reqwest::Error),
     #[error("invalid API key provided")]
     UserNotFound,
+    #[error("cannot upload to namespace '{namespace}' as user '{username}'")]
+    NamespaceMismatch { namespace: String, username: String },
     #[error("server {0} responded with error status: {1}")]
     Server(Url, StatusCode),
+    #[error("could not decode the user status from server {0}: {1}")]
+    DecodeResponse(Url, reqwest::Error),
 }
 
 #[derive(Error, Debug)]
@@ -83,7 +124,22 @@ pub enum RockCheckError {
     #[error(transparent)]
     ParseError(#[from] url::ParseError),
     #[error(transparent)]
-    Request(#[from] reqwest::Error),
+    Request(reqwest::Error),
+}
+
+// NOTE: The user and rock checks request URLs that contain the API key,
+// so we redact it from request errors.
+
+impl From<reqwest::Error> for UserCheckError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Request(helpers::redact_request_error(err))
+    }
+}
+
+impl From<reqwest::Error> for RockCheckError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Request(helpers::redact_request_error(err))
+    }
 }
 
 #[derive(Error, Debug)]
@@ -92,7 +148,8 @@ pub enum UploadError {
     #[error("error parsing upload URL: {0}")]
     ParseError(#[from] url::ParseError),
     Lua(#[from] mlua::Error),
-    Request(#[from] reqwest::Error),
+    #[error("{}", display_request_error(.0))]
+    Request(reqwest::Error),
     #[error("server {0} responded with error status: {1}")]
     Server(Url, StatusCode),
     #[error("client error when requesting {0}\nStatus code: {1}")]
@@ -115,16 +172,27 @@ pub enum UploadError {
     UnsupportedVersion(String),
     #[error("{0}")] // We don't know the concrete error type
     Rockspec(String),
-    #[error("the maximum supported number of rockspec revisions per version has been exceeded")]
-    MaxSpecRevsExceeded,
+    #[error("the maximum number of rockspec revisions per version ({0}) has been exceeded")]
+    MaxSpecRevsExceeded(u16),
     #[error("rock already exists on server. Error downloading existing rockspec:\n{0}")]
     SearchAndDownload(#[from] SearchAndDownloadError),
     #[error("error computing rockspec hash:\n{0}")]
     Hash(io::Error),
+    #[error("error initialising HTTP client:\n{0}")]
+    HttpClient(#[from] ConfigError),
+}
+
+impl From<reqwest::Error> for UploadError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Request(helpers::redact_request_error(err))
+    }
 }
 
 pub struct ApiKey(String);
 
+/// Replaces the API key in URLs that are displayed.
+const REDACTED_API_KEY: &str = "REDACTED";
+
 #[derive(Error, Debug)]
 #[error("no API key provided! Please set the $LUX_API_KEY variable")]
 pub struct ApiKeyUnspecified;
@@ -217,23 +285,40 @@ impl From<SignatureProtocol> for gpgme::Protocol {
     }
 }
 
-async fn upload_from_project(args: ProjectUpload<'_>) -> Result<(), UploadError> {
+async fn upload_from_project(args: ProjectUpload<'_>) -> Result<UploadedPackage, UploadError> {
     let project = args.project;
     let api_key = args.api_key.unwrap_or(ApiKey::new()?);
     #[cfg(feature = "gpgme")]
     let protocol = args.sign_protocol;
     let config = args.config;
     let progress = args.progress;
-    let package_db = args.package_db;
+    let namespace = args.namespace.or_else(|| config.namespace().cloned());
 
-    let client = Client::builder().https_only(true).build()?;
+    // Only allow plain HTTP if insecure connections have been explicitly enabled.
+    let client = config
+        .http_client_builder()?
+        .https_only(!config.allow_insecure())
+        .build()?;
 
+    config.select_server().await;
     helpers::ensure_tool_version(&client, config.server()).await?;
-    helpers::ensure_user_exists(&client, &api_key, config.server()).await?;
-
-    let (rockspec, rockspec_content) =
-        helpers::generate_rockspec(&project, &client, &api_key, config, progress, package_db)
-            .await?;
+    helpers::ensure_user_exists(&client, &api_key, config.server(), namespace.as_deref()).await?;
+
+    let (rockspec, rockspec_content, specrev) = helpers::generate_rockspec(
+        &project,
+        &client,
+        &api_key,
+        namespace.as_deref(),
+        config,
+        progress,
+        args.max_specrev.unwrap_or(MAX_SPECREV),
+    )
+    .await?;
+    let uploaded = UploadedPackage {
+        package: rockspec.package().clone(),
+        version: rockspec.version().clone(),
+        specrev,
+    };
 
     #[cfg(not(feature = "gpgme"))]
     let signed: Option<String> = None;
@@ -257,13 +342,17 @@ async fn upload_from_project(args: ProjectUpload<'_>) -> Result<(), UploadError>
     let rockspec = Part::text(rockspec_content)
         .file_name(format!(
             "{}-{}.rockspec",
-            rockspec.package(),
-            rockspec.version()
+            uploaded.package(),
+            uploaded.version()
         ))
         .mime_str("application/octet-stream")?;
 
     let multipart = {
         let multipart = Form::new().part("rockspec_file", rockspec);
+        let multipart = match namespace {
+            Some(namespace) => multipart.text("namespace", namespace),
+            None => multipart,
+        };
 
         match signed {
             Some(signature) => {
@@ -282,24 +371,29 @@ async fn upload_from_project(args: ProjectUpload<'_>) -> Result<(), UploadError>
 
     let status = response.status();
     if status.is_client_error() {
-        Err(UploadError::Client(config.server().clone(), status))
+        Err(UploadError::Client(
+            helpers::redact_api_key(response.url()),
+            status,
+        ))
     } else if status.is_server_error() {
-        Err(UploadError::Server(config.server().clone(), status))
+        Err(UploadError::Server(
+            helpers::redact_api_key(response.url()),
+            status,
+        ))
     } else {
-        Ok(())
+        Ok(uploaded)
     }
 }
 
-mod helpers {
+pub(crate) mod helpers {
     use super::*;
     use crate::hash::HasIntegrity;
     use crate::operations::Download;
-    use crate::package::{PackageName, PackageSpec, PackageVersion};
-    use crate::project::project_toml::RemoteProjectToml;
+    use crate::package::PackageSpec;
+    use crate::project::project_toml::{ProjectTomlError, RemoteProjectToml};
     use crate::upload::RockCheckError;
     use crate::upload::{ToolCheckError, UserCheckError};
     use reqwest::Client;
-    use ssri::Integrity;
     use url::Url;
 
     /// WARNING: This function is unsafe,
@@ -318,6 +412,44 @@ mod helpers {
             .join(endpoint)
     }
 
+    /// Replace the API key in a URL created by [`url_for_method`],
+    /// so that it can be displayed, e.g. in an error.
+    /// URLs without an API key are returned unchanged.
+    pub(crate) fn redact_api_key(url: &Url) -> Url {
+        let mut url = url.clone();
+        let segments = match url.path_segments() {
+            Some(segments) => segments.map(str::to_string).collect_vec(),
+            None => return url,
+        };
+        let api_key_index = segments
+            .windows(2)
+            .position(|window| window == ["api", "1"])
+            .map(|index| index + 2)
+            .filter(|index| *index < segments.len());
+        if let Some(api_key_index) = api_key_index {
+            if let Ok(mut path_segments) = url.path_segments_mut() {
+                path_segments
+                    .clear()
+                    .extend(segments.iter().enumerate().map(|(index, segment)| {
+                        if index == api_key_index {
+                            REDACTED_API_KEY
+                        } else {
+                            segment
+                        }
+                    }));
+            }
+        }
+        url
+    }
+
+    /// Redact the API key from the URL of a failed request.
+    pub(crate) fn redact_request_error(mut err: reqwest::Error) -> reqwest::Error {
+        if let Some(url) = err.url_mut() {
+            *url = redact_api_key(url);
+        }
+        err
+    }
+
     pub(crate) async fn ensure_tool_version(
         client: &Client,
         server_url: &Url,
@@ -341,10 +473,13 @@ mod helpers {
         }
     }
 
+    /// Ensure the API key belongs to an existing user,
+    /// who is allowed to upload to the `namespace`, if the server reports the user's name.
     pub(crate) async fn ensure_user_exists(
         client: &Client,
         api_key: &ApiKey,
         server_url: &Url,
+        namespace: Option<&str>,
     ) -> Result<(), UserCheckError> {
         let response = client
             .get(unsafe { url_for_method(server_url, api_key, "status")? })
@@ -352,28 +487,57 @@ mod helpers {
             .await?;
         let status = response.status();
         if status.is_client_error() {
-            Err(UserCheckError::UserNotFound)
+            return Err(UserCheckError::UserNotFound);
         } else if status.is_server_error() {
-            Err(UserCheckError::Server(server_url.clone(), status))
-        } else {
-            Ok(())
+            return Err(UserCheckError::Server(
+                redact_api_key(response.url()),
+                status,
+            ));
+        }
+        let url = redact_api_key(response.url());
+        let user_status: UserStatusResponse = response
+            .json()
+            .await
+            .map_err(|err| UserCheckError::DecodeResponse(url, redact_request_error(err)))?;
+        match (namespace, user_status.username) {
+            (Some(namespace), Some(username)) if !namespace.eq_ignore_ascii_case(&username) => {
+                Err(UserCheckError::NamespaceMismatch {
+                    namespace: namespace.to_string(),
+                    username,
+                })
+            }
+            _ => Ok(()),
         }
     }
 
+    /// Generate a remote rockspec from a project, along with its Lua representation.
+    pub(crate) fn generate_remote_rockspec(
+        project: &Project,
+        specrev: Option<SpecRev>,
+    ) -> Result<(RemoteProjectToml, String), GenerateRockspecError> {
+        let rockspec = project.toml().into_remote(specrev)?;
+        let rockspec_content = rockspec.to_lua_remote_rockspec_string()?;
+        Ok((rockspec, rockspec_content))
+    }
+
     pub(crate) async fn generate_rockspec(
         project: &Project,
         client: &Client,
         api_key: &ApiKey,
+        namespace: Option<&str>,
         config: &Config,
         progress: &Progress<ProgressBar>,
-        package_db: &RemotePackageDB,
-    ) -> Result<(RemoteProjectToml, String), UploadError> {
-        for specrev in SpecRevIterator::new() {
-            let rockspec = project.toml().into_remote(Some(specrev))?;
-
-            let rockspec_content = rockspec
-                .to_lua_remote_rockspec_string()
-                .map_err(|err| UploadError::Rockspec(err.to_string()))?;
+        max_specrev: u16,
+    ) -> Result<(RemoteProjectToml, String, SpecRev), UploadError> {
+        for specrev in SpecRevIterator::with_max(max_specrev) {
+            let (rockspec, rockspec_content) = generate_remote_rockspec(
+                project,
+                Some(specrev.clone()),
+            )
+            .map_err(|err| match err {
+                GenerateRockspecError::Validation(err) => UploadError::ValidationError(err),
+                GenerateRockspecError::Rockspec(err) => UploadError::Rockspec(err.to_string()),
+            })?;
 
             if let PackageVersion::StringVer(ver) = rockspec.version() {
                 return Err(UploadError::UnsupportedVersion(ver.to_string()));
@@ -381,6 +545,7 @@ mod helpers {
             if helpers::rock_exists(
                 client,
                 api_key,
+                namespace,
                 rockspec.package(),
                 rockspec.version(),
                 config.server(),
@@ -389,13 +554,18 @@ mod helpers {
             {
                 let package =
                     PackageSpec::new(rockspec.package().clone(), rockspec.version().clone());
+                let rockspec_url = match namespace {
+                    Some(namespace) => config.server().join(&format!("manifests/{namespace}/"))?,
+                    None => config.server().clone(),
+                };
                 let existing_rockspec = Download::new(&package.into(), config, progress)
-                    .package_db(package_db)
-                    .download_rockspec()
+                    .download_rockspec_from_url(&rockspec_url)
                     .await?
                     .rockspec;
                 let existing_rockspec_hash = existing_rockspec.hash().map_err(UploadError::Hash)?;
-                let rockspec_content_hash = Integrity::from(&rockspec_content);
+                let rockspec_content_hash = rockspec
+                    .content_hash()
+                    .map_err(|err| UploadError::Rockspec(err.to_string()))?;
                 if existing_rockspec_hash
                     .matches(&rockspec_content_hash)
                     .is_some()
@@ -403,29 +573,123 @@ mod helpers {
                     return Err(UploadError::RockExists(config.server().clone()));
                 }
             } else {
-                return Ok((rockspec, rockspec_content));
+                return Ok((rockspec, rockspec_content, specrev));
             }
         }
-        Err(UploadError::MaxSpecRevsExceeded)
+        Err(UploadError::MaxSpecRevsExceeded(max_specrev))
+    }
+
+    #[derive(Error, Debug)]
+    #[error(transparent)]
+    pub enum GenerateRockspecError {
+        Validation(#[from] RemoteProjectTomlValidationError),
+        Rockspec(#[from] ProjectTomlError),
     }
 
     pub(crate) async fn rock_exists(
         client: &Client,
         api_key: &ApiKey,
+        namespace: Option<&str>,
         name: &PackageName,
         version: &PackageVersion,
         server: &Url,
     ) -> Result<bool, RockCheckError> {
-        Ok(client
+        let request = client
             .get(unsafe { url_for_method(server, api_key, "check_rockspec")? })
             .query(&(
                 ("package", name.to_string()),
                 ("version", version.to_string()),
-            ))
-            .send()
-            .await?
-            .text()
-            .await?
-            != "{}")
+            ));
+        let request = match namespace {
+            Some(namespace) => request.query(&[("namespace", namespace)]),
+            None => request,
+        };
+        Ok(request.send().await?.text().await? != "{}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use httptest::{
+        matchers::{all_of, request},
+        responders::{json_encoded, status_code},
+        Expectation, Server,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn ensure_user_exists_namespace_mismatch() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("GET"),
+                request::path("/api/1/secret/status"),
+            ])
+            .times(2)
+            .respond_with(json_encoded(serde_json::json!({ "username": "alice" }))),
+        );
+        let server_url: Url = server.url_str("/").parse().unwrap();
+        let client = reqwest::Client::new();
+        let api_key = unsafe { ApiKey::from("secret".into()) };
+
+        helpers::ensure_user_exists(&client, &api_key, &server_url, Some("Alice"))
+            .await
+            .unwrap();
+        let result = helpers::ensure_user_exists(&client, &api_key, &server_url, Some("bob")).await;
+        assert!(matches!(
+            result,
+            Err(UserCheckError::NamespaceMismatch { namespace, username })
+                if namespace == "bob" && username == "alice"
+        ));
+    }
+
+    #[tokio::test]
+    async fn ensure_user_exists_invalid_response() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::path("/api/1/secret/status"))
+                .respond_with(status_code(200).body("not json")),
+        );
+        let server_url: Url = server.url_str("/").parse().unwrap();
+        let client = reqwest::Client::new();
+        let api_key = unsafe { ApiKey::from("secret".into()) };
+
+        let err = helpers::ensure_user_exists(&client, &api_key, &server_url, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserCheckError::DecodeResponse(..)));
+        assert!(!err.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn redact_api_key_in_upload_error() {
+        let server_url: Url = "https://luarocks.org/".parse().unwrap();
+        let api_key = unsafe { ApiKey::from("s3cr3t-k3y".into()) };
+        let url = unsafe { helpers::url_for_method(&server_url, &api_key, "upload").unwrap() };
+        assert!(url.as_str().contains("s3cr3t-k3y"));
+
+        let url = helpers::redact_api_key(&url);
+        assert_eq!(url.as_str(), "https://luarocks.org/api/1/REDACTED/upload");
+        let err = UploadError::Server(url, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!format!("{err} {err:?}").contains("s3cr3t-k3y"));
+
+        assert_eq!(helpers::redact_api_key(&server_url), server_url);
+    }
+
+    #[tokio::test]
+    async fn redact_api_key_in_request_error() {
+        // Nothing listens on port 1, so the request fails with an error containing its URL.
+        let server_url: Url = "http://127.0.0.1:1/".parse().unwrap();
+        let client = reqwest::Client::new();
+        let api_key = unsafe { ApiKey::from("s3cr3t-k3y".into()) };
+
+        let err = helpers::ensure_user_exists(&client, &api_key, &server_url, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UserCheckError::Request(_)));
+        let message = format!("{err} {err:?}");
+        assert!(message.contains("REDACTED"), "{message}");
+        assert!(!message.contains("s3cr3t-k3y"), "{message}");
     }
 }
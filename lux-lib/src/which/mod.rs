@@ -5,8 +5,8 @@ use itertools::Itertools;
 use thiserror::Error;
 
 use crate::{
-    config::{Config, LuaVersion, LuaVersionUnset},
-    lua_rockspec::LuaModule,
+    config::Config,
+    lua_rockspec::{LuaModule, LuaVersionError},
     package::PackageReq,
     tree::TreeError,
 };
@@ -52,14 +52,14 @@ pub enum WhichError {
     #[error(transparent)]
     Tree(#[from] TreeError),
     #[error(transparent)]
-    LuaVersionUnset(#[from] LuaVersionUnset),
+    LuaVersion(#[from] LuaVersionError),
     #[error("lua module {0} not found.")]
     ModuleNotFound(LuaModule),
 }
 
 fn do_search(which: Which<'_>) -> Result<PathBuf, WhichError> {
     let config = which.config;
-    let lua_version = LuaVersion::from(config)?;
+    let lua_version = config.resolve_lua_version(None)?;
     let tree = config.user_tree(lua_version.clone())?;
     let lockfile = tree.lockfile()?;
     let local_packages = if which.packages.is_empty() {
@@ -1,12 +1,13 @@
 use std::path::PathBuf;
 
-use assert_fs::prelude::PathCopy;
+use assert_fs::prelude::{FileWriteStr, PathChild, PathCopy};
 use assert_fs::TempDir;
 use lux_lib::progress::MultiProgress;
 use lux_lib::rockspec::Rockspec;
 use lux_lib::{
-    build::{Build, BuildBehaviour::Force},
+    build::{Build, BuildBehaviour::Force, BuildError},
     config::{ConfigBuilder, LuaVersion},
+    lockfile::PinnedState,
     lua_installation::{detect_installed_lua_version, LuaInstallation},
     lua_rockspec::RemoteLuaRockspec,
     progress::Progress,
@@ -244,6 +245,76 @@ async fn test_build_local_project_no_source() {
     assert!(plugin_file.is_file());
 }
 
+#[tokio::test]
+async fn test_build_local_project_with_overrides() {
+    let sample_project: PathBuf = "resources/test/sample-projects/no-source/".into();
+    let project_root = TempDir::new().unwrap();
+    project_root.copy_from(&sample_project, &["**"]).unwrap();
+    project_root
+        .child("bin/foo-cli")
+        .write_str("print('foo')")
+        .unwrap();
+    let lux_toml = project_root.child("lux.toml");
+    let content = std::fs::read_to_string(&lux_toml).unwrap();
+    lux_toml
+        .write_str(&format!(
+            "{content}bin = {{ \"foo-cli\" = \"bin/foo-cli\" }}\n"
+        ))
+        .unwrap();
+
+    let project = Project::from(&project_root).unwrap().unwrap();
+    let project_toml = project.toml().into_local().unwrap();
+
+    let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
+
+    let config = ConfigBuilder::new()
+        .unwrap()
+        .lua_version(lua_version)
+        .build()
+        .unwrap();
+
+    let tree = project.tree(&config).unwrap();
+    let bar = Progress::no_progress();
+
+    let lua = LuaInstallation::new_from_config(&config, &bar)
+        .await
+        .unwrap();
+
+    let result = Build::new()
+        .rockspec(&project_toml)
+        .lua(&lua)
+        .tree(&tree)
+        .entry_type(tree::EntryType::Entrypoint)
+        .config(&config)
+        .progress(&bar)
+        .behaviour(Force)
+        .override_name("vendored-fork".into())
+        .build()
+        .await;
+    assert!(matches!(result, Err(BuildError::PartialOverride(..))));
+
+    let package = Build::new()
+        .rockspec(&project_toml)
+        .lua(&lua)
+        .tree(&tree)
+        .entry_type(tree::EntryType::Entrypoint)
+        .config(&config)
+        .progress(&bar)
+        .behaviour(Force)
+        .pin(PinnedState::Pinned)
+        .override_name("vendored-fork".into())
+        .override_version("1.0.0-1".parse().unwrap())
+        .build()
+        .await
+        .unwrap();
+
+    assert_eq!(package.name().to_string(), "vendored-fork");
+    assert_eq!(package.version().to_string(), "1.0.0-1");
+    let rock_layout = tree.installed_rock_layout(&package).unwrap();
+    assert!(rock_layout.rock_path.is_dir());
+    assert!(tree.bin().join("foo-cli").is_file());
+}
+
 #[tokio::test]
 async fn test_build_local_project_only_src() {
     let sample_project: PathBuf = "resources/test/sample-projects/only-src/".into();
@@ -286,6 +357,47 @@ async fn test_build_local_project_only_src() {
     assert!(layout.src.join("foo.lua").is_file());
 }
 
+#[tokio::test]
+async fn test_build_local_project_install_conf_and_doc() {
+    let sample_project: PathBuf = "resources/test/sample-projects/install-conf-doc/".into();
+    let project_root = assert_fs::TempDir::new().unwrap();
+    project_root.copy_from(&sample_project, &["**"]).unwrap();
+
+    let project = Project::from(&project_root).unwrap().unwrap();
+    let project_toml = project.toml().into_local().unwrap();
+
+    let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
+
+    let config = ConfigBuilder::new()
+        .unwrap()
+        .lua_version(lua_version)
+        .build()
+        .unwrap();
+
+    let tree = project.tree(&config).unwrap();
+    let bar = Progress::no_progress();
+
+    let lua = LuaInstallation::new_from_config(&config, &bar)
+        .await
+        .unwrap();
+
+    let pkg = Build::new()
+        .rockspec(&project_toml)
+        .lua(&lua)
+        .tree(&tree)
+        .entry_type(tree::EntryType::Entrypoint)
+        .config(&config)
+        .progress(&bar)
+        .behaviour(Force)
+        .build()
+        .await
+        .unwrap();
+
+    let layout = tree.installed_rock_layout(&pkg).unwrap();
+    assert!(layout.conf.join("sample.conf").is_file());
+    assert!(layout.doc.join("README.md").is_file());
+}
+
 #[test]
 fn test_build_multiple_treesitter_parsers() {
     let dir = TempDir::new().unwrap();
@@ -423,6 +535,47 @@ async fn test_multiline_command_build() {
     assert!(success_dir.is_dir());
 }
 
+#[cfg(not(target_env = "msvc"))]
+#[tokio::test]
+async fn test_build_hooks() {
+    let sample_project: PathBuf = "resources/test/sample-projects/build-hooks/".into();
+    let project_root = TempDir::new().unwrap();
+    project_root.copy_from(&sample_project, &["**"]).unwrap();
+    let project = Project::from(&project_root).unwrap().unwrap();
+    let project_toml = project.toml().into_local().unwrap();
+
+    let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
+
+    let config = ConfigBuilder::new()
+        .unwrap()
+        .lua_version(lua_version)
+        .build()
+        .unwrap();
+
+    let tree = project.tree(&config).unwrap();
+    let bar = Progress::no_progress();
+
+    let lua = LuaInstallation::new_from_config(&config, &bar)
+        .await
+        .unwrap();
+
+    let package = Build::new()
+        .rockspec(&project_toml)
+        .lua(&lua)
+        .tree(&tree)
+        .entry_type(tree::EntryType::Entrypoint)
+        .config(&config)
+        .progress(&bar)
+        .behaviour(BuildBehaviour::Force)
+        .build()
+        .await
+        .unwrap();
+
+    let rock_layout = tree.installed_rock_layout(&package).unwrap();
+    assert!(rock_layout.src.join("sample").join("pre.lua").is_file());
+    assert!(rock_layout.src.join("post_build").is_dir());
+}
+
 #[tokio::test]
 async fn builtin_build_install_include() {
     let dir = TempDir::new().unwrap();
@@ -1,10 +1,12 @@
+use std::path::PathBuf;
+
 use assert_fs::TempDir;
 use lux_lib::{
     config::{ConfigBuilder, LuaVersion},
     git::GitSource,
     lua_installation::detect_installed_lua_version,
     lua_rockspec::RockSourceSpec,
-    operations::{Install, PackageInstallSpec},
+    operations::{Install, InstallRockspec, PackageInstallSpec},
     tree::EntryType,
 };
 
@@ -31,6 +33,68 @@ async fn install_http_package() {
     test_install(install_spec).await
 }
 
+#[tokio::test]
+async fn install_local_rockspec() {
+    let dir = TempDir::new().unwrap();
+    let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
+
+    let config = ConfigBuilder::new()
+        .unwrap()
+        .user_tree(Some(dir.to_path_buf()))
+        .lua_version(lua_version)
+        .build()
+        .unwrap();
+
+    let rockspec_path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/lua-cjson-2.1.0-1.rockspec");
+    let installed = InstallRockspec::new(&rockspec_path, &config)
+        .install()
+        .await
+        .unwrap();
+    assert_eq!(installed.name().to_string(), "lua-cjson");
+
+    let tree = config
+        .user_tree(LuaVersion::from(&config).unwrap().clone())
+        .unwrap();
+    let lockfile = tree.lockfile().unwrap();
+    assert!(lockfile.is_entrypoint(&installed.id()));
+    assert_eq!(
+        lockfile.get(&installed.id()).unwrap().source_path(),
+        Some(rockspec_path.as_path())
+    );
+}
+
+#[tokio::test]
+async fn install_into_explicit_tree_without_project() {
+    let dir = TempDir::new().unwrap();
+    assert!(!dir.join("lux.toml").exists());
+    let lua_version = detect_installed_lua_version().unwrap_or(LuaVersion::Lua51);
+
+    let config = ConfigBuilder::new()
+        .unwrap()
+        .lua_version(Some(lua_version.clone()))
+        .build()
+        .unwrap();
+
+    let tree_root = dir.join("tree");
+    let tree = config.tree(tree_root.clone(), lua_version.clone()).unwrap();
+    let install_spec =
+        PackageInstallSpec::new("say@1.4.1".parse().unwrap(), EntryType::Entrypoint).build();
+    let installed = Install::new(&config)
+        .package(install_spec)
+        .tree(tree.clone())
+        .install()
+        .await
+        .unwrap();
+    assert_eq!(installed.len(), 1);
+
+    let lockfile_path = tree_root.join(lua_version.to_string()).join("lux.lock");
+    assert_eq!(tree.lockfile_path(), lockfile_path);
+    assert!(lockfile_path.is_file());
+    let lockfile = tree.lockfile().unwrap();
+    assert!(lockfile.is_entrypoint(&installed[0].id()));
+}
+
 async fn test_install(install_spec: PackageInstallSpec) {
     let dir = TempDir::new().unwrap();
     let lua_version = detect_installed_lua_version().or(Some(LuaVersion::Lua51));
@@ -4,7 +4,8 @@ use assert_fs::{prelude::PathCopy, TempDir};
 use lux_lib::{
     config::{ConfigBuilder, LuaVersion},
     lua_installation::detect_installed_lua_version,
-    operations::{BuildProject, Pack},
+    operations::{BuildProject, Pack, PackSrcRock},
+    progress::MultiProgress,
     project::Project,
 };
 use mlua::Lua;
@@ -94,3 +95,52 @@ url = "https://github.com/nvim-neorocks/luarocks-stub"
         .contains_key("sample-project-0.1.0-1.rockspec")
         .unwrap());
 }
+
+#[tokio::test]
+async fn pack_project_src_rock() {
+    let project_root =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-projects/init/");
+    let temp_dir = TempDir::new().unwrap();
+    temp_dir.copy_from(&project_root, &["**"]).unwrap();
+    let project_root = temp_dir.path();
+    let project_toml_file = project_root.join("lux.toml");
+    let project_toml_content = tokio::fs::read_to_string(&project_toml_file).await.unwrap();
+    let project_toml_content = format!(
+        r#"{project_toml_content}
+[source]
+url = "file://{}"
+"#,
+        project_root.display()
+    );
+    tokio::fs::write(&project_toml_file, project_toml_content)
+        .await
+        .unwrap();
+
+    let config = ConfigBuilder::new().unwrap().build().unwrap();
+    let project = Project::from_exact(project_root).unwrap().unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let dest_dir = temp_dir.to_path_buf();
+    let progress = MultiProgress::new(&config);
+    let bar = progress.map(|p| p.new_bar());
+
+    let archive_path = PackSrcRock::new(dest_dir.clone(), &project, &config, &bar)
+        .pack()
+        .await
+        .unwrap();
+    assert_eq!(
+        archive_path,
+        dest_dir.join("sample-project-0.1.0-1.src.rock")
+    );
+    let archive_file = File::open(&archive_path).unwrap();
+    let mut archive = ZipArchive::new(archive_file).unwrap();
+    assert!(archive.by_name("src/main.lua").is_ok());
+    let mut rockspec_entry = archive.by_name("sample-project-0.1.0-1.rockspec").unwrap();
+    let mut rockspec_content = String::new();
+    rockspec_entry
+        .read_to_string(&mut rockspec_content)
+        .unwrap();
+    let lua = Lua::new();
+    lua.load(rockspec_content).exec().unwrap();
+    let package: String = lua.globals().get("package").unwrap();
+    assert_eq!(package, "sample-project");
+}
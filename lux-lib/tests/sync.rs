@@ -1,5 +1,12 @@
 use assert_fs::{prelude::PathCopy, TempDir};
-use lux_lib::{config::ConfigBuilder, operations::Sync, project::Project, tree::RockMatches};
+use lux_lib::{
+    config::ConfigBuilder,
+    lua_rockspec::RockSourceSpec,
+    operations::Sync,
+    project::Project,
+    rockspec::{lua_dependency::LuaDependencyType, Rockspec},
+    tree::RockMatches,
+};
 use std::path::PathBuf;
 
 #[tokio::test]
@@ -50,3 +57,46 @@ async fn sync_test_dependencies_empty_project() {
         RockMatches::Single { .. }
     ));
 }
+
+#[tokio::test]
+async fn sync_git_test_dependency() {
+    if std::env::var("LUX_SKIP_IMPURE_TESTS").unwrap_or("0".into()) == "1" {
+        println!("Skipping impure test");
+        return;
+    }
+    let sample_project_dir =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/sample-projects/init/");
+    let temp_dir = TempDir::new().unwrap();
+    temp_dir.copy_from(sample_project_dir, &["**"]).unwrap();
+    let mut project = Project::from_exact(temp_dir.path()).unwrap().unwrap();
+    let config = ConfigBuilder::new().unwrap().build().unwrap();
+
+    project
+        .add_git(LuaDependencyType::Test(vec!["github:mrcjkb/rustaceanvim"
+            .parse()
+            .unwrap()]))
+        .await
+        .unwrap();
+    let project_toml = project.toml().into_local().unwrap();
+    let test_dependency = project_toml
+        .test_dependencies()
+        .current_platform()
+        .first()
+        .cloned()
+        .unwrap();
+    assert!(matches!(
+        test_dependency.source(),
+        Some(RockSourceSpec::Git(_))
+    ));
+
+    Sync::new(&project, &config)
+        .sync_test_dependencies()
+        .await
+        .unwrap();
+
+    let test_tree = project.test_tree(&config).unwrap();
+    assert!(test_tree
+        .match_rocks(test_dependency.package_req())
+        .unwrap()
+        .is_found());
+}
@@ -31,3 +31,12 @@ async fn tree_userdata() {
     .exec()
     .unwrap();
 }
+
+#[test]
+fn explicit_tree_lockfile_path() {
+    let temp = TempDir::new().unwrap();
+    let config = ConfigBuilder::new().unwrap().build().unwrap();
+    let tree = config.tree(temp.to_path_buf(), LuaVersion::Lua51).unwrap();
+    assert_eq!(tree.lockfile_path(), temp.join("5.1").join("lux.lock"));
+    assert!(tree.lockfile().unwrap().rocks().is_empty());
+}
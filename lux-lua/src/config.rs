@@ -64,6 +64,7 @@ mod tests {
                 :user_tree(tree)
                 :verbose(true)
                 :timeout(10)
+                :download_timeout(60)
                 :cache_dir(cache)
                 :data_dir(data)
                 -- :entrypoint_layout("rockspec")
@@ -81,6 +82,8 @@ mod tests {
             assert(full_config:user_tree("5.1"), "tree should be not nil")
             assert(full_config:verbose(), "verbose should be true")
             assert(full_config:timeout() == 10, "timeout should be 10")
+            assert(full_config:request_timeout() == 10, "request_timeout should be 10")
+            assert(full_config:download_timeout() == 60, "download_timeout should be 60")
             assert(full_config:cache_dir() == cache, "cache_dir should be /cache")
             assert(full_config:data_dir() == data, "data_dir should be /data")
             -- assert(full_config.entrypoint_layout() == ...)
@@ -1,15 +1,35 @@
 use std::{
     env::{self, consts::DLL_EXTENSION},
     fs::{self},
+    io,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, ExitStatus},
 };
 use strum_macros::EnumIter;
 use target_lexicon::Triple;
+use thiserror::Error;
 
 pub type DynError = Box<dyn std::error::Error>;
 
-#[derive(EnumIter, PartialEq, Eq)]
+#[derive(Error, Debug)]
+pub enum DistError {
+    #[error(
+        "no Lua version feature enabled (expected one of lua51, lua52, lua53, lua54 or luajit)"
+    )]
+    NoLuaFeature,
+    #[error("cargo build failed ({0}), see the cargo output above")]
+    CargoBuild(ExitStatus),
+    #[error("lux-lua artifact {0} not found after building")]
+    MissingArtifact(PathBuf),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("failed to get the lux-lua version from {0}: {1}")]
+    VersionParse(PathBuf, String),
+    #[error("cannot link against the Lua library in {0} when building with the vendored feature")]
+    VendoredLuaLib(PathBuf),
+}
+
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq)]
 pub enum LuaFeature {
     Lua51,
     Lua52,
@@ -30,12 +50,42 @@ impl LuaFeature {
     }
 }
 
+/// How to link against a [`LuaLib`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LuaLink {
+    Static,
+    Dylib,
+}
+
+impl LuaLink {
+    fn as_str(&self) -> &str {
+        match self {
+            LuaLink::Static => "static",
+            LuaLink::Dylib => "dylib",
+        }
+    }
+}
+
+/// A Lua library to link against instead of discovering one with pkg-config.
+#[derive(Debug, Clone)]
+pub struct LuaLib {
+    /// Directory containing the library. Passed to the build as `LUA_LIB`.
+    pub dir: PathBuf,
+    /// Name of the library, e.g. `lua5.1`. Passed to the build as `LUA_LIB_NAME`.
+    pub name: String,
+    /// Passed to the build as `LUA_LINK`.
+    pub link: LuaLink,
+}
+
 pub struct DistOpts {
     pub lua_feature: Option<LuaFeature>,
     /// Whether to delete the `target/dist` directory
     pub clean_dist_dir: bool,
     /// Whether to enable the vendored feature
     pub vendored: bool,
+    /// The Lua library to link against.
+    /// Cannot be combined with `vendored`.
+    pub lua_lib: Option<LuaLib>,
 }
 
 impl Default for DistOpts {
@@ -44,12 +94,16 @@ impl Default for DistOpts {
             lua_feature: None,
             clean_dist_dir: true,
             vendored: false,
+            lua_lib: None,
         }
     }
 }
 
-pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DynError> {
+pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DistError> {
     let opts = opts.unwrap_or_default();
+    if let (true, Some(lua_lib)) = (opts.vendored, &opts.lua_lib) {
+        return Err(DistError::VendoredLuaLib(lua_lib.dir.clone()));
+    }
     let dist_dir = dist_dir();
     if opts.clean_dist_dir && dist_dir.is_dir() {
         println!("removing {}", dist_dir.display());
@@ -60,7 +114,6 @@ pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DynError> {
 
     let profile = if release { "release" } else { "debug" };
 
-    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
     let project_root = project_root();
 
     let lua_feature = match opts.lua_feature {
@@ -77,7 +130,7 @@ pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DynError> {
             } else if cfg!(feature = "luajit") {
                 LuaFeature::Luajit
             } else {
-                Err("No Lua version feature enabled")?
+                return Err(DistError::NoLuaFeature);
             }
         }
     };
@@ -86,34 +139,12 @@ pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DynError> {
 
     let (lua_feature_flag, canonical_lua_version) = lua_feature.lua_feature_strs();
 
-    let mut args = vec![
-        "build".into(),
-        "--package".into(),
-        "lux-lua".into(),
-        "--locked".into(),
-        "--target-dir".into(),
-        target_dir.to_string_lossy().to_string(),
-        "--no-default-features".into(),
-        "--features".into(),
-        lua_feature_flag.into(),
-    ];
-
-    if opts.vendored {
-        args.push("--features".into());
-        args.push("vendored".into());
-    }
-
-    if release {
-        args.push("--release".into());
-    }
+    let mut cmd = build_command(&project_root, &target_dir, lua_feature_flag, release, &opts);
 
-    let status = Command::new(&cargo)
-        .current_dir(&project_root)
-        .args(args)
-        .status()?;
+    let status = cmd.status()?;
 
     if !status.success() {
-        Err("cargo build failed")?;
+        return Err(DistError::CargoBuild(status));
     }
 
     let dest_dir = target_dir.join(profile);
@@ -153,7 +184,7 @@ pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DynError> {
     );
 
     if !src_file.is_file() {
-        Err(format!("{} not found", src_file.display()))?;
+        return Err(DistError::MissingArtifact(src_file));
     }
 
     println!("copying {} to {}", src_file.display(), dest_file.display());
@@ -167,11 +198,20 @@ pub fn dist(release: bool, opts: Option<DistOpts>) -> Result<(), DynError> {
 
     let version = {
         let manifest_path = project_root.join("Cargo.toml");
-        let manifest = fs::read_to_string(manifest_path)?;
-        let package: toml::Value = toml::from_str(&manifest)?;
-        package["workspace"]["package"]["version"]
-            .as_str()
-            .ok_or("lux-lua: Failed to get version from Cargo.toml")?
+        let manifest = fs::read_to_string(&manifest_path)?;
+        let package: toml::Value = toml::from_str(&manifest)
+            .map_err(|err| DistError::VersionParse(manifest_path.clone(), err.to_string()))?;
+        package
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package"))
+            .and_then(|package| package.get("version"))
+            .and_then(|version| version.as_str())
+            .ok_or_else(|| {
+                DistError::VersionParse(
+                    manifest_path.clone(),
+                    "missing workspace.package.version".into(),
+                )
+            })?
             .to_string()
     };
 
@@ -206,6 +246,54 @@ Libs: -L${{libdir}}"#
     Ok(())
 }
 
+fn build_command(
+    project_root: &Path,
+    target_dir: &Path,
+    lua_feature_flag: &str,
+    release: bool,
+    opts: &DistOpts,
+) -> Command {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+    let mut args = vec![
+        "build".into(),
+        "--package".into(),
+        "lux-lua".into(),
+        "--locked".into(),
+        "--target-dir".into(),
+        target_dir.to_string_lossy().to_string(),
+        "--no-default-features".into(),
+        "--features".into(),
+        lua_feature_flag.into(),
+    ];
+
+    if opts.vendored {
+        args.push("--features".into());
+        args.push("vendored".into());
+    }
+
+    if release {
+        args.push("--release".into());
+    }
+
+    let mut cmd = Command::new(&cargo);
+    cmd.current_dir(project_root).args(args);
+
+    if let Some(lua_lib) = &opts.lua_lib {
+        println!(
+            "linking against Lua library {} in {} ({})",
+            lua_lib.name,
+            lua_lib.dir.display(),
+            lua_lib.link.as_str()
+        );
+        cmd.env("LUA_LIB", &lua_lib.dir)
+            .env("LUA_LIB_NAME", &lua_lib.name)
+            .env("LUA_LINK", lua_lib.link.as_str());
+    }
+
+    cmd
+}
+
 fn project_root() -> PathBuf {
     Path::new(&env!("CARGO_MANIFEST_DIR"))
         .ancestors()
@@ -217,3 +305,62 @@ fn project_root() -> PathBuf {
 fn dist_dir() -> PathBuf {
     project_root().join("target/dist")
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn build_command_lua_lib_env() {
+        let opts = DistOpts {
+            lua_lib: Some(LuaLib {
+                dir: PathBuf::from("/opt/lua/lib"),
+                name: "lua5.1".into(),
+                link: LuaLink::Static,
+            }),
+            ..DistOpts::default()
+        };
+        let cmd = build_command(
+            Path::new("/project"),
+            Path::new("/project/target"),
+            "lua51",
+            false,
+            &opts,
+        );
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert!(envs.contains(&(OsStr::new("LUA_LIB"), Some(OsStr::new("/opt/lua/lib")))));
+        assert!(envs.contains(&(OsStr::new("LUA_LIB_NAME"), Some(OsStr::new("lua5.1")))));
+        assert!(envs.contains(&(OsStr::new("LUA_LINK"), Some(OsStr::new("static")))));
+    }
+
+    #[test]
+    fn build_command_without_lua_lib_env() {
+        let cmd = build_command(
+            Path::new("/project"),
+            Path::new("/project/target"),
+            "lua51",
+            false,
+            &DistOpts::default(),
+        );
+        assert_eq!(cmd.get_envs().count(), 0);
+    }
+
+    #[test]
+    fn reject_vendored_lua_lib() {
+        let opts = DistOpts {
+            vendored: true,
+            lua_lib: Some(LuaLib {
+                dir: PathBuf::from("/opt/lua/lib"),
+                name: "lua5.1".into(),
+                link: LuaLink::Dylib,
+            }),
+            ..DistOpts::default()
+        };
+        assert!(matches!(
+            dist(false, Some(opts)),
+            Err(DistError::VendoredLuaLib(_))
+        ));
+    }
+}
@@ -212,6 +212,7 @@ fn dist_package() -> Result<(), DynError> {
                 lua_feature: Some(lua_feature),
                 clean_dist_dir: false,
                 vendored: true,
+                lua_lib: None,
             }),
         )?;
     }